@@ -25,5 +25,31 @@ fn simple_periodic_bench(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, simple_periodic_bench);
+fn message_delivery_bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("message delivery bench");
+
+    // Delivery cost should not depend on how many other agents there are.
+    for bystanders in [0, 1000] {
+        group.bench_function(format!("{} bystanders", bystanders), |b| {
+            b.iter(|| {
+                let mut agents = vec![
+                    periodic_producing_agent("producer".to_string(), 1, "consumer".to_string()),
+                    periodic_consuming_agent("consumer".to_string(), 1),
+                ];
+                agents.extend(
+                    (0..bystanders).map(|i| periodic_consuming_agent(format!("idle{}", i), 1000)),
+                );
+
+                let mut simulation = Simulation::new(SimulationParameters {
+                    agents,
+                    halt_check: |s: &Simulation| s.time == 1000,
+                    ..Default::default()
+                });
+                simulation.run();
+            })
+        });
+    }
+}
+
+criterion_group!(benches, simple_periodic_bench, message_delivery_bench);
 criterion_main!(benches);
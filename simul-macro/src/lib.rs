@@ -37,6 +37,18 @@ pub fn agent(attr: TokenStream, item: TokenStream) -> TokenStream {
             fn state_mut(&mut self) -> &mut AgentState {
                 &mut self.state
             }
+
+            fn as_send(&mut self) -> Option<&mut (dyn simul::Agent + Send)> {
+                #[allow(unused_imports)]
+                use simul::agent::NotSend as _;
+                simul::agent::SendProbe(self).send_mut()
+            }
+
+            fn clone_send(&self) -> Option<Box<dyn simul::Agent + Send>> {
+                #[allow(unused_imports)]
+                use simul::agent::NotSend as _;
+                simul::agent::SendProbe(self).send_clone()
+            }
         }
     };
 
@@ -48,3 +60,149 @@ pub fn agent(attr: TokenStream, item: TokenStream) -> TokenStream {
     )
     .into()
 }
+
+/// Declares the agents of a simulation and generates its `SimulationParameters`.
+///
+/// Agents are declared by name, and anywhere in the declaration `@name` is
+/// replaced with the string `"name"`. References to undeclared agents are
+/// compile errors, so a misspelled destination is caught before anything runs.
+/// Every other field is passed through to `SimulationParameters`, with the
+/// rest defaulted.
+///
+/// ```ignore
+/// let parameters = simulation! {
+///     agents {
+///         producer: periodic_producing_agent(@producer, 1, @consumer),
+///         consumer: periodic_consuming_agent(@consumer, 1),
+///     }
+///     halt_check: |s: &Simulation| s.time == 10,
+/// };
+/// ```
+#[proc_macro]
+pub fn simulation(input: TokenStream) -> TokenStream {
+    match expand_simulation(input.into()) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand_simulation(input: proc_macro2::TokenStream) -> syn::Result<proc_macro2::TokenStream> {
+    use proc_macro2::{Delimiter, TokenTree};
+
+    let mut tokens = input.into_iter();
+    let agents_block = match (tokens.next(), tokens.next()) {
+        (Some(TokenTree::Ident(kw)), Some(TokenTree::Group(group)))
+            if kw == "agents" && group.delimiter() == Delimiter::Brace =>
+        {
+            group
+        }
+        (first, _) => {
+            return Err(syn::Error::new(
+                first.map_or_else(proc_macro2::Span::call_site, |t| t.span()),
+                "expected `agents { name: agent, ... }`",
+            ))
+        }
+    };
+
+    // Each declaration is `name: tokens` up to the next top-level comma.
+    let mut declarations: Vec<(Ident, proc_macro2::TokenStream)> = vec![];
+    let mut declaration_tokens: Vec<Vec<TokenTree>> = vec![vec![]];
+    for token in agents_block.stream() {
+        match token {
+            TokenTree::Punct(ref p) if p.as_char() == ',' => declaration_tokens.push(vec![]),
+            token => declaration_tokens.last_mut().unwrap().push(token),
+        }
+    }
+
+    for declaration in declaration_tokens.into_iter().filter(|d| !d.is_empty()) {
+        match declaration.as_slice() {
+            [TokenTree::Ident(name), TokenTree::Punct(colon), rest @ ..]
+                if colon.as_char() == ':' && !rest.is_empty() =>
+            {
+                if declarations.iter().any(|(n, _)| n == name) {
+                    return Err(syn::Error::new(
+                        name.span(),
+                        format!("agent `{}` is declared more than once", name),
+                    ));
+                }
+                declarations.push((name.clone(), rest.iter().cloned().collect()));
+            }
+            _ => {
+                return Err(syn::Error::new(
+                    declaration[0].span(),
+                    "expected `name: agent`",
+                ))
+            }
+        }
+    }
+
+    let names: Vec<Ident> = declarations.iter().map(|(n, _)| n.clone()).collect();
+    let agents = declarations
+        .into_iter()
+        .map(|(_, agent)| replace_agent_references(agent, &names))
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    let mut fields = replace_agent_references(tokens.collect(), &names)?;
+    if !fields.is_empty() && !fields.to_string().trim_end().ends_with(',') {
+        fields.extend(quote!(,));
+    }
+
+    Ok(quote! {
+        simul::SimulationParameters {
+            agents: vec![#(#agents),*],
+            #fields
+            ..::std::default::Default::default()
+        }
+    })
+}
+
+/// Replaces every `@name` with the string literal `"name"`, failing on names
+/// that are not declared.
+fn replace_agent_references(
+    tokens: proc_macro2::TokenStream,
+    names: &[Ident],
+) -> syn::Result<proc_macro2::TokenStream> {
+    use proc_macro2::{Group, Literal, TokenTree};
+
+    let mut output = vec![];
+    let mut tokens = tokens.into_iter();
+    while let Some(token) = tokens.next() {
+        match token {
+            TokenTree::Punct(ref p) if p.as_char() == '@' => match tokens.next() {
+                Some(TokenTree::Ident(name)) => {
+                    if !names.contains(&name) {
+                        let declared: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+                        return Err(syn::Error::new(
+                            name.span(),
+                            format!(
+                                "unknown agent `{}`; declared agents are: {}",
+                                name,
+                                declared.join(", ")
+                            ),
+                        ));
+                    }
+                    let mut literal = Literal::string(&name.to_string());
+                    literal.set_span(name.span());
+                    output.push(TokenTree::Literal(literal));
+                }
+                _ => {
+                    return Err(syn::Error::new(
+                        p.span(),
+                        "expected an agent name after `@`",
+                    ))
+                }
+            },
+            TokenTree::Group(group) => {
+                let mut replaced = Group::new(
+                    group.delimiter(),
+                    replace_agent_references(group.stream(), names)?,
+                );
+                replaced.set_span(group.span());
+                output.push(TokenTree::Group(replaced));
+            }
+            token => output.push(token),
+        }
+    }
+
+    Ok(output.into_iter().collect())
+}
@@ -0,0 +1,77 @@
+use crate::{AgentMode, DiscreteTime, Simulation};
+
+/// What an Agent did in a tick.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Activity {
+    /// The Agent processed a message, or a tick if proactive.
+    Processing,
+    /// The Agent was reactive and had no message to process.
+    Idle,
+    Asleep,
+    Dead,
+}
+
+impl Activity {
+    /// What an Agent in the given mode does in a tick.
+    pub(crate) fn of(mode: AgentMode, has_message: bool) -> Activity {
+        match mode {
+            AgentMode::Proactive => Activity::Processing,
+            AgentMode::Reactive if has_message => Activity::Processing,
+            AgentMode::Reactive => Activity::Idle,
+            AgentMode::AsleepUntil(_) => Activity::Asleep,
+            AgentMode::Dead => Activity::Dead,
+        }
+    }
+}
+
+/// A run of consecutive ticks in which an Agent did the same thing.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct ActivitySpan {
+    /// The first tick of the span.
+    pub from: DiscreteTime,
+    /// The tick after the last tick of the span.
+    pub until: DiscreteTime,
+    pub activity: Activity,
+}
+
+/// Records what an Agent did at a tick, extending its last span if it did
+/// the same thing in the previous tick.
+pub(crate) fn record_activity(
+    spans: &mut Vec<ActivitySpan>,
+    time: DiscreteTime,
+    activity: Activity,
+) {
+    match spans.last_mut() {
+        Some(last) if last.activity == activity && last.until == time => last.until = time + 1,
+        _ => spans.push(ActivitySpan {
+            from: time,
+            until: time + 1,
+            activity,
+        }),
+    }
+}
+
+impl Simulation {
+    /// Returns the timeline of what an Agent did, in order. Requires
+    /// `enable_activity_metrics`.
+    pub fn activity(&self, id: &str) -> Option<&[ActivitySpan]> {
+        Some(&self.metadata_for_agent(id)?.activity)
+    }
+
+    /// Returns the fraction of ticks an Agent spent processing. Requires
+    /// `enable_activity_metrics`.
+    pub fn utilization(&self, id: &str) -> Option<f64> {
+        let spans = self.activity(id)?;
+        let ticks = |spans: &mut dyn Iterator<Item = &ActivitySpan>| -> DiscreteTime {
+            spans.map(|s| s.until - s.from).sum()
+        };
+
+        let total = ticks(&mut spans.iter());
+        let processing = ticks(&mut spans.iter().filter(|s| s.activity == Activity::Processing));
+        if total == 0 {
+            return None;
+        }
+
+        Some(processing as f64 / total as f64)
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{message::*, DiscreteTime, SimulationState};
+use crate::{message::*, AgentError, DiscreteTime, Series, SimulationState};
 use dyn_clone::DynClone;
 use rand::prelude::*;
 use rand_distr::Poisson;
@@ -21,6 +21,31 @@ pub enum AgentMode {
     Dead,
 }
 
+/// Options of how the engine processes an Agent.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AgentOptions {
+    /// How many messages the Agent can process at once, like a pool of
+    /// identical servers. Each message taken occupies a slot until the Agent
+    /// wakes up from the sleep it went into after processing it, and the
+    /// Agent takes a message per free slot and tick.
+    pub concurrency: usize,
+    /// The roles of the Agent, e.g. "barista", by which other Agents can
+    /// address it without knowing its id; see `Message::to_tag`.
+    pub tags: Vec<String>,
+    /// The id of the Agent's parent, which it dies with; see `hierarchy`.
+    pub parent: Option<String>,
+}
+
+impl Default for AgentOptions {
+    fn default() -> Self {
+        Self {
+            concurrency: 1,
+            tags: vec![],
+            parent: None,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct AgentState {
     pub mode: AgentMode,
@@ -30,6 +55,7 @@ pub struct AgentState {
     pub queue: VecDeque<Message>,
     pub consumed: Vec<Message>,
     pub produced: Vec<Message>,
+    pub options: AgentOptions,
 }
 
 impl Default for AgentState {
@@ -41,6 +67,7 @@ impl Default for AgentState {
             queue: VecDeque::new(),
             consumed: vec![],
             produced: vec![],
+            options: AgentOptions::default(),
         }
     }
 }
@@ -56,6 +83,53 @@ pub trait AgentCommon {
     fn push_message(&mut self, msg: Message) {
         self.state_mut().queue.push_back(msg);
     }
+
+    /// The Agent as `Send`, if it is, so that parallel runs may process it on
+    /// another thread. `#[agent]` implements this for the Agents that are.
+    fn as_send(&mut self) -> Option<&mut (dyn Agent + Send)> {
+        None
+    }
+
+    /// A copy of the Agent as `Send`, if it is, e.g. to run it in an
+    /// ensemble on another thread. `#[agent]` implements this like `as_send`.
+    fn clone_send(&self) -> Option<Box<dyn Agent + Send>> {
+        None
+    }
+}
+
+/// Tells whether an Agent is `Send` for the `AgentCommon` that `#[agent]`
+/// implements: the inherent methods apply to Agents that are `Send`, and the
+/// `NotSend` ones to the rest.
+#[doc(hidden)]
+pub struct SendProbe<T>(pub T);
+
+impl<'a, T: Agent + Send> SendProbe<&'a mut T> {
+    pub fn send_mut(self) -> Option<&'a mut (dyn Agent + Send)> {
+        Some(self.0)
+    }
+}
+
+impl<'a, T: Agent + Clone + Send + 'static> SendProbe<&'a T> {
+    pub fn send_clone(self) -> Option<Box<dyn Agent + Send>> {
+        Some(Box::new(self.0.clone()))
+    }
+}
+
+#[doc(hidden)]
+pub trait NotSend<'a> {
+    fn send_mut(self) -> Option<&'a mut (dyn Agent + Send)>;
+
+    fn send_clone(self) -> Option<Box<dyn Agent + Send>>;
+}
+
+impl<'a, T> NotSend<'a> for SendProbe<T> {
+    fn send_mut(self) -> Option<&'a mut (dyn Agent + Send)> {
+        None
+    }
+
+    fn send_clone(self) -> Option<Box<dyn Agent + Send>> {
+        None
+    }
 }
 
 /// The bread and butter of the Simulation -- the Agent.
@@ -74,11 +148,29 @@ pub trait Agent: std::fmt::Debug + DynClone + AgentCommon {
     fn process(&mut self, simulation_state: SimulationState, msg: &Message)
         -> Option<Vec<Message>>;
 
+    /// Like `process`, for Agents that can fail; the engine records the
+    /// failure and handles it per its `ErrorPolicy`. The engine calls this,
+    /// which by default processes the message with `process`. Agents that
+    /// override it still implement `process`, e.g. by ignoring the failure.
+    fn try_process(
+        &mut self,
+        simulation_state: SimulationState,
+        msg: &Message,
+    ) -> Result<Option<Vec<Message>>, AgentError> {
+        Ok(self.process(simulation_state, msg))
+    }
+
     /// For annealing experiments, you may implement a cost function for the agent.
     /// For example, a periodic consuming agent has cost implented equal to its period.
     fn cost(&self) -> i64 {
         0
     }
+
+    /// Exposes timeseries the agent recorded itself, e.g. an inventory level,
+    /// by name. Plots of the agent draw them alongside the engine's metrics.
+    fn plot_series(&self) -> Vec<(&str, &Series)> {
+        vec![]
+    }
 }
 
 dyn_clone::clone_trait_object!(Agent);
@@ -101,7 +193,7 @@ where
         ) -> Option<Vec<Message>> {
             // This agent will go to sleep for a "cooldown period",
             // as determined by a poisson distribution function.
-            let cooldown_period = self.period.sample(&mut rand::thread_rng()) as u64;
+            let cooldown_period = self.period.sample(&mut crate::rng()) as u64;
             self.state.mode = AgentMode::AsleepUntil(simulation_state.time + cooldown_period);
             None
         }
@@ -142,7 +234,7 @@ where
         ) -> Option<Vec<Message>> {
             // This agent will go to sleep for a "cooldown period",
             // as determined by a poisson distribution function.
-            let cooldown_period = self.period.sample(&mut rand::thread_rng()) as u64;
+            let cooldown_period = self.period.sample(&mut crate::rng()) as u64;
 
             self.state.mode = AgentMode::AsleepUntil(simulation_state.time + cooldown_period);
 
@@ -0,0 +1,119 @@
+//! End-of-run assertions for testing Simulations.
+//!
+//! These read far better in a test than digging through the HashMaps returned
+//! by the `calc_*_statistics` functions, and panic with a message naming the
+//! Agent and the actual value when they fail. They count with the O(1)
+//! lookups like `Simulation::consumed_count`.
+
+/// Asserts on the number of messages an Agent consumed.
+///
+/// `assert_consumed!(simulation, "consumer", >= 4);`
+#[macro_export]
+macro_rules! assert_consumed {
+    ($sim:expr, $id:expr, $op:tt $expected:expr) => {{
+        let actual = $sim
+            .consumed_count($id)
+            .unwrap_or_else(|| panic!("No agent with id {:?}", $id));
+        assert!(
+            actual $op $expected,
+            "Expected agent {:?} to have consumed {} {} messages, but it consumed {}",
+            $id,
+            stringify!($op),
+            $expected,
+            actual
+        );
+    }};
+}
+
+/// Asserts on the number of messages an Agent produced.
+///
+/// `assert_produced!(simulation, "producer", == 5);`
+#[macro_export]
+macro_rules! assert_produced {
+    ($sim:expr, $id:expr, $op:tt $expected:expr) => {{
+        let actual = $sim
+            .produced_count($id)
+            .unwrap_or_else(|| panic!("No agent with id {:?}", $id));
+        assert!(
+            actual $op $expected,
+            "Expected agent {:?} to have produced {} {} messages, but it produced {}",
+            $id,
+            stringify!($op),
+            $expected,
+            actual
+        );
+    }};
+}
+
+/// Asserts on the number of messages left in an Agent's queue.
+///
+/// `assert_queue_len!(simulation, "consumer", <= 1);`
+#[macro_export]
+macro_rules! assert_queue_len {
+    ($sim:expr, $id:expr, $op:tt $expected:expr) => {{
+        let actual = $sim
+            .queue_len($id)
+            .unwrap_or_else(|| panic!("No agent with id {:?}", $id));
+        assert!(
+            actual $op $expected,
+            "Expected agent {:?} to have a queue length {} {}, but it was {}",
+            $id,
+            stringify!($op),
+            $expected,
+            actual
+        );
+    }};
+}
+
+/// Asserts that an Agent's queue is empty.
+///
+/// `assert_queue_empty!(simulation, "consumer");`
+#[macro_export]
+macro_rules! assert_queue_empty {
+    ($sim:expr, $id:expr) => {
+        $crate::assert_queue_len!($sim, $id, == 0)
+    };
+}
+
+/// Asserts that the Simulation halted for the given `HaltReason`. The reason
+/// is a pattern, so variants with data may be matched with wildcards. The
+/// safety limits may be named alone, for `LimitReached` by them.
+///
+/// `assert_halted_by!(simulation, HaltCheck);`
+/// `assert_halted_by!(simulation, Interrupt(_));`
+/// `assert_halted_by!(simulation, MaxTicks);`
+#[macro_export]
+macro_rules! assert_halted_by {
+    ($sim:expr, MaxTicks) => {
+        $crate::assert_halted_by!($sim, LimitReached($crate::Limit::MaxTicks))
+    };
+    ($sim:expr, MaxWallClock) => {
+        $crate::assert_halted_by!($sim, LimitReached($crate::Limit::MaxWallClock))
+    };
+    ($sim:expr, $($reason:tt)+) => {{
+        assert!(
+            matches!($sim.halt_reason, Some($crate::HaltReason::$($reason)+)),
+            "Expected the simulation to halt by {}, but it halted by {:?}",
+            stringify!($($reason)+),
+            $sim.halt_reason
+        );
+    }};
+}
+
+/// Asserts that the message ledger of the Simulation balances, i.e. that every
+/// message produced is accounted for.
+///
+/// `assert_messages_conserved!(simulation);`
+#[macro_export]
+macro_rules! assert_messages_conserved {
+    ($sim:expr) => {{
+        let ledger = $sim.message_ledger();
+        let discrepancies = ledger.discrepancies();
+        assert!(
+            discrepancies.is_empty(),
+            "Expected every message to be accounted for, but {}: {:?}",
+            discrepancies.join("; "),
+            ledger
+        );
+    }};
+}
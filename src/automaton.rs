@@ -0,0 +1,201 @@
+//! Cellular automata, in which every Agent is a cell whose next state
+//! depends on its neighbors' states, like Game of Life or forest fires.
+//!
+//! The updates are synchronous: the engine keeps the states of the cells
+//! double-buffered. Agents read the states as of the previous tick in their
+//! SimulationState, and send their next state with `Message::set_cell`. The
+//! engine collects the next states while the tick runs, and applies all of
+//! them at once at its end, so no Agent sees a neighbor's next state early,
+//! whatever order the Agents run in.
+
+use crate::{
+    Agent, AgentMode, AgentState, DiscreteTime, Interrupt, Message, Simulation, SimulationState,
+};
+use simul_macro::agent;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// The states of the cells of a cellular automaton, by Agent id.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Cells {
+    states: BTreeMap<String, Vec<u8>>,
+}
+
+/// A rule of a cellular automaton: the next state of a cell given its state
+/// and the states of its neighbors.
+pub type CellRule = fn(&[u8], &[&[u8]]) -> Vec<u8>;
+
+impl Cells {
+    pub fn new() -> Cells {
+        Cells::default()
+    }
+
+    /// Sets the state of an Agent's cell to begin with.
+    pub fn with_cell<T>(mut self, id: T, state: Vec<u8>) -> Cells
+    where
+        T: Into<String>,
+    {
+        self.states.insert(id.into(), state);
+        self
+    }
+
+    /// The state of an Agent's cell.
+    pub fn get(&self, id: &str) -> Option<&[u8]> {
+        self.states.get(id).map(|s| s.as_slice())
+    }
+
+    /// The states of the cells, in id order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.states
+            .iter()
+            .map(|(id, s)| (id.as_str(), s.as_slice()))
+    }
+}
+
+impl Message {
+    /// Creates a message that sets the state of src's cell at the end of
+    /// this tick.
+    pub fn set_cell<S>(time: DiscreteTime, src: S, state: Vec<u8>) -> Message
+    where
+        S: Into<String>,
+    {
+        let src = src.into();
+        Message {
+            interrupt: Some(Interrupt::SetCell(state)),
+            ..Message::new(time, src.as_str(), src.as_str())
+        }
+    }
+}
+
+impl Simulation {
+    /// Returns the states of the cells, as of the end of the last tick.
+    pub fn cells(&self) -> Option<&Cells> {
+        self.cells.as_deref()
+    }
+
+    /// Applies the next states the Agents sent this tick, all at once.
+    pub(crate) fn swap_cells(&mut self) {
+        let Some(cells) = self.cells.as_mut() else {
+            self.next_cells.clear();
+            return;
+        };
+        let cells = Arc::make_mut(cells);
+        for (id, state) in self.next_cells.drain(..) {
+            cells.states.insert(id, state);
+        }
+    }
+}
+
+/// Game of Life: a live cell (`[1]`) with 2 or 3 live neighbors lives, and a
+/// dead one (`[0]`) with 3 comes to life.
+pub fn life(state: &[u8], neighbors: &[&[u8]]) -> Vec<u8> {
+    let alive = neighbors.iter().filter(|n| **n == [1]).count();
+    match (state, alive) {
+        ([1], 2 | 3) | (_, 3) => vec![1],
+        _ => vec![0],
+    }
+}
+
+/// Returns an Agent that is a cell of a cellular automaton on the grid. Every
+/// tick it applies the rule to its state and those of the Agents within 1 on
+/// the grid that have cells, and sends its next state if it changed.
+pub fn cell_agent<T>(id: T, rule: CellRule) -> Box<dyn Agent>
+where
+    T: Into<String>,
+{
+    #[agent]
+    struct CellAgent {
+        rule: CellRule,
+    }
+
+    impl Agent for CellAgent {
+        fn process(
+            &mut self,
+            simulation_state: SimulationState,
+            _: &Message,
+        ) -> Option<Vec<Message>> {
+            let (grid, cells) = (simulation_state.grid?, simulation_state.cells?);
+            let id = self.state.id.as_str();
+            let state = cells.get(id)?;
+            let neighbors: Vec<&[u8]> = grid
+                .neighbors(id, 1)
+                .into_iter()
+                .filter_map(|n| cells.get(n))
+                .collect();
+
+            let next = (self.rule)(state, &neighbors);
+            if next == state {
+                return None;
+            }
+            Some(vec![Message::set_cell(simulation_state.time, id, next)])
+        }
+    }
+
+    Box::new(CellAgent {
+        rule,
+        state: AgentState {
+            mode: AgentMode::Proactive,
+            wake_mode: AgentMode::Proactive,
+            id: id.into(),
+            ..Default::default()
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    /// Runs Game of Life on a 5x5 torus until halt_check, one generation per
+    /// tick, and returns the live cells.
+    fn live_after(halt_check: fn(&Simulation) -> bool, live: &[(i64, i64)]) -> Vec<(i64, i64)> {
+        let (mut agents, mut grid, mut cells) =
+            (vec![], Grid::new(5, 5).with_torus(), Cells::new());
+        for x in 0..5 {
+            for y in 0..5 {
+                let id = format!("{},{}", x, y);
+                agents.push(cell_agent(id.as_str(), life));
+                grid = grid.with_agent(id.as_str(), x, y);
+                cells = cells.with_cell(id, vec![live.contains(&(x, y)) as u8]);
+            }
+        }
+
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents,
+            grid: Some(grid),
+            cells: Some(cells),
+            halt_check,
+            ..Default::default()
+        });
+        simulation.run();
+        assert!(simulation.message_ledger().is_balanced());
+
+        let grid = simulation.grid().unwrap();
+        let mut live: Vec<_> = simulation
+            .cells()
+            .unwrap()
+            .iter()
+            .filter(|(_, state)| *state == [1])
+            .map(|(id, _)| grid.position(id).unwrap())
+            .collect();
+        live.sort();
+        live
+    }
+
+    #[test]
+    fn life_test() {
+        let blinker = [(1, 2), (2, 2), (3, 2)];
+        assert_eq!(
+            live_after(|s| s.time == 1, &blinker),
+            [(2, 1), (2, 2), (2, 3)]
+        );
+        assert_eq!(live_after(|s| s.time == 2, &blinker), blinker);
+
+        // A glider moves one cell diagonally every 4 generations.
+        let glider = [(0, 1), (1, 2), (2, 0), (2, 1), (2, 2)];
+        let mut moved: Vec<_> = glider.iter().map(|(x, y)| (x + 1, y + 1)).collect();
+        moved.sort();
+        assert_eq!(live_after(|s| s.time == 4, &glider), moved);
+    }
+}
@@ -0,0 +1,367 @@
+//! Controllers that grow and shrink a group of workers while a Simulation
+//! runs, like the autoscaler of a service, according to a scaling policy.
+//!
+//! Every `interval` ticks an Autoscaler observes the total queue length of
+//! its group, and of the consumer pool named like the group's prefix, if any,
+//! and asks its policy how many workers there should be. It spawns new
+//! workers right away, and they join the pool. Retired workers leave the pool
+//! at once, so they get no more work, and die once they drained their queues.
+
+use crate::{Agent, AgentGroup, AgentMode, DiscreteTime, Simulation};
+use dyn_clone::DynClone;
+
+/// What an Autoscaler sees of its group when it asks its policy.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScalingObservation {
+    pub time: DiscreteTime,
+    /// The workers active, excluding retired ones still draining.
+    pub workers: usize,
+    /// The messages queued at the workers and in their pool.
+    pub queue_len: usize,
+}
+
+/// Decides how many workers a group should have.
+pub trait ScalingPolicy: std::fmt::Debug + DynClone + Send {
+    /// Returns the number of workers wanted. The Autoscaler clamps it to
+    /// its bounds, so it can be any number.
+    fn desired_workers(&mut self, observation: &ScalingObservation) -> usize;
+}
+
+dyn_clone::clone_trait_object!(ScalingPolicy);
+
+/// Scales to keep about `target` queued messages per worker.
+#[derive(Clone, Debug)]
+pub struct TargetQueuePerWorker {
+    pub target: f64,
+}
+
+impl ScalingPolicy for TargetQueuePerWorker {
+    fn desired_workers(&mut self, observation: &ScalingObservation) -> usize {
+        (observation.queue_len as f64 / self.target).ceil() as usize
+    }
+}
+
+/// Adds `step` workers while the queue per worker is above `scale_up_above`,
+/// and removes `step` while it's below `scale_down_below`.
+#[derive(Clone, Debug)]
+pub struct StepScaling {
+    pub scale_up_above: f64,
+    pub scale_down_below: f64,
+    pub step: usize,
+}
+
+impl ScalingPolicy for StepScaling {
+    fn desired_workers(&mut self, observation: &ScalingObservation) -> usize {
+        let per_worker = observation.queue_len as f64 / observation.workers.max(1) as f64;
+        if per_worker > self.scale_up_above {
+            observation.workers + self.step
+        } else if per_worker < self.scale_down_below {
+            observation.workers.saturating_sub(self.step)
+        } else {
+            observation.workers
+        }
+    }
+}
+
+/// A change of the number of workers of an Autoscaler.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct ScalingEvent {
+    pub time: DiscreteTime,
+    pub from: usize,
+    pub to: usize,
+}
+
+/// Grows and shrinks a group of workers; see the module docs.
+#[derive(Clone, Debug)]
+pub struct Autoscaler {
+    /// The workers, of which the first ones are there from the start.
+    pub group: AgentGroup,
+    /// Makes a new worker with the given id.
+    pub spawn: fn(String) -> Box<dyn Agent>,
+    pub policy: Box<dyn ScalingPolicy>,
+    pub min_workers: usize,
+    pub max_workers: usize,
+    /// The ticks between observations.
+    pub interval: DiscreteTime,
+    /// The ticks after a change during which the workers don't change again.
+    pub cooldown: DiscreteTime,
+    /// The ids of the active workers, oldest first.
+    active: Vec<String>,
+    /// The ids of retired workers that are still draining their queues.
+    draining: Vec<String>,
+    /// The workers spawned over the run, which numbers the next one.
+    spawned: usize,
+    events: Vec<ScalingEvent>,
+}
+
+impl Autoscaler {
+    /// Creates an Autoscaler of a group, e.g. made by `agent_pool` with the
+    /// same function as `spawn`, that keeps between 1 and `usize::MAX`
+    /// workers, observing them every tick.
+    pub fn new(
+        group: AgentGroup,
+        spawn: fn(String) -> Box<dyn Agent>,
+        policy: Box<dyn ScalingPolicy>,
+    ) -> Autoscaler {
+        Autoscaler {
+            active: group.ids().to_vec(),
+            spawned: group.len(),
+            group,
+            spawn,
+            policy,
+            min_workers: 1,
+            max_workers: usize::MAX,
+            interval: 1,
+            cooldown: 0,
+            draining: vec![],
+            events: vec![],
+        }
+    }
+
+    pub fn with_bounds(self, min_workers: usize, max_workers: usize) -> Autoscaler {
+        Autoscaler {
+            min_workers,
+            max_workers,
+            ..self
+        }
+    }
+
+    pub fn with_interval(self, interval: DiscreteTime) -> Autoscaler {
+        Autoscaler {
+            interval: interval.max(1),
+            ..self
+        }
+    }
+
+    pub fn with_cooldown(self, cooldown: DiscreteTime) -> Autoscaler {
+        Autoscaler { cooldown, ..self }
+    }
+
+    /// The ids of the active workers, oldest first.
+    pub fn workers(&self) -> &[String] {
+        &self.active
+    }
+
+    /// Every change of the number of workers, in order.
+    pub fn events(&self) -> &[ScalingEvent] {
+        &self.events
+    }
+
+    /// Whether the Autoscaler may change the workers at `time`.
+    fn is_due(&self, time: DiscreteTime) -> bool {
+        let cooled_down = self
+            .events
+            .last()
+            .map_or(true, |e| time >= e.time + self.cooldown);
+        time % self.interval == 0 && cooled_down
+    }
+}
+
+impl Simulation {
+    /// Returns the Autoscaler of the group with the given prefix.
+    pub fn autoscaler(&self, prefix: &str) -> Option<&Autoscaler> {
+        self.autoscalers.iter().find(|a| a.group.prefix == prefix)
+    }
+
+    /// Adds an Agent to the running Simulation, returning its handle.
+    fn add_agent(&mut self, agent: Box<dyn Agent>) -> usize {
+        let handle = self.agents.len();
+        self.agents.push(agent);
+        self.register_agent(handle);
+        handle
+    }
+
+    /// Runs every Autoscaler that is due, and retires the drained workers.
+    pub(crate) fn autoscale(&mut self) {
+        for index in 0..self.autoscalers.len() {
+            self.retire_drained(index);
+            if !self.autoscalers[index].is_due(self.time) {
+                continue;
+            }
+
+            let autoscaler = &self.autoscalers[index];
+            let prefix = autoscaler.group.prefix.clone();
+            let queue_len = autoscaler
+                .active
+                .iter()
+                .chain(autoscaler.draining.iter())
+                .filter_map(|id| self.queue_len(id))
+                .sum::<usize>()
+                + self.pool(&prefix).map_or(0, |p| p.queue_len());
+            let observation = ScalingObservation {
+                time: self.time,
+                workers: autoscaler.active.len(),
+                queue_len,
+            };
+
+            let autoscaler = &mut self.autoscalers[index];
+            let desired = autoscaler
+                .policy
+                .desired_workers(&observation)
+                .clamp(autoscaler.min_workers, autoscaler.max_workers);
+            if desired == observation.workers {
+                continue;
+            }
+            autoscaler.events.push(ScalingEvent {
+                time: self.time,
+                from: observation.workers,
+                to: desired,
+            });
+
+            for _ in observation.workers..desired {
+                let autoscaler = &mut self.autoscalers[index];
+                let id = AgentGroup::member_id(&prefix, autoscaler.spawned);
+                autoscaler.spawned += 1;
+                autoscaler.active.push(id.clone());
+                let mut agent = (autoscaler.spawn)(id.clone());
+                agent.state_mut().id = id.clone();
+                self.add_agent(agent);
+                if let Some(pool) = self.pools.iter_mut().find(|p| p.name == prefix) {
+                    pool.add_member(id);
+                }
+            }
+            for _ in desired..observation.workers {
+                let autoscaler = &mut self.autoscalers[index];
+                let id = autoscaler.active.pop().expect("There are more workers");
+                autoscaler.draining.push(id.clone());
+                if let Some(pool) = self.pools.iter_mut().find(|p| p.name == prefix) {
+                    pool.remove_member(&id);
+                }
+            }
+            self.retire_drained(index);
+        }
+    }
+
+    /// Kills the retired workers of an Autoscaler whose queues are empty.
+    fn retire_drained(&mut self, index: usize) {
+        let draining = std::mem::take(&mut self.autoscalers[index].draining);
+        for id in draining {
+            match self.agent_handles.get(&id).copied() {
+                Some(handle) if !self.agents[handle].state().queue.is_empty() => {
+                    self.autoscalers[index].draining.push(id);
+                }
+                Some(handle) => {
+                    let state = self.agents[handle].state_mut();
+                    state.mode = AgentMode::Dead;
+                    state.wake_mode = AgentMode::Dead;
+                }
+                None => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use simul_macro::agent;
+
+    fn worker(id: String) -> Box<dyn Agent> {
+        periodic_consuming_agent(id, 4)
+    }
+
+    /// Sends a message to the workers every tick until `until`.
+    fn producer(until: DiscreteTime) -> Box<dyn Agent> {
+        #[agent]
+        struct Producer {
+            until: DiscreteTime,
+        }
+
+        impl Agent for Producer {
+            fn process(&mut self, state: SimulationState, _: &Message) -> Option<Vec<Message>> {
+                if state.time >= self.until {
+                    self.state.mode = AgentMode::Dead;
+                    return None;
+                }
+                Some(vec![Message::new(state.time, "producer", "worker")])
+            }
+        }
+
+        Box::new(Producer {
+            until,
+            state: AgentState {
+                mode: AgentMode::Proactive,
+                wake_mode: AgentMode::Proactive,
+                id: "producer".to_string(),
+                ..Default::default()
+            },
+        })
+    }
+
+    fn run(until: DiscreteTime, policy: Box<dyn ScalingPolicy>) -> Simulation {
+        let (mut agents, group) = agent_pool("worker", 1, |id, _| worker(id));
+        agents.push(producer(until));
+        let autoscaler = Autoscaler::new(group.clone(), worker, policy)
+            .with_bounds(1, 6)
+            .with_interval(5)
+            .with_cooldown(10);
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents,
+            pools: vec![group.consumer_pool("worker")],
+            autoscalers: vec![autoscaler],
+            halt_check: |s: &Simulation| s.time == 300,
+            ..Default::default()
+        });
+        simulation.run();
+        simulation
+    }
+
+    #[test]
+    fn autoscaler_test() {
+        // Each worker consumes a message per 4 ticks, and one arrives every
+        // tick, so it takes 4 workers to keep up.
+        let policy = StepScaling {
+            scale_up_above: 1.5,
+            scale_down_below: 0.5,
+            step: 1,
+        };
+        let simulation = run(300, Box::new(policy));
+
+        let autoscaler = simulation.autoscaler("worker").unwrap();
+        let events = autoscaler.events();
+        assert!(autoscaler.workers().len() >= 4, "{:?}", events);
+        assert!(events.windows(2).all(|e| e[1].time >= e[0].time + 10));
+        assert!(simulation.pool("worker").unwrap().queue_len() < 10);
+        assert!(simulation.message_ledger().is_balanced());
+    }
+
+    #[test]
+    fn target_queue_per_worker_test() {
+        let mut policy = TargetQueuePerWorker { target: 4.0 };
+        let observation = ScalingObservation {
+            time: 0,
+            workers: 2,
+            queue_len: 9,
+        };
+        assert_eq!(policy.desired_workers(&observation), 3);
+    }
+
+    #[test]
+    fn scale_down_test() {
+        // Nothing arrives after tick 100, so the workers are retired once
+        // the backlog is gone, down to the minimum.
+        let policy = StepScaling {
+            scale_up_above: 2.0,
+            scale_down_below: 0.5,
+            step: 1,
+        };
+        let simulation = run(100, Box::new(policy));
+
+        let autoscaler = simulation.autoscaler("worker").unwrap();
+        let peak = autoscaler.events().iter().map(|e| e.to).max().unwrap();
+        assert!(peak > 1, "{:?}", autoscaler.events());
+        assert_eq!(autoscaler.workers(), &["worker-0"]);
+
+        let dead = (1..autoscaler.events().iter().map(|e| e.to).max().unwrap())
+            .map(|n| {
+                simulation
+                    .agent(&AgentGroup::member_id("worker", n))
+                    .unwrap()
+            })
+            .all(|a| a.state().mode == AgentMode::Dead);
+        assert!(dead);
+        assert_eq!(simulation.totals().queued, 0);
+        assert!(simulation.message_ledger().is_balanced());
+    }
+}
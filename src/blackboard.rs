@@ -0,0 +1,269 @@
+//! A blackboard: typed world state every Agent can read, and write without
+//! knowing who reads it, e.g. a market price, a shared map or a flag.
+//!
+//! Agents read the blackboard as of the end of the previous tick in their
+//! SimulationState, and write to it by sending `Message::post` or
+//! `Message::erase`. The engine applies the writes at the end of the tick,
+//! in the order they were sent, so every Agent of a tick reads the same
+//! values and the last write to a key wins.
+
+use crate::{DiscreteTime, Interrupt, Message, Simulation};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// A value on the blackboard.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BlackboardValue {
+    Flag(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl From<bool> for BlackboardValue {
+    fn from(value: bool) -> Self {
+        BlackboardValue::Flag(value)
+    }
+}
+
+impl From<i64> for BlackboardValue {
+    fn from(value: i64) -> Self {
+        BlackboardValue::Int(value)
+    }
+}
+
+impl From<f64> for BlackboardValue {
+    fn from(value: f64) -> Self {
+        BlackboardValue::Float(value)
+    }
+}
+
+impl From<&str> for BlackboardValue {
+    fn from(value: &str) -> Self {
+        BlackboardValue::Text(value.to_string())
+    }
+}
+
+impl From<String> for BlackboardValue {
+    fn from(value: String) -> Self {
+        BlackboardValue::Text(value)
+    }
+}
+
+impl From<Vec<u8>> for BlackboardValue {
+    fn from(value: Vec<u8>) -> Self {
+        BlackboardValue::Bytes(value)
+    }
+}
+
+/// The values on the blackboard, by key; see the module docs.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Blackboard {
+    values: BTreeMap<String, BlackboardValue>,
+}
+
+impl Blackboard {
+    pub fn new() -> Blackboard {
+        Blackboard::default()
+    }
+
+    /// Puts a value on the blackboard to begin with.
+    pub fn with<K, V>(mut self, key: K, value: V) -> Blackboard
+    where
+        K: Into<String>,
+        V: Into<BlackboardValue>,
+    {
+        self.values.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn get(&self, key: &str) -> Option<&BlackboardValue> {
+        self.values.get(key)
+    }
+
+    /// The flag at key. None if there is none, or it's another type.
+    pub fn flag(&self, key: &str) -> Option<bool> {
+        match self.get(key)? {
+            BlackboardValue::Flag(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// The integer at key. None if there is none, or it's another type.
+    pub fn int(&self, key: &str) -> Option<i64> {
+        match self.get(key)? {
+            BlackboardValue::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// The float at key. None if there is none, or it's another type.
+    pub fn float(&self, key: &str) -> Option<f64> {
+        match self.get(key)? {
+            BlackboardValue::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// The text at key. None if there is none, or it's another type.
+    pub fn text(&self, key: &str) -> Option<&str> {
+        match self.get(key)? {
+            BlackboardValue::Text(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The bytes at key. None if there are none, or it's another type.
+    pub fn bytes(&self, key: &str) -> Option<&[u8]> {
+        match self.get(key)? {
+            BlackboardValue::Bytes(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The keys and values, in key order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &BlackboardValue)> {
+        self.values.iter().map(|(key, value)| (key.as_str(), value))
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl Message {
+    /// Creates a message that puts a value at key on the blackboard at the
+    /// end of this tick.
+    pub fn post<S, V>(time: DiscreteTime, src: S, key: S, value: V) -> Message
+    where
+        S: Into<String>,
+        V: Into<BlackboardValue>,
+    {
+        let src = src.into();
+        Message {
+            interrupt: Some(Interrupt::Blackboard {
+                key: key.into(),
+                value: Some(value.into()),
+            }),
+            ..Message::new(time, src.as_str(), src.as_str())
+        }
+    }
+
+    /// Creates a message that takes the value at key off the blackboard at
+    /// the end of this tick.
+    pub fn erase<S>(time: DiscreteTime, src: S, key: S) -> Message
+    where
+        S: Into<String>,
+    {
+        let src = src.into();
+        Message {
+            interrupt: Some(Interrupt::Blackboard {
+                key: key.into(),
+                value: None,
+            }),
+            ..Message::new(time, src.as_str(), src.as_str())
+        }
+    }
+}
+
+impl Simulation {
+    /// Returns the blackboard, as of the end of the last tick.
+    pub fn blackboard(&self) -> &Blackboard {
+        &self.blackboard
+    }
+
+    /// Applies the writes the Agents sent this tick, in order.
+    pub(crate) fn apply_blackboard_writes(&mut self) {
+        let blackboard = Arc::make_mut(&mut self.blackboard);
+        for (key, value) in self.blackboard_writes.drain(..) {
+            match value {
+                Some(value) => blackboard.values.insert(key, value),
+                None => blackboard.values.remove(&key),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use simul_macro::agent;
+
+    /// Posts the price it reads plus one every tick, and closes the market
+    /// at tick 3.
+    fn trader(id: &str) -> Box<dyn Agent> {
+        #[agent]
+        struct Trader {}
+
+        impl Agent for Trader {
+            fn process(&mut self, state: SimulationState, _: &Message) -> Option<Vec<Message>> {
+                let id = self.state.id.as_str();
+                if state.time == 3 {
+                    return Some(vec![
+                        Message::erase(state.time, id, "price"),
+                        Message::post(state.time, id, "open", false),
+                    ]);
+                }
+                let price = state.blackboard.int("price")?;
+                Some(vec![Message::post(state.time, id, "price", price + 1)])
+            }
+        }
+
+        Box::new(Trader {
+            state: AgentState {
+                mode: AgentMode::Proactive,
+                wake_mode: AgentMode::Proactive,
+                id: id.to_string(),
+                ..Default::default()
+            },
+        })
+    }
+
+    fn run(halt_check: fn(&Simulation) -> bool) -> Simulation {
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![trader("a"), trader("b")],
+            blackboard: Blackboard::new().with("price", 10_i64).with("open", true),
+            halt_check,
+            ..Default::default()
+        });
+        simulation.run();
+        assert!(simulation.message_ledger().is_balanced());
+        simulation
+    }
+
+    #[test]
+    fn blackboard_test() {
+        // Both read the same price every tick, so it rose once per tick.
+        let simulation = run(|s| s.time == 3);
+        assert_eq!(simulation.blackboard().int("price"), Some(13));
+        assert_eq!(simulation.blackboard().flag("open"), Some(true));
+        assert_eq!(simulation.blackboard().float("price"), None);
+
+        let simulation = run(|s| s.time == 5);
+        assert_eq!(simulation.blackboard().get("price"), None);
+        assert_eq!(simulation.blackboard().flag("open"), Some(false));
+    }
+
+    #[test]
+    fn blackboard_over_lossy_channels_test() {
+        // Writes don't travel over a channel, so its faults don't touch them.
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![trader("a")],
+            blackboard: Blackboard::new().with("price", 10_i64),
+            channel_model: ChannelModel::default().with_default_faults(ChannelFaults {
+                loss_probability: 1.0,
+                ..Default::default()
+            }),
+            halt_check: |s| s.time == 3,
+            ..Default::default()
+        });
+        simulation.run();
+        assert_eq!(simulation.blackboard().int("price"), Some(13));
+        assert_eq!(simulation.channel_metrics("a", "a"), None);
+    }
+}
@@ -0,0 +1,334 @@
+//! A calendar: maps ticks onto simulated wall-clock time, e.g. 1 tick = 1
+//! minute from 2024-01-01 00:00, and finds the ticks of recurring events like
+//! "every weekday at 9:00", for business-process simulations.
+//!
+//! Times are UTC, without leap seconds. An event that falls inside a tick
+//! happens at the end of it, i.e. at the first tick at or after the event.
+
+use crate::{Agent, AgentMode, AgentState, DiscreteTime, Message, SimulationState};
+use simul_macro::agent;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// A day of the week.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+    const ALL: [Weekday; 7] = [
+        Weekday::Monday,
+        Weekday::Tuesday,
+        Weekday::Wednesday,
+        Weekday::Thursday,
+        Weekday::Friday,
+        Weekday::Saturday,
+        Weekday::Sunday,
+    ];
+
+    /// The weekday of a day since 1970-01-01, which was a Thursday.
+    fn of_day(day: i64) -> Weekday {
+        Weekday::ALL[(day + 3).rem_euclid(7) as usize]
+    }
+}
+
+/// A point in simulated wall-clock time.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    pub weekday: Weekday,
+}
+
+/// An event recurring at a time of day, on some days of the week.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Recurrence {
+    pub weekdays: Vec<Weekday>,
+    pub hour: u32,
+    pub minute: u32,
+}
+
+impl Recurrence {
+    /// Every day at hour:minute.
+    pub fn daily_at(hour: u32, minute: u32) -> Recurrence {
+        Recurrence::on(&Weekday::ALL, hour, minute)
+    }
+
+    /// Monday to Friday at hour:minute.
+    pub fn weekdays_at(hour: u32, minute: u32) -> Recurrence {
+        Recurrence::on(&Weekday::ALL[..5], hour, minute)
+    }
+
+    /// The given days at hour:minute.
+    pub fn on(weekdays: &[Weekday], hour: u32, minute: u32) -> Recurrence {
+        Recurrence {
+            weekdays: weekdays.to_vec(),
+            hour,
+            minute,
+        }
+    }
+}
+
+/// Maps ticks onto wall-clock time, starting at an epoch.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct Calendar {
+    /// The wall-clock time of tick 0, in seconds since 1970-01-01 00:00.
+    pub epoch: i64,
+    /// The wall-clock length of a tick, at least 1.
+    pub seconds_per_tick: u64,
+}
+
+impl Calendar {
+    /// A calendar whose tick 0 is at midnight of the given date.
+    pub fn starting(year: i64, month: u32, day: u32, seconds_per_tick: u64) -> Calendar {
+        Calendar {
+            epoch: days_from_civil(year, month, day) * SECONDS_PER_DAY,
+            seconds_per_tick: seconds_per_tick.max(1),
+        }
+    }
+
+    /// The wall-clock time of a tick, in seconds since 1970-01-01 00:00.
+    pub fn seconds(&self, time: DiscreteTime) -> i64 {
+        self.epoch + (time * self.seconds_per_tick) as i64
+    }
+
+    /// The first tick at or after a wall-clock time, in seconds since
+    /// 1970-01-01 00:00. Tick 0 for any time before the epoch.
+    pub fn tick(&self, seconds: i64) -> DiscreteTime {
+        let since_epoch = (seconds - self.epoch).max(0) as u64;
+        (since_epoch + self.seconds_per_tick - 1) / self.seconds_per_tick
+    }
+
+    /// The wall-clock time of a tick.
+    pub fn date_time(&self, time: DiscreteTime) -> DateTime {
+        let seconds = self.seconds(time);
+        let days = seconds.div_euclid(SECONDS_PER_DAY);
+        let of_day = seconds.rem_euclid(SECONDS_PER_DAY) as u32;
+        let (year, month, day) = civil_from_days(days);
+        DateTime {
+            year,
+            month,
+            day,
+            hour: of_day / 3600,
+            minute: of_day / 60 % 60,
+            second: of_day % 60,
+            weekday: Weekday::of_day(days),
+        }
+    }
+
+    /// The first tick at or after `from` the recurrence happens in. None if
+    /// it happens on no day.
+    pub fn next(&self, recurrence: &Recurrence, from: DiscreteTime) -> Option<DiscreteTime> {
+        // The earliest wall-clock time that falls in the tick `from`.
+        let earliest = match from {
+            0 => self.epoch,
+            _ => self.seconds(from - 1) + 1,
+        };
+        let time_of_day = (recurrence.hour * 3600 + recurrence.minute * 60) as i64;
+        let first_day = earliest.div_euclid(SECONDS_PER_DAY);
+
+        (first_day..first_day + 8)
+            .filter(|day| recurrence.weekdays.contains(&Weekday::of_day(*day)))
+            .map(|day| day * SECONDS_PER_DAY + time_of_day)
+            .find(|seconds| *seconds >= earliest)
+            .map(|seconds| self.tick(seconds))
+    }
+
+    /// The ticks the recurrence happens in, from `from` until before `until`.
+    pub fn occurrences(
+        &self,
+        recurrence: &Recurrence,
+        from: DiscreteTime,
+        until: DiscreteTime,
+    ) -> Vec<DiscreteTime> {
+        let mut occurrences = vec![];
+        let mut from = from;
+        while let Some(time) = self.next(recurrence, from).filter(|t| *t < until) {
+            occurrences.push(time);
+            from = time + 1;
+        }
+        occurrences
+    }
+
+    /// Creates a message src receives back at the next tick after `time`
+    /// the recurrence happens in; see `Message::schedule_at`.
+    pub fn schedule_next<S>(
+        &self,
+        time: DiscreteTime,
+        src: S,
+        recurrence: &Recurrence,
+        payload: Option<Vec<u8>>,
+    ) -> Option<Message>
+    where
+        S: Into<String>,
+    {
+        let at = self.next(recurrence, time + 1)?;
+        Some(Message::schedule_at(time, src, at, payload))
+    }
+}
+
+/// The days since 1970-01-01 of a date of the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The date of the proleptic Gregorian calendar of a day since 1970-01-01.
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// An agent that sends a message to target every time the recurrence
+/// happens, e.g. to open a shop every weekday at 9:00.
+pub fn calendar_agent<T>(
+    id: T,
+    calendar: Calendar,
+    recurrence: Recurrence,
+    target: T,
+) -> Box<dyn Agent>
+where
+    T: Into<String>,
+{
+    #[agent]
+    struct CalendarAgent {
+        calendar: Calendar,
+        recurrence: Recurrence,
+        target: String,
+    }
+
+    impl Agent for CalendarAgent {
+        fn process(&mut self, state: SimulationState, _msg: &Message) -> Option<Vec<Message>> {
+            let id = self.state.id.as_str();
+            let mut messages = vec![];
+            if self.calendar.next(&self.recurrence, state.time) == Some(state.time) {
+                messages.push(Message::new(state.time, id, self.target.as_str()));
+            }
+            messages.extend(
+                self.calendar
+                    .schedule_next(state.time, id, &self.recurrence, None),
+            );
+            Some(messages)
+        }
+    }
+
+    let id = id.into();
+    let mut queue = std::collections::VecDeque::new();
+    queue.push_back(Message::new(0, id.as_str(), id.as_str()));
+    Box::new(CalendarAgent {
+        calendar,
+        recurrence,
+        target: target.into(),
+        state: AgentState {
+            mode: AgentMode::Reactive,
+            wake_mode: AgentMode::Reactive,
+            id,
+            queue,
+            ..Default::default()
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn date_time_test() {
+        let calendar = Calendar::starting(2024, 2, 28, 3600);
+        assert_eq!(
+            calendar.date_time(24 + 13),
+            DateTime {
+                year: 2024,
+                month: 2,
+                day: 29,
+                hour: 13,
+                minute: 0,
+                second: 0,
+                weekday: Weekday::Thursday,
+            }
+        );
+        assert_eq!(calendar.date_time(48).month, 3);
+        assert_eq!(calendar.tick(calendar.seconds(50)), 50);
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+    }
+
+    #[test]
+    fn recurrence_test() {
+        // 2024-01-01 was a Monday; 1 tick is 1 minute.
+        let calendar = Calendar::starting(2024, 1, 1, 60);
+        let day = 24 * 60;
+        let weekdays = Recurrence::weekdays_at(9, 0);
+        assert_eq!(
+            calendar.occurrences(&weekdays, 0, 8 * day),
+            [
+                540,
+                day + 540,
+                2 * day + 540,
+                3 * day + 540,
+                4 * day + 540,
+                7 * day + 540
+            ]
+        );
+        assert_eq!(calendar.next(&weekdays, 540), Some(540));
+        assert_eq!(calendar.next(&Recurrence::on(&[], 9, 0), 0), None);
+
+        // 9:30 falls inside the 2-hour tick from 8:00 to 10:00.
+        let calendar = Calendar::starting(2024, 1, 1, 7200);
+        let sundays = Recurrence::on(&[Weekday::Sunday], 9, 30);
+        assert_eq!(calendar.next(&sundays, 0), Some(6 * 12 + 5));
+    }
+
+    #[test]
+    fn calendar_agent_test() {
+        let calendar = Calendar::starting(2024, 1, 1, 60);
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                calendar_agent("clock", calendar, Recurrence::weekdays_at(9, 0), "shop"),
+                periodic_consuming_agent("shop", 1),
+            ],
+            halt_check: |s: &Simulation| s.time == 14 * 24 * 60,
+            ..Default::default()
+        });
+        simulation.run();
+
+        let opened: Vec<_> = simulation
+            .consumed_for_agent("shop")
+            .unwrap()
+            .iter()
+            .map(|m| calendar.date_time(m.queued_time))
+            .collect();
+        assert_eq!(opened.len(), 10);
+        assert!(opened
+            .iter()
+            .all(|t| t.hour == 9 && t.weekday < Weekday::Saturday));
+        assert!(simulation.message_ledger().is_balanced());
+    }
+}
@@ -0,0 +1,166 @@
+use crate::{Message, Simulation};
+use rand::Rng;
+use std::collections::HashMap;
+
+/// The faults of an unreliable channel between two Agents. Each probability
+/// is applied independently to every message sent over the channel.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChannelFaults {
+    /// The probability a message is lost and never delivered.
+    pub loss_probability: f64,
+    /// The probability a message is delivered twice.
+    pub duplication_probability: f64,
+    /// The probability a message is inserted at a random position of the
+    /// destination's queue, rather than at the back.
+    pub reorder_probability: f64,
+}
+
+impl ChannelFaults {
+    /// The name and value of the first probability not in [0, 1], if any.
+    pub(crate) fn invalid_probability(&self) -> Option<(&'static str, f64)> {
+        [
+            ("loss_probability", self.loss_probability),
+            ("duplication_probability", self.duplication_probability),
+            ("reorder_probability", self.reorder_probability),
+        ]
+        .into_iter()
+        .find(|(_, p)| !(0.0..=1.0).contains(p))
+    }
+
+    fn assert_valid(&self) {
+        if let Some((name, p)) = self.invalid_probability() {
+            panic!("the channel's {} of {} is not in [0, 1]", name, p);
+        }
+    }
+}
+
+/// The channel-fault model of a Simulation, configured per source→destination
+/// pair. Channels without configured faults are perfectly reliable.
+#[derive(Clone, Debug, Default)]
+pub struct ChannelModel {
+    /// Maps from (source, destination) => the faults of that channel.
+    pub channels: HashMap<(String, String), ChannelFaults>,
+    /// The faults of every channel not configured in `channels`.
+    pub default_faults: Option<ChannelFaults>,
+}
+
+impl ChannelModel {
+    /// Configures the faults of the channel from source to destination.
+    ///
+    /// # Panics
+    ///
+    /// If a probability of the faults is not in [0, 1].
+    pub fn with_channel<S>(mut self, source: S, destination: S, faults: ChannelFaults) -> Self
+    where
+        S: Into<String>,
+    {
+        faults.assert_valid();
+        self.channels
+            .insert((source.into(), destination.into()), faults);
+        self
+    }
+
+    /// Configures the faults of every channel without faults of its own.
+    ///
+    /// # Panics
+    ///
+    /// If a probability of the faults is not in [0, 1].
+    pub fn with_default_faults(mut self, faults: ChannelFaults) -> Self {
+        faults.assert_valid();
+        self.default_faults = Some(faults);
+        self
+    }
+
+    /// Returns the faults of the channel from source to destination, if any.
+    pub fn faults(&self, source: &str, destination: &str) -> Option<&ChannelFaults> {
+        if self.channels.is_empty() {
+            return self.default_faults.as_ref();
+        }
+
+        self.channels
+            .get(&(source.to_string(), destination.to_string()))
+            .or(self.default_faults.as_ref())
+    }
+}
+
+/// Counts of the faults that occurred on a channel during a Simulation.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct ChannelMetrics {
+    pub dropped: usize,
+    pub duplicated: usize,
+    pub reordered: usize,
+    /// Messages dropped because the link was down; see `Topology`.
+    pub partitioned: usize,
+    /// Messages that waited for room on the edge; see `Edge::capacity`.
+    pub congested: usize,
+}
+
+/// How a single message is to be delivered after applying channel faults.
+pub(crate) struct ChannelDelivery {
+    pub copies: usize,
+    pub reorder: bool,
+}
+
+impl Simulation {
+    /// Returns the fault counts for the channel from source to destination.
+    pub fn channel_metrics(&self, source: &str, destination: &str) -> Option<&ChannelMetrics> {
+        self.channel_metrics
+            .get(&(source.to_string(), destination.to_string()))
+    }
+
+    /// Rolls the channel faults for a message and records them. Messages over
+    /// links that are down in the Topology are never delivered.
+    pub(crate) fn channel_delivery(&mut self, message: &Message) -> ChannelDelivery {
+        if !self
+            .topology
+            .is_linked(&message.source, &message.destination)
+        {
+            self.channel_metrics
+                .entry((message.source.clone(), message.destination.clone()))
+                .or_default()
+                .partitioned += 1;
+            return ChannelDelivery {
+                copies: 0,
+                reorder: false,
+            };
+        }
+
+        let Some(faults) = self
+            .channel_model
+            .faults(&message.source, &message.destination)
+        else {
+            return ChannelDelivery {
+                copies: 1,
+                reorder: false,
+            };
+        };
+
+        let rng = &mut self.rng;
+        let metrics = self
+            .channel_metrics
+            .entry((message.source.clone(), message.destination.clone()))
+            .or_default();
+
+        if rng.gen_bool(faults.loss_probability) {
+            metrics.dropped += 1;
+            return ChannelDelivery {
+                copies: 0,
+                reorder: false,
+            };
+        }
+
+        let copies = if rng.gen_bool(faults.duplication_probability) {
+            metrics.duplicated += 1;
+            2
+        } else {
+            1
+        };
+
+        let reorder = rng.gen_bool(faults.reorder_probability);
+        if reorder {
+            metrics.reordered += 1;
+        }
+
+        ChannelDelivery { copies, reorder }
+    }
+}
@@ -0,0 +1,173 @@
+//! A canned chaos experiment: sweeps network latency and message loss over a
+//! grid, and reports how the KPIs degrade compared to a healthy network.
+
+use crate::experiment::{run_ensemble, Kpi, ParameterPoint, ParameterSpace};
+use crate::{ChannelFaults, DiscreteTime, FixedLatency, SimulationParameters};
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// The dimension name of the latency in ticks, in a ChaosSweepReport.
+pub const LATENCY: &str = "latency";
+/// The dimension name of the loss probability, in a ChaosSweepReport.
+pub const LOSS_PROBABILITY: &str = "loss_probability";
+
+/// The grid of network conditions a chaos sweep runs the model under.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChaosSweep {
+    /// The latencies to try, in ticks added to every message hop.
+    pub latencies: Vec<DiscreteTime>,
+    /// The loss probabilities to try, applied to every channel.
+    pub loss_probabilities: Vec<f64>,
+    /// Every grid point runs once per seed; KPIs are averaged over the seeds.
+    pub seeds: Vec<u64>,
+}
+
+impl Default for ChaosSweep {
+    fn default() -> Self {
+        ChaosSweep {
+            latencies: vec![0, 1, 2, 5, 10],
+            loss_probabilities: vec![0.0, 0.01, 0.05, 0.1, 0.2],
+            seeds: (0..10).collect(),
+        }
+    }
+}
+
+/// The KPIs of a model under every network condition of a chaos sweep.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChaosSweepReport {
+    pub kpi_names: Vec<String>,
+    /// Every grid point, with `LATENCY` and `LOSS_PROBABILITY` dimensions,
+    /// and the mean of every KPI over the seeds.
+    pub results: Vec<(ParameterPoint, Vec<f64>)>,
+}
+
+impl ChaosSweepReport {
+    /// Returns the degradation surface of a KPI: its mean at every grid point
+    /// minus its mean at the healthiest point (least latency and loss).
+    pub fn degradation(&self, kpi: &str) -> Option<Vec<(ParameterPoint, f64)>> {
+        let column = self.kpi_names.iter().position(|k| k == kpi)?;
+        let (_, baseline) = self.results.iter().min_by(|(a, _), (b, _)| {
+            (a[LATENCY], a[LOSS_PROBABILITY])
+                .partial_cmp(&(b[LATENCY], b[LOSS_PROBABILITY]))
+                .expect("Grid points are finite")
+        })?;
+        let baseline = baseline[column];
+
+        Some(
+            self.results
+                .iter()
+                .map(|(point, kpis)| (point.clone(), kpis[column] - baseline))
+                .collect(),
+        )
+    }
+
+    /// Renders the report as CSV: the latency, the loss probability, then the
+    /// mean of every KPI.
+    pub fn to_csv(&self) -> String {
+        let mut csv = format!("{},{}", LATENCY, LOSS_PROBABILITY);
+        for name in self.kpi_names.iter() {
+            let _ = write!(csv, ",{}", name);
+        }
+        csv.push('\n');
+
+        for (point, kpis) in self.results.iter() {
+            let _ = write!(csv, "{},{}", point[LATENCY], point[LOSS_PROBABILITY]);
+            for kpi in kpis {
+                let _ = write!(csv, ",{}", kpi);
+            }
+            csv.push('\n');
+        }
+
+        csv
+    }
+
+    /// Writes the CSV rendering of the report to `path`.
+    pub fn write_csv<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        std::fs::write(path, self.to_csv())
+    }
+}
+
+/// Runs the model under every combination of latency and loss of the sweep,
+/// once per seed, and measures the KPIs. The latency is added as a
+/// `FixedLatency` message transform, and the loss as the default faults of
+/// every channel, on top of the model's own.
+pub fn chaos_sweep(
+    simulation_parameters: &SimulationParameters,
+    sweep: &ChaosSweep,
+    kpis: &[(&str, Kpi)],
+) -> ChaosSweepReport {
+    let space = ParameterSpace::default()
+        .with_dimension(LATENCY, sweep.latencies.iter().map(|l| *l as f64))
+        .with_dimension(LOSS_PROBABILITY, sweep.loss_probabilities.iter().copied());
+
+    let results = space
+        .points()
+        .into_iter()
+        .map(|point| {
+            let mut parameters = simulation_parameters.clone();
+            parameters.message_transforms.push(Box::new(FixedLatency {
+                name: LATENCY,
+                ticks: point[LATENCY] as DiscreteTime,
+            }));
+            parameters.channel_model.default_faults = Some(ChannelFaults {
+                loss_probability: point[LOSS_PROBABILITY],
+                ..Default::default()
+            });
+
+            let means = run_ensemble(&parameters, &sweep.seeds, kpis)
+                .summary()
+                .into_iter()
+                .map(|s| s.mean)
+                .collect();
+            (point, means)
+        })
+        .collect();
+
+    ChaosSweepReport {
+        kpi_names: kpis.iter().map(|(name, _)| name.to_string()).collect(),
+        results,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::experiment::mean_wait_time;
+    use crate::*;
+
+    #[test]
+    fn chaos_sweep_test() {
+        let parameters = SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer", 1, "consumer"),
+                periodic_consuming_agent("consumer", 1),
+            ],
+            halt_check: |s: &Simulation| s.time == 50,
+            ..Default::default()
+        };
+        let kpis: &[(&str, Kpi)] = &[
+            ("consumed", |s| {
+                s.calc_consumed_len_statistics()["consumer"] as f64
+            }),
+            ("wait", mean_wait_time),
+        ];
+        let sweep = ChaosSweep {
+            latencies: vec![0, 5],
+            loss_probabilities: vec![0.0, 0.5],
+            seeds: vec![1, 2],
+        };
+
+        let report = chaos_sweep(&parameters, &sweep, kpis);
+        assert_eq!(report.results.len(), 4);
+
+        let wait = report.degradation("wait").unwrap();
+        assert_eq!(wait[0].1, 0.0);
+        assert_eq!(wait[2].1, 5.0);
+
+        let consumed = report.degradation("consumed").unwrap();
+        assert!(consumed[1].1 < -10.0);
+
+        let csv = report.to_csv();
+        assert!(csv.starts_with("latency,loss_probability,consumed,wait\n0,0,49,1\n"));
+    }
+}
@@ -0,0 +1,216 @@
+//! Custom commands: engine-level actions defined outside the crate, e.g. a
+//! domain-specific kind of resource, without adding to `Interrupt`.
+//!
+//! An Agent sends a command as `Interrupt::Custom`, e.g. by
+//! `Message::command`, and the engine passes it to the handler registered for
+//! its name in `command_handlers`, with the whole Simulation. A command no
+//! handler is registered for is dead-lettered.
+
+use crate::{DeadLetter, DeadLetterReason, DiscreteTime, Interrupt, Message, Simulation};
+use dyn_clone::DynClone;
+use log::warn;
+use std::any::Any;
+
+/// A command an Agent sends to the engine, handled by the `CommandHandler`
+/// with the same name.
+pub trait CustomCommand: std::fmt::Debug + DynClone + Send + Sync {
+    /// The name the command's handler is registered for.
+    fn name(&self) -> &str;
+
+    /// Returns the command as Any, for handlers to downcast it.
+    fn as_any(&self) -> &dyn Any;
+}
+
+dyn_clone::clone_trait_object!(CustomCommand);
+
+/// Handles the custom commands of one name, when the message bus gets them.
+pub trait CommandHandler: std::fmt::Debug + DynClone + Send {
+    /// The name of the commands it handles.
+    fn name(&self) -> &str;
+
+    /// Handles a command sent in message, returning the messages to deliver
+    /// in reply, in this tick.
+    fn handle(
+        &mut self,
+        simulation: &mut Simulation,
+        message: &Message,
+        command: &dyn CustomCommand,
+    ) -> Option<Vec<Message>>;
+}
+
+dyn_clone::clone_trait_object!(CommandHandler);
+
+impl Message {
+    /// Creates a message that sends a custom command to the engine.
+    pub fn command<S, C>(time: DiscreteTime, src: S, command: C) -> Message
+    where
+        S: Into<String>,
+        C: CustomCommand + 'static,
+    {
+        let src = src.into();
+        Message {
+            interrupt: Some(Interrupt::Custom(Box::new(command))),
+            ..Message::new(time, src.as_str(), src.as_str())
+        }
+    }
+}
+
+impl Simulation {
+    /// Handles a custom command with its handler, returning the replies, or
+    /// dead-letters it if there's no handler. None if it isn't one.
+    pub(crate) fn handle_custom_command(&mut self, message: &Message) -> Option<Vec<Message>> {
+        let Some(Interrupt::Custom(command)) = &message.interrupt else {
+            return None;
+        };
+
+        // Taken out for the call, so the handler can see the rest of the Simulation.
+        let mut handlers = std::mem::take(&mut self.command_handlers);
+        let replies = match handlers.iter_mut().find(|h| h.name() == command.name()) {
+            Some(handler) => {
+                self.ledger.to_resources += 1;
+                handler.handle(self, message, command.as_ref())
+            }
+            None => {
+                warn!("Dead-lettering a command without a handler: {:?}", message);
+                self.dead_letters.push(DeadLetter {
+                    time: self.time,
+                    reason: DeadLetterReason::UnhandledCommand(command.name().to_string()),
+                    message: message.clone(),
+                });
+                self.ledger.dead_lettered += 1;
+                None
+            }
+        };
+        self.command_handlers = handlers;
+        Some(replies.unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use simul_macro::agent;
+
+    /// Asks for a number of tickets, or for a refund.
+    #[derive(Clone, Debug)]
+    struct Book {
+        tickets: u32,
+    }
+
+    impl CustomCommand for Book {
+        fn name(&self) -> &str {
+            "book"
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    struct Refund;
+
+    impl CustomCommand for Refund {
+        fn name(&self) -> &str {
+            "refund"
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    /// Sells tickets while there are some, replying with how many it sold,
+    /// and halts the Simulation once sold out.
+    #[derive(Clone, Debug)]
+    struct BoxOffice {
+        left: u32,
+    }
+
+    impl CommandHandler for BoxOffice {
+        fn name(&self) -> &str {
+            "book"
+        }
+
+        fn handle(
+            &mut self,
+            simulation: &mut Simulation,
+            message: &Message,
+            command: &dyn CustomCommand,
+        ) -> Option<Vec<Message>> {
+            let book = command.as_any().downcast_ref::<Book>()?;
+            let sold = book.tickets.min(self.left);
+            self.left -= sold;
+            if self.left == 0 {
+                simulation.mode = SimulationMode::Completed;
+            }
+            Some(vec![Message {
+                custom_payload: Some(vec![sold as u8]),
+                ..Message::new(simulation.time, "box-office", message.source.as_str())
+            }])
+        }
+    }
+
+    /// Books two tickets every tick, keeping the replies, and asks for a
+    /// refund at tick 1.
+    fn fan() -> Box<dyn Agent> {
+        #[agent]
+        struct Fan {}
+
+        impl Agent for Fan {
+            fn process(&mut self, state: SimulationState, msg: &Message) -> Option<Vec<Message>> {
+                if msg.source == "box-office" {
+                    self.state.consumed.push(Message {
+                        completed_time: Some(state.time),
+                        ..msg.clone()
+                    });
+                }
+                let mut commands = vec![Message::command(state.time, "fan", Book { tickets: 2 })];
+                if state.time == 1 {
+                    commands.push(Message::command(state.time, "fan", Refund));
+                }
+                Some(commands)
+            }
+        }
+
+        Box::new(Fan {
+            state: AgentState {
+                mode: AgentMode::Proactive,
+                wake_mode: AgentMode::Proactive,
+                id: "fan".to_string(),
+                ..Default::default()
+            },
+        })
+    }
+
+    #[test]
+    fn custom_command_test() {
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![fan()],
+            command_handlers: vec![Box::new(BoxOffice { left: 5 })],
+            halt_check: |s: &Simulation| s.time == 10,
+            ..Default::default()
+        });
+        simulation.run();
+
+        // Sold out on the third booking, at tick 2.
+        assert_eq!(simulation.time, 3);
+        let fan = simulation.agent("fan").unwrap().state();
+        let sold: Vec<_> = fan
+            .consumed
+            .iter()
+            .chain(fan.queue.iter())
+            .map(|m| m.custom_payload.as_ref().unwrap()[0])
+            .collect();
+        assert_eq!(sold, [2, 2, 1]);
+
+        let dead_letters = simulation.dead_letters();
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(
+            dead_letters[0].reason,
+            DeadLetterReason::UnhandledCommand("refund".to_string())
+        );
+        assert!(simulation.message_ledger().is_balanced());
+    }
+}
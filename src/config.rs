@@ -0,0 +1,885 @@
+//! Declarative configuration: SimulationParameters loaded from TOML or YAML,
+//! so experiments can be defined in files and versioned alongside their
+//! results, e.g.:
+//!
+//! ```toml
+//! ticks = 1000
+//! seed = 7
+//! queue_depth_metrics = true
+//!
+//! [agent.producer]
+//! kind = "poisson_producer"
+//! mean = 3.0
+//! target = "consumer"
+//!
+//! [agent.consumer]
+//! kind = "periodic_consumer"
+//! period = 2
+//! ```
+//!
+//! or, in YAML:
+//!
+//! ```yaml
+//! ticks: 1000
+//! seed: 7
+//! agents:
+//!   producer:
+//!     kind: poisson_producer
+//!     mean: 3.0
+//!     target: consumer
+//! ```
+//!
+//! The agents are the tables of `agent` in TOML, and the mappings of `agents`
+//! in YAML, in the order they're written. Nested tables are read as dotted
+//! keys, so `route = { sink = 0.5 }` is the same as `route.sink = 0.5`.
+//!
+//! Top-level keys:
+//!
+//! - Halt conditions, at least one of: `ticks`, the length of the run,
+//!   `max_wall_clock_seconds` and `halt_when_quiescent`.
+//! - `max_ticks`, a safety limit on the ticks; see `Limit`.
+//! - `seed` and `warm_up`.
+//! - Metric flags: `queue_depth_metrics`, `queue_depth_stats`,
+//!   `asleep_cycles_metrics`, `activity_metrics`, `trace` and
+//!   `throughput_window`.
+//! - `parallel_agents`.
+//!
+//! Every agent has a `kind`, which an `AgentRegistry` maps to a constructor
+//! that reads the rest of its keys. The built-in kinds are:
+//!
+//! - `periodic_producer`: `period`, `target`; see `periodic_producing_agent`.
+//! - `periodic_consumer`: `period`; see `periodic_consuming_agent`.
+//! - `poisson_producer`: `mean`, `target`; see
+//!   `poisson_distributed_producing_agent`.
+//! - `poisson_consumer`: `mean`; see `poisson_distributed_consuming_agent`.
+//! - `poisson_arrivals`: `rate`, `target`; see `network::poisson_arrivals`.
+//! - `exponential_station`: `rate`, and optionally `servers` and a
+//!   `route.<destination> = <probability>` per route; see
+//!   `network::exponential_station`.
+//! - `server`: `service_period`; see `workload::serving_agent`.
+
+use crate::network::{exponential_station, poisson_arrivals};
+use crate::workload::serving_agent;
+use crate::{
+    periodic_consuming_agent, periodic_producing_agent, poisson_distributed_consuming_agent,
+    poisson_distributed_producing_agent, Agent, DiscreteTime, QuiescencePolicy, Simulation,
+    SimulationParameters,
+};
+use rand_distr::Poisson;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+/// Why a configuration couldn't be loaded.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct ConfigError {
+    /// The line of a syntax error, from 1. 0 for the other errors, which
+    /// name the key they're about.
+    pub line: usize,
+    pub message: String,
+}
+
+impl ConfigError {
+    pub fn new<T>(line: usize, message: T) -> ConfigError
+    where
+        T: Into<String>,
+    {
+        ConfigError {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            0 => write!(f, "{}", self.message),
+            line => write!(f, "line {}: {}", line, self.message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// The formats a configuration can be written in.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum ConfigFormat {
+    Toml,
+    Yaml,
+}
+
+impl ConfigFormat {
+    /// The format of a file by its extension: YAML for `.yaml` and `.yml`,
+    /// and TOML otherwise.
+    pub fn of_path<P: AsRef<Path>>(path: P) -> ConfigFormat {
+        match path.as_ref().extension().and_then(|e| e.to_str()) {
+            Some("yaml" | "yml") => ConfigFormat::Yaml,
+            _ => ConfigFormat::Toml,
+        }
+    }
+}
+
+/// A value of a configuration.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigValue {
+    String(String),
+    /// A number written as a whole number, kept exact.
+    Integer(i128),
+    Float(f64),
+    Bool(bool),
+    Array(Vec<ConfigValue>),
+}
+
+impl ConfigValue {
+    fn from_toml(value: toml::Value) -> Option<ConfigValue> {
+        Some(match value {
+            toml::Value::String(string) => ConfigValue::String(string),
+            toml::Value::Integer(integer) => ConfigValue::Integer(integer.into()),
+            toml::Value::Float(float) => ConfigValue::Float(float),
+            toml::Value::Boolean(b) => ConfigValue::Bool(b),
+            toml::Value::Array(values) => ConfigValue::Array(
+                values
+                    .into_iter()
+                    .map(ConfigValue::from_toml)
+                    .collect::<Option<_>>()?,
+            ),
+            toml::Value::Datetime(_) | toml::Value::Table(_) => return None,
+        })
+    }
+
+    fn from_yaml(value: serde_yaml::Value) -> Option<ConfigValue> {
+        Some(match value {
+            serde_yaml::Value::String(string) => ConfigValue::String(string),
+            serde_yaml::Value::Number(number) => match (number.as_u64(), number.as_i64()) {
+                (Some(integer), _) => ConfigValue::Integer(integer.into()),
+                (_, Some(integer)) => ConfigValue::Integer(integer.into()),
+                _ => ConfigValue::Float(number.as_f64()?),
+            },
+            serde_yaml::Value::Bool(b) => ConfigValue::Bool(b),
+            serde_yaml::Value::Sequence(values) => ConfigValue::Array(
+                values
+                    .into_iter()
+                    .map(ConfigValue::from_yaml)
+                    .collect::<Option<_>>()?,
+            ),
+            serde_yaml::Value::Null
+            | serde_yaml::Value::Mapping(_)
+            | serde_yaml::Value::Tagged(_) => return None,
+        })
+    }
+}
+
+/// The keys of a table of a configuration, i.e. the top level or an agent,
+/// which are taken out as they're read. Keys left over are errors.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConfigTable {
+    /// What the table is, for errors, e.g. `agent "server"`. Empty for the
+    /// top level.
+    pub name: String,
+    entries: BTreeMap<String, ConfigValue>,
+    /// The agents the table's agent sends to.
+    targets: Vec<String>,
+}
+
+impl ConfigTable {
+    fn new<T>(name: T) -> ConfigTable
+    where
+        T: Into<String>,
+    {
+        ConfigTable {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+
+    fn insert(&mut self, key: String, value: ConfigValue) -> Result<(), ConfigError> {
+        if self.entries.contains_key(&key) {
+            return Err(self.error(&key, "is set twice"));
+        }
+        self.entries.insert(key, value);
+        Ok(())
+    }
+
+    fn insert_toml(&mut self, key: String, value: toml::Value) -> Result<(), ConfigError> {
+        if let toml::Value::Table(table) = value {
+            for (nested, value) in table {
+                self.insert_toml(format!("{}.{}", key, nested), value)?;
+            }
+            return Ok(());
+        }
+        let value = ConfigValue::from_toml(value)
+            .ok_or_else(|| self.error(&key, "must be a string, number, boolean or array"))?;
+        self.insert(key, value)
+    }
+
+    fn insert_yaml(&mut self, key: String, value: serde_yaml::Value) -> Result<(), ConfigError> {
+        if let serde_yaml::Value::Mapping(mapping) = value {
+            for (nested, value) in mapping {
+                let nested = yaml_key(&nested)
+                    .ok_or_else(|| self.error(&key, "has a key that isn't a string"))?;
+                self.insert_yaml(format!("{}.{}", key, nested), value)?;
+            }
+            return Ok(());
+        }
+        let value = ConfigValue::from_yaml(value)
+            .ok_or_else(|| self.error(&key, "must be a string, number, boolean or sequence"))?;
+        self.insert(key, value)
+    }
+
+    /// An error about the table, naming it.
+    fn table_error<T>(&self, message: T) -> ConfigError
+    where
+        T: fmt::Display,
+    {
+        match self.name.as_str() {
+            "" => ConfigError::new(0, message.to_string()),
+            name => ConfigError::new(0, format!("{}: {}", name, message)),
+        }
+    }
+
+    fn error<T>(&self, key: &str, message: T) -> ConfigError
+    where
+        T: fmt::Display,
+    {
+        self.table_error(format!("{} {}", key, message))
+    }
+
+    /// Errors on a key that's missing.
+    pub fn required<T>(&self, key: &str, value: Option<T>) -> Result<T, ConfigError> {
+        value.ok_or_else(|| self.error(key, "is missing"))
+    }
+
+    /// The value of a key as it is, e.g. an array.
+    pub fn value(&mut self, key: &str) -> Option<ConfigValue> {
+        self.entries.remove(key)
+    }
+
+    pub fn string(&mut self, key: &str) -> Result<Option<String>, ConfigError> {
+        match self.entries.remove(key) {
+            None => Ok(None),
+            Some(ConfigValue::String(string)) => Ok(Some(string)),
+            Some(_) => Err(self.error(key, "must be a string")),
+        }
+    }
+
+    pub fn number(&mut self, key: &str) -> Result<Option<f64>, ConfigError> {
+        match self.entries.remove(key) {
+            None => Ok(None),
+            Some(ConfigValue::Integer(number)) => Ok(Some(number as f64)),
+            Some(ConfigValue::Float(number)) => Ok(Some(number)),
+            Some(_) => Err(self.error(key, "must be a number")),
+        }
+    }
+
+    pub fn bool(&mut self, key: &str) -> Result<Option<bool>, ConfigError> {
+        match self.entries.remove(key) {
+            None => Ok(None),
+            Some(ConfigValue::Bool(b)) => Ok(Some(b)),
+            Some(_) => Err(self.error(key, "must be true or false")),
+        }
+    }
+
+    /// A whole number from 0 to `u64::MAX`.
+    pub fn count(&mut self, key: &str) -> Result<Option<u64>, ConfigError> {
+        let error = self.error(
+            key,
+            format!("must be a whole number from 0 to {}", u64::MAX),
+        );
+        match self.entries.remove(key) {
+            None => Ok(None),
+            Some(ConfigValue::Integer(n)) => u64::try_from(n).map(Some).map_err(|_| error),
+            Some(_) => Err(error),
+        }
+    }
+
+    /// A finite number greater than 0.
+    pub fn positive(&mut self, key: &str) -> Result<Option<f64>, ConfigError> {
+        let error = self.error(key, "must be positive");
+        match self.number(key)? {
+            Some(n) if n <= 0.0 || !n.is_finite() => Err(error),
+            n => Ok(n),
+        }
+    }
+
+    /// The id of an agent the table's agent sends to, which must be defined.
+    pub fn target(&mut self, key: &str) -> Result<Option<String>, ConfigError> {
+        let target = self.string(key)?;
+        if let Some(target) = &target {
+            self.targets.push(target.clone());
+        }
+        Ok(target)
+    }
+
+    /// Every key `<prefix>.<name>`, as (name, number), e.g. the routes of a
+    /// station. The names must be ids of agents.
+    pub fn targets_with_numbers(
+        &mut self,
+        prefix: &str,
+    ) -> Result<Vec<(String, f64)>, ConfigError> {
+        let prefix = format!("{}.", prefix);
+        let keys: Vec<String> = self
+            .entries
+            .keys()
+            .filter(|k| k.starts_with(&prefix))
+            .cloned()
+            .collect();
+
+        let mut numbers = vec![];
+        for key in keys {
+            let number = self.number(&key)?.unwrap_or_default();
+            let name = key[prefix.len()..].to_string();
+            self.targets.push(name.clone());
+            numbers.push((name, number));
+        }
+        Ok(numbers)
+    }
+
+    /// Errors on the first key no one took.
+    pub fn finish(&self) -> Result<(), ConfigError> {
+        match self.entries.keys().next() {
+            Some(key) => Err(self.table_error(format!("unknown key {:?}", key))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The string of a YAML mapping's key, which may be written as a number.
+fn yaml_key(key: &serde_yaml::Value) -> Option<String> {
+    match key {
+        serde_yaml::Value::String(key) => Some(key.clone()),
+        serde_yaml::Value::Number(key) => Some(key.to_string()),
+        _ => None,
+    }
+}
+
+/// A parsed configuration: its top-level table, and the table of every
+/// agent in the order they were defined.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConfigDocument {
+    pub top: ConfigTable,
+    pub agents: Vec<(String, ConfigTable)>,
+}
+
+impl ConfigDocument {
+    pub fn parse(text: &str, format: ConfigFormat) -> Result<ConfigDocument, ConfigError> {
+        match format {
+            ConfigFormat::Toml => ConfigDocument::parse_toml(text),
+            ConfigFormat::Yaml => ConfigDocument::parse_yaml(text),
+        }
+    }
+
+    /// Reads and parses a file, in the format of its extension.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ConfigDocument, ConfigError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| ConfigError::new(0, format!("{}: {}", path.display(), e)))?;
+        ConfigDocument::parse(&text, ConfigFormat::of_path(path))
+    }
+
+    /// Starts the table of an agent.
+    fn add_agent(&mut self, id: String) -> Result<&mut ConfigTable, ConfigError> {
+        if id.is_empty() {
+            return Err(ConfigError::new(0, "an agent needs an id"));
+        }
+        let table = ConfigTable::new(format!("agent {:?}", id));
+        self.agents.push((id, table));
+        Ok(&mut self.agents.last_mut().expect("the agent").1)
+    }
+
+    fn parse_toml(text: &str) -> Result<ConfigDocument, ConfigError> {
+        let mut top: toml::Table = text.parse().map_err(|e: toml::de::Error| {
+            let line = e
+                .span()
+                .map_or(0, |span| text[..span.start].matches('\n').count() + 1);
+            ConfigError::new(line, e.message())
+        })?;
+
+        let mut document = ConfigDocument::default();
+        match top.remove("agent") {
+            None => {}
+            Some(toml::Value::Table(agents)) => {
+                for (id, agent) in agents {
+                    let toml::Value::Table(agent) = agent else {
+                        return Err(ConfigError::new(0, format!("agent.{} must be a table", id)));
+                    };
+                    let table = document.add_agent(id)?;
+                    for (key, value) in agent {
+                        table.insert_toml(key, value)?;
+                    }
+                }
+            }
+            Some(_) => return Err(ConfigError::new(0, "agent must be a table of agents")),
+        }
+        for (key, value) in top {
+            document.top.insert_toml(key, value)?;
+        }
+        Ok(document)
+    }
+
+    fn parse_yaml(text: &str) -> Result<ConfigDocument, ConfigError> {
+        let top = match serde_yaml::from_str(text) {
+            Ok(serde_yaml::Value::Mapping(top)) => top,
+            Ok(serde_yaml::Value::Null) => Default::default(),
+            Ok(_) => return Err(ConfigError::new(0, "expected a mapping of keys")),
+            Err(e) => {
+                let line = e.location().map_or(0, |l| l.line());
+                return Err(ConfigError::new(line, e.to_string()));
+            }
+        };
+
+        let mut document = ConfigDocument::default();
+        for (key, value) in top {
+            let key = yaml_key(&key).ok_or_else(|| ConfigError::new(0, "keys must be strings"))?;
+            if key != "agents" {
+                document.top.insert_yaml(key, value)?;
+                continue;
+            }
+            let serde_yaml::Value::Mapping(agents) = value else {
+                return Err(ConfigError::new(0, "agents must be a mapping of agents"));
+            };
+            for (id, agent) in agents {
+                let id =
+                    yaml_key(&id).ok_or_else(|| ConfigError::new(0, "an agent needs an id"))?;
+                let serde_yaml::Value::Mapping(agent) = agent else {
+                    return Err(ConfigError::new(
+                        0,
+                        format!("agents.{} must be a mapping", id),
+                    ));
+                };
+                let table = document.add_agent(id)?;
+                for (key, value) in agent {
+                    let key =
+                        yaml_key(&key).ok_or_else(|| table.table_error("keys must be strings"))?;
+                    table.insert_yaml(key, value)?;
+                }
+            }
+        }
+        Ok(document)
+    }
+}
+
+/// Creates an Agent with the id from the rest of its table.
+pub type AgentConstructor = fn(&str, &mut ConfigTable) -> Result<Box<dyn Agent>, ConfigError>;
+
+/// Maps the kinds of agents to their constructors. The default registry has
+/// the built-in kinds; register more to configure custom Agents.
+#[derive(Clone, Debug)]
+pub struct AgentRegistry {
+    constructors: BTreeMap<String, AgentConstructor>,
+}
+
+impl Default for AgentRegistry {
+    fn default() -> Self {
+        let mut registry = AgentRegistry::empty();
+        registry.register("periodic_producer", |id, t| {
+            let period = t.count("period")?;
+            let target = t.target("target")?;
+            Ok(periodic_producing_agent(
+                id,
+                t.required("period", period)?,
+                &t.required("target", target)?,
+            ))
+        });
+        registry.register("periodic_consumer", |id, t| {
+            let period = t.count("period")?;
+            Ok(periodic_consuming_agent(id, t.required("period", period)?))
+        });
+        registry.register("poisson_producer", |id, t| {
+            let mean = poisson(t, "mean")?;
+            let target = t.target("target")?;
+            Ok(poisson_distributed_producing_agent(
+                id,
+                mean,
+                &t.required("target", target)?,
+            ))
+        });
+        registry.register("poisson_consumer", |id, t| {
+            let mean = poisson(t, "mean")?;
+            Ok(Box::new(poisson_distributed_consuming_agent(
+                id.to_string(),
+                mean,
+            )))
+        });
+        registry.register("poisson_arrivals", |id, t| {
+            let rate = t.positive("rate")?;
+            let target = t.target("target")?;
+            Ok(poisson_arrivals(
+                id,
+                t.required("rate", rate)?,
+                &t.required("target", target)?,
+            ))
+        });
+        registry.register("exponential_station", |id, t| {
+            let servers = t.count("servers")?.unwrap_or(1).max(1) as usize;
+            let rate = t.positive("rate")?;
+            let routes = t.targets_with_numbers("route")?;
+            Ok(exponential_station(
+                id,
+                servers,
+                t.required("rate", rate)?,
+                routes,
+            ))
+        });
+        registry.register("server", |id, t| {
+            let service_period = t.count("service_period")?;
+            Ok(serving_agent(
+                id,
+                t.required("service_period", service_period)?,
+            ))
+        });
+        registry
+    }
+}
+
+/// The Poisson distribution of the mean at the key.
+fn poisson(table: &mut ConfigTable, key: &str) -> Result<Poisson<f64>, ConfigError> {
+    let error = table.error(key, "must be positive");
+    let mean = table.positive(key)?;
+    Poisson::new(table.required(key, mean)?).map_err(|_| error)
+}
+
+impl AgentRegistry {
+    /// A registry without any kinds.
+    pub fn empty() -> AgentRegistry {
+        AgentRegistry {
+            constructors: BTreeMap::new(),
+        }
+    }
+
+    /// Registers the constructor of a kind, replacing any before.
+    pub fn register<T>(&mut self, kind: T, constructor: AgentConstructor)
+    where
+        T: Into<String>,
+    {
+        self.constructors.insert(kind.into(), constructor);
+    }
+
+    /// The kinds registered, in sorted order.
+    pub fn kinds(&self) -> impl Iterator<Item = &str> {
+        self.constructors.keys().map(String::as_str)
+    }
+
+    /// Creates the agent of a table, by its kind.
+    pub fn build(&self, id: &str, table: &mut ConfigTable) -> Result<Box<dyn Agent>, ConfigError> {
+        let kind = table.string("kind")?;
+        let kind = table.required("kind", kind)?;
+        let constructor = self
+            .constructors
+            .get(&kind)
+            .ok_or_else(|| table.table_error(format!("unknown kind {:?}", kind)))?;
+        let agent = constructor(id, table)?;
+        table.finish()?;
+        Ok(agent)
+    }
+}
+
+/// The SimulationParameters of a configuration; see the module docs for its
+/// keys.
+#[derive(Clone, Debug)]
+pub struct SimulationConfig {
+    pub ticks: Option<DiscreteTime>,
+    pub max_ticks: Option<DiscreteTime>,
+    pub max_wall_clock: Option<Duration>,
+    pub halt_when_quiescent: bool,
+    pub seed: Option<u64>,
+    pub warm_up: Option<DiscreteTime>,
+    pub queue_depth_metrics: bool,
+    pub queue_depth_stats: bool,
+    pub asleep_cycles_metrics: bool,
+    pub activity_metrics: bool,
+    pub trace: bool,
+    pub throughput_window: Option<DiscreteTime>,
+    pub parallel_agents: bool,
+    /// The agents, in the order they were defined.
+    pub agents: Vec<Box<dyn Agent>>,
+}
+
+impl SimulationConfig {
+    /// Parses a configuration of the built-in kinds of agents.
+    pub fn parse(text: &str, format: ConfigFormat) -> Result<SimulationConfig, ConfigError> {
+        let document = ConfigDocument::parse(text, format)?;
+        SimulationConfig::from_document(document, &AgentRegistry::default())
+    }
+
+    /// Reads and parses a file of the built-in kinds of agents, in the format
+    /// of its extension.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<SimulationConfig, ConfigError> {
+        let document = ConfigDocument::load(path)?;
+        SimulationConfig::from_document(document, &AgentRegistry::default())
+    }
+
+    /// Reads a parsed configuration, creating its agents with the registry.
+    /// Errors on any top-level key left in the document that it doesn't know.
+    pub fn from_document(
+        mut document: ConfigDocument,
+        registry: &AgentRegistry,
+    ) -> Result<SimulationConfig, ConfigError> {
+        let top = &mut document.top;
+        let max_wall_clock = top.positive("max_wall_clock_seconds")?;
+        let config = SimulationConfig {
+            ticks: top.count("ticks")?,
+            max_ticks: top.count("max_ticks")?,
+            max_wall_clock: max_wall_clock.map(Duration::from_secs_f64),
+            halt_when_quiescent: top.bool("halt_when_quiescent")?.unwrap_or(false),
+            seed: top.count("seed")?,
+            warm_up: top.count("warm_up")?,
+            queue_depth_metrics: top.bool("queue_depth_metrics")?.unwrap_or(false),
+            queue_depth_stats: top.bool("queue_depth_stats")?.unwrap_or(false),
+            asleep_cycles_metrics: top.bool("asleep_cycles_metrics")?.unwrap_or(false),
+            activity_metrics: top.bool("activity_metrics")?.unwrap_or(false),
+            trace: top.bool("trace")?.unwrap_or(false),
+            throughput_window: top.count("throughput_window")?,
+            parallel_agents: top.bool("parallel_agents")?.unwrap_or(false),
+            agents: vec![],
+        };
+        top.finish()?;
+        if config.ticks.is_none() && config.max_wall_clock.is_none() && !config.halt_when_quiescent
+        {
+            return Err(ConfigError::new(
+                0,
+                "set ticks, max_wall_clock_seconds or halt_when_quiescent, or it never halts",
+            ));
+        }
+
+        let ids: Vec<String> = document.agents.iter().map(|(id, _)| id.clone()).collect();
+        let mut agents = vec![];
+        for (id, mut table) in document.agents {
+            agents.push(registry.build(&id, &mut table)?);
+            if let Some(target) = table.targets.iter().find(|t| !ids.contains(t)) {
+                return Err(ConfigError::new(
+                    0,
+                    format!("agent {:?} sends to {:?}, which isn't defined", id, target),
+                ));
+            }
+        }
+
+        Ok(SimulationConfig { agents, ..config })
+    }
+
+    /// The SimulationParameters of the configuration.
+    pub fn parameters(&self) -> SimulationParameters {
+        SimulationParameters {
+            agents: self.agents.clone(),
+            halt_check: |_: &Simulation| false,
+            ending_time: self.ticks,
+            max_ticks: self.max_ticks,
+            max_wall_clock: self.max_wall_clock,
+            quiescence_policy: if self.halt_when_quiescent {
+                QuiescencePolicy::Halt
+            } else {
+                QuiescencePolicy::Ignore
+            },
+            seed: self.seed,
+            warm_up: self.warm_up,
+            enable_queue_depth_metrics: self.queue_depth_metrics,
+            enable_queue_depth_stats: self.queue_depth_stats,
+            enable_agent_asleep_cycles_metric: self.asleep_cycles_metrics,
+            enable_activity_metrics: self.activity_metrics,
+            enable_trace: self.trace,
+            throughput_window: self.throughput_window,
+            enable_parallel_agents: self.parallel_agents,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn config_test() {
+        let toml = r#"
+ticks = 100
+seed = 7
+queue_depth_metrics = true
+
+[agent.producer]
+kind = "periodic_producer"
+period = 2
+target = "consumer"
+
+[agent.consumer]
+kind = "periodic_consumer"
+period = 1
+"#;
+        let yaml = "
+ticks: 100
+seed: 7
+queue_depth_metrics: true
+agents:
+  producer:
+    kind: periodic_producer
+    period: 2
+    target: \"consumer\"  # Quoted or not.
+  consumer:
+    kind: periodic_consumer
+    period: 1
+";
+        let toml = ConfigDocument::parse(toml, ConfigFormat::Toml).unwrap();
+        let yaml = ConfigDocument::parse(yaml, ConfigFormat::Yaml).unwrap();
+        assert_eq!(toml.agents.len(), 2);
+        assert_eq!(
+            toml.agents.iter().map(|(id, _)| id).collect::<Vec<_>>(),
+            yaml.agents.iter().map(|(id, _)| id).collect::<Vec<_>>()
+        );
+
+        for document in [toml, yaml] {
+            let config = SimulationConfig::from_document(document, &Default::default()).unwrap();
+            let mut simulation = Simulation::new(config.parameters());
+            simulation.run();
+            assert_eq!(simulation.time, 100);
+            assert_halted_by!(simulation, HaltCheck);
+            assert_eq!(simulation.seed, 7);
+            assert_eq!(simulation.consumed_count("consumer"), Some(50));
+            assert_eq!(
+                simulation.queue_depth_timeline("consumer").unwrap().len(),
+                100
+            );
+        }
+
+        let config = SimulationConfig::parse("ticks = 100\nmax_ticks = 10", ConfigFormat::Toml);
+        let mut simulation = Simulation::new(config.unwrap().parameters());
+        simulation.run();
+        assert_eq!(simulation.time, 10);
+        assert_halted_by!(simulation, LimitReached(Limit::MaxTicks));
+    }
+
+    #[test]
+    fn config_error_test() {
+        let error = |text: &str| {
+            SimulationConfig::parse(text, ConfigFormat::Toml)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            error("seed = 1"),
+            "set ticks, max_wall_clock_seconds or halt_when_quiescent, or it never halts"
+        );
+        let count_error = "ticks must be a whole number from 0 to 18446744073709551615";
+        assert_eq!(error("ticks = 1.5"), count_error);
+        assert_eq!(error("ticks = -1"), count_error);
+        let config =
+            SimulationConfig::parse("ticks = 1\nseed = 9007199254740993", ConfigFormat::Toml);
+        assert_eq!(config.unwrap().seed, Some(9007199254740993));
+        assert_eq!(error("ticks = 1\ncolor = 2"), "unknown key \"color\"");
+        assert!(error("ticks = 1\nseed = x").starts_with("line 2: "));
+        assert_eq!(
+            error("ticks = 1\n[agent.a]\nkind = \"poisson_arrivals\"\nrate = 0.5"),
+            "agent \"a\": target is missing"
+        );
+        assert_eq!(
+            error("ticks = 1\n[agent.a]\nkind = \"teleporter\""),
+            "agent \"a\": unknown kind \"teleporter\""
+        );
+        assert_eq!(
+            error("ticks = 1\n[agent.a]\nkind = \"periodic_producer\"\nperiod = 1\ntarget = \"b\""),
+            "agent \"a\" sends to \"b\", which isn't defined"
+        );
+        assert_eq!(
+            error(
+                "ticks = 1\n[agent.a]\nkind = \"server\"\nservice_period = 1\nstart = 1979-05-27"
+            ),
+            "agent \"a\": start must be a string, number, boolean or array"
+        );
+        let yaml_error = SimulationConfig::parse("ticks: 1\nagents: [a]", ConfigFormat::Yaml);
+        assert_eq!(
+            yaml_error.unwrap_err().to_string(),
+            "agents must be a mapping of agents"
+        );
+    }
+
+    #[test]
+    fn config_syntax_test() {
+        // Escapes, literal strings, inline tables and arrays are all read.
+        let toml = r#"
+name = "a \"quoted\" name"
+ticks = 10
+
+[agent.arrivals]
+kind = 'poisson_arrivals'
+rate = 0.5
+target = "server"
+
+[agent.server]
+kind = "exponential_station"
+rate = 0.8
+route = { "sink" = 1.0 }
+tags = ["fast", "front"]
+
+[agent.sink]
+kind = "periodic_consumer"
+period = 1
+"#;
+        let yaml = r#"
+name: "a \"quoted\" name"
+ticks: 10
+agents:
+  arrivals: {kind: poisson_arrivals, rate: 0.5, target: 'server'}
+  server:
+    kind: exponential_station
+    rate: 0.8
+    route.sink: 1.0
+    tags: [fast, front]
+  sink:
+    kind: periodic_consumer
+    period: 1
+"#;
+        for (text, format) in [(toml, ConfigFormat::Toml), (yaml, ConfigFormat::Yaml)] {
+            let mut document = ConfigDocument::parse(text, format).unwrap();
+            assert_eq!(
+                document.top.string("name").unwrap().as_deref(),
+                Some("a \"quoted\" name")
+            );
+            let (id, server) = &mut document.agents[1];
+            assert_eq!(id, "server");
+            assert_eq!(
+                server.value("tags"),
+                Some(ConfigValue::Array(vec![
+                    ConfigValue::String("fast".to_string()),
+                    ConfigValue::String("front".to_string())
+                ]))
+            );
+            assert_eq!(
+                server.targets_with_numbers("route").unwrap(),
+                [("sink".to_string(), 1.0)]
+            );
+            assert_eq!(
+                document
+                    .agents
+                    .iter()
+                    .map(|(id, _)| id.as_str())
+                    .collect::<Vec<_>>(),
+                ["arrivals", "server", "sink"]
+            );
+        }
+    }
+
+    #[test]
+    fn agent_registry_test() {
+        let mut registry = AgentRegistry::default();
+        registry.register("sink", |id, _| Ok(periodic_consuming_agent(id, 1)));
+        assert!(registry.kinds().any(|k| k == "sink"));
+
+        let yaml = "
+halt_when_quiescent: true
+agents:
+  producer:
+    kind: poisson_producer
+    mean: 2.0
+    target: sink
+  sink:
+    kind: sink
+";
+        let document = ConfigDocument::parse(yaml, ConfigFormat::Yaml).unwrap();
+        let config = SimulationConfig::from_document(document, &registry).unwrap();
+        assert_eq!(config.agents.len(), 2);
+        assert_eq!(
+            config.parameters().quiescence_policy,
+            QuiescencePolicy::Halt
+        );
+    }
+}
@@ -0,0 +1,370 @@
+//! The contract-net protocol: a manager announces a task to contractors with a
+//! call for proposals, collects their bids for a bidding window, awards the
+//! task to the lowest bidder, and waits for the winner to report completion.
+//! Every message of one contract carries the same correlation id.
+
+use crate::{
+    next_correlation_id, Agent, AgentMode, AgentState, DiscreteTime, Message, Simulation,
+    SimulationState,
+};
+use simul_macro::agent;
+use std::collections::{BTreeMap, HashSet};
+
+/// The protocol messages of the contract-net, carried in `custom_payload`.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ContractMessage {
+    /// Manager -> contractors: announces a task (its payload) and asks for bids.
+    CallForProposals(Vec<u8>),
+    /// Contractor -> manager: offers to perform the task at a cost.
+    Bid(i64),
+    /// Manager -> winning contractor: awards the task (its payload).
+    Award(Vec<u8>),
+    /// Manager -> losing contractors: the task went to someone else.
+    Reject,
+    /// Contractor -> manager: the awarded task is done.
+    Completed,
+}
+
+impl ContractMessage {
+    /// Encodes the protocol message as a `custom_payload`.
+    pub fn to_payload(&self) -> Vec<u8> {
+        match self {
+            ContractMessage::CallForProposals(task) => [&[0], task.as_slice()].concat(),
+            ContractMessage::Bid(cost) => [&[1], cost.to_le_bytes().as_slice()].concat(),
+            ContractMessage::Award(task) => [&[2], task.as_slice()].concat(),
+            ContractMessage::Reject => vec![3],
+            ContractMessage::Completed => vec![4],
+        }
+    }
+
+    /// Decodes the protocol message of a Message, if it is one.
+    pub fn from_message(msg: &Message) -> Option<ContractMessage> {
+        msg.correlation_id?;
+        let (tag, rest) = msg.custom_payload.as_ref()?.split_first()?;
+
+        match tag {
+            0 => Some(ContractMessage::CallForProposals(rest.to_vec())),
+            1 => Some(ContractMessage::Bid(i64::from_le_bytes(
+                rest.try_into().ok()?,
+            ))),
+            2 => Some(ContractMessage::Award(rest.to_vec())),
+            3 => Some(ContractMessage::Reject),
+            4 => Some(ContractMessage::Completed),
+            _ => None,
+        }
+    }
+
+    fn into_message(self, time: DiscreteTime, src: &str, dst: &str, id: u64) -> Message {
+        Message {
+            custom_payload: Some(self.to_payload()),
+            correlation_id: Some(id),
+            ..Message::new(time, src, dst)
+        }
+    }
+}
+
+/// A contract-net manager. Every message it receives that is not a bid or a
+/// completion report from a contractor is a task, which it announces to all
+/// contractors under a fresh correlation id. After
+/// `bidding_window` ticks the task is awarded to the lowest bid; tasks that
+/// received no bids are abandoned. Completed tasks are recorded as consumed,
+/// with their `completed_time` set to when the contractor reported completion.
+pub fn contract_net_manager<T>(
+    id: T,
+    contractors: Vec<String>,
+    bidding_window: DiscreteTime,
+) -> Box<dyn Agent>
+where
+    T: Into<String>,
+{
+    #[derive(Clone, Debug)]
+    struct OpenContract {
+        task: Message,
+        deadline: DiscreteTime,
+        bids: Vec<(String, i64)>,
+    }
+
+    #[agent]
+    struct ContractNetManager {
+        contractors: Vec<String>,
+        bidding_window: DiscreteTime,
+        open: BTreeMap<u64, OpenContract>,
+        awarded: BTreeMap<u64, Message>,
+    }
+
+    impl ContractNetManager {
+        fn handle(&mut self, time: DiscreteTime, msg: Message) -> Vec<Message> {
+            // Bids and completions come from contractors. Anything else is a
+            // new task, even one that already carries a correlation id.
+            if self.contractors.contains(&msg.source) {
+                if let Some(reply) = ContractMessage::from_message(&msg) {
+                    self.handle_reply(time, msg, reply);
+                    return vec![];
+                }
+            }
+
+            // A new task: announce it to every contractor.
+            let id = next_correlation_id();
+            let task = msg.custom_payload.clone().unwrap_or_default();
+            self.open.insert(
+                id,
+                OpenContract {
+                    task: msg,
+                    deadline: time + self.bidding_window,
+                    bids: vec![],
+                },
+            );
+
+            self.contractors
+                .iter()
+                .map(|c| {
+                    ContractMessage::CallForProposals(task.clone()).into_message(
+                        time,
+                        &self.state.id,
+                        c,
+                        id,
+                    )
+                })
+                .collect()
+        }
+
+        fn handle_reply(&mut self, time: DiscreteTime, msg: Message, reply: ContractMessage) {
+            let Some(correlation_id) = msg.correlation_id else {
+                return;
+            };
+
+            match reply {
+                ContractMessage::Bid(cost) => {
+                    if let Some(contract) = self.open.get_mut(&correlation_id) {
+                        contract.bids.push((msg.source, cost));
+                    }
+                }
+                ContractMessage::Completed => {
+                    if let Some(task) = self.awarded.remove(&correlation_id) {
+                        self.state.consumed.push(Message {
+                            completed_time: Some(time),
+                            correlation_id: Some(correlation_id),
+                            ..task
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        fn close_expired_contracts(&mut self, time: DiscreteTime) -> Vec<Message> {
+            let expired: Vec<u64> = self
+                .open
+                .iter()
+                .filter(|(_, c)| c.deadline <= time)
+                .map(|(id, _)| *id)
+                .collect();
+
+            let mut messages = vec![];
+            for id in expired {
+                let contract = self.open.remove(&id).expect("Expired contract is open");
+                let Some((winner, _)) = contract.bids.iter().min_by_key(|(_, cost)| *cost) else {
+                    continue;
+                };
+
+                let task = contract.task.custom_payload.clone().unwrap_or_default();
+                messages.push(ContractMessage::Award(task).into_message(
+                    time,
+                    &self.state.id,
+                    winner,
+                    id,
+                ));
+
+                for (loser, _) in contract.bids.iter().filter(|(c, _)| c != winner) {
+                    messages.push(ContractMessage::Reject.into_message(
+                        time,
+                        &self.state.id,
+                        loser,
+                        id,
+                    ));
+                }
+
+                self.awarded.insert(id, contract.task);
+            }
+
+            messages
+        }
+    }
+
+    impl Agent for ContractNetManager {
+        fn process(
+            &mut self,
+            simulation_state: SimulationState,
+            msg: &Message,
+        ) -> Option<Vec<Message>> {
+            let time = simulation_state.time;
+            let mut messages = vec![];
+
+            // Bids arrive in bursts, so handle everything that is queued.
+            let incoming = std::iter::once(msg.clone()).chain(self.state.queue.drain(..));
+            for msg in incoming.collect::<Vec<_>>() {
+                if msg.source != "SIM_SRC" {
+                    messages.extend(self.handle(time, msg));
+                }
+            }
+
+            messages.extend(self.close_expired_contracts(time));
+            Some(messages)
+        }
+    }
+
+    Box::new(ContractNetManager {
+        contractors,
+        bidding_window,
+        open: BTreeMap::new(),
+        awarded: BTreeMap::new(),
+        state: AgentState {
+            mode: AgentMode::Proactive,
+            wake_mode: AgentMode::Proactive,
+            id: id.into(),
+            ..Default::default()
+        },
+    })
+}
+
+/// A contract-net contractor. It bids `bid(task)` on every call for
+/// proposals, or declines when that returns None. An awarded task takes
+/// `service_period` ticks, after which it reports completion to the manager.
+pub fn contract_net_contractor<T>(
+    id: T,
+    bid: fn(&[u8]) -> Option<i64>,
+    service_period: DiscreteTime,
+) -> Box<dyn Agent>
+where
+    T: Into<String>,
+{
+    #[agent]
+    struct ContractNetContractor {
+        bid: fn(&[u8]) -> Option<i64>,
+        service_period: DiscreteTime,
+        /// The (manager, correlation id, completion time) of awarded work.
+        working_on: Vec<(String, u64, DiscreteTime)>,
+    }
+
+    impl Agent for ContractNetContractor {
+        fn process(
+            &mut self,
+            simulation_state: SimulationState,
+            msg: &Message,
+        ) -> Option<Vec<Message>> {
+            let time = simulation_state.time;
+            let mut messages = vec![];
+
+            match ContractMessage::from_message(msg) {
+                Some(ContractMessage::CallForProposals(task)) => {
+                    if let Some(cost) = (self.bid)(&task) {
+                        messages.push(ContractMessage::Bid(cost).into_message(
+                            time,
+                            &self.state.id,
+                            &msg.source,
+                            msg.correlation_id?,
+                        ));
+                    }
+                }
+                Some(ContractMessage::Award(_)) => {
+                    // Awarded work is done back to back, in the order awarded.
+                    let start = self.working_on.last().map_or(time, |(_, _, done)| *done);
+                    self.working_on.push((
+                        msg.source.clone(),
+                        msg.correlation_id?,
+                        start.max(time) + self.service_period,
+                    ));
+                    self.state.consumed.push(Message {
+                        completed_time: Some(time),
+                        ..msg.clone()
+                    });
+                }
+                _ => {}
+            }
+
+            while let Some((manager, id, _)) = self
+                .working_on
+                .first()
+                .filter(|(_, _, done)| *done <= time)
+                .cloned()
+            {
+                self.working_on.remove(0);
+                messages.push(ContractMessage::Completed.into_message(
+                    time,
+                    &self.state.id,
+                    &manager,
+                    id,
+                ));
+            }
+
+            Some(messages)
+        }
+    }
+
+    Box::new(ContractNetContractor {
+        bid,
+        service_period,
+        working_on: vec![],
+        state: AgentState {
+            mode: AgentMode::Proactive,
+            wake_mode: AgentMode::Proactive,
+            id: id.into(),
+            ..Default::default()
+        },
+    })
+}
+
+/// The outcomes of the contracts a contract-net manager ran.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ContractNetOutcomes {
+    /// The number of tasks announced with a call for proposals.
+    pub announced: usize,
+    /// The number of tasks awarded to a contractor.
+    pub awarded: usize,
+    /// The number of awarded tasks reported completed.
+    pub completed: usize,
+    /// The mean time from a task arriving at the manager to its completion.
+    pub mean_completion_time: Option<f64>,
+}
+
+/// Computes the contract outcomes of a contract-net manager from its message history.
+pub fn contract_net_outcomes(
+    simulation: &Simulation,
+    manager_id: &str,
+) -> Option<ContractNetOutcomes> {
+    let manager = simulation
+        .agents
+        .iter()
+        .find(|a| a.state().id == manager_id)?;
+
+    let mut announced = HashSet::new();
+    let mut awarded = 0;
+    for msg in manager.state().produced.iter() {
+        match ContractMessage::from_message(msg) {
+            Some(ContractMessage::CallForProposals(_)) => {
+                announced.insert(msg.correlation_id);
+            }
+            Some(ContractMessage::Award(_)) => awarded += 1,
+            _ => {}
+        }
+    }
+
+    let completed: Vec<&Message> = manager
+        .state()
+        .consumed
+        .iter()
+        .filter(|m| m.correlation_id.is_some())
+        .collect();
+    let total_completion_time: u64 = completed
+        .iter()
+        .filter_map(|m| Some(m.completed_time? - m.queued_time))
+        .sum();
+
+    Some(ContractNetOutcomes {
+        announced: announced.len(),
+        awarded,
+        completed: completed.len(),
+        mean_completion_time: (!completed.is_empty())
+            .then(|| total_completion_time as f64 / completed.len() as f64),
+    })
+}
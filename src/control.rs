@@ -0,0 +1,153 @@
+//! Engine controls an Agent can exercise besides halting: pausing the run,
+//! checkpointing it, and raising alarms.
+//!
+//! A pause or a checkpoint takes effect at the end of the tick it was asked
+//! for, so every Agent of the tick processes first. A paused Simulation
+//! resumes where it left off on the next `run`, and a checkpoint is a copy of
+//! the Simulation as of then, which runs on from there.
+
+use crate::{DiscreteTime, HaltReason, Interrupt, Message, Simulation, SimulationMode};
+use log::{info, warn};
+
+/// An alarm an Agent raised, e.g. on an invariant it saw violated.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Alarm {
+    pub time: DiscreteTime,
+    pub agent: String,
+    pub reason: String,
+}
+
+impl Simulation {
+    /// Returns the alarms raised so far, in order.
+    pub fn alarms(&self) -> &[Alarm] {
+        &self.alarms
+    }
+
+    /// Returns the checkpoints taken so far, in order. Each is the
+    /// Simulation as of the end of the tick it was asked for, and resumes
+    /// from there on `run`.
+    pub fn checkpoints(&self) -> &[Simulation] {
+        &self.checkpoints
+    }
+
+    /// Handles a pause, checkpoint or alarm interrupt. Returns whether the
+    /// message was one.
+    pub(crate) fn handle_control_interrupt(&mut self, message: &Message) -> bool {
+        match &message.interrupt {
+            Some(Interrupt::PauseSimulation(reason)) => {
+                info!("Received a pause interrupt: {:?}", reason);
+                self.pause_requested = Some(reason.clone());
+            }
+            Some(Interrupt::CheckpointNow) => self.checkpoint_requested = true,
+            Some(Interrupt::RaiseAlarm(reason)) => {
+                warn!("{} raised an alarm: {}", message.source, reason);
+                self.alarms.push(Alarm {
+                    time: self.time,
+                    agent: message.source.clone(),
+                    reason: reason.clone(),
+                });
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Takes the checkpoint and pause asked for this tick, if any.
+    pub(crate) fn apply_controls(&mut self) {
+        if std::mem::take(&mut self.checkpoint_requested) {
+            // The checkpoint doesn't carry the checkpoints before it.
+            let checkpoints = std::mem::take(&mut self.checkpoints);
+            let checkpoint = self.clone();
+            self.checkpoints = checkpoints;
+            self.checkpoints.push(checkpoint);
+        }
+        if let Some(reason) = self.pause_requested.take() {
+            if self.mode == SimulationMode::Running {
+                self.mode = SimulationMode::Paused;
+                self.halt_reason = Some(HaltReason::Paused(reason));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use simul_macro::agent;
+
+    /// Raises an alarm at tick 1, asks for a checkpoint at 2 and for a pause
+    /// at 3.
+    fn operator() -> Box<dyn Agent> {
+        #[agent]
+        struct Operator {}
+
+        impl Agent for Operator {
+            fn process(&mut self, state: SimulationState, _: &Message) -> Option<Vec<Message>> {
+                let interrupt = match state.time {
+                    1 => Interrupt::RaiseAlarm("too hot".to_string()),
+                    2 => Interrupt::CheckpointNow,
+                    3 => Interrupt::PauseSimulation("inspect".to_string()),
+                    _ => return None,
+                };
+                Some(vec![Message {
+                    interrupt: Some(interrupt),
+                    ..Message::new(state.time, "operator", "operator")
+                }])
+            }
+        }
+
+        Box::new(Operator {
+            state: AgentState {
+                mode: AgentMode::Proactive,
+                wake_mode: AgentMode::Proactive,
+                id: "operator".to_string(),
+                ..Default::default()
+            },
+        })
+    }
+
+    #[test]
+    fn control_interrupts_test() {
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                operator(),
+                periodic_producing_agent("producer", 1, "consumer"),
+                periodic_consuming_agent("consumer", 1),
+            ],
+            halt_check: |s: &Simulation| s.time == 6,
+            ..Default::default()
+        });
+        simulation.run();
+
+        assert_eq!(simulation.mode, SimulationMode::Paused);
+        assert_eq!(
+            simulation.halt_reason,
+            Some(HaltReason::Paused("inspect".to_string()))
+        );
+        assert_eq!(simulation.time, 4);
+        assert_eq!(simulation.produced_count("producer"), Some(4));
+        assert_eq!(
+            simulation.alarms(),
+            [Alarm {
+                time: 1,
+                agent: "operator".to_string(),
+                reason: "too hot".to_string(),
+            }]
+        );
+
+        // It resumes where it paused.
+        simulation.run();
+        assert_eq!(simulation.mode, SimulationMode::Completed);
+        assert_eq!(simulation.halt_reason, Some(HaltReason::HaltCheck));
+        assert_eq!(simulation.produced_count("producer"), Some(6));
+        assert!(simulation.message_ledger().is_balanced());
+
+        // The checkpoint runs on from the end of tick 2, and pauses again.
+        let mut checkpoint = simulation.checkpoints()[0].clone();
+        assert_eq!(checkpoint.time, 3);
+        assert_eq!(checkpoint.produced_count("producer"), Some(3));
+        checkpoint.run();
+        assert_eq!(checkpoint.mode, SimulationMode::Paused);
+        assert_eq!(checkpoint.produced_count("producer"), Some(4));
+    }
+}
@@ -0,0 +1,160 @@
+//! Interactive control of a run from another thread, e.g. a UI or a test
+//! harness: play, pause, single-step and set the speed, by commands sent
+//! over a channel to `Simulation::run_controlled`.
+
+use crate::{DiscreteTime, HaltReason, Simulation, SimulationMode};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant};
+
+/// A command to a controlled run.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ControlCommand {
+    /// Run tick after tick, at the set speed.
+    Play,
+    /// Stop running until the next command.
+    Pause,
+    /// Run this many ticks, then pause.
+    Step(DiscreteTime),
+    /// Run at most this many ticks per second of real time. None runs as
+    /// fast as possible.
+    SetSpeed(Option<f64>),
+    /// Stop controlling the run, leaving it paused where it is.
+    Stop,
+}
+
+/// Sends commands to a run, from any thread. Cloneable, so several parts of
+/// a UI can drive the same run.
+#[derive(Clone, Debug)]
+pub struct Controller {
+    sender: Sender<ControlCommand>,
+}
+
+impl Controller {
+    /// Creates a controller, and the receiving end to pass to
+    /// `Simulation::run_controlled`.
+    pub fn new() -> (Controller, Receiver<ControlCommand>) {
+        let (sender, receiver) = mpsc::channel();
+        (Controller { sender }, receiver)
+    }
+
+    /// Sends a command. Returns false if the run stopped listening.
+    pub fn send(&self, command: ControlCommand) -> bool {
+        self.sender.send(command).is_ok()
+    }
+
+    pub fn play(&self) -> bool {
+        self.send(ControlCommand::Play)
+    }
+
+    pub fn pause(&self) -> bool {
+        self.send(ControlCommand::Pause)
+    }
+
+    pub fn step(&self, ticks: DiscreteTime) -> bool {
+        self.send(ControlCommand::Step(ticks))
+    }
+
+    pub fn set_speed(&self, ticks_per_second: Option<f64>) -> bool {
+        self.send(ControlCommand::SetSpeed(ticks_per_second))
+    }
+
+    pub fn stop(&self) -> bool {
+        self.send(ControlCommand::Stop)
+    }
+}
+
+impl Simulation {
+    /// Runs the simulation as the commands say, starting paused, until it
+    /// halts or is stopped. Once every Controller is dropped, a playing run
+    /// plays on until it halts, and a paused one returns.
+    pub fn run_controlled(&mut self, commands: Receiver<ControlCommand>) {
+        let mut playing = false;
+        let mut ticks_per_second = None;
+
+        while !matches!(
+            self.mode,
+            SimulationMode::Completed | SimulationMode::Failed
+        ) {
+            let command = if playing {
+                commands.try_recv().ok()
+            } else {
+                match commands.recv() {
+                    Ok(command) => Some(command),
+                    Err(_) => return,
+                }
+            };
+
+            match command {
+                Some(ControlCommand::Play) => playing = true,
+                Some(ControlCommand::Pause) => playing = false,
+                Some(ControlCommand::Step(ticks)) => {
+                    playing = false;
+                    self.run_for(ticks);
+                }
+                Some(ControlCommand::SetSpeed(speed)) => ticks_per_second = speed,
+                Some(ControlCommand::Stop) => return,
+                None => {}
+            }
+
+            if playing {
+                let started = Instant::now();
+                self.step();
+                if let Some(ticks_per_second) = ticks_per_second {
+                    let tick = Duration::from_secs_f64(1.0 / ticks_per_second);
+                    std::thread::sleep(tick.saturating_sub(started.elapsed()));
+                }
+            }
+            // An Agent's pause interrupt pauses a playing run too.
+            if matches!(self.halt_reason, Some(HaltReason::Paused(_))) {
+                playing = false;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use std::thread;
+
+    fn simulation() -> Simulation {
+        Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer", 1, "consumer"),
+                periodic_consuming_agent("consumer", 1),
+            ],
+            halt_check: |s: &Simulation| s.time == 20,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn controller_test() {
+        let (controller, commands) = Controller::new();
+        controller.step(3);
+        controller.step(2);
+        controller.stop();
+        let mut simulation = simulation();
+        simulation.run_controlled(commands);
+        assert_eq!(simulation.time, 5);
+        assert_eq!(simulation.mode, SimulationMode::Paused);
+        assert!(!controller.play());
+
+        // Played from another thread at 1000 ticks per second, it takes at
+        // least the 15 ticks left in real time.
+        let (controller, commands) = Controller::new();
+        let started = Instant::now();
+        let ui = thread::spawn(move || {
+            controller.set_speed(Some(1000.0));
+            controller.play();
+        });
+        simulation.run_controlled(commands);
+        ui.join().unwrap();
+
+        assert!(started.elapsed() >= Duration::from_millis(15));
+        assert_eq!(simulation.mode, SimulationMode::Completed);
+        assert_eq!(simulation.time, 20);
+        assert_eq!(simulation.produced_count("producer"), Some(20));
+    }
+}
@@ -0,0 +1,27 @@
+//! Writing CSV by hand, for the exports that render it without a CSV crate.
+
+use std::borrow::Cow;
+
+/// Renders a string as a CSV field, quoted if it has a comma, quote or line
+/// break, with its quotes doubled, as RFC 4180 asks.
+pub(crate) fn field(s: &str) -> Cow<'_, str> {
+    if s.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", s.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field_test() {
+        assert_eq!(field("consumer"), "consumer");
+        assert_eq!(field("a,b"), "\"a,b\"");
+        assert_eq!(field("a \"b\""), "\"a \"\"b\"\"\"");
+        assert_eq!(field("a\nb"), "\"a\nb\"");
+        assert_eq!(field(""), "");
+    }
+}
@@ -0,0 +1,358 @@
+//! Workflows: DAGs of tasks with durations, each of which starts once all
+//! the tasks it depends on finished, like the targets of a build system or
+//! the jobs of a batch pipeline.
+//!
+//! Every task is an Agent, which hears from each of its dependencies when it
+//! finishes, works for its duration, and then tells its dependents. Tasks
+//! only start once the previous ones told them, so any delay of a message,
+//! e.g. by a slow channel, delays the tasks after it.
+
+use crate::{
+    Agent, AgentMode, AgentState, DiscreteTime, Interrupt, Message, Simulation, SimulationState,
+};
+use simul_macro::agent;
+
+/// A task of a Workflow.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Task {
+    /// The id of the task's Agent.
+    pub name: String,
+    pub duration: DiscreteTime,
+    pub dependencies: Vec<String>,
+}
+
+/// A DAG of tasks; see the module docs.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Workflow {
+    /// The id of the Agent that hears when every task finished.
+    pub name: String,
+    /// Whether to halt the Simulation once every task finished.
+    pub halt_when_done: bool,
+    tasks: Vec<Task>,
+}
+
+/// When a task of a Workflow ran.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct TaskTiming {
+    pub name: String,
+    pub start: DiscreteTime,
+    pub finish: DiscreteTime,
+}
+
+/// When the tasks of a Workflow ran, as of the end of a Simulation.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct WorkflowReport {
+    /// The tasks that finished, in the order they were declared.
+    pub tasks: Vec<TaskTiming>,
+    /// The tasks that didn't finish.
+    pub unfinished: Vec<String>,
+    /// The ticks from the first start until the last finish.
+    pub makespan: DiscreteTime,
+    /// The chain of tasks that determined the makespan, in order: the last
+    /// to finish, preceded by the dependency it waited for last, and so on.
+    pub critical_path: Vec<String>,
+}
+
+impl Workflow {
+    pub fn new<T>(name: T) -> Workflow
+    where
+        T: Into<String>,
+    {
+        Workflow {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Adds a task, which starts once all its dependencies finished.
+    ///
+    /// # Panics
+    ///
+    /// If a dependency isn't a task added before, which keeps the tasks
+    /// acyclic, or the name is taken.
+    pub fn task<T>(mut self, name: T, duration: DiscreteTime, dependencies: &[&str]) -> Self
+    where
+        T: Into<String>,
+    {
+        let name = name.into();
+        assert!(
+            self.get(&name).is_none(),
+            "the task {} already exists",
+            name
+        );
+        for dependency in dependencies {
+            assert!(
+                self.get(dependency).is_some(),
+                "{} depends on {}, which must be added before it",
+                name,
+                dependency
+            );
+        }
+
+        self.tasks.push(Task {
+            name,
+            duration,
+            dependencies: dependencies.iter().map(|d| d.to_string()).collect(),
+        });
+        self
+    }
+
+    pub fn with_halt_when_done(self) -> Self {
+        Workflow {
+            halt_when_done: true,
+            ..self
+        }
+    }
+
+    /// The tasks, in the order they were added.
+    pub fn tasks(&self) -> &[Task] {
+        &self.tasks
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Task> {
+        self.tasks.iter().find(|t| t.name == name)
+    }
+
+    /// The names of the tasks that depend on a task.
+    fn dependents(&self, name: &str) -> Vec<String> {
+        self.tasks
+            .iter()
+            .filter(|t| t.dependencies.iter().any(|d| d == name))
+            .map(|t| t.name.clone())
+            .collect()
+    }
+
+    /// Returns the Agents of the tasks, and the Agent named like the
+    /// Workflow, which hears from the tasks no other task depends on.
+    pub fn agents(&self) -> Vec<Box<dyn Agent>> {
+        let mut agents: Vec<Box<dyn Agent>> = vec![];
+        let mut last = vec![];
+        for task in &self.tasks {
+            let mut dependents = self.dependents(&task.name);
+            if dependents.is_empty() {
+                dependents.push(self.name.clone());
+                last.push(task.name.clone());
+            }
+            agents.push(task_agent(task, dependents));
+        }
+        agents.push(completion_agent(
+            &self.name,
+            last.len(),
+            self.halt_when_done,
+        ));
+        agents
+    }
+
+    /// Returns when the tasks ran in a Simulation of the Workflow.
+    pub fn report(&self, simulation: &Simulation) -> WorkflowReport {
+        let mut report = WorkflowReport::default();
+        for task in &self.tasks {
+            let ran = simulation
+                .consumed_for_agent(&task.name)
+                .and_then(|consumed| consumed.into_iter().last())
+                .and_then(|m| Some((m.queued_time, m.completed_time?)));
+            match ran {
+                Some((start, finish)) => report.tasks.push(TaskTiming {
+                    name: task.name.clone(),
+                    start,
+                    finish,
+                }),
+                None => report.unfinished.push(task.name.clone()),
+            }
+        }
+
+        let timing = |name: &str| report.tasks.iter().find(|t| t.name == name);
+        let first_start = report.tasks.iter().map(|t| t.start).min().unwrap_or(0);
+        // Ties go to the task declared first, for reproducible paths.
+        let mut next = report
+            .tasks
+            .iter()
+            .rev()
+            .max_by_key(|t| t.finish)
+            .filter(|_| report.unfinished.is_empty());
+        report.makespan = next.map_or(0, |t| t.finish - first_start);
+
+        let mut critical_path = vec![];
+        while let Some(current) = next {
+            critical_path.push(current.name.clone());
+            next = self
+                .get(&current.name)
+                .expect("Every timing is of a task")
+                .dependencies
+                .iter()
+                .filter_map(|d| timing(d))
+                .rev()
+                .max_by_key(|t| t.finish);
+        }
+        critical_path.reverse();
+        report.critical_path = critical_path;
+        report
+    }
+}
+
+/// Returns the Agent of a task, which tells `dependents` when it finished.
+fn task_agent(task: &Task, dependents: Vec<String>) -> Box<dyn Agent> {
+    #[agent]
+    struct TaskAgent {
+        duration: DiscreteTime,
+        dependents: Vec<String>,
+        /// The dependencies that haven't finished yet.
+        waiting_for: usize,
+        started: Option<DiscreteTime>,
+    }
+
+    impl TaskAgent {
+        fn finish(&mut self, time: DiscreteTime) -> Vec<Message> {
+            let start = self.started.expect("The task started");
+            self.state.consumed.push(Message {
+                queued_time: start,
+                completed_time: Some(time),
+                ..Message::new(start, self.state.id.as_str(), self.state.id.as_str())
+            });
+            self.state.mode = AgentMode::Dead;
+            self.dependents
+                .iter()
+                .map(|d| Message::new(time, self.state.id.as_str(), d.as_str()))
+                .collect()
+        }
+    }
+
+    impl Agent for TaskAgent {
+        fn process(
+            &mut self,
+            simulation_state: SimulationState,
+            msg: &Message,
+        ) -> Option<Vec<Message>> {
+            let time = simulation_state.time;
+            if self.started.is_some() {
+                return Some(self.finish(time));
+            }
+            if msg.source != "SIM_SRC" {
+                self.waiting_for = self.waiting_for.saturating_sub(1);
+            }
+            if self.waiting_for > 0 {
+                self.state.mode = AgentMode::Reactive;
+                return None;
+            }
+
+            self.started = Some(time);
+            if self.duration == 0 {
+                return Some(self.finish(time));
+            }
+            self.state.mode = AgentMode::AsleepUntil(time + self.duration);
+            self.state.wake_mode = AgentMode::Proactive;
+            None
+        }
+    }
+
+    Box::new(TaskAgent {
+        duration: task.duration,
+        dependents,
+        waiting_for: task.dependencies.len(),
+        started: None,
+        state: AgentState {
+            mode: AgentMode::Proactive,
+            wake_mode: AgentMode::Reactive,
+            id: task.name.clone(),
+            ..Default::default()
+        },
+    })
+}
+
+/// Returns the Agent that hears from the last tasks of a Workflow, and halts
+/// the Simulation once all of them finished, if `halt`.
+fn completion_agent(id: &str, last: usize, halt: bool) -> Box<dyn Agent> {
+    #[agent]
+    struct Completion {
+        waiting_for: usize,
+        halt: bool,
+    }
+
+    impl Agent for Completion {
+        fn process(
+            &mut self,
+            simulation_state: SimulationState,
+            msg: &Message,
+        ) -> Option<Vec<Message>> {
+            self.state.consumed.push(Message {
+                completed_time: Some(simulation_state.time),
+                ..msg.clone()
+            });
+            self.waiting_for = self.waiting_for.saturating_sub(1);
+            if self.waiting_for > 0 || !self.halt {
+                return None;
+            }
+
+            let id = self.state.id.as_str();
+            Some(vec![Message {
+                interrupt: Some(Interrupt::HaltSimulation(format!("{} is done", id))),
+                ..Message::new(simulation_state.time, id, id)
+            }])
+        }
+    }
+
+    Box::new(Completion {
+        waiting_for: last,
+        halt,
+        state: AgentState {
+            mode: AgentMode::Reactive,
+            wake_mode: AgentMode::Reactive,
+            id: id.to_string(),
+            ..Default::default()
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    fn build() -> Workflow {
+        Workflow::new("build")
+            .task("fetch", 2, &[])
+            .task("codegen", 1, &[])
+            .task("compile", 5, &["fetch", "codegen"])
+            .task("docs", 3, &["fetch"])
+            .task("test", 4, &["compile"])
+            .task("package", 1, &["test", "docs"])
+            .with_halt_when_done()
+    }
+
+    #[test]
+    fn workflow_test() {
+        let workflow = build();
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: workflow.agents(),
+            halt_check: |s: &Simulation| s.time == 1000,
+            ..Default::default()
+        });
+        simulation.run();
+
+        assert!(matches!(
+            simulation.halt_reason,
+            Some(HaltReason::Interrupt(_))
+        ));
+        let report = workflow.report(&simulation);
+        assert!(report.unfinished.is_empty());
+        assert_eq!(
+            report.critical_path,
+            ["fetch", "compile", "test", "package"]
+        );
+
+        // Every task starts the tick after its last dependency told it.
+        let timing = |name| report.tasks.iter().find(|t| t.name == name).unwrap();
+        assert_eq!((timing("fetch").start, timing("fetch").finish), (0, 2));
+        assert_eq!(timing("compile").start, 3);
+        assert_eq!(timing("package").finish, report.makespan);
+        assert!(timing("docs").finish < timing("test").start);
+    }
+
+    #[test]
+    #[should_panic]
+    fn workflow_cycle_test() {
+        let _ = Workflow::new("cycle")
+            .task("a", 1, &["b"])
+            .task("b", 1, &["a"]);
+    }
+}
@@ -0,0 +1,323 @@
+//! Checks of a Simulation before it runs, for mistakes that would otherwise
+//! only show as odd results mid-run: agents with the same id, messages to no
+//! one, agents nothing can ever reach, and options that contradict each
+//! other or are out of range. `Simulation::new` logs what
+//! `Simulation::validate` finds.
+
+use crate::{AgentMode, Simulation};
+use std::collections::HashSet;
+use std::fmt;
+
+/// How bad a Diagnostic is.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub enum Severity {
+    /// Likely a mistake, but the run makes sense.
+    Warning,
+    /// The run won't do what its parameters say.
+    Error,
+}
+
+/// A problem `Simulation::validate` found.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Diagnostic {
+    /// More than one Agent has the id, so the Simulation won't run.
+    DuplicateAgent(String),
+    /// A message queued on an Agent from the start is addressed to no Agent
+    /// or pool.
+    UnknownDestination { agent: String, destination: String },
+    /// A pool or shadow refers to an Agent that doesn't exist.
+    UnknownAgent { referrer: String, agent: String },
+    /// The Agent only processes messages, has none, and no other Agent is
+    /// linked to it, so it can never do anything.
+    UnreachableAgent(String),
+    /// Options that can't all hold, described.
+    ContradictoryOptions(String),
+    /// An option outside the values it can take, described.
+    InvalidOption(String),
+}
+
+impl Diagnostic {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Diagnostic::UnreachableAgent(_) => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Diagnostic::DuplicateAgent(id) => write!(f, "more than one agent has the id {:?}", id),
+            Diagnostic::UnknownDestination { agent, destination } => write!(
+                f,
+                "agent {:?} has a message queued to {:?}, which doesn't exist",
+                agent, destination
+            ),
+            Diagnostic::UnknownAgent { referrer, agent } => {
+                write!(
+                    f,
+                    "{} refers to agent {:?}, which doesn't exist",
+                    referrer, agent
+                )
+            }
+            Diagnostic::UnreachableAgent(id) => {
+                write!(f, "agent {:?} can never receive a message", id)
+            }
+            Diagnostic::ContradictoryOptions(description)
+            | Diagnostic::InvalidOption(description) => write!(f, "{}", description),
+        }
+    }
+}
+
+impl Simulation {
+    /// Checks the Simulation for mistakes; see `Diagnostic`. Returns them in
+    /// the order of the checks, or nothing if all is well.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+        let ids: Vec<&str> = self.agents.iter().map(|a| a.state().id.as_str()).collect();
+        let exists = |id: &str| self.agent_handles.contains_key(id);
+
+        let mut seen = HashSet::new();
+        let mut duplicates = HashSet::new();
+        for id in ids.iter() {
+            if !seen.insert(*id) && duplicates.insert(*id) {
+                diagnostics.push(Diagnostic::DuplicateAgent(id.to_string()));
+            }
+        }
+
+        for agent in self.agents.iter() {
+            let state = agent.state();
+            let mut destinations = HashSet::new();
+            for message in state.queue.iter() {
+                let destination = message.destination.as_str();
+                let known = exists(destination) || self.pools.iter().any(|p| p.name == destination);
+                if !known && destinations.insert(destination) {
+                    diagnostics.push(Diagnostic::UnknownDestination {
+                        agent: state.id.clone(),
+                        destination: destination.to_string(),
+                    });
+                }
+            }
+        }
+
+        for pool in self.pools.iter() {
+            let referrer = format!("pool {:?}", pool.name);
+            for member in pool.members.iter().filter(|m| !exists(m)) {
+                diagnostics.push(Diagnostic::UnknownAgent {
+                    referrer: referrer.clone(),
+                    agent: member.clone(),
+                });
+            }
+            if exists(&pool.name) {
+                diagnostics.push(Diagnostic::ContradictoryOptions(format!(
+                    "pool {:?} has the id of an agent, which gets its messages instead",
+                    pool.name
+                )));
+            }
+        }
+        for shadow in self.shadow_agents.iter() {
+            if !exists(&shadow.shadowed) {
+                diagnostics.push(Diagnostic::UnknownAgent {
+                    referrer: format!("shadow {:?}", shadow.agent.state().id),
+                    agent: shadow.shadowed.clone(),
+                });
+            }
+        }
+
+        let pooled: HashSet<&str> = self
+            .pools
+            .iter()
+            .flat_map(|p| p.members.iter().map(String::as_str))
+            .collect();
+        for agent in self.agents.iter() {
+            let state = agent.state();
+            let reactive = match state.mode {
+                AgentMode::Reactive => true,
+                AgentMode::AsleepUntil(_) => state.wake_mode == AgentMode::Reactive,
+                AgentMode::Proactive | AgentMode::Dead => false,
+            };
+            let id = state.id.as_str();
+            let linked = ids
+                .iter()
+                .any(|other| *other != id && self.topology.is_linked(other, id));
+            if reactive && state.queue.is_empty() && !pooled.contains(id) && !linked {
+                diagnostics.push(Diagnostic::UnreachableAgent(id.to_string()));
+            }
+        }
+
+        let limit = self.max_ticks.map(|ticks| self.starting_time + ticks);
+        let end = match (self.ending_time, limit) {
+            (Some(end), Some(limit)) => Some(end.min(limit)),
+            (end, limit) => end.or(limit),
+        };
+        if let (Some(warm_up), Some(end)) = (self.warm_up, end) {
+            if warm_up >= end {
+                diagnostics.push(Diagnostic::ContradictoryOptions(format!(
+                    "the warm-up until {} lasts the whole run, which ends by {}",
+                    warm_up, end
+                )));
+            }
+        }
+        if self.throughput_window == Some(0) {
+            diagnostics.push(Diagnostic::ContradictoryOptions(
+                "throughput is recorded per window of 0 ticks".to_string(),
+            ));
+        }
+        if self.time_scale.map_or(false, |s| s.tick.is_zero()) {
+            diagnostics.push(Diagnostic::ContradictoryOptions(
+                "the time scale's ticks last no time".to_string(),
+            ));
+        }
+        let mut channels: Vec<_> = self.channel_model.channels.iter().collect();
+        channels.sort_by(|a, b| a.0.cmp(b.0));
+        let channels = channels
+            .into_iter()
+            .map(|((s, d), f)| (format!("the channel from {:?} to {:?}", s, d), f))
+            .chain(
+                (self.channel_model.default_faults.iter())
+                    .map(|f| ("the default channel".to_string(), f)),
+            );
+        for (channel, faults) in channels {
+            if let Some((name, p)) = faults.invalid_probability() {
+                diagnostics.push(Diagnostic::InvalidOption(format!(
+                    "{} has a {} of {}, not in [0, 1]",
+                    channel, name, p
+                )));
+            }
+        }
+        for autoscaler in self.autoscalers.iter() {
+            if autoscaler.min_workers > autoscaler.max_workers {
+                diagnostics.push(Diagnostic::ContradictoryOptions(format!(
+                    "autoscaler {:?} has more min_workers than max_workers",
+                    autoscaler.group.prefix
+                )));
+            }
+        }
+
+        diagnostics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn validate_test() {
+        let mut queued = periodic_consuming_agent("consumer", 1);
+        queued
+            .state_mut()
+            .queue
+            .push_back(Message::new(0, "consumer", "nobody"));
+        let simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer", 1, "consumer"),
+                queued,
+                periodic_consuming_agent("consumer", 2),
+            ],
+            pools: vec![ConsumerPool::new("consumer", vec!["ghost".to_string()])],
+            warm_up: Some(10),
+            max_ticks: Some(10),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            simulation.validate(),
+            [
+                Diagnostic::DuplicateAgent("consumer".to_string()),
+                Diagnostic::UnknownDestination {
+                    agent: "consumer".to_string(),
+                    destination: "nobody".to_string()
+                },
+                Diagnostic::UnknownAgent {
+                    referrer: "pool \"consumer\"".to_string(),
+                    agent: "ghost".to_string()
+                },
+                Diagnostic::ContradictoryOptions(
+                    "pool \"consumer\" has the id of an agent, which gets its messages instead"
+                        .to_string()
+                ),
+                Diagnostic::ContradictoryOptions(
+                    "the warm-up until 10 lasts the whole run, which ends by 10".to_string()
+                ),
+            ]
+        );
+
+        let simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer", 1, "consumer"),
+                periodic_consuming_agent("consumer", 1),
+            ],
+            ..Default::default()
+        });
+        assert_eq!(simulation.validate(), []);
+    }
+
+    #[test]
+    fn unreachable_agent_test() {
+        let simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer", 1, "consumer"),
+                workload::serving_agent("server", 1),
+            ],
+            topology: Topology::default().with_link_down("producer", "server"),
+            ..Default::default()
+        });
+        let diagnostics = simulation.validate();
+        assert_eq!(
+            diagnostics,
+            [Diagnostic::UnreachableAgent("server".to_string())]
+        );
+        assert_eq!(diagnostics[0].severity(), Severity::Warning);
+    }
+
+    #[test]
+    fn invalid_channel_faults_test() {
+        let faults = |p| ChannelFaults {
+            duplication_probability: p,
+            ..Default::default()
+        };
+        let mut channel_model = ChannelModel {
+            default_faults: Some(faults(f64::NAN)),
+            ..Default::default()
+        };
+        channel_model.channels.insert(
+            ("producer".to_string(), "consumer".to_string()),
+            faults(1.5),
+        );
+        let simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer", 1, "consumer"),
+                periodic_consuming_agent("consumer", 1),
+            ],
+            channel_model,
+            ..Default::default()
+        });
+
+        assert_eq!(
+            simulation.validate(),
+            [
+                Diagnostic::InvalidOption(
+                    "the channel from \"producer\" to \"consumer\" has a \
+                     duplication_probability of 1.5, not in [0, 1]"
+                        .to_string()
+                ),
+                Diagnostic::InvalidOption(
+                    "the default channel has a duplication_probability of NaN, not in [0, 1]"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "the channel's loss_probability of -0.5 is not in [0, 1]")]
+    fn invalid_channel_faults_panic_test() {
+        ChannelModel::default().with_default_faults(ChannelFaults {
+            loss_probability: -0.5,
+            ..Default::default()
+        });
+    }
+}
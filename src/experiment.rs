@@ -1,5 +1,11 @@
+use crate::csv;
 use crate::Simulation;
 use crate::SimulationParameters;
+use crate::{Agent, ShadowAgent};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::collections::BTreeMap;
 
 /// ObjectiveScore is a measure of how a Simulation performed according to an
 /// objective function. This is used to find approximate global optimazations.
@@ -38,3 +44,1281 @@ pub fn experiment_by_annealing_objective(
 
     approx_optimal_simulation
 }
+
+/// Wraps a SimulationParameters generator so that every candidate it
+/// generates runs with the same seed: common random numbers.
+///
+/// Every Agent draws from its own stream derived from the seed and its id, so
+/// an Agent sees the same random numbers in every candidate, no matter how the
+/// other Agents are configured. Differences in score then come from the
+/// configurations rather than from luck, which makes comparisons between
+/// candidates far less noisy. This works with any experiment taking a
+/// generator, e.g. `experiment_by_annealing_objective` or `genetic_search`.
+pub fn with_common_random_numbers(
+    simulation_parameters_generator: impl Fn() -> SimulationParameters,
+    seed: u64,
+) -> impl Fn() -> SimulationParameters {
+    move || SimulationParameters {
+        seed: Some(seed),
+        ..simulation_parameters_generator()
+    }
+}
+
+/// Runs the SimulationParameters twice with the same seed: once as is, and
+/// once as its antithetic replication, in which every random draw of the
+/// Agents is mirrored. Returns the first Simulation and the mean of the two
+/// scores.
+///
+/// A lucky draw in one run is an unlucky draw in the other, so the two scores
+/// are negatively correlated, and their mean estimates the expected score
+/// with less variance than two independent runs.
+pub fn evaluate_antithetic(
+    simulation_parameters: &SimulationParameters,
+    objective_function: impl Fn(&Simulation) -> ObjectiveScore,
+) -> (Simulation, f64) {
+    let seed = simulation_parameters.seed.unwrap_or_else(rand::random);
+    let mut simulation = Simulation::new(SimulationParameters {
+        seed: Some(seed),
+        antithetic: false,
+        ..simulation_parameters.clone()
+    });
+    let mut antithetic = Simulation::new(SimulationParameters {
+        seed: Some(seed),
+        antithetic: true,
+        ..simulation_parameters.clone()
+    });
+    simulation.run();
+    antithetic.run();
+
+    let score =
+        (objective_function(&simulation) as f64 + objective_function(&antithetic) as f64) / 2.0;
+    (simulation, score)
+}
+
+/// Like `experiment_by_annealing_objective`, but every candidate is paired
+/// with its antithetic replication and scored on the mean of the two scores.
+/// See `evaluate_antithetic`.
+pub fn experiment_by_annealing_antithetic_objective(
+    simulation_parameters_generator: impl Fn() -> SimulationParameters,
+    replications_limit: u32,
+    objective_function: impl Fn(&Simulation) -> ObjectiveScore,
+) -> Option<Simulation> {
+    let mut approx_optimal_simulation: Option<Simulation> = None;
+    let mut high_score = f64::NEG_INFINITY;
+
+    for _ in 0..replications_limit {
+        let (simulation, score) =
+            evaluate_antithetic(&simulation_parameters_generator(), &objective_function);
+        if score > high_score {
+            approx_optimal_simulation = Some(simulation);
+            high_score = score;
+        }
+    }
+
+    approx_optimal_simulation
+}
+
+/// How an objective's scores for one set of SimulationParameters vary across seeds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SeedStability {
+    /// The score of each seed's run, in the order of the seeds.
+    pub scores: Vec<ObjectiveScore>,
+    pub mean: f64,
+    /// The sample standard deviation of the scores.
+    pub std_dev: f64,
+}
+
+impl SeedStability {
+    /// The risk-adjusted score: the mean minus lambda standard deviations.
+    /// A higher lambda penalizes configurations that only score well on
+    /// lucky seeds more heavily.
+    pub fn risk_adjusted(&self, lambda: f64) -> f64 {
+        self.mean - lambda * self.std_dev
+    }
+}
+
+/// Runs the SimulationParameters once per seed and scores every run with the
+/// objective function. Returns the Simulation of the first seed alongside the
+/// scores, or None if there are no seeds.
+pub fn evaluate_across_seeds(
+    simulation_parameters: &SimulationParameters,
+    seeds: &[u64],
+    objective_function: impl Fn(&Simulation) -> ObjectiveScore,
+) -> Option<(Simulation, SeedStability)> {
+    let mut first_simulation = None;
+    let mut scores = vec![];
+
+    for seed in seeds {
+        let mut simulation = Simulation::new(SimulationParameters {
+            seed: Some(*seed),
+            ..simulation_parameters.clone()
+        });
+        simulation.run();
+        scores.push(objective_function(&simulation));
+        first_simulation.get_or_insert(simulation);
+    }
+
+    let n = scores.len() as f64;
+    let mean = scores.iter().sum::<ObjectiveScore>() as f64 / n;
+    let variance = if scores.len() > 1 {
+        scores
+            .iter()
+            .map(|s| (*s as f64 - mean).powi(2))
+            .sum::<f64>()
+            / (n - 1.0)
+    } else {
+        0.0
+    };
+
+    Some((
+        first_simulation?,
+        SeedStability {
+            scores,
+            mean,
+            std_dev: variance.sqrt(),
+        },
+    ))
+}
+
+/// A key performance indicator of a Simulation run, e.g. the mean wait time.
+pub type Kpi = fn(&Simulation) -> f64;
+
+/// The KPIs of an ensemble: one run of the same SimulationParameters per seed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EnsembleReport {
+    /// The names of the KPIs, in column order.
+    pub kpi_names: Vec<String>,
+    /// One row per seed, in the order of the seeds: the seed and its KPI values.
+    pub rows: Vec<(u64, Vec<f64>)>,
+}
+
+/// The distribution of one KPI across the runs of an ensemble.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KpiSummary {
+    pub name: String,
+    pub mean: f64,
+    /// The sample standard deviation.
+    pub std_dev: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl EnsembleReport {
+    /// Summarizes every KPI across the runs, in column order.
+    pub fn summary(&self) -> Vec<KpiSummary> {
+        let n = self.rows.len() as f64;
+
+        self.kpi_names
+            .iter()
+            .enumerate()
+            .map(|(column, name)| {
+                let values: Vec<f64> = self.rows.iter().map(|(_, kpis)| kpis[column]).collect();
+                let mean = values.iter().sum::<f64>() / n;
+                let variance = if values.len() > 1 {
+                    values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0)
+                } else {
+                    0.0
+                };
+
+                KpiSummary {
+                    name: name.clone(),
+                    mean,
+                    std_dev: variance.sqrt(),
+                    min: values.iter().cloned().fold(f64::INFINITY, f64::min),
+                    max: values.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+                }
+            })
+            .collect()
+    }
+
+    /// Renders the report as CSV, with a `seed` column followed by the KPIs.
+    pub fn to_csv(&self) -> String {
+        let mut csv = std::iter::once("seed".into())
+            .chain(self.kpi_names.iter().map(|name| csv::field(name)))
+            .collect::<Vec<_>>()
+            .join(",");
+        csv.push('\n');
+
+        for (seed, kpis) in self.rows.iter() {
+            let values: Vec<String> = kpis.iter().map(f64::to_string).collect();
+            csv.push_str(&format!("{},{}\n", seed, values.join(",")));
+        }
+
+        csv
+    }
+
+    /// Writes the CSV rendering of the report to `path`.
+    pub fn write_csv<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
+        std::fs::write(path, self.to_csv())
+    }
+}
+
+/// Puts SimulationParameters back together from their Agents.
+type Assemble =
+    Box<dyn FnOnce(Vec<Box<dyn Agent>>, Vec<ShadowAgent>) -> SimulationParameters + Send>;
+
+/// SimulationParameters taken apart to go to another thread: their Agents
+/// as `Send`, and the rest, to put back together there.
+struct SendParameters {
+    agents: Vec<Box<dyn Agent + Send>>,
+    shadow_agents: Vec<(String, Box<dyn Agent + Send>)>,
+    assemble: Assemble,
+}
+
+impl SendParameters {
+    /// Takes a copy of the parameters apart, or None if an Agent isn't `Send`.
+    fn new(parameters: &SimulationParameters) -> Option<Self> {
+        let agents = parameters
+            .agents
+            .iter()
+            .map(|a| a.clone_send())
+            .collect::<Option<Vec<_>>>()?;
+        let shadow_agents = parameters
+            .shadow_agents
+            .iter()
+            .map(|s| Some((s.shadowed.clone(), s.agent.clone_send()?)))
+            .collect::<Option<Vec<_>>>()?;
+
+        // Listing every field makes sure none holds an Agent but these.
+        let SimulationParameters {
+            agents: _,
+            shadow_agents: _,
+            halt_check,
+            starting_time,
+            ending_time,
+            max_ticks,
+            max_wall_clock,
+            time_scale,
+            warm_up,
+            enable_queue_depth_metrics,
+            enable_agent_asleep_cycles_metric,
+            enable_queue_depth_stats,
+            enable_trace,
+            enable_activity_metrics,
+            throughput_window,
+            unstable_growth_rate,
+            environment,
+            world_dynamics,
+            resources,
+            stores,
+            containers,
+            pools,
+            autoscalers,
+            groups,
+            grid,
+            space,
+            cells,
+            blackboard,
+            enable_environment_metrics,
+            channel_model,
+            topology,
+            message_transforms,
+            command_middleware,
+            command_handlers,
+            default_ttl,
+            report_sinks,
+            observers,
+            seed,
+            antithetic,
+            enable_parallel_agents,
+            enable_agent_views,
+            error_policy,
+            enable_panic_isolation,
+            quiescence_policy,
+        } = parameters.clone();
+        let assemble = move |agents, shadow_agents| SimulationParameters {
+            agents,
+            shadow_agents,
+            halt_check,
+            starting_time,
+            ending_time,
+            max_ticks,
+            max_wall_clock,
+            time_scale,
+            warm_up,
+            enable_queue_depth_metrics,
+            enable_agent_asleep_cycles_metric,
+            enable_queue_depth_stats,
+            enable_trace,
+            enable_activity_metrics,
+            throughput_window,
+            unstable_growth_rate,
+            environment,
+            world_dynamics,
+            resources,
+            stores,
+            containers,
+            pools,
+            autoscalers,
+            groups,
+            grid,
+            space,
+            cells,
+            blackboard,
+            enable_environment_metrics,
+            channel_model,
+            topology,
+            message_transforms,
+            command_middleware,
+            command_handlers,
+            default_ttl,
+            report_sinks,
+            observers,
+            seed,
+            antithetic,
+            enable_parallel_agents,
+            enable_agent_views,
+            error_policy,
+            enable_panic_isolation,
+            quiescence_policy,
+        };
+
+        Some(SendParameters {
+            agents,
+            shadow_agents,
+            assemble: Box::new(assemble),
+        })
+    }
+
+    /// Puts the parameters back together.
+    fn assemble(self) -> SimulationParameters {
+        let agents = self
+            .agents
+            .into_iter()
+            .map(|a| a as Box<dyn Agent>)
+            .collect();
+        let shadow_agents = self
+            .shadow_agents
+            .into_iter()
+            .map(|(shadowed, agent)| ShadowAgent::new(shadowed, agent))
+            .collect();
+        (self.assemble)(agents, shadow_agents)
+    }
+}
+
+/// Runs the SimulationParameters once per seed, in parallel across all cores,
+/// and measures the named KPIs of every run. This is the common batch
+/// workflow of estimating how a configuration performs across many seeds.
+/// Agents that aren't `Send` can't go to other threads, so with any of them
+/// the runs are one after another on this thread.
+pub fn run_ensemble(
+    simulation_parameters: &SimulationParameters,
+    seeds: &[u64],
+    kpis: &[(&str, Kpi)],
+) -> EnsembleReport {
+    let run = |parameters: &SimulationParameters, seed: u64| {
+        let mut simulation = Simulation::new(SimulationParameters {
+            seed: Some(seed),
+            ..parameters.clone()
+        });
+        simulation.run();
+        let measured: Vec<f64> = kpis.iter().map(|(_, kpi)| kpi(&simulation)).collect();
+        (seed, measured)
+    };
+
+    let rows = match SendParameters::new(simulation_parameters) {
+        None => seeds
+            .iter()
+            .map(|seed| run(simulation_parameters, *seed))
+            .collect(),
+        Some(_) => {
+            let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+            let chunk_size = ((seeds.len() + threads - 1) / threads).max(1);
+
+            std::thread::scope(|scope| {
+                let workers: Vec<_> = seeds
+                    .chunks(chunk_size)
+                    .map(|seeds| {
+                        // Agents aren't Sync, so every worker gets its own copy.
+                        let parameters = SendParameters::new(simulation_parameters)
+                            .expect("The Agents were Send");
+                        scope.spawn(move || {
+                            let parameters = parameters.assemble();
+                            seeds
+                                .iter()
+                                .map(|seed| run(&parameters, *seed))
+                                .collect::<Vec<_>>()
+                        })
+                    })
+                    .collect();
+
+                workers
+                    .into_iter()
+                    .flat_map(|w| w.join().expect("Ensemble worker thread panicked"))
+                    .collect()
+            })
+        }
+    };
+
+    EnsembleReport {
+        kpi_names: kpis.iter().map(|(name, _)| name.to_string()).collect(),
+        rows,
+    }
+}
+
+/// The KPIs of several labelled configurations, each run as an ensemble over
+/// the same seeds.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExperimentReport {
+    /// The (label, ensemble) of every configuration, in the order they were run.
+    pub configurations: Vec<(String, EnsembleReport)>,
+}
+
+impl ExperimentReport {
+    /// Returns the values of a KPI across the runs of the labelled configuration.
+    pub fn kpi_values(&self, label: &str, kpi: &str) -> Option<Vec<f64>> {
+        let (_, ensemble) = self.configurations.iter().find(|(l, _)| l == label)?;
+        let column = ensemble.kpi_names.iter().position(|k| k == kpi)?;
+        Some(ensemble.rows.iter().map(|(_, kpis)| kpis[column]).collect())
+    }
+}
+
+/// Runs every labelled configuration as an ensemble over the same seeds, to
+/// compare their KPIs. See `run_ensemble`.
+pub fn run_experiment(
+    configurations: &[(&str, SimulationParameters)],
+    seeds: &[u64],
+    kpis: &[(&str, Kpi)],
+) -> ExperimentReport {
+    ExperimentReport {
+        configurations: configurations
+            .iter()
+            .map(|(label, parameters)| (label.to_string(), run_ensemble(parameters, seeds, kpis)))
+            .collect(),
+    }
+}
+
+/// The simulation time at which the Simulation completed.
+pub fn completion_time(simulation: &Simulation) -> f64 {
+    simulation.time as f64
+}
+
+/// The mean of `completed_time - queued_time` over every consumed message.
+pub fn mean_wait_time(simulation: &Simulation) -> f64 {
+    let waits: Vec<u64> = simulation
+        .agents
+        .iter()
+        .flat_map(|a| a.state().consumed.iter())
+        .filter_map(|m| Some(m.completed_time? - m.queued_time))
+        .collect();
+    if waits.is_empty() {
+        return 0.0;
+    }
+    waits.iter().sum::<u64>() as f64 / waits.len() as f64
+}
+
+/// The mean length of the Agents' queues when the Simulation completed.
+pub fn mean_queue_length(simulation: &Simulation) -> f64 {
+    if simulation.agents.is_empty() {
+        return 0.0;
+    }
+    let total: usize = simulation
+        .agents
+        .iter()
+        .map(|a| a.state().queue.len())
+        .sum();
+    total as f64 / simulation.agents.len() as f64
+}
+
+/// The estimate of one metric across replications.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MetricEstimate {
+    pub name: String,
+    pub mean: f64,
+    /// The sample variance.
+    pub variance: f64,
+    /// The 95% confidence interval of the mean, from Student's t-distribution.
+    pub confidence_interval: (f64, f64),
+}
+
+/// The estimates of the selected metrics across independent replications.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReplicationReport {
+    /// One estimate per metric, in the order they were selected.
+    pub metrics: Vec<MetricEstimate>,
+    /// The metric values of every replication.
+    pub runs: EnsembleReport,
+}
+
+impl ReplicationReport {
+    /// Returns the estimate of the metric with the given name.
+    pub fn metric(&self, name: &str) -> Option<&MetricEstimate> {
+        self.metrics.iter().find(|m| m.name == name)
+    }
+}
+
+/// Runs n independent replications of the SimulationParameters, seeded from
+/// `seed`, and estimates the mean of every metric with a 95% confidence
+/// interval. Reproducible: the same seed runs the same replications.
+///
+/// Built-in metrics include `completion_time`, `mean_wait_time` and
+/// `mean_queue_length`; any `Kpi` works.
+pub fn replicate(
+    simulation_parameters: &SimulationParameters,
+    n: usize,
+    seed: u64,
+    metrics: &[(&str, Kpi)],
+) -> ReplicationReport {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let seeds: Vec<u64> = (0..n).map(|_| rng.gen()).collect();
+    let runs = run_ensemble(simulation_parameters, &seeds, metrics);
+
+    let metrics = runs
+        .summary()
+        .into_iter()
+        .map(|summary| {
+            let variance = summary.std_dev.powi(2);
+            let half_width = crate::stats::t_critical_value_95(n.saturating_sub(1))
+                * summary.std_dev
+                / (n.max(1) as f64).sqrt();
+
+            MetricEstimate {
+                name: summary.name,
+                mean: summary.mean,
+                variance,
+                confidence_interval: (summary.mean - half_width, summary.mean + half_width),
+            }
+        })
+        .collect();
+
+    ReplicationReport { metrics, runs }
+}
+
+/// Like `experiment_by_annealing_objective`, but every candidate is run once
+/// per seed and scored on its risk-adjusted score (mean minus lambda standard
+/// deviations) across the seeds. This stops the experiment from selecting a
+/// configuration that merely got lucky on a single run.
+///
+/// Returns the best candidate's Simulation (run with the first seed) and its
+/// stability across the seeds.
+pub fn experiment_by_annealing_stable_objective(
+    simulation_parameters_generator: impl Fn() -> SimulationParameters,
+    replications_limit: u32,
+    seeds: &[u64],
+    lambda: f64,
+    objective_function: impl Fn(&Simulation) -> ObjectiveScore,
+) -> Option<(Simulation, SeedStability)> {
+    let mut approx_optimal: Option<(Simulation, SeedStability)> = None;
+    let mut high_score = f64::NEG_INFINITY;
+
+    for _ in 0..replications_limit {
+        let parameters = simulation_parameters_generator();
+        let Some((simulation, stability)) =
+            evaluate_across_seeds(&parameters, seeds, &objective_function)
+        else {
+            continue;
+        };
+
+        let score = stability.risk_adjusted(lambda);
+        if score > high_score {
+            approx_optimal = Some((simulation, stability));
+            high_score = score;
+        }
+    }
+
+    approx_optimal
+}
+
+/// A point in a ParameterSpace: maps from dimension name => value.
+pub type ParameterPoint = BTreeMap<String, f64>;
+
+/// A space of parameters to search, described as the cartesian product of
+/// named dimensions, each with the values to try.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ParameterSpace {
+    pub dimensions: Vec<(String, Vec<f64>)>,
+}
+
+impl ParameterSpace {
+    /// Adds a dimension with the values to try for it.
+    pub fn with_dimension<S>(mut self, name: S, values: impl IntoIterator<Item = f64>) -> Self
+    where
+        S: Into<String>,
+    {
+        self.dimensions
+            .push((name.into(), values.into_iter().collect()));
+        self
+    }
+
+    /// Returns every point of the space, varying the last dimension fastest.
+    pub fn points(&self) -> Vec<ParameterPoint> {
+        self.dimensions
+            .iter()
+            .fold(vec![ParameterPoint::new()], |points, (name, values)| {
+                points
+                    .iter()
+                    .flat_map(|point| {
+                        values.iter().map(move |value| {
+                            let mut point = point.clone();
+                            point.insert(name.clone(), *value);
+                            point
+                        })
+                    })
+                    .collect()
+            })
+    }
+}
+
+/// The outcome of a grid search: the score of every point in the space.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GridSearchReport {
+    /// Every point of the space with its score, in the order of `points()`.
+    pub results: Vec<(ParameterPoint, ObjectiveScore)>,
+}
+
+impl GridSearchReport {
+    /// Returns the point with the highest score; the first one on ties.
+    pub fn best(&self) -> Option<&(ParameterPoint, ObjectiveScore)> {
+        self.results.iter().rev().max_by_key(|(_, score)| *score)
+    }
+}
+
+/// Exhaustively searches a parameter space: for every point, creates the
+/// SimulationParameters for it, runs the Simulation, and scores it with the
+/// objective function. Unlike annealing, this covers the whole space, which
+/// makes it the baseline to compare other experiments against.
+pub fn grid_search(
+    param_space: &ParameterSpace,
+    simulation_parameters_fn: impl Fn(&ParameterPoint) -> SimulationParameters,
+    objective_function: impl Fn(&Simulation) -> ObjectiveScore,
+) -> GridSearchReport {
+    let results = param_space
+        .points()
+        .into_iter()
+        .map(|point| {
+            let mut simulation = Simulation::new(simulation_parameters_fn(&point));
+            simulation.run();
+            let score = objective_function(&simulation);
+            (point, score)
+        })
+        .collect();
+
+    GridSearchReport { results }
+}
+
+/// The parameters of a genetic search.
+#[derive(Clone, Debug)]
+pub struct GeneticSearchParameters {
+    /// The number of candidates in every generation.
+    pub population_size: usize,
+    /// The number of best candidates carried over unchanged to the next generation.
+    pub elitism: usize,
+    /// The number of generations to evolve; the first is the random population.
+    pub generations: u32,
+    /// How many candidates compete in a tournament to become a parent.
+    pub tournament_size: usize,
+    /// The seed for picking parents. None seeds from entropy.
+    pub seed: Option<u64>,
+}
+
+impl Default for GeneticSearchParameters {
+    fn default() -> Self {
+        GeneticSearchParameters {
+            population_size: 32,
+            elitism: 2,
+            generations: 10,
+            tournament_size: 3,
+            seed: None,
+        }
+    }
+}
+
+/// Searches for the SimulationParameters that maximize the objective function
+/// with a genetic algorithm, for search spaces where annealing gets stuck.
+///
+/// The first generation is created by the generator. Every next generation
+/// keeps the `elitism` best candidates, and fills the rest with children: the
+/// crossover of two parents, each the best of a random tournament, passed
+/// through the mutation function. The mutation function decides itself how
+/// often and how much to mutate.
+///
+/// Returns the Simulation of the best candidate of all generations.
+pub fn genetic_search(
+    simulation_parameters_generator: impl Fn() -> SimulationParameters,
+    crossover: impl Fn(&SimulationParameters, &SimulationParameters) -> SimulationParameters,
+    mutation: impl Fn(SimulationParameters) -> SimulationParameters,
+    genetic_search_parameters: GeneticSearchParameters,
+    objective_function: impl Fn(&Simulation) -> ObjectiveScore,
+) -> Option<Simulation> {
+    let GeneticSearchParameters {
+        population_size,
+        elitism,
+        generations,
+        tournament_size,
+        seed,
+    } = genetic_search_parameters;
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    let evaluate = |parameters: SimulationParameters| {
+        let mut simulation = Simulation::new(parameters.clone());
+        simulation.run();
+        let score = objective_function(&simulation);
+        (parameters, simulation, score)
+    };
+
+    let mut population: Vec<_> = (0..population_size)
+        .map(|_| evaluate(simulation_parameters_generator()))
+        .collect();
+    let mut best: Option<(Simulation, ObjectiveScore)> = None;
+
+    for generation in 0..generations {
+        // Best first, so the elite are at the front.
+        population.sort_by_key(|(_, _, score)| std::cmp::Reverse(*score));
+
+        if let Some((_, simulation, score)) = population.first() {
+            if best.as_ref().map_or(true, |(_, high)| score > high) {
+                best = Some((simulation.clone(), *score));
+            }
+        }
+
+        if generation + 1 == generations || population.is_empty() {
+            break;
+        }
+
+        let tournament = |rng: &mut StdRng| {
+            population
+                .choose_multiple(rng, tournament_size.max(1))
+                .max_by_key(|(_, _, score)| *score)
+                .map(|(parameters, _, _)| parameters)
+                .expect("Population is not empty")
+        };
+
+        let children: Vec<SimulationParameters> = (elitism.min(population_size)..population_size)
+            .map(|_| {
+                let (mother, father) = (tournament(&mut rng), tournament(&mut rng));
+                mutation(crossover(mother, father))
+            })
+            .collect();
+
+        population.truncate(elitism);
+        population.extend(children.into_iter().map(evaluate));
+    }
+
+    best.map(|(simulation, _)| simulation)
+}
+
+/// The parameters of a Bayesian search.
+#[derive(Clone, Debug)]
+pub struct BayesianSearchParameters {
+    /// The (min, max) bounds of every dimension of the parameter vector.
+    pub bounds: Vec<(f64, f64)>,
+    /// The number of random parameter vectors evaluated before the surrogate
+    /// model starts proposing them.
+    pub initial_samples: usize,
+    /// The number of parameter vectors proposed by the surrogate model.
+    pub iterations: usize,
+    /// How many random candidates the acquisition function is evaluated on
+    /// to choose each proposal.
+    pub candidates_per_iteration: usize,
+    /// The length scale of the surrogate's kernel, relative to the bounds.
+    /// Smaller values fit more rugged objectives.
+    pub length_scale: f64,
+    /// The noise variance of the surrogate, relative to the score variance.
+    /// Stochastic simulations need more noise.
+    pub noise: f64,
+    /// The seed for proposing candidates. None seeds from entropy.
+    pub seed: Option<u64>,
+}
+
+impl Default for BayesianSearchParameters {
+    fn default() -> Self {
+        BayesianSearchParameters {
+            bounds: vec![],
+            initial_samples: 5,
+            iterations: 20,
+            candidates_per_iteration: 1000,
+            length_scale: 0.2,
+            noise: 0.01,
+            seed: None,
+        }
+    }
+}
+
+/// The outcome of a Bayesian search: every parameter vector evaluated and its score.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BayesianSearchReport {
+    /// Every evaluated parameter vector with its score, in evaluation order.
+    pub evaluations: Vec<(Vec<f64>, ObjectiveScore)>,
+}
+
+impl BayesianSearchReport {
+    /// Returns the parameter vector with the highest score; the first one on ties.
+    pub fn best(&self) -> Option<&(Vec<f64>, ObjectiveScore)> {
+        self.evaluations
+            .iter()
+            .rev()
+            .max_by_key(|(_, score)| *score)
+    }
+}
+
+/// Searches for the numeric parameter vector that maximizes the objective
+/// function with Bayesian optimization, for expensive simulations where only
+/// tens of replications are affordable.
+///
+/// After some random initial samples, a Gaussian process surrogate is fitted
+/// to all scores so far, and the next parameter vector to evaluate is the
+/// candidate with the highest expected improvement over the best score.
+pub fn bayesian_search(
+    simulation_parameters_fn: impl Fn(&[f64]) -> SimulationParameters,
+    bayesian_search_parameters: BayesianSearchParameters,
+    objective_function: impl Fn(&Simulation) -> ObjectiveScore,
+) -> BayesianSearchReport {
+    let parameters = bayesian_search_parameters;
+    let mut rng = match parameters.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    // The surrogate works on the unit hypercube.
+    let dimensions = parameters.bounds.len();
+    let to_parameters = |unit: &[f64]| -> Vec<f64> {
+        unit.iter()
+            .zip(parameters.bounds.iter())
+            .map(|(u, (min, max))| min + u * (max - min))
+            .collect()
+    };
+
+    let mut observed: Vec<(Vec<f64>, f64)> = vec![];
+    let mut report = BayesianSearchReport::default();
+
+    for iteration in 0..parameters.initial_samples + parameters.iterations {
+        let random_point =
+            |rng: &mut StdRng| -> Vec<f64> { (0..dimensions).map(|_| rng.gen()).collect() };
+
+        let next = if iteration < parameters.initial_samples || observed.is_empty() {
+            random_point(&mut rng)
+        } else {
+            let surrogate =
+                GaussianProcess::fit(&observed, parameters.length_scale, parameters.noise);
+            (0..parameters.candidates_per_iteration.max(1))
+                .map(|_| random_point(&mut rng))
+                .map(|c| (surrogate.expected_improvement(&c), c))
+                .max_by(|(a, _), (b, _)| a.total_cmp(b))
+                .map(|(_, c)| c)
+                .expect("There is at least one candidate")
+        };
+
+        let vector = to_parameters(&next);
+        let mut simulation = Simulation::new(simulation_parameters_fn(&vector));
+        simulation.run();
+        let score = objective_function(&simulation);
+
+        observed.push((next, score as f64));
+        report.evaluations.push((vector, score));
+    }
+
+    report
+}
+
+/// A Gaussian process regression with a squared-exponential kernel, fitted to
+/// standardized scores.
+struct GaussianProcess {
+    points: Vec<Vec<f64>>,
+    /// The Cholesky factor of the kernel matrix of the points.
+    cholesky: Vec<Vec<f64>>,
+    /// The kernel matrix solved against the standardized scores.
+    alpha: Vec<f64>,
+    length_scale: f64,
+    /// The best standardized score observed.
+    best: f64,
+}
+
+impl GaussianProcess {
+    fn fit(observed: &[(Vec<f64>, f64)], length_scale: f64, noise: f64) -> GaussianProcess {
+        let n = observed.len() as f64;
+        let mean = observed.iter().map(|(_, y)| y).sum::<f64>() / n;
+        let std_dev = (observed
+            .iter()
+            .map(|(_, y)| (y - mean).powi(2))
+            .sum::<f64>()
+            / n)
+            .sqrt()
+            .max(f64::EPSILON);
+        let scores: Vec<f64> = observed.iter().map(|(_, y)| (y - mean) / std_dev).collect();
+        let points: Vec<Vec<f64>> = observed.iter().map(|(x, _)| x.clone()).collect();
+
+        let kernel: Vec<Vec<f64>> = points
+            .iter()
+            .enumerate()
+            .map(|(i, a)| {
+                points
+                    .iter()
+                    .enumerate()
+                    .map(|(j, b)| kernel(a, b, length_scale) + if i == j { noise } else { 0.0 })
+                    .collect()
+            })
+            .collect();
+
+        let cholesky = cholesky(&kernel);
+        let alpha = solve_upper(&cholesky, &solve_lower(&cholesky, &scores));
+
+        GaussianProcess {
+            points,
+            cholesky,
+            alpha,
+            length_scale,
+            best: scores.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+        }
+    }
+
+    /// The expected improvement of the point over the best observed score.
+    fn expected_improvement(&self, point: &[f64]) -> f64 {
+        let k: Vec<f64> = self
+            .points
+            .iter()
+            .map(|p| kernel(p, point, self.length_scale))
+            .collect();
+        let mean: f64 = k.iter().zip(self.alpha.iter()).map(|(k, a)| k * a).sum();
+        let v = solve_lower(&self.cholesky, &k);
+        let variance = (1.0 - v.iter().map(|v| v * v).sum::<f64>()).max(0.0);
+        let sigma = variance.sqrt();
+
+        // A small exploration margin, so proposals don't collapse onto the best point.
+        let improvement = mean - self.best - 0.01;
+        if sigma < 1e-12 {
+            return improvement.max(0.0);
+        }
+
+        let z = improvement / sigma;
+        improvement * normal_cdf(z) + sigma * normal_pdf(z)
+    }
+}
+
+fn kernel(a: &[f64], b: &[f64], length_scale: f64) -> f64 {
+    let squared_distance: f64 = a.iter().zip(b.iter()).map(|(a, b)| (a - b).powi(2)).sum();
+    (-squared_distance / (2.0 * length_scale * length_scale)).exp()
+}
+
+/// The lower-triangular Cholesky factor L of a positive-definite matrix A = LLᵀ.
+fn cholesky(a: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let n = a.len();
+    let mut l = vec![vec![0.0; n]; n];
+    for i in 0..n {
+        for j in 0..=i {
+            let sum: f64 = (0..j).map(|k| l[i][k] * l[j][k]).sum();
+            l[i][j] = if i == j {
+                (a[i][i] - sum).max(f64::EPSILON).sqrt()
+            } else {
+                (a[i][j] - sum) / l[j][j]
+            };
+        }
+    }
+    l
+}
+
+/// Solves Lx = b for lower-triangular L.
+fn solve_lower(l: &[Vec<f64>], b: &[f64]) -> Vec<f64> {
+    let mut x = vec![0.0; b.len()];
+    for i in 0..b.len() {
+        let sum: f64 = (0..i).map(|k| l[i][k] * x[k]).sum();
+        x[i] = (b[i] - sum) / l[i][i];
+    }
+    x
+}
+
+/// Solves Lᵀx = b for lower-triangular L.
+fn solve_upper(l: &[Vec<f64>], b: &[f64]) -> Vec<f64> {
+    let mut x = vec![0.0; b.len()];
+    for i in (0..b.len()).rev() {
+        let sum: f64 = (i + 1..b.len()).map(|k| l[k][i] * x[k]).sum();
+        x[i] = (b[i] - sum) / l[i][i];
+    }
+    x
+}
+
+fn normal_pdf(z: f64) -> f64 {
+    (-z * z / 2.0).exp() / (2.0 * std::f64::consts::PI).sqrt()
+}
+
+/// The standard normal CDF, via the Abramowitz and Stegun approximation of erf.
+fn normal_cdf(z: f64) -> f64 {
+    let x = z.abs() / std::f64::consts::SQRT_2;
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let polynomial = t
+        * (0.254829592
+            + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let erf = 1.0 - polynomial * (-x * x).exp();
+    if z >= 0.0 {
+        (1.0 + erf) / 2.0
+    } else {
+        (1.0 - erf) / 2.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::*;
+    use rand_distr::Poisson;
+
+    #[test]
+    fn seed_stability_test() {
+        let parameters = SimulationParameters {
+            agents: vec![
+                poisson_distributed_producing_agent(
+                    "producer",
+                    Poisson::new(3.0).unwrap(),
+                    "consumer",
+                ),
+                periodic_consuming_agent("consumer", 1),
+            ],
+            halt_check: |s: &Simulation| s.time == 100,
+            ..Default::default()
+        };
+        let objective = |s: &Simulation| s.calc_produced_len_statistics()["producer"] as i64;
+
+        let (_, first) = evaluate_across_seeds(&parameters, &[1, 2, 3], objective).unwrap();
+        let (_, again) = evaluate_across_seeds(&parameters, &[1, 2, 3], objective).unwrap();
+        assert_eq!(first, again);
+        assert!(first.std_dev > 0.0);
+        assert!(first.risk_adjusted(1.0) < first.mean);
+    }
+
+    #[test]
+    fn common_random_numbers_test() {
+        let generator = with_common_random_numbers(
+            || SimulationParameters {
+                agents: vec![
+                    poisson_distributed_producing_agent(
+                        "producer",
+                        Poisson::new(3.0).unwrap(),
+                        "consumer",
+                    ),
+                    periodic_consuming_agent("consumer", rand::thread_rng().gen_range(1..4)),
+                ],
+                halt_check: |s: &Simulation| s.time == 100,
+                ..Default::default()
+            },
+            7,
+        );
+
+        // The producer sees the same random numbers in every arm.
+        let produced: Vec<usize> = (0..5)
+            .map(|_| {
+                let mut simulation = Simulation::new(generator());
+                simulation.run();
+                simulation.calc_produced_len_statistics()["producer"]
+            })
+            .collect();
+        assert!(produced.iter().all(|p| *p == produced[0]));
+    }
+
+    #[test]
+    fn ensemble_test() {
+        let parameters = SimulationParameters {
+            agents: vec![
+                poisson_distributed_producing_agent(
+                    "producer",
+                    Poisson::new(3.0).unwrap(),
+                    "consumer",
+                ),
+                periodic_consuming_agent("consumer", 1),
+            ],
+            halt_check: |s: &Simulation| s.time == 100,
+            ..Default::default()
+        };
+        let kpis: &[(&str, Kpi)] = &[
+            ("produced", |s| {
+                s.calc_produced_len_statistics()["producer"] as f64
+            }),
+            ("time", |s| s.time as f64),
+        ];
+
+        let seeds: Vec<u64> = (1..=20).collect();
+        let report = run_ensemble(&parameters, &seeds, kpis);
+        assert_eq!(report, run_ensemble(&parameters, &seeds, kpis));
+        assert_eq!(report.rows.len(), 20);
+        assert_eq!(report.rows[4].0, 5);
+
+        let summary = report.summary();
+        assert_eq!(summary[1].mean, 100.0);
+        assert_eq!(summary[1].std_dev, 0.0);
+        assert!(summary[0].min < summary[0].max);
+
+        let csv = report.to_csv();
+        assert!(csv.starts_with("seed,produced,time\n1,"));
+        assert_eq!(csv.lines().count(), 21);
+
+        let report = EnsembleReport {
+            kpi_names: vec!["wait, p99".to_string()],
+            rows: vec![(1, vec![2.5])],
+        };
+        assert_eq!(report.to_csv(), "seed,\"wait, p99\"\n1,2.5\n");
+    }
+
+    #[test]
+    fn antithetic_test() {
+        let parameters = SimulationParameters {
+            agents: vec![
+                poisson_distributed_producing_agent(
+                    "producer",
+                    Poisson::new(3.0).unwrap(),
+                    "consumer",
+                ),
+                periodic_consuming_agent("consumer", 1),
+            ],
+            halt_check: |s: &Simulation| s.time == 100,
+            ..Default::default()
+        };
+        let objective = |s: &Simulation| s.calc_produced_len_statistics()["producer"] as i64;
+
+        // The mean of 20 antithetic pairs varies less than the mean of 20
+        // independent pairs.
+        let variance = |scores: &[f64]| {
+            let mean = scores.iter().sum::<f64>() / scores.len() as f64;
+            scores.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / scores.len() as f64
+        };
+        let antithetic: Vec<f64> = (0..20)
+            .map(|seed| {
+                let parameters = SimulationParameters {
+                    seed: Some(seed),
+                    ..parameters.clone()
+                };
+                evaluate_antithetic(&parameters, objective).1
+            })
+            .collect();
+        let (_, independent) =
+            evaluate_across_seeds(&parameters, &(0..40).collect::<Vec<_>>(), objective).unwrap();
+        let independent: Vec<f64> = independent
+            .scores
+            .chunks(2)
+            .map(|pair| (pair[0] + pair[1]) as f64 / 2.0)
+            .collect();
+
+        assert!(variance(&antithetic) < variance(&independent));
+    }
+
+    #[test]
+    fn replicate_test() {
+        let parameters = SimulationParameters {
+            agents: vec![
+                poisson_distributed_producing_agent(
+                    "producer",
+                    Poisson::new(3.0).unwrap(),
+                    "consumer",
+                ),
+                periodic_consuming_agent("consumer", 3),
+            ],
+            halt_check: |s: &Simulation| s.time == 100,
+            ..Default::default()
+        };
+        let metrics: &[(&str, Kpi)] = &[
+            ("completion_time", completion_time),
+            ("wait_time", mean_wait_time),
+            ("queue_length", mean_queue_length),
+        ];
+
+        let report = replicate(&parameters, 10, 42, metrics);
+        assert_eq!(report, replicate(&parameters, 10, 42, metrics));
+        assert_eq!(report.runs.rows.len(), 10);
+
+        let completion = report.metric("completion_time").unwrap();
+        assert_eq!(completion.mean, 100.0);
+        assert_eq!(completion.confidence_interval, (100.0, 100.0));
+
+        let queue = report.metric("queue_length").unwrap();
+        assert!(queue.variance > 0.0);
+        assert!(queue.confidence_interval.0 < queue.mean);
+        assert!(queue.mean < queue.confidence_interval.1);
+    }
+
+    #[test]
+    fn grid_search_test() {
+        let space = ParameterSpace::default()
+            .with_dimension("producer_period", [1.0, 2.0])
+            .with_dimension("consumer_period", [1.0, 2.0, 3.0]);
+        assert_eq!(space.points().len(), 6);
+
+        let report = grid_search(
+            &space,
+            |point| SimulationParameters {
+                agents: vec![
+                    periodic_producing_agent(
+                        "producer",
+                        point["producer_period"] as u64,
+                        "consumer",
+                    ),
+                    periodic_consuming_agent("consumer", point["consumer_period"] as u64),
+                ],
+                halt_check: |s: &Simulation| s.time == 60,
+                ..Default::default()
+            },
+            |s| s.calc_consumed_len_statistics()["consumer"] as i64,
+        );
+
+        assert_eq!(report.results.len(), 6);
+        let (best, _) = report.best().unwrap();
+        assert_eq!(best["producer_period"], 1.0);
+        assert_eq!(best["consumer_period"], 1.0);
+    }
+
+    #[test]
+    fn genetic_search_test() {
+        fn parameters(consumer_period: u64) -> SimulationParameters {
+            SimulationParameters {
+                agents: vec![
+                    periodic_producing_agent("producer", 1, "consumer"),
+                    periodic_consuming_agent("consumer", consumer_period),
+                ],
+                halt_check: |s: &Simulation| s.time == 10,
+                ..Default::default()
+            }
+        }
+
+        let consumer_period = |p: &SimulationParameters| -p.agents[1].cost() as u64;
+        let search = |seed| {
+            // The parents picked, to compare searches with the same seed.
+            let parents = std::cell::RefCell::new(vec![]);
+            let generated = std::cell::Cell::new(10);
+            let best = genetic_search(
+                || parameters(generated.replace(generated.get() + 1)),
+                |a, b| {
+                    parents
+                        .borrow_mut()
+                        .push((consumer_period(a), consumer_period(b)));
+                    parameters(consumer_period(a).min(consumer_period(b)))
+                },
+                |p| parameters(consumer_period(&p).saturating_sub(1)),
+                GeneticSearchParameters {
+                    population_size: 4,
+                    elitism: 1,
+                    generations: 5,
+                    tournament_size: 2,
+                    seed: Some(seed),
+                },
+                |s| s.agents[1].cost(),
+            )
+            .unwrap();
+            (best.agents[1].cost(), parents.into_inner())
+        };
+
+        // Every generation's children improve on their parents by one.
+        let (best, parents) = search(7);
+        assert_eq!(best, -6);
+        assert_eq!(search(7).1, parents);
+    }
+
+    #[test]
+    fn bayesian_search_test() {
+        let report = bayesian_search(
+            |x| SimulationParameters {
+                environment: [("x".to_string(), x[0])].into(),
+                ..Default::default()
+            },
+            BayesianSearchParameters {
+                bounds: vec![(0.0, 10.0)],
+                iterations: 10,
+                seed: Some(3),
+                ..Default::default()
+            },
+            |s| (-(s.environment["x"] - 7.0).powi(2) * 100.0) as i64,
+        );
+
+        assert_eq!(report.evaluations.len(), 15);
+        let (best, _) = report.best().unwrap();
+        assert!((best[0] - 7.0).abs() < 0.5, "{:?}", best);
+    }
+}
@@ -0,0 +1,203 @@
+//! Exhaustive exploration of the same-tick interleavings of a small model.
+//!
+//! When several messages are sent to an Agent in the same tick, the engine
+//! queues them in an arbitrary but fixed order. `explore_interleavings` reruns
+//! the Simulation under every such order, so users can check that the
+//! conclusions of their model don't depend on the engine's tie-breaking.
+//!
+//! Only the relative order of messages to the same Agent is permuted: messages
+//! to different Agents land on different queues, so their interleavings are
+//! equivalent and are pruned. This partial-order reduction assumes messages
+//! are independent in the engine too, i.e. no channel faults and no
+//! interrupts, whose effects depend on their position in the tick.
+
+use crate::{Message, Simulation, SimulationParameters};
+
+/// The outcomes of exploring the interleavings of a model.
+#[derive(Clone, Debug)]
+pub struct ExplorationReport<T> {
+    /// The number of interleavings run.
+    pub runs: usize,
+    /// Whether every interleaving was run, rather than stopping at `max_runs`.
+    pub exhaustive: bool,
+    /// The distinct outcomes observed, in the order they were first observed.
+    /// The first is the outcome of the engine's default order.
+    pub outcomes: Vec<T>,
+}
+
+impl<T> ExplorationReport<T> {
+    /// Whether every interleaving run had the same outcome.
+    pub fn is_order_independent(&self) -> bool {
+        self.outcomes.len() <= 1
+    }
+}
+
+/// The choices of message order made in a run, replaying a prefix of the
+/// choices of a previous run.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Interleaving {
+    prefix: Vec<usize>,
+    /// Every choice made, as (chosen, alternatives).
+    choices: Vec<(usize, usize)>,
+}
+
+impl Interleaving {
+    /// Permutes the messages to each Agent in a tick's message bus, keeping
+    /// the positions the engine gave to messages for each Agent.
+    pub(crate) fn reorder(&mut self, message_bus: &mut [Message]) {
+        let mut groups: Vec<Vec<usize>> = vec![];
+        for (position, message) in message_bus.iter().enumerate() {
+            match groups
+                .iter_mut()
+                .find(|g| message_bus[g[0]].destination == message.destination)
+            {
+                Some(group) => group.push(position),
+                None => groups.push(vec![position]),
+            }
+        }
+
+        for group in groups.into_iter().filter(|g| g.len() > 1) {
+            let alternatives = (1..=group.len()).product();
+            let choice = self.choose(alternatives);
+            let messages: Vec<Message> = group.iter().map(|p| message_bus[*p].clone()).collect();
+            for (position, index) in group.iter().zip(nth_permutation(group.len(), choice)) {
+                message_bus[*position] = messages[index].clone();
+            }
+        }
+    }
+
+    fn choose(&mut self, alternatives: usize) -> usize {
+        let choice = self.prefix.get(self.choices.len()).copied().unwrap_or(0);
+        self.choices.push((choice, alternatives));
+        choice
+    }
+
+    /// The prefix of the next interleaving to run, in depth-first order.
+    fn next_prefix(&self) -> Option<Vec<usize>> {
+        let last = self
+            .choices
+            .iter()
+            .rposition(|(chosen, alternatives)| chosen + 1 < *alternatives)?;
+        let mut prefix: Vec<usize> = self.choices[..last].iter().map(|(c, _)| *c).collect();
+        prefix.push(self.choices[last].0 + 1);
+        Some(prefix)
+    }
+}
+
+/// Returns the nth permutation of 0..len in lexicographic order.
+fn nth_permutation(len: usize, mut n: usize) -> Vec<usize> {
+    let mut items: Vec<usize> = (0..len).collect();
+    let mut permutation = Vec::with_capacity(len);
+    for remaining in (1..=len).rev() {
+        let block: usize = (1..remaining).product();
+        permutation.push(items.remove(n / block));
+        n %= block;
+    }
+    permutation
+}
+
+/// Runs the Simulation under every distinct order of same-tick messages to
+/// each Agent, up to `max_runs` runs, and reports the distinct outcomes
+/// `observe` returns. Every run uses the parameters' seed, or 0 if unset, so
+/// only the order of messages differs between runs.
+///
+/// The number of interleavings grows factorially with the messages an Agent
+/// receives in a tick, so this is for small models.
+pub fn explore_interleavings<T, F>(
+    simulation_parameters: &SimulationParameters,
+    max_runs: usize,
+    observe: F,
+) -> ExplorationReport<T>
+where
+    T: PartialEq,
+    F: Fn(&Simulation) -> T,
+{
+    let mut report = ExplorationReport {
+        runs: 0,
+        exhaustive: false,
+        outcomes: vec![],
+    };
+    let mut prefix = Some(vec![]);
+
+    while let Some(next) = prefix {
+        if report.runs == max_runs {
+            return report;
+        }
+
+        let mut simulation = Simulation::new(SimulationParameters {
+            seed: Some(simulation_parameters.seed.unwrap_or_default()),
+            ..simulation_parameters.clone()
+        });
+        simulation.interleaving = Some(Interleaving {
+            prefix: next,
+            choices: vec![],
+        });
+        simulation.run();
+        report.runs += 1;
+
+        let outcome = observe(&simulation);
+        if !report.outcomes.contains(&outcome) {
+            report.outcomes.push(outcome);
+        }
+
+        prefix = simulation.interleaving.and_then(|i| i.next_prefix());
+    }
+
+    report.exhaustive = true;
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn nth_permutation_test() {
+        let permutations: Vec<Vec<usize>> = (0..6).map(|n| nth_permutation(3, n)).collect();
+        assert_eq!(
+            permutations,
+            vec![
+                vec![0, 1, 2],
+                vec![0, 2, 1],
+                vec![1, 0, 2],
+                vec![1, 2, 0],
+                vec![2, 0, 1],
+                vec![2, 1, 0]
+            ]
+        );
+    }
+
+    #[test]
+    fn explore_interleavings_test() {
+        // Three producers race to the same consumer on the first tick.
+        let parameters = SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("a", 10, "consumer"),
+                periodic_producing_agent("b", 10, "consumer"),
+                periodic_producing_agent("c", 10, "other"),
+                periodic_consuming_agent("consumer", 1),
+                periodic_consuming_agent("other", 1),
+            ],
+            halt_check: |s: &Simulation| s.time == 3,
+            ..Default::default()
+        };
+
+        // Who is served first depends on the tie-breaking; how many are
+        // served doesn't. The message to "other" is never permuted.
+        let first_served = explore_interleavings(&parameters, 100, |s| {
+            s.consumed_for_agent("consumer").unwrap()[0].source.clone()
+        });
+        assert!(first_served.exhaustive);
+        assert_eq!(first_served.runs, 2);
+        assert!(!first_served.is_order_independent());
+
+        let served = explore_interleavings(&parameters, 100, |s| {
+            s.consumed_for_agent("consumer").unwrap().len()
+        });
+        assert!(served.is_order_independent());
+
+        let bounded = explore_interleavings(&parameters, 1, |s| s.time);
+        assert_eq!((bounded.runs, bounded.exhaustive), (1, false));
+    }
+}
@@ -0,0 +1,236 @@
+use crate::{csv, json, Activity, DiscreteTime, Message, Simulation};
+use std::fmt::Write as _;
+use std::ops::RangeBounds;
+use std::path::Path;
+
+impl Simulation {
+    /// Writes the collected metrics and message histories as CSV files to
+    /// `dir`, creating it if needed, for analysis in spreadsheets or pandas:
+    ///
+    /// * `summary.csv`: one row of summary statistics per Agent.
+    /// * `<agent>_queue_depth.csv`: the Agent's queue depth at every tick,
+    ///   if `enable_queue_depth_metrics` was set.
+    /// * `<agent>_consumed.csv` and `<agent>_produced.csv`: the Agent's
+    ///   message logs, one message per row.
+    ///
+    /// Characters of Agent ids that aren't alphanumeric, `-` or `_` are
+    /// written as `%` and the hex of their UTF-8 bytes in file names, like in
+    /// URLs, so no two Agents share a file: `a b` is `a%20b`.
+    pub fn export_csv<P: AsRef<Path>>(&self, dir: P) -> std::io::Result<()> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+
+        let queue_lengths = self.calc_queue_len_statistics();
+        let consumed = self.calc_consumed_len_statistics();
+        let produced = self.calc_produced_len_statistics();
+        let average_wait = self.calc_avg_wait_statistics();
+
+        let mut summary = "agent,queue_length,consumed,produced,average_wait\n".to_string();
+        for agent in self.agents.iter() {
+            let id = &agent.state().id;
+            let _ = writeln!(
+                summary,
+                "{},{},{},{},{}",
+                csv::field(id),
+                queue_lengths[id],
+                consumed[id],
+                produced[id],
+                average_wait
+                    .get(id)
+                    .map(|w| w.to_string())
+                    .unwrap_or_default()
+            );
+
+            let file_name = |suffix: &str| dir.join(format!("{}_{}.csv", file_id(id), suffix));
+
+            let timeline = self.queue_depth_timeline(id).unwrap_or_default();
+            if !timeline.is_empty() {
+                let mut csv = "time,queue_depth\n".to_string();
+                for (time, depth) in timeline {
+                    let _ = writeln!(csv, "{},{}", time, depth);
+                }
+                std::fs::write(file_name("queue_depth"), csv)?;
+            }
+
+            std::fs::write(file_name("consumed"), messages_csv(&agent.state().consumed))?;
+            std::fs::write(file_name("produced"), messages_csv(&agent.state().produced))?;
+        }
+
+        std::fs::write(dir.join("summary.csv"), summary)
+    }
+
+    /// Renders the messages the Agents sent within a window of ticks as a
+    /// Mermaid sequence diagram, to explain or debug a protocol between a
+    /// handful of Agents. Messages are in the order they were sent, with a
+    /// note at the start of every tick, and labelled with their interrupt,
+    /// correlation id and payload size, or "message" if none. `..` renders the whole run.
+    pub fn sequence_diagram<R>(&self, window: R) -> String
+    where
+        R: RangeBounds<DiscreteTime>,
+    {
+        let mut messages: Vec<&Message> = self
+            .agents
+            .iter()
+            .flat_map(|a| a.state().produced.iter())
+            .filter(|m| window.contains(&m.queued_time))
+            .collect();
+        messages.sort_by_key(|m| m.queued_time);
+
+        // The Agents in their order, then anyone else messages were between.
+        let mut participants: Vec<&str> = vec![];
+        let agents = self.agents.iter().map(|a| a.state().id.as_str());
+        let others = messages
+            .iter()
+            .flat_map(|m| [m.source.as_str(), m.destination.as_str()]);
+        for id in agents.chain(others) {
+            if !participants.contains(&id) {
+                participants.push(id);
+            }
+        }
+        let alias = |id: &str| participants.iter().position(|p| *p == id).unwrap_or(0);
+
+        let mut diagram = "sequenceDiagram\n".to_string();
+        for (n, id) in participants.iter().enumerate() {
+            let _ = writeln!(diagram, "    participant p{} as {}", n, mermaid_text(id));
+        }
+        let mut tick = None;
+        for message in messages {
+            if tick != Some(message.queued_time) && participants.len() > 1 {
+                tick = Some(message.queued_time);
+                let _ = writeln!(
+                    diagram,
+                    "    Note over p0,p{}: {}",
+                    participants.len() - 1,
+                    mermaid_text(&self.format_time(message.queued_time))
+                );
+            }
+
+            let mut label = vec![];
+            if let Some(interrupt) = &message.interrupt {
+                label.push(format!("{:?}", interrupt));
+            }
+            if let Some(id) = message.correlation_id {
+                label.push(format!("correlation {}", id));
+            }
+            if let Some(payload) = &message.custom_payload {
+                label.push(format!("{} bytes", payload.len()));
+            }
+            if label.is_empty() {
+                label.push("message".to_string());
+            }
+            let _ = writeln!(
+                diagram,
+                "    p{}->>p{}: {}",
+                alias(&message.source),
+                alias(&message.destination),
+                mermaid_text(&label.join(", "))
+            );
+        }
+        diagram
+    }
+
+    /// Renders the run in the Chrome trace event format, as JSON, to explore
+    /// long runs in the Perfetto UI or `chrome://tracing`. Every Agent has a
+    /// track, with a slice per span of its activity, if
+    /// `enable_activity_metrics` was set, and an instant event per message
+    /// it sent or consumed. Ticks last their time scale, or a microsecond
+    /// without one.
+    pub fn chrome_trace(&self) -> String {
+        let micros_per_tick = self
+            .time_scale()
+            .map_or(1, |s| s.tick.as_micros().max(1) as DiscreteTime);
+        let ts = |time: DiscreteTime| time * micros_per_tick;
+
+        let mut events = vec![
+            "{\"name\":\"process_name\",\"ph\":\"M\",\"pid\":0,\"args\":{\"name\":\"simulation\"}}"
+                .to_string(),
+        ];
+        for (tid, agent) in self.agents.iter().enumerate() {
+            let state = agent.state();
+            events.push(format!(
+                "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":0,\"tid\":{},\"args\":{{\"name\":{}}}}}",
+                tid,
+                json::string(&state.id)
+            ));
+
+            let spans = self.activity(&state.id).unwrap_or_default();
+            for span in spans.iter().filter(|s| s.activity != Activity::Idle) {
+                events.push(format!(
+                    "{{\"name\":\"{:?}\",\"ph\":\"X\",\"pid\":0,\"tid\":{},\"ts\":{},\"dur\":{}}}",
+                    span.activity,
+                    tid,
+                    ts(span.from),
+                    ts(span.until - span.from)
+                ));
+            }
+
+            let sent = state.produced.iter().map(|m| ("send", m.queued_time, m));
+            let consumed = state
+                .consumed
+                .iter()
+                .filter_map(|m| Some(("consume", m.completed_time?, m)));
+            for (name, time, message) in sent.chain(consumed) {
+                events.push(format!(
+                    "{{\"name\":\"{}\",\"ph\":\"i\",\"s\":\"t\",\"pid\":0,\"tid\":{},\"ts\":{},\"args\":{{\"source\":{},\"destination\":{},\"queued_time\":{}}}}}",
+                    name,
+                    tid,
+                    ts(time),
+                    json::string(&message.source),
+                    json::string(&message.destination),
+                    message.queued_time
+                ));
+            }
+        }
+
+        format!("{{\"traceEvents\":[{}]}}", events.join(","))
+    }
+}
+
+/// Escapes the characters Mermaid gives a meaning to in text.
+fn mermaid_text(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '#' => "#35;".to_string(),
+            ';' => "#59;".to_string(),
+            '\n' => " ".to_string(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+/// Encodes an Agent id for file names; see `Simulation::export_csv`.
+fn file_id(id: &str) -> String {
+    let mut encoded = String::new();
+    for c in id.chars() {
+        if c.is_alphanumeric() || c == '-' || c == '_' {
+            encoded.push(c);
+        } else {
+            for byte in c.encode_utf8(&mut [0; 4]).bytes() {
+                let _ = write!(encoded, "%{:02X}", byte);
+            }
+        }
+    }
+    encoded
+}
+
+/// Renders a message log as CSV, one message per row. Missing values are empty.
+fn messages_csv(messages: &[Message]) -> String {
+    let mut csv = "source,destination,queued_time,completed_time,correlation_id,hops\n".to_string();
+    for msg in messages {
+        let _ = writeln!(
+            csv,
+            "{},{},{},{},{},{}",
+            csv::field(&msg.source),
+            csv::field(&msg.destination),
+            msg.queued_time,
+            msg.completed_time
+                .map(|t| t.to_string())
+                .unwrap_or_default(),
+            msg.correlation_id
+                .map(|c| c.to_string())
+                .unwrap_or_default(),
+            msg.hops
+        );
+    }
+    csv
+}
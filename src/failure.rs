@@ -0,0 +1,271 @@
+//! Fallible Agents: Agents that implement `Agent::try_process` can fail to
+//! process a message, e.g. on a malformed payload, and the engine records
+//! every failure and handles it per the Simulation's `ErrorPolicy`.
+//!
+//! With `enable_panic_isolation`, a panic while processing is a failure too,
+//! rather than the end of the run and all the data it collected.
+
+use crate::{DiscreteTime, Message, Simulation};
+use std::any::Any;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+/// Why an Agent failed to process a message.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct AgentError {
+    pub message: String,
+}
+
+impl AgentError {
+    pub fn new<T>(message: T) -> AgentError
+    where
+        T: Into<String>,
+    {
+        AgentError {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for AgentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for AgentError {}
+
+impl From<&str> for AgentError {
+    fn from(message: &str) -> Self {
+        AgentError::new(message)
+    }
+}
+
+impl From<String> for AgentError {
+    fn from(message: String) -> Self {
+        AgentError::new(message)
+    }
+}
+
+/// A failure of an Agent, and when it happened.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct AgentFailure {
+    pub time: DiscreteTime,
+    pub agent: String,
+    pub error: AgentError,
+    /// Whether the Agent panicked, with the panic message as the error.
+    pub panicked: bool,
+}
+
+/// What the engine does when an Agent fails, besides recording it. The
+/// message it failed on is taken off its queue all the same. An Agent that
+/// panicked dies whatever the policy, as its state may be half updated.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub enum ErrorPolicy {
+    /// The Agent carries on with its next message.
+    #[default]
+    Record,
+    /// The Agent dies.
+    KillAgent,
+    /// The Simulation fails at the end of the tick, with
+    /// `HaltReason::AgentFailed`.
+    FailSimulation,
+}
+
+/// The result of processing a message, or the panic message if it panicked.
+pub(crate) type Processed = Result<Result<Option<Vec<Message>>, AgentError>, AgentError>;
+
+/// Processes a message with `process`, catching a panic if `isolate`.
+pub(crate) fn isolate<F>(isolate: bool, process: F) -> Processed
+where
+    F: FnOnce() -> Result<Option<Vec<Message>>, AgentError>,
+{
+    if !isolate {
+        return Ok(process());
+    }
+    panic::catch_unwind(AssertUnwindSafe(process))
+        .map_err(|panic| AgentError::new(format!("panicked: {}", panic_message(panic.as_ref()))))
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown"
+    }
+}
+
+impl Simulation {
+    /// Returns the failures of an Agent, in order. None if the Agent
+    /// doesn't exist.
+    pub fn agent_failures(&self, id: &str) -> Option<&[AgentFailure]> {
+        let handle = self.agent_handles.get(id)?;
+        Some(&self.agent_metadata[*handle].failures)
+    }
+
+    /// Returns the failures of every Agent, in order of time, then Agent.
+    pub fn failures(&self) -> Vec<&AgentFailure> {
+        let mut failures: Vec<_> = self
+            .agent_metadata
+            .iter()
+            .flat_map(|m| m.failures.iter())
+            .collect();
+        failures.sort_by_key(|f| f.time);
+        failures
+    }
+
+    /// Returns the first failure of this tick, if any.
+    pub(crate) fn failure_now(&self) -> Option<&AgentFailure> {
+        self.agent_metadata
+            .iter()
+            .filter_map(|m| m.failures.last())
+            .find(|f| f.time == self.time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use simul_macro::agent;
+
+    /// Fails on every message without a payload.
+    fn picky_agent() -> Box<dyn Agent> {
+        #[agent]
+        struct Picky {}
+
+        impl Agent for Picky {
+            fn process(&mut self, state: SimulationState, msg: &Message) -> Option<Vec<Message>> {
+                self.try_process(state, msg).unwrap_or_default()
+            }
+
+            fn try_process(
+                &mut self,
+                state: SimulationState,
+                msg: &Message,
+            ) -> Result<Option<Vec<Message>>, AgentError> {
+                if msg.custom_payload.is_none() {
+                    return Err(AgentError::new(format!("no payload at {}", state.time)));
+                }
+                self.state.consumed.push(Message {
+                    completed_time: Some(state.time),
+                    ..msg.clone()
+                });
+                Ok(None)
+            }
+        }
+
+        let mut queue = std::collections::VecDeque::new();
+        for payload in [Some(vec![1]), None, Some(vec![2]), None, Some(vec![3])] {
+            queue.push_back(Message {
+                custom_payload: payload,
+                ..Message::new(0, "", "picky")
+            });
+        }
+        Box::new(Picky {
+            state: AgentState {
+                mode: AgentMode::Reactive,
+                wake_mode: AgentMode::Reactive,
+                id: "picky".to_string(),
+                queue,
+                ..Default::default()
+            },
+        })
+    }
+
+    fn run(error_policy: ErrorPolicy) -> Simulation {
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![picky_agent()],
+            error_policy,
+            halt_check: |s: &Simulation| s.time == 10,
+            ..Default::default()
+        });
+        simulation.run();
+        simulation
+    }
+
+    #[test]
+    fn error_policy_test() {
+        let simulation = run(ErrorPolicy::Record);
+        assert_eq!(simulation.consumed_count("picky"), Some(3));
+        let failures = simulation.agent_failures("picky").unwrap();
+        assert_eq!(failures.len(), 2);
+        assert_eq!(failures[0].error.to_string(), "no payload at 1");
+        assert_eq!(simulation.mode, SimulationMode::Completed);
+        assert!(simulation.message_ledger().is_balanced());
+
+        let simulation = run(ErrorPolicy::KillAgent);
+        assert_eq!(simulation.consumed_count("picky"), Some(1));
+        assert_eq!(simulation.queue_len("picky"), Some(3));
+        assert_eq!(simulation.failures().len(), 1);
+        assert!(simulation.message_ledger().is_balanced());
+
+        let simulation = run(ErrorPolicy::FailSimulation);
+        assert_eq!(simulation.mode, SimulationMode::Failed);
+        // It stopped at the end of the tick of the failure.
+        assert_eq!(simulation.time, 2);
+        assert_eq!(
+            simulation.halt_reason,
+            Some(HaltReason::AgentFailed(
+                "picky".to_string(),
+                AgentError::new("no payload at 1")
+            ))
+        );
+    }
+
+    /// Panics on its third message.
+    fn fragile_agent() -> Box<dyn Agent> {
+        #[agent]
+        struct Fragile {}
+
+        impl Agent for Fragile {
+            fn process(&mut self, state: SimulationState, msg: &Message) -> Option<Vec<Message>> {
+                self.state.consumed.push(Message {
+                    completed_time: Some(state.time),
+                    ..msg.clone()
+                });
+                if self.state.consumed.len() == 3 {
+                    panic!("boom at {}", state.time);
+                }
+                None
+            }
+        }
+
+        Box::new(Fragile {
+            state: AgentState {
+                mode: AgentMode::Reactive,
+                wake_mode: AgentMode::Reactive,
+                id: "fragile".to_string(),
+                ..Default::default()
+            },
+        })
+    }
+
+    #[test]
+    fn panic_isolation_test() {
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer", 1, "fragile"),
+                fragile_agent(),
+            ],
+            enable_panic_isolation: true,
+            halt_check: |s: &Simulation| s.time == 10,
+            ..Default::default()
+        });
+        simulation.run();
+
+        // The run carries on without the fragile Agent.
+        assert_eq!(simulation.mode, SimulationMode::Completed);
+        assert_eq!(simulation.produced_count("producer"), Some(10));
+        let failures = simulation.agent_failures("fragile").unwrap();
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0].panicked);
+        assert_eq!(failures[0].error.message, "panicked: boom at 3");
+        assert_eq!(
+            simulation.agent("fragile").unwrap().state().mode,
+            AgentMode::Dead
+        );
+        assert!(simulation.message_ledger().is_balanced());
+    }
+}
@@ -0,0 +1,334 @@
+//! Fork/join and scatter-gather: splitting one message into a child message
+//! per branch, and joining the replies to them into one.
+//!
+//! The children of a fork share a correlation id, and the engine keeps track
+//! of them: it holds back the replies to the children, i.e. the messages
+//! with their correlation id sent back to the Agent that forked them, until
+//! every branch replied, and then delivers them all at once to the join
+//! Agent. A join Agent thus always finds complete sets of replies on its
+//! queue. A branch whose child or reply is lost keeps its fork open.
+
+use crate::{
+    Agent, AgentMode, AgentState, DiscreteTime, Interrupt, Message, RequestHandle, Simulation,
+    SimulationState,
+};
+use simul_macro::agent;
+use std::collections::BTreeMap;
+
+/// A fork whose branches haven't all replied yet.
+#[derive(Clone, Debug)]
+pub(crate) struct OpenFork {
+    /// The Agent that forked, to which the branches reply.
+    forker: String,
+    /// The Agent the replies are delivered to once all are in.
+    join: String,
+    branches: usize,
+    forked_at: DiscreteTime,
+    replies: Vec<Message>,
+}
+
+/// A fork all of whose branches replied.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct CompletedFork {
+    pub correlation_id: u64,
+    pub join: String,
+    pub branches: usize,
+    pub forked_at: DiscreteTime,
+    /// When the last branch replied.
+    pub joined_at: DiscreteTime,
+}
+
+impl CompletedFork {
+    /// The ticks from the fork until the slowest branch replied.
+    pub fn latency(&self) -> DiscreteTime {
+        self.joined_at - self.forked_at
+    }
+}
+
+/// What the engine does with a message, given the open forks.
+pub(crate) enum Gathered {
+    /// The message isn't a reply to an open fork.
+    Pass(Message),
+    /// The message is a reply, held until its siblings are in.
+    Held,
+    /// The message was the last reply; deliver them all to the join Agent.
+    Joined(String, Vec<Message>),
+}
+
+impl Message {
+    /// Forks a message from src into a child per target, all sharing a fresh
+    /// correlation id, which the RequestHandle matches. The engine delivers
+    /// the replies to the children to `join` once every target replied.
+    pub fn fork<S>(
+        time: DiscreteTime,
+        src: S,
+        targets: &[String],
+        join: S,
+        payload: Option<Vec<u8>>,
+    ) -> (Vec<Message>, RequestHandle)
+    where
+        S: Into<String>,
+    {
+        let src = src.into();
+        let (parent, handle) = Message::request(time, src.as_str(), src.as_str(), payload);
+        let fork = Interrupt::Fork {
+            join: join.into(),
+            branches: targets.len(),
+        };
+
+        let children = targets
+            .iter()
+            .map(|target| Message {
+                destination: target.clone(),
+                interrupt: Some(fork.clone()),
+                ..parent.clone()
+            })
+            .collect();
+        (children, handle)
+    }
+}
+
+/// Returns an Agent that forks each message it receives to all of targets,
+/// and has their replies joined at `join`, e.g. a `join_agent`.
+pub fn scatter_agent<T>(id: T, targets: Vec<String>, join: T) -> Box<dyn Agent>
+where
+    T: Into<String>,
+{
+    #[agent]
+    struct Scatter {
+        targets: Vec<String>,
+        join: String,
+    }
+
+    impl Agent for Scatter {
+        fn process(
+            &mut self,
+            simulation_state: SimulationState,
+            msg: &Message,
+        ) -> Option<Vec<Message>> {
+            let incoming = std::iter::once(msg.clone()).chain(self.state.queue.drain(..));
+            let mut children = vec![];
+            for msg in incoming.collect::<Vec<_>>() {
+                let (forked, _) = Message::fork(
+                    simulation_state.time,
+                    self.state.id.as_str(),
+                    &self.targets,
+                    self.join.as_str(),
+                    msg.custom_payload,
+                );
+                children.extend(forked);
+            }
+            Some(children)
+        }
+    }
+
+    Box::new(Scatter {
+        targets,
+        join: join.into(),
+        state: AgentState {
+            mode: AgentMode::Reactive,
+            wake_mode: AgentMode::Reactive,
+            id: id.into(),
+            ..Default::default()
+        },
+    })
+}
+
+/// Returns an Agent that joins the replies to every fork into one message to
+/// target, with the payload `aggregate` makes of them, in the order they
+/// replied, and the fork's correlation id.
+pub fn join_agent<T>(
+    id: T,
+    target: T,
+    aggregate: fn(&[Message]) -> Option<Vec<u8>>,
+) -> Box<dyn Agent>
+where
+    T: Into<String>,
+{
+    #[agent]
+    struct Join {
+        target: String,
+        aggregate: fn(&[Message]) -> Option<Vec<u8>>,
+    }
+
+    impl Agent for Join {
+        fn process(
+            &mut self,
+            simulation_state: SimulationState,
+            msg: &Message,
+        ) -> Option<Vec<Message>> {
+            let time = simulation_state.time;
+            let incoming = std::iter::once(msg.clone()).chain(self.state.queue.drain(..));
+
+            // The engine delivers the replies of a fork together, in order.
+            let mut forks: BTreeMap<Option<u64>, Vec<Message>> = BTreeMap::new();
+            for msg in incoming.collect::<Vec<_>>() {
+                self.state.consumed.push(Message {
+                    completed_time: Some(time),
+                    ..msg.clone()
+                });
+                forks.entry(msg.correlation_id).or_default().push(msg);
+            }
+
+            let joined = forks
+                .into_iter()
+                .map(|(correlation_id, replies)| Message {
+                    custom_payload: (self.aggregate)(&replies),
+                    correlation_id,
+                    ..Message::new(time, self.state.id.as_str(), self.target.as_str())
+                })
+                .collect();
+            Some(joined)
+        }
+    }
+
+    Box::new(Join {
+        target: target.into(),
+        aggregate,
+        state: AgentState {
+            mode: AgentMode::Reactive,
+            wake_mode: AgentMode::Reactive,
+            id: id.into(),
+            ..Default::default()
+        },
+    })
+}
+
+impl Simulation {
+    /// Returns every fork all of whose branches replied, in order.
+    pub fn completed_forks(&self) -> &[CompletedFork] {
+        &self.completed_forks
+    }
+
+    /// The number of forks still waiting on replies.
+    pub fn open_forks(&self) -> usize {
+        self.open_forks.len()
+    }
+
+    /// The replies held until their forks are complete.
+    pub(crate) fn held_replies(&self) -> usize {
+        self.open_forks.values().map(|f| f.replies.len()).sum()
+    }
+
+    /// Opens the fork of a child message, if it is one, which is delivered
+    /// like any other message from then on.
+    pub(crate) fn open_fork(&mut self, message: &mut Message) {
+        let Some(Interrupt::Fork { join, branches }) = &message.interrupt else {
+            return;
+        };
+        let Some(correlation_id) = message.correlation_id else {
+            return;
+        };
+        let (join, branches) = (join.clone(), *branches);
+        message.interrupt = None;
+
+        self.open_forks
+            .entry(correlation_id)
+            .or_insert_with(|| OpenFork {
+                forker: message.source.clone(),
+                join,
+                branches,
+                forked_at: message.queued_time,
+                replies: vec![],
+            });
+    }
+
+    /// Holds a reply to an open fork until its siblings are in.
+    pub(crate) fn gather(&mut self, message: Message) -> Gathered {
+        let fork = match message.correlation_id {
+            Some(id) => self.open_forks.get_mut(&id),
+            None => None,
+        };
+        let Some(fork) = fork.filter(|f| f.forker == message.destination) else {
+            return Gathered::Pass(message);
+        };
+
+        fork.replies.push(message);
+        if fork.replies.len() < fork.branches {
+            return Gathered::Held;
+        }
+
+        let correlation_id = fork.replies[0]
+            .correlation_id
+            .expect("Replies are correlated");
+        let fork = self
+            .open_forks
+            .remove(&correlation_id)
+            .expect("The fork is open");
+        self.completed_forks.push(CompletedFork {
+            correlation_id,
+            join: fork.join.clone(),
+            branches: fork.branches,
+            forked_at: fork.forked_at,
+            joined_at: self.time,
+        });
+
+        let replies = fork
+            .replies
+            .into_iter()
+            .map(|reply| Message {
+                destination: fork.join.clone(),
+                ..reply
+            })
+            .collect();
+        Gathered::Joined(fork.join, replies)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    /// The sum of the first byte of every reply.
+    fn sum(replies: &[Message]) -> Option<Vec<u8>> {
+        let sum = replies
+            .iter()
+            .filter_map(|r| r.custom_payload.as_ref()?.first())
+            .sum();
+        Some(vec![sum])
+    }
+
+    #[test]
+    fn fork_test() {
+        let targets = vec!["a".to_string(), "b".to_string()];
+        let (children, handle) = Message::fork(3, "forker", &targets, "join", Some(vec![1]));
+        assert_eq!(children.len(), 2);
+        assert!(children.iter().all(|c| handle.matches(c)));
+        assert_eq!(children[1].destination, "b");
+    }
+
+    #[test]
+    fn scatter_gather_test() {
+        // A fork arrives every 5 ticks, but the slow branch takes 10 ticks a
+        // request, so every join waits 5 ticks longer on it than the last.
+        let targets = vec!["fast".to_string(), "slow".to_string()];
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer", 5, "scatter"),
+                scatter_agent("scatter", targets, "join"),
+                serving_agent("fast", 1),
+                serving_agent("slow", 10),
+                join_agent("join", "sink", sum),
+                periodic_consuming_agent("sink", 1),
+            ],
+            halt_check: |s: &Simulation| s.time == 50,
+            ..Default::default()
+        });
+        simulation.run();
+
+        let latencies: Vec<_> = simulation
+            .completed_forks()
+            .iter()
+            .map(|f| f.latency())
+            .collect();
+        assert_eq!(latencies, [1, 6, 11, 16, 21]);
+        // The rest are still waiting on the slow branch.
+        assert_eq!(simulation.open_forks(), 5);
+
+        let joined = simulation.consumed_for_agent("join").unwrap();
+        assert_eq!(joined.len(), 2 * latencies.len());
+        assert_eq!(simulation.consumed_for_agent("sink").unwrap().len(), 5);
+        assert!(simulation.message_ledger().is_balanced());
+    }
+}
@@ -0,0 +1,265 @@
+//! A 2D grid on which Agents have cells, for spatial models like epidemics,
+//! foraging or traffic, in which Agents interact with the ones near them.
+//!
+//! Agents see the grid as of the start of the tick in their SimulationState,
+//! e.g. to find their neighbors, and move by sending `Message::move_to`. The
+//! engine applies the moves at the start of the next tick, in the order they
+//! were sent, so every Agent of a tick sees the same grid.
+
+use crate::{DiscreteTime, Interrupt, Message, Simulation};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Which cells around a cell are within a radius of it.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub enum Neighborhood {
+    /// The cells within the radius along both axes, i.e. the square around it.
+    #[default]
+    Moore,
+    /// The cells within the radius in steps along the axes, i.e. the diamond.
+    VonNeumann,
+}
+
+/// A grid of `width` by `height` cells, each holding any number of Agents.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Grid {
+    pub width: i64,
+    pub height: i64,
+    /// Whether the edges wrap around, making the grid a torus. Otherwise
+    /// moves off the grid stop at its edge.
+    pub torus: bool,
+    pub neighborhood: Neighborhood,
+    positions: BTreeMap<String, (i64, i64)>,
+    /// The Agents in every occupied cell, in the order they arrived.
+    cells: BTreeMap<(i64, i64), Vec<String>>,
+}
+
+impl Grid {
+    pub fn new(width: i64, height: i64) -> Grid {
+        Grid {
+            width,
+            height,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_torus(self) -> Grid {
+        Grid {
+            torus: true,
+            ..self
+        }
+    }
+
+    pub fn with_neighborhood(self, neighborhood: Neighborhood) -> Grid {
+        Grid {
+            neighborhood,
+            ..self
+        }
+    }
+
+    /// Places an Agent on a cell to begin with.
+    pub fn with_agent<T>(mut self, id: T, x: i64, y: i64) -> Grid
+    where
+        T: Into<String>,
+    {
+        self.move_to(&id.into(), x, y);
+        self
+    }
+
+    /// The cell of an Agent.
+    pub fn position(&self, id: &str) -> Option<(i64, i64)> {
+        self.positions.get(id).copied()
+    }
+
+    /// The Agents on a cell, in the order they arrived.
+    pub fn at(&self, x: i64, y: i64) -> &[String] {
+        self.cells.get(&(x, y)).map_or(&[], |ids| ids.as_slice())
+    }
+
+    /// The Agents on the grid, with their cells, in id order.
+    pub fn positions(&self) -> impl Iterator<Item = (&str, (i64, i64))> {
+        self.positions.iter().map(|(id, cell)| (id.as_str(), *cell))
+    }
+
+    /// The number of steps between two cells, per the neighborhood.
+    pub fn distance(&self, a: (i64, i64), b: (i64, i64)) -> i64 {
+        let axis = |a: i64, b: i64, size: i64| {
+            let d = (a - b).abs();
+            if self.torus {
+                d.min(size - d)
+            } else {
+                d
+            }
+        };
+        let (dx, dy) = (axis(a.0, b.0, self.width), axis(a.1, b.1, self.height));
+        match self.neighborhood {
+            Neighborhood::Moore => dx.max(dy),
+            Neighborhood::VonNeumann => dx + dy,
+        }
+    }
+
+    /// The other Agents within `radius` of an Agent, nearest cells first,
+    /// then by cell and arrival. Empty if the Agent isn't on the grid.
+    pub fn neighbors(&self, id: &str, radius: i64) -> Vec<&str> {
+        let Some(center) = self.position(id) else {
+            return vec![];
+        };
+
+        let mut cells = vec![];
+        for dx in -radius..=radius {
+            for dy in -radius..=radius {
+                let cell = self.wrap(center.0 + dx, center.1 + dy);
+                if cell == center && (dx, dy) != (0, 0) {
+                    continue;
+                }
+                let distance = self.distance(center, cell);
+                if distance <= radius && self.cells.contains_key(&cell) {
+                    cells.push((distance, cell));
+                }
+            }
+        }
+        cells.sort();
+        cells.dedup();
+
+        cells
+            .iter()
+            .flat_map(|(_, cell)| self.cells[cell].iter())
+            .map(|n| n.as_str())
+            .filter(|n| *n != id)
+            .collect()
+    }
+
+    /// The cell a move to (x, y) ends up on, wrapped or clamped to the grid.
+    fn wrap(&self, x: i64, y: i64) -> (i64, i64) {
+        let (w, h) = (self.width.max(1), self.height.max(1));
+        if self.torus {
+            (x.rem_euclid(w), y.rem_euclid(h))
+        } else {
+            (x.clamp(0, w - 1), y.clamp(0, h - 1))
+        }
+    }
+
+    /// Moves an Agent to a cell, placing it if it isn't on the grid.
+    fn move_to(&mut self, id: &str, x: i64, y: i64) {
+        let cell = self.wrap(x, y);
+        if let Some(previous) = self.positions.insert(id.to_string(), cell) {
+            let ids = self.cells.get_mut(&previous).expect("The Agent was on it");
+            ids.retain(|i| i != id);
+            if ids.is_empty() {
+                self.cells.remove(&previous);
+            }
+        }
+        self.cells.entry(cell).or_default().push(id.to_string());
+    }
+}
+
+impl Message {
+    /// Creates a message that moves src to the cell (x, y) of the grid.
+    pub fn move_to<S>(time: DiscreteTime, src: S, x: i64, y: i64) -> Message
+    where
+        S: Into<String>,
+    {
+        let src = src.into();
+        Message {
+            interrupt: Some(Interrupt::MoveTo { x, y }),
+            ..Message::new(time, src.as_str(), src.as_str())
+        }
+    }
+}
+
+impl Simulation {
+    /// Returns the grid, as of now.
+    pub fn grid(&self) -> Option<&Grid> {
+        self.grid.as_deref()
+    }
+
+    /// Applies the moves Agents sent in the previous tick.
+    pub(crate) fn apply_grid_moves(&mut self) {
+        let Some(grid) = self.grid.as_mut() else {
+            self.grid_moves.clear();
+            return;
+        };
+        let grid = Arc::make_mut(grid);
+        for (id, x, y) in self.grid_moves.drain(..) {
+            grid.move_to(&id, x, y);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use simul_macro::agent;
+
+    #[test]
+    fn neighbors_test() {
+        let grid = Grid::new(10, 10)
+            .with_agent("a", 0, 0)
+            .with_agent("b", 1, 1)
+            .with_agent("c", 9, 9)
+            .with_agent("d", 0, 2);
+        assert_eq!(grid.neighbors("a", 1), ["b"]);
+        assert_eq!(grid.neighbors("a", 2), ["b", "d"]);
+
+        let torus = grid.clone().with_torus();
+        assert_eq!(torus.neighbors("a", 1), ["b", "c"]);
+
+        let diamond = grid.with_neighborhood(Neighborhood::VonNeumann);
+        assert_eq!(diamond.neighbors("a", 2), ["d", "b"]);
+        assert_eq!(diamond.distance((0, 0), (1, 1)), 2);
+    }
+
+    /// Walks one cell right per tick, and counts the ticks it had neighbors.
+    fn walker(id: &str) -> Box<dyn Agent> {
+        #[agent]
+        struct Walker {}
+
+        impl Agent for Walker {
+            fn process(&mut self, state: SimulationState, _: &Message) -> Option<Vec<Message>> {
+                let grid = state.grid.as_ref()?;
+                let id = self.state.id.as_str();
+                if !grid.neighbors(id, 0).is_empty() {
+                    self.state.consumed.push(Message {
+                        completed_time: Some(state.time),
+                        ..Message::new(state.time, id, id)
+                    });
+                }
+                let (x, y) = grid.position(id)?;
+                Some(vec![Message::move_to(state.time, id, x + 1, y)])
+            }
+        }
+
+        Box::new(Walker {
+            state: AgentState {
+                mode: AgentMode::Proactive,
+                wake_mode: AgentMode::Proactive,
+                id: id.to_string(),
+                ..Default::default()
+            },
+        })
+    }
+
+    #[test]
+    fn grid_movement_test() {
+        // The walker catches up with the one stuck at the edge, and then
+        // stays on its cell.
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![walker("walker"), walker("stuck")],
+            grid: Some(
+                Grid::new(5, 1)
+                    .with_agent("walker", 0, 0)
+                    .with_agent("stuck", 4, 0),
+            ),
+            halt_check: |s: &Simulation| s.time == 10,
+            ..Default::default()
+        });
+        simulation.run();
+
+        let grid = simulation.grid().unwrap();
+        assert_eq!(grid.position("walker"), Some((4, 0)));
+        assert_eq!(grid.at(4, 0), ["stuck", "walker"]);
+        assert_eq!(simulation.consumed_count("walker"), Some(6));
+        assert!(simulation.message_ledger().is_balanced());
+    }
+}
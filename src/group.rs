@@ -0,0 +1,329 @@
+//! Groups of Agents, like a population of workers, addressed as one.
+//!
+//! Groups registered with the Simulation, in `SimulationParameters::groups`,
+//! can be messaged by name with `Message::to_group`, and have their
+//! statistics rolled up, e.g. in `calc_group_queue_len_statistics`.
+
+use crate::{Agent, ConsumerPool, DiscreteTime, Interrupt, Message, Simulation};
+use rand::Rng;
+use std::collections::HashMap;
+
+/// A handle to a group of Agents, by id.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct AgentGroup {
+    /// The name of the group, which `agent_pool` also prefixes the ids of
+    /// the members with.
+    pub prefix: String,
+    ids: Vec<String>,
+}
+
+/// Which members of a group get a message sent to it.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub enum GroupDelivery {
+    /// Every member gets a copy.
+    #[default]
+    All,
+    /// A member picked at random.
+    Any,
+    /// The members in turn, one message each.
+    RoundRobin,
+}
+
+impl AgentGroup {
+    /// A group of related Agents with the given ids, e.g. the staff of a cafe.
+    pub fn new<T>(name: T, ids: &[&str]) -> AgentGroup
+    where
+        T: Into<String>,
+    {
+        AgentGroup {
+            prefix: name.into(),
+            ids: ids.iter().map(|id| id.to_string()).collect(),
+        }
+    }
+
+    /// The id of the nth member of a group with the given prefix.
+    pub fn member_id(prefix: &str, n: usize) -> String {
+        format!("{}-{}", prefix, n)
+    }
+
+    /// The ids of the members, in order.
+    pub fn ids(&self) -> &[String] {
+        &self.ids
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.ids.iter().any(|i| i == id)
+    }
+
+    /// Returns a pool of the members sharing a queue; see `ConsumerPool`.
+    pub fn consumer_pool<T>(&self, name: T) -> ConsumerPool
+    where
+        T: Into<String>,
+    {
+        ConsumerPool::new(name, self.ids.clone())
+    }
+}
+
+/// Makes n Agents with the initializer, which gets the id and index of each,
+/// e.g. "worker-0" and 0 for the prefix "worker". Returns the Agents, and the
+/// group to address them by, e.g. as the targets of a router.
+pub fn agent_pool<T, F>(
+    name_prefix: T,
+    n: usize,
+    mut initializer: F,
+) -> (Vec<Box<dyn Agent>>, AgentGroup)
+where
+    T: Into<String>,
+    F: FnMut(String, usize) -> Box<dyn Agent>,
+{
+    let prefix = name_prefix.into();
+    let ids: Vec<String> = (0..n).map(|i| AgentGroup::member_id(&prefix, i)).collect();
+    let agents = ids
+        .iter()
+        .enumerate()
+        .map(|(i, id)| {
+            let mut agent = initializer(id.clone(), i);
+            agent.state_mut().id = id.clone();
+            agent
+        })
+        .collect();
+
+    (agents, AgentGroup { prefix, ids })
+}
+
+impl Message {
+    /// Creates a message from src to the members of a registered group, per
+    /// the delivery. The engine sends a copy to each member that gets it in
+    /// place of this message, so an unknown group means no message at all.
+    pub fn to_group<S>(
+        time: DiscreteTime,
+        src: S,
+        group: S,
+        delivery: GroupDelivery,
+        payload: Option<Vec<u8>>,
+    ) -> Message
+    where
+        S: Into<String>,
+    {
+        let src = src.into();
+        Message {
+            custom_payload: payload,
+            interrupt: Some(Interrupt::ToGroup {
+                group: group.into(),
+                delivery,
+            }),
+            ..Message::new(time, src.as_str(), src.as_str())
+        }
+    }
+}
+
+impl Simulation {
+    /// The registered groups, in order.
+    pub fn groups(&self) -> &[AgentGroup] {
+        &self.groups
+    }
+
+    /// The registered group with the name.
+    pub fn group(&self, name: &str) -> Option<&AgentGroup> {
+        self.groups.iter().find(|g| g.prefix == name)
+    }
+
+    /// The total length of the queues of the members of each group, by name.
+    pub fn calc_group_queue_len_statistics(&self) -> HashMap<String, usize> {
+        self.groups
+            .iter()
+            .map(|g| (g.prefix.clone(), self.group_queue_len(g)))
+            .collect()
+    }
+
+    /// The total messages the members of each group consumed, by name.
+    pub fn calc_group_consumed_len_statistics(&self) -> HashMap<String, usize> {
+        let consumed = self.calc_consumed_len_statistics();
+        self.groups
+            .iter()
+            .map(|g| {
+                let total = g.ids().iter().filter_map(|id| consumed.get(id)).sum();
+                (g.prefix.clone(), total)
+            })
+            .collect()
+    }
+
+    /// The average waiting time of the messages the members of each group
+    /// consumed, by name, like `calc_avg_wait_statistics`. Groups whose
+    /// members consumed nothing are left out.
+    pub fn calc_group_avg_wait_statistics(&self) -> HashMap<String, usize> {
+        let mut data = HashMap::new();
+        for group in &self.groups {
+            let waits: Vec<u64> = group
+                .ids()
+                .iter()
+                .filter_map(|id| self.consumed_for_agent(id))
+                .flatten()
+                .filter(|m| self.is_after_warm_up(m.queued_time))
+                .map(|m| m.completed_time.unwrap() - m.queued_time)
+                .collect();
+
+            if !waits.is_empty() {
+                data.insert(
+                    group.prefix.clone(),
+                    waits.iter().sum::<u64>() as usize / waits.len(),
+                );
+            }
+        }
+        data
+    }
+
+    /// Returns the copies of a message to the members of its group that get
+    /// it. None if it isn't a message to a group.
+    pub(crate) fn expand_group(&mut self, message: &Message) -> Option<Vec<Message>> {
+        let Some(Interrupt::ToGroup { group, delivery }) = &message.interrupt else {
+            return None;
+        };
+        let Some(ids) = self.group(group).map(|g| g.ids().to_vec()) else {
+            return Some(vec![]);
+        };
+
+        let destinations = match (delivery, ids.len()) {
+            (_, 0) => vec![],
+            (GroupDelivery::All, _) => ids,
+            (GroupDelivery::Any, n) => vec![ids[self.rng.gen_range(0..n)].clone()],
+            (GroupDelivery::RoundRobin, n) => {
+                let turn = self.group_turns.entry(group.clone()).or_default();
+                let destination = ids[*turn % n].clone();
+                *turn += 1;
+                vec![destination]
+            }
+        };
+
+        let copies = destinations
+            .into_iter()
+            .map(|destination| Message {
+                destination,
+                interrupt: None,
+                ..message.clone()
+            })
+            .collect();
+        Some(copies)
+    }
+
+    /// The total length of the queues of the members of a group.
+    pub fn group_queue_len(&self, group: &AgentGroup) -> usize {
+        group.ids().iter().filter_map(|id| self.queue_len(id)).sum()
+    }
+
+    /// The total messages the members of a group consumed.
+    pub fn group_consumed_count(&self, group: &AgentGroup) -> usize {
+        group
+            .ids()
+            .iter()
+            .filter_map(|id| self.consumed_count(id))
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use simul_macro::agent;
+
+    #[test]
+    fn agent_pool_test() {
+        let (mut agents, group) = agent_pool("worker", 3, |id, i| {
+            periodic_consuming_agent(id, i as DiscreteTime + 1)
+        });
+        assert_eq!(group.ids(), &["worker-0", "worker-1", "worker-2"]);
+        assert!(group.contains("worker-2"));
+
+        agents.push(round_robin_router("router", group.ids().to_vec()));
+        agents.push(periodic_producing_agent("producer", 1, "router"));
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents,
+            halt_check: |s: &Simulation| s.time == 30,
+            ..Default::default()
+        });
+        simulation.run();
+
+        let produced = simulation.produced_count("producer").unwrap();
+        assert_eq!(
+            simulation.group_consumed_count(&group) + simulation.group_queue_len(&group),
+            produced - 1
+        );
+    }
+
+    /// Sends a message to the staff every tick, per the delivery.
+    fn boss(delivery: GroupDelivery) -> Box<dyn Agent> {
+        #[agent]
+        struct Boss {
+            delivery: GroupDelivery,
+        }
+
+        impl Agent for Boss {
+            fn process(&mut self, state: SimulationState, _: &Message) -> Option<Vec<Message>> {
+                Some(vec![Message::to_group(
+                    state.time,
+                    "boss",
+                    "staff",
+                    self.delivery,
+                    None,
+                )])
+            }
+        }
+
+        Box::new(Boss {
+            delivery,
+            state: AgentState {
+                mode: AgentMode::Proactive,
+                wake_mode: AgentMode::Proactive,
+                id: "boss".to_string(),
+                ..Default::default()
+            },
+        })
+    }
+
+    fn staff_consumed(delivery: GroupDelivery) -> (Vec<usize>, Simulation) {
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                boss(delivery),
+                periodic_consuming_agent("barista", 1),
+                periodic_consuming_agent("cashier", 1),
+            ],
+            groups: vec![AgentGroup::new("staff", &["barista", "cashier"])],
+            halt_check: |s: &Simulation| s.time == 11,
+            ..Default::default()
+        });
+        simulation.run();
+        assert!(simulation.message_ledger().is_balanced());
+
+        let consumed = ["barista", "cashier"]
+            .iter()
+            .map(|id| simulation.consumed_count(id).unwrap())
+            .collect();
+        (consumed, simulation)
+    }
+
+    #[test]
+    fn group_delivery_test() {
+        let (consumed, simulation) = staff_consumed(GroupDelivery::All);
+        assert_eq!(consumed, [10, 10]);
+        assert_eq!(simulation.calc_group_consumed_len_statistics()["staff"], 20);
+        // Each got the message of the last tick, too.
+        assert_eq!(simulation.calc_group_queue_len_statistics()["staff"], 2);
+        assert_eq!(simulation.calc_group_avg_wait_statistics()["staff"], 1);
+
+        let (consumed, _) = staff_consumed(GroupDelivery::RoundRobin);
+        assert_eq!(consumed, [5, 5]);
+
+        let (consumed, _) = staff_consumed(GroupDelivery::Any);
+        assert_eq!(consumed.iter().sum::<usize>(), 10);
+        assert!(consumed.iter().all(|c| *c > 0));
+    }
+}
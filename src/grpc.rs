@@ -0,0 +1,344 @@
+//! Serves a gRPC service that controls and inspects a running Simulation, so
+//! simulations can be embedded in services and driven remotely. Requires the
+//! `grpc` feature.
+//!
+//! The service is written by hand, so building it needs no `protoc`. Its
+//! definition, for generating clients, is:
+//!
+//! ```text
+//! syntax = "proto3";
+//! package simul;
+//!
+//! service Control {
+//!   // Plays the run, tick after tick.
+//!   rpc Start(Empty) returns (Empty);
+//!   // Stops playing the run until the next command.
+//!   rpc Pause(Empty) returns (Empty);
+//!   // Runs the given number of ticks, then pauses.
+//!   rpc Step(StepRequest) returns (Empty);
+//!   // Returns the run as of the end of its last tick.
+//!   rpc Inspect(Empty) returns (Snapshot);
+//! }
+//!
+//! message Empty {}
+//! message StepRequest { uint64 ticks = 1; }
+//! message Snapshot {
+//!   uint64 time = 1;
+//!   string mode = 2;
+//!   map<string, uint64> queue_lengths = 3;
+//!   map<string, uint64> consumed = 4;
+//!   map<string, uint64> produced = 5;
+//! }
+//! ```
+//!
+//! Commands return once the run has them, not once it has carried them out,
+//! and fail with `UNAVAILABLE` once the run is over. `Inspect` fails with
+//! `UNAVAILABLE` until the first tick has run.
+
+use crate::controller::{ControlCommand, Controller};
+use crate::report::{Cadence, Report, ReportSink};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tonic::body::BoxBody;
+use tonic::codec::ProstCodec;
+use tonic::codegen::{http, Body, BoxFuture, Service, StdError};
+use tonic::server::NamedService;
+use tonic::Status;
+
+/// The `Empty` message of the service.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Empty {}
+
+/// The `StepRequest` message of the service.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct StepRequest {
+    #[prost(uint64, tag = "1")]
+    pub ticks: u64,
+}
+
+/// The `Snapshot` message of the service: a Report, in part.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Snapshot {
+    #[prost(uint64, tag = "1")]
+    pub time: u64,
+    /// The SimulationMode, e.g. `Running`.
+    #[prost(string, tag = "2")]
+    pub mode: String,
+    #[prost(map = "string, uint64", tag = "3")]
+    pub queue_lengths: HashMap<String, u64>,
+    #[prost(map = "string, uint64", tag = "4")]
+    pub consumed: HashMap<String, u64>,
+    #[prost(map = "string, uint64", tag = "5")]
+    pub produced: HashMap<String, u64>,
+}
+
+impl From<&Report> for Snapshot {
+    fn from(report: &Report) -> Snapshot {
+        let counts = |map: &HashMap<String, usize>| {
+            map.iter()
+                .map(|(id, count)| (id.clone(), *count as u64))
+                .collect()
+        };
+        Snapshot {
+            time: report.time,
+            mode: format!("{:?}", report.mode),
+            queue_lengths: counts(&report.queue_lengths),
+            consumed: counts(&report.consumed),
+            produced: counts(&report.produced),
+        }
+    }
+}
+
+/// A ReportSink that serves the `simul.Control` service on its address; see
+/// the module docs. It sends commands to `Simulation::run_controlled` with
+/// its Controller, and answers `Inspect` with its latest report.
+#[derive(Clone, Debug)]
+pub struct GrpcServer {
+    address: SocketAddr,
+    report: Arc<Mutex<Option<Report>>>,
+}
+
+impl GrpcServer {
+    /// Binds the address and serves from a background thread until the
+    /// process exits. Bind port 0 to pick any free port.
+    pub fn bind<A: ToSocketAddrs>(address: A, controller: Controller) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        let server = GrpcServer {
+            address: listener.local_addr()?,
+            report: Arc::new(Mutex::new(None)),
+        };
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let service = ControlService {
+            controller: Arc::new(Mutex::new(controller)),
+            report: server.report.clone(),
+        };
+        std::thread::spawn(move || {
+            runtime.block_on(async move {
+                let incoming = tokio::net::TcpListener::from_std(listener).and_then(|l| {
+                    tonic::transport::server::TcpIncoming::from_listener(l, true, None)
+                        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+                });
+                let served = match incoming {
+                    Ok(incoming) => tonic::transport::Server::builder()
+                        .add_service(service)
+                        .serve_with_incoming(incoming)
+                        .await
+                        .map_err(|e| e.to_string()),
+                    Err(e) => Err(e.to_string()),
+                };
+                if let Err(e) = served {
+                    log::warn!("Stopped serving gRPC: {}", e);
+                }
+            })
+        });
+
+        Ok(server)
+    }
+
+    /// The address the service is served on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+impl ReportSink for GrpcServer {
+    fn cadence(&self) -> Cadence {
+        Cadence::EveryTick
+    }
+
+    fn write(&mut self, report: &Report) -> std::io::Result<()> {
+        let mut latest = self
+            .report
+            .lock()
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "poisoned report"))?;
+        *latest = Some(report.clone());
+        Ok(())
+    }
+}
+
+/// The `simul.Control` service, routing its calls by path.
+#[derive(Clone, Debug)]
+struct ControlService {
+    controller: Arc<Mutex<Controller>>,
+    report: Arc<Mutex<Option<Report>>>,
+}
+
+impl ControlService {
+    fn send(&self, command: ControlCommand) -> Result<Empty, Status> {
+        let sent = self.controller.lock().map_or(false, |c| c.send(command));
+        if !sent {
+            return Err(Status::unavailable("the run is over"));
+        }
+        Ok(Empty {})
+    }
+
+    fn inspect(&self) -> Result<Snapshot, Status> {
+        let report = self.report.lock().ok().and_then(|r| r.clone());
+        let report = report.ok_or_else(|| Status::unavailable("no tick has run yet"))?;
+        Ok(Snapshot::from(&report))
+    }
+}
+
+impl NamedService for ControlService {
+    const NAME: &'static str = "simul.Control";
+}
+
+impl<B> Service<http::Request<B>> for ControlService
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let service = self.clone();
+        match request.uri().path() {
+            "/simul.Control/Start" => {
+                unary(request, move |_: Empty| service.send(ControlCommand::Play))
+            }
+            "/simul.Control/Pause" => {
+                unary(request, move |_: Empty| service.send(ControlCommand::Pause))
+            }
+            "/simul.Control/Step" => unary(request, move |step: StepRequest| {
+                service.send(ControlCommand::Step(step.ticks))
+            }),
+            "/simul.Control/Inspect" => unary(request, move |_: Empty| service.inspect()),
+            path => {
+                let status = Status::unimplemented(format!("no method {}", path));
+                Box::pin(async move { Ok(status.to_http()) })
+            }
+        }
+    }
+}
+
+/// Answers a unary call with the handler.
+fn unary<B, Req, Res, F>(
+    request: http::Request<B>,
+    handler: F,
+) -> BoxFuture<http::Response<BoxBody>, Infallible>
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+    Req: prost::Message + Default + Send + 'static,
+    Res: prost::Message + Send + 'static,
+    F: FnMut(Req) -> Result<Res, Status> + Send + 'static,
+{
+    Box::pin(async move {
+        let mut grpc = tonic::server::Grpc::new(ProstCodec::<Res, Req>::default());
+        Ok(grpc.unary(Handler(handler), request).await)
+    })
+}
+
+/// A handler of unary calls, as the tower Service tonic calls.
+struct Handler<F>(F);
+
+impl<F, Req, Res> Service<tonic::Request<Req>> for Handler<F>
+where
+    F: FnMut(Req) -> Result<Res, Status>,
+{
+    type Response = tonic::Response<Res>;
+    type Error = Status;
+    type Future = std::future::Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: tonic::Request<Req>) -> Self::Future {
+        std::future::ready((self.0)(request.into_inner()).map(tonic::Response::new))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use tonic::codegen::http::uri::PathAndQuery;
+
+    /// Calls a method of the service at the address.
+    fn call<Req, Res>(
+        address: SocketAddr,
+        method: &'static str,
+        request: Req,
+    ) -> Result<Res, Status>
+    where
+        Req: prost::Message + Send + Sync + 'static,
+        Res: prost::Message + Default + Send + Sync + 'static,
+    {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async move {
+            let channel = tonic::transport::Endpoint::from_shared(format!("http://{}", address))
+                .unwrap()
+                .connect()
+                .await
+                .unwrap();
+            let mut client = tonic::client::Grpc::new(channel);
+            client.ready().await.unwrap();
+            let path = PathAndQuery::from_static(method);
+            let codec = ProstCodec::<Req, Res>::default();
+            let response = client
+                .unary(tonic::Request::new(request), path, codec)
+                .await?;
+            Ok(response.into_inner())
+        })
+    }
+
+    #[test]
+    fn grpc_server_test() {
+        let (controller, commands) = Controller::new();
+        let server = GrpcServer::bind("127.0.0.1:0", controller).unwrap();
+        let address = server.local_addr();
+
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer", 1, "consumer"),
+                periodic_consuming_agent("consumer", 1),
+            ],
+            report_sinks: vec![Box::new(server)],
+            halt_check: |s: &Simulation| s.time == 5,
+            ..Default::default()
+        });
+
+        // Driven from another thread, as a remote client would.
+        let client = std::thread::spawn(move || {
+            let inspect = || call::<Empty, Snapshot>(address, "/simul.Control/Inspect", Empty {});
+            assert_eq!(inspect().unwrap_err().code(), tonic::Code::Unavailable);
+
+            call::<_, Empty>(address, "/simul.Control/Step", StepRequest { ticks: 2 }).unwrap();
+            while inspect().map_or(true, |s| s.time < 1) {
+                std::thread::yield_now();
+            }
+            let snapshot = inspect().unwrap();
+            assert_eq!(snapshot.time, 1);
+            assert_eq!(snapshot.produced["producer"], 2);
+
+            call::<_, Empty>(address, "/simul.Control/Start", Empty {}).unwrap();
+            while inspect().unwrap().mode != "Completed" {
+                std::thread::yield_now();
+            }
+            let unknown = call::<_, Empty>(address, "/simul.Control/Stop", Empty {});
+            assert_eq!(unknown.unwrap_err().code(), tonic::Code::Unimplemented);
+        });
+        simulation.run_controlled(commands);
+        client.join().unwrap();
+
+        assert_eq!(simulation.time, 5);
+        assert_eq!(simulation.mode, SimulationMode::Completed);
+    }
+}
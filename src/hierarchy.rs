@@ -0,0 +1,181 @@
+//! Parent/child relationships between Agents, for organizations and nested
+//! components, e.g. a company of departments of employees.
+//!
+//! An Agent's parent is set when it is made, in `AgentOptions::parent`. When
+//! a parent dies, the engine kills its children, and theirs, at the start of
+//! the next tick, so a component never outlives what contains it.
+
+use crate::{Agent, AgentMode, Simulation};
+use std::collections::{HashMap, HashSet};
+
+/// The statistics of all the descendants of an Agent, rolled up.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct DescendantStats {
+    pub descendants: usize,
+    /// The descendants that aren't dead.
+    pub alive: usize,
+    pub consumed: usize,
+    pub produced: usize,
+    pub queued: usize,
+}
+
+/// Maps every parent to its children, in the order of the Agents.
+pub(crate) fn children_of(agents: &[Box<dyn Agent>]) -> HashMap<String, Vec<String>> {
+    let mut children: HashMap<String, Vec<String>> = HashMap::new();
+    for agent in agents {
+        if let Some(parent) = &agent.state().options.parent {
+            children
+                .entry(parent.clone())
+                .or_default()
+                .push(agent.state().id.clone());
+        }
+    }
+    children
+}
+
+impl Simulation {
+    /// The children of an Agent, in the order of the Agents.
+    pub fn children(&self, id: &str) -> &[String] {
+        self.children.get(id).map_or(&[], |c| c.as_slice())
+    }
+
+    /// The children of an Agent, their children, and so on, breadth first.
+    pub fn descendants(&self, id: &str) -> Vec<&str> {
+        let mut seen = HashSet::from([id]);
+        let mut descendants = vec![];
+        let mut next = 0;
+        let mut parent = id;
+        loop {
+            for child in self.children(parent) {
+                if seen.insert(child.as_str()) {
+                    descendants.push(child.as_str());
+                }
+            }
+            let Some(child) = descendants.get(next) else {
+                return descendants;
+            };
+            parent = child;
+            next += 1;
+        }
+    }
+
+    /// Rolls up the statistics of the descendants of an Agent. None if the
+    /// Agent doesn't exist.
+    pub fn descendant_stats(&self, id: &str) -> Option<DescendantStats> {
+        self.agent(id)?;
+        let mut stats = DescendantStats::default();
+        for state in self
+            .descendants(id)
+            .into_iter()
+            .filter_map(|d| self.agent(d))
+            .map(|a| a.state())
+        {
+            stats.descendants += 1;
+            stats.alive += (state.mode != AgentMode::Dead) as usize;
+            stats.consumed += state.consumed.len();
+            stats.produced += state.produced.len();
+            stats.queued += state.queue.len();
+        }
+        Some(stats)
+    }
+
+    /// Kills the descendants of every dead parent.
+    pub(crate) fn propagate_terminations(&mut self) {
+        let is_dead = |s: &Simulation, id: &str| {
+            s.agent(id)
+                .map_or(false, |a| a.state().mode == AgentMode::Dead)
+        };
+        let mut dead: Vec<String> = self
+            .children
+            .keys()
+            .filter(|parent| is_dead(self, parent))
+            .cloned()
+            .collect();
+
+        // Only the children killed now go on the stack, so cycles end.
+        while let Some(parent) = dead.pop() {
+            for child in self.children.get(&parent).into_iter().flatten() {
+                let Some(handle) = self.agent_handles.get(child) else {
+                    continue;
+                };
+                let state = self.agents[*handle].state_mut();
+                if state.mode != AgentMode::Dead {
+                    state.mode = AgentMode::Dead;
+                    dead.push(child.clone());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use simul_macro::agent;
+
+    fn child_of(mut agent: Box<dyn Agent>, parent: &str) -> Box<dyn Agent> {
+        agent.state_mut().options.parent = Some(parent.to_string());
+        agent
+    }
+
+    /// Closes down at tick 3.
+    fn department() -> Box<dyn Agent> {
+        #[agent]
+        struct Department {}
+
+        impl Agent for Department {
+            fn process(&mut self, state: SimulationState, _: &Message) -> Option<Vec<Message>> {
+                if state.time == 3 {
+                    self.state.mode = AgentMode::Dead;
+                }
+                None
+            }
+        }
+
+        Box::new(Department {
+            state: AgentState {
+                mode: AgentMode::Proactive,
+                wake_mode: AgentMode::Proactive,
+                id: "department".to_string(),
+                options: AgentOptions {
+                    parent: Some("company".to_string()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        })
+    }
+
+    #[test]
+    fn hierarchy_test() {
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_consuming_agent("company", 1),
+                department(),
+                child_of(periodic_consuming_agent("alice", 1), "department"),
+                child_of(periodic_consuming_agent("bob", 1), "department"),
+                child_of(periodic_consuming_agent("carol", 1), "company"),
+                periodic_producing_agent("customer", 1, "bob"),
+            ],
+            halt_check: |s: &Simulation| s.time == 10,
+            ..Default::default()
+        });
+        assert_eq!(simulation.children("company"), ["department", "carol"]);
+        assert_eq!(
+            simulation.descendants("company"),
+            ["department", "carol", "alice", "bob"]
+        );
+        simulation.run();
+
+        // The department's team closes down with it, at the start of tick 4.
+        let stats = simulation.descendant_stats("company").unwrap();
+        assert_eq!(stats.descendants, 4);
+        assert_eq!(stats.alive, 1);
+        let bob = simulation.agent("bob").unwrap().state();
+        assert_eq!(bob.mode, AgentMode::Dead);
+        assert_eq!(bob.consumed.len(), 3);
+        assert_eq!(stats.consumed, 3);
+        assert_eq!(stats.queued, bob.queue.len());
+        assert_eq!(simulation.descendant_stats("nobody"), None);
+    }
+}
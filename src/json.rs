@@ -0,0 +1,41 @@
+//! Writing JSON by hand, for the exports that render it without a JSON crate.
+
+use std::fmt::Write as _;
+
+/// Renders a string as a JSON string literal, quoted and escaped. Unlike
+/// `{:?}`, whose escapes like `\u{1b}` aren't JSON, this follows RFC 8259.
+pub(crate) fn string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c < ' ' => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn string_test() {
+        assert_eq!(string("consumer"), r#""consumer""#);
+        assert_eq!(string("a \"b\" \\ c"), r#""a \"b\" \\ c""#);
+        assert_eq!(
+            string("a\nb\tc\u{1b}d\u{7f}"),
+            "\"a\\nb\\tc\\u001bd\u{7f}\""
+        );
+        assert_eq!(string("café ☕"), "\"café ☕\"");
+    }
+}
@@ -0,0 +1,124 @@
+//! The engine's ledger of messages, which accounts for every message produced
+//! in a Simulation. A ledger that doesn't balance means messages were lost or
+//! made up by the engine, or by an Agent that edits its own queue, and that the
+//! statistics computed from them can't be trusted.
+
+use crate::Simulation;
+
+/// Where every message of a Simulation went, as of now.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct MessageLedger {
+    /// Messages queued on Agents before the Simulation started.
+    pub initially_queued: usize,
+    /// Messages Agents sent onto the message bus.
+    pub produced: usize,
+    /// Extra copies of messages made by channel faults.
+    pub duplicated: usize,
+    /// Messages dropped by channel loss or by a link that was down.
+    pub lost: usize,
+    /// Messages addressed to an Agent that doesn't exist.
+    pub unroutable: usize,
+    /// Messages the engine refused to deliver, e.g. because they exceeded their hops.
+    pub dead_lettered: usize,
+    /// Commands vetoed by the command middleware.
+    pub vetoed: usize,
+    /// Messages to resources, stores, containers, the grid, the space, the
+    /// cells, the blackboard and the engine's controls, handled by the engine.
+    pub to_resources: usize,
+    /// Messages delayed by their transforms and not yet delivered.
+    pub in_flight: usize,
+    /// Messages Agents scheduled to themselves, not yet due.
+    pub scheduled: usize,
+    /// Messages waiting in the shared queues of consumer pools.
+    pub pooled: usize,
+    /// Replies to forks held until all their branches replied.
+    pub joining: usize,
+    /// Messages delivered onto the queues of Agents.
+    pub delivered: usize,
+    /// Messages taken off the queues of Agents to be processed.
+    pub processed: usize,
+    /// Messages still waiting on the queues of Agents.
+    pub queued: usize,
+}
+
+impl MessageLedger {
+    /// Reconciles the ledger, returning a description of every way in which
+    /// it doesn't balance. Empty if every message is accounted for.
+    pub fn discrepancies(&self) -> Vec<String> {
+        let mut discrepancies = vec![];
+
+        let sent = self.produced + self.duplicated;
+        let routed = self.lost
+            + self.unroutable
+            + self.dead_lettered
+            + self.vetoed
+            + self.to_resources
+            + self.in_flight
+            + self.scheduled
+            + self.pooled
+            + self.joining
+            + self.delivered;
+        if sent != routed {
+            discrepancies.push(format!(
+                "{} messages were produced or duplicated, but {} were lost, unroutable, dead-lettered, vetoed, to resources, in flight, scheduled, pooled, joining or delivered",
+                sent, routed
+            ));
+        }
+
+        let received = self.initially_queued + self.delivered;
+        let handled = self.processed + self.queued;
+        if received != handled {
+            discrepancies.push(format!(
+                "{} messages were initially queued or delivered, but {} were processed or are still queued",
+                received, handled
+            ));
+        }
+
+        discrepancies
+    }
+
+    /// Whether every message is accounted for.
+    pub fn is_balanced(&self) -> bool {
+        self.discrepancies().is_empty()
+    }
+}
+
+impl Simulation {
+    /// Returns the ledger of every message in the Simulation, as of now.
+    pub fn message_ledger(&self) -> MessageLedger {
+        MessageLedger {
+            in_flight: self.in_flight.len(),
+            scheduled: self.scheduled_messages(),
+            pooled: self.pools.iter().map(|p| p.queue_len()).sum(),
+            joining: self.held_replies(),
+            processed: self.agent_metadata.iter().map(|m| m.processed).sum(),
+            queued: self.agents.iter().map(|a| a.state().queue.len()).sum(),
+            ..self.ledger.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn discrepancies_test() {
+        let ledger = MessageLedger {
+            produced: 10,
+            duplicated: 1,
+            lost: 2,
+            delivered: 9,
+            processed: 7,
+            queued: 2,
+            ..Default::default()
+        };
+        assert!(ledger.is_balanced());
+
+        let ledger = MessageLedger {
+            queued: 1,
+            ..ledger
+        };
+        assert_eq!(ledger.discrepancies().len(), 1);
+    }
+}
@@ -1,14 +1,126 @@
 extern crate self as simul;
+pub mod activity;
 pub mod agent;
+mod assertions;
+pub mod automaton;
+pub mod autoscale;
+pub mod blackboard;
+pub mod calendar;
+pub mod channel;
+pub mod chaos;
+pub mod command;
+#[cfg(feature = "config")]
+pub mod config;
+pub mod contract;
+pub mod control;
+pub mod controller;
+mod csv;
+pub mod dag;
+pub mod diagnostics;
 pub mod experiment;
+pub mod exploration;
+mod export;
+pub mod failure;
+pub mod fork;
+pub mod grid;
+pub mod group;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod hierarchy;
+mod json;
+pub mod ledger;
 pub mod message;
+pub mod middleware;
+pub mod module;
+pub mod network;
+pub mod observer;
+pub mod petri;
+pub mod pipeline;
+#[cfg(feature = "plot")]
+pub mod plot;
+pub mod pool;
+pub mod processes;
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
+pub mod proximity;
+pub mod quiescence;
+pub mod random;
+pub mod replay;
+pub mod report;
+pub mod resource;
+pub mod router;
+#[cfg(feature = "config")]
+pub mod scenario;
+pub mod series;
+pub mod shadow;
+pub mod space;
+mod spans;
+pub mod stats;
+pub mod store;
+pub mod tag;
+pub mod time_scale;
+pub mod timer;
+pub mod topology;
+pub mod trace;
+pub mod transform;
+pub mod validate;
+pub mod view;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+pub mod workload;
+pub mod world;
 
+pub use activity::{Activity, ActivitySpan};
 pub use agent::*;
+pub use automaton::{cell_agent, Cells};
+pub use autoscale::{Autoscaler, ScalingPolicy};
+pub use blackboard::{Blackboard, BlackboardValue};
+pub use calendar::{calendar_agent, Calendar, Recurrence, Weekday};
+pub use channel::*;
+pub use command::{CommandHandler, CustomCommand};
+pub use control::Alarm;
+pub use controller::{ControlCommand, Controller};
+pub use dag::{Workflow, WorkflowReport};
+pub use diagnostics::{Diagnostic, Severity};
+pub use failure::{AgentError, AgentFailure, ErrorPolicy};
+pub use fork::{join_agent, scatter_agent, CompletedFork};
+pub use grid::{Grid, Neighborhood};
+pub use group::{agent_pool, AgentGroup, GroupDelivery};
+pub use hierarchy::DescendantStats;
+pub use ledger::MessageLedger;
 pub use message::*;
+pub use middleware::CommandMiddleware;
+pub use observer::SimulationObserver;
+pub use pipeline::{stage_agent, Pipeline};
+pub use pool::ConsumerPool;
+pub use quiescence::QuiescencePolicy;
+pub use random::rng;
+pub use replay::*;
+pub use report::*;
+pub use resource::{Resource, ResourceStats};
+pub use router::*;
+pub use series::*;
+pub use shadow::*;
 pub use simul_macro;
+pub use space::{Body, Space};
+pub use stats::{Histogram, LittlesLaw, QueueStability, StreamingStats};
+pub use store::{Container, FlowStats, Store};
+pub use time_scale::TimeScale;
+pub use timer::TimerHandle;
+pub use topology::*;
+pub use trace::*;
+pub use transform::*;
+pub use view::{AgentView, AgentViews};
+pub use workload::*;
+pub use world::*;
 
-use log::{debug, info};
-use std::collections::HashMap;
+use exploration::Interleaving;
+use log::{debug, error, info, warn};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 /// DiscreteTime is a Simulation's internal representation of time.
 pub type DiscreteTime = u64;
@@ -20,18 +132,62 @@ pub enum SimulationMode {
     Constructed,
     /// The Simulation is actively simulating.
     Running,
+    /// The Simulation was paused by an Agent, and resumes on `run`.
+    Paused,
     /// The Simulation successfully reached the halt condition.
     Completed,
     /// The Simulation catastrophically crashed.
     Failed,
 }
 
+/// Why a Simulation stopped running.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum HaltReason {
+    /// The `halt_check` function returned true, or the run got to its
+    /// `ending_time`.
+    HaltCheck,
+    /// An Agent sent an `Interrupt::HaltSimulation` with the given reason.
+    Interrupt(String),
+    /// An Agent sent an `Interrupt::PauseSimulation` with the given reason.
+    Paused(String),
+    /// The Agent failed, with `ErrorPolicy::FailSimulation`.
+    AgentFailed(String, AgentError),
+    /// Nothing could happen anymore; see `QuiescencePolicy`.
+    Quiescent,
+    /// A safety limit of the run was reached before the `halt_check`.
+    LimitReached(Limit),
+}
+
+/// The safety limits of a run, which guard against a `halt_check` that
+/// never returns true.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Limit {
+    /// `SimulationParameters::max_ticks`.
+    MaxTicks,
+    /// `SimulationParameters::max_wall_clock`.
+    MaxWallClock,
+}
+
 /// State about the simulation that agents are aware of.
 /// TODO: This may later just become the `Simulation` itself passed about.
 #[derive(Clone, Debug)]
 pub struct SimulationState {
     pub time: DiscreteTime,
     pub mode: SimulationMode,
+    /// The environment variables as of this tick, after WorldDynamics updated them.
+    pub environment: Arc<Environment>,
+    /// The grid as of the start of this tick, if the Simulation has one.
+    pub grid: Option<Arc<Grid>>,
+    /// The space as of the start of this tick, if the Simulation has one.
+    pub space: Option<Arc<Space>>,
+    /// The states of the cells as of the end of the previous tick, if the
+    /// Simulation is a cellular automaton.
+    pub cells: Option<Arc<Cells>>,
+    /// The blackboard as of the end of the previous tick.
+    pub blackboard: Arc<Blackboard>,
+    /// The views of the Agents as of the start of this tick, if enabled; see
+    /// `peek_agent`.
+    pub agent_views: Option<Arc<AgentViews>>,
 }
 
 /// A Simulation struct is responsible to hold all the state for a simulation
@@ -47,17 +203,166 @@ pub struct Simulation {
     /// The agents within the simulation, e.g. adaptive agents.
     pub agents: Vec<Box<dyn Agent>>,
     /// A halt check function: given the state of the Simulation determine halt or not.
+    /// It runs every tick, so it should read what's cheap: `time`, `totals()`
+    /// and the per-Agent counts like `consumed_count()` are O(1), whereas the
+    /// `calc_*_statistics` and `*_for_agent` functions scan or copy.
     pub halt_check: fn(&Simulation) -> bool,
     /// The current discrete time of the Simulation.
     pub time: DiscreteTime,
+    /// The time the Simulation started at.
+    starting_time: DiscreteTime,
+    /// The time the Simulation ends at, if any, as if by the `halt_check`.
+    pub ending_time: Option<DiscreteTime>,
+    /// The safety limits of the run; see `Limit`.
+    pub max_ticks: Option<DiscreteTime>,
+    pub max_wall_clock: Option<Duration>,
+    /// What a tick stands for, to show human-readable times; see `time_scale`.
+    time_scale: Option<TimeScale>,
+    /// The ticks left to run before pausing, when run by `run_for`.
+    step_budget: Option<DiscreteTime>,
+    /// The end of the warm-up period. Statistics exclude what happened before
+    /// it, so steady-state estimates aren't biased by initial transients.
+    pub warm_up: Option<DiscreteTime>,
     /// Whether to record metrics on queue depths. Takes space.
     pub enable_queue_depth_metric: bool,
     /// Records a metric on the number of cycles an agent was asleep for.
     pub enable_agent_asleep_cycles_metric: bool,
+    /// Whether to keep streaming statistics of queue depths. Takes constant space.
+    pub enable_queue_depth_stats: bool,
+    /// Whether to record a run trace; see `trace_json`. Takes space.
+    pub enable_trace: bool,
+    /// Whether to record what every Agent did over time; see `activity`.
+    pub enable_activity_metrics: bool,
+    /// The window of the throughput metrics, if recorded; see `throughput`.
+    pub throughput_window: Option<DiscreteTime>,
+    /// The queue growth, in messages per tick, at which an Agent is unstable;
+    /// see `queue_stability`.
+    pub unstable_growth_rate: f64,
+    /// The arrival events of the run trace.
+    trace_arrivals: Vec<TraceEvent>,
     /// The mode of the Simulation.
     pub mode: SimulationMode,
+    /// Why the Simulation halted; None until the Simulation has completed.
+    pub halt_reason: Option<HaltReason>,
+    /// The shared environment variables, updated every tick by `world_dynamics`.
+    pub environment: Environment,
+    /// The background processes that update the environment every tick.
+    pub world_dynamics: Vec<Box<dyn WorldDynamics>>,
+    /// The shared resources Agents acquire and release; see `resource`.
+    resources: Vec<Resource>,
+    /// The stores and containers Agents put to and get from; see `store`.
+    stores: Vec<Store>,
+    containers: Vec<Container>,
+    /// The pools of consumers sharing a queue; see `pool`.
+    pools: Vec<ConsumerPool>,
+    /// The controllers that grow and shrink groups of workers; see `autoscale`.
+    autoscalers: Vec<Autoscaler>,
+    /// The groups messages can be sent to, and the turn of the next member
+    /// of each to get a round-robin message; see `group`.
+    groups: Vec<AgentGroup>,
+    group_turns: HashMap<String, usize>,
+    /// The forks waiting on replies by correlation id, and the completed
+    /// ones; see `fork`.
+    open_forks: HashMap<u64, fork::OpenFork>,
+    completed_forks: Vec<CompletedFork>,
+    /// The grid Agents have cells on, and the moves to apply to it at the
+    /// start of the next tick; see `grid`.
+    grid: Option<Arc<Grid>>,
+    grid_moves: Vec<(String, i64, i64)>,
+    /// The space Agents have bodies in, and the updates of the bodies to
+    /// apply at the start of the next tick; see `space`.
+    space: Option<Arc<Space>>,
+    space_steers: Vec<(String, space::Steer)>,
+    /// The states of the cells, and the next states sent this tick, to
+    /// apply at its end; see `automaton`.
+    cells: Option<Arc<Cells>>,
+    next_cells: Vec<(String, Vec<u8>)>,
+    /// The blackboard, and the writes sent this tick, to apply at its end;
+    /// see `blackboard`.
+    blackboard: Arc<Blackboard>,
+    blackboard_writes: Vec<(String, Option<BlackboardValue>)>,
+    /// Records every environment variable as a Series over time.
+    pub enable_environment_metrics: bool,
+    /// Maps from an environment variable name => its recorded Series.
+    environment_series: HashMap<String, Series>,
+    /// The faults of unreliable channels between Agents.
+    pub channel_model: ChannelModel,
+    /// Maps from (source, destination) => the faults that occurred on that channel.
+    channel_metrics: HashMap<(String, String), ChannelMetrics>,
+    /// The links between Agents that are currently up or down.
+    pub topology: Topology,
+    /// The middleware overheads applied to every message on every hop.
+    pub message_transforms: Vec<Box<dyn MessageTransform>>,
+    /// The middleware every command passes through; see `middleware`.
+    pub command_middleware: Vec<Box<dyn CommandMiddleware>>,
+    /// The handlers of custom commands; see `command`.
+    pub command_handlers: Vec<Box<dyn CommandHandler>>,
+    /// Messages delayed by the cost of their transforms.
+    in_flight: Vec<InFlightMessage>,
+    /// The hops a message without a `ttl` may take. None means unlimited.
+    pub default_ttl: Option<u32>,
+    /// The messages the engine refused to deliver, in order.
+    dead_letters: Vec<DeadLetter>,
+    /// The engine's counts of where messages went; see `message_ledger`.
+    ledger: MessageLedger,
+    /// Running totals over all Agents; see `totals`.
+    totals: Totals,
+    /// Overrides the order of same-tick messages; see `explore_interleavings`.
+    interleaving: Option<Interleaving>,
+    /// Every link change that happened while running, in order.
+    topology_events: Vec<LinkChange>,
+    /// The latest tick messages depart on each edge with a capacity, and how
+    /// many depart then.
+    edge_load: HashMap<(String, String), (DiscreteTime, usize)>,
+    /// The sinks that receive report snapshots at their cadence while running.
+    pub report_sinks: Vec<Box<dyn ReportSink>>,
+    /// The observers called around ticks at their cadence; see `observer`.
+    pub observers: Vec<Box<dyn SimulationObserver>>,
+    /// The pause asked for this tick, with its reason; see `control`.
+    pause_requested: Option<String>,
+    /// Whether a checkpoint was asked for this tick.
+    checkpoint_requested: bool,
+    checkpoints: Vec<Simulation>,
+    alarms: Vec<Alarm>,
+    /// The messages Agents scheduled to themselves, by the tick they're due;
+    /// see `timer`.
+    timers: BTreeMap<DiscreteTime, Vec<timer::Timer>>,
+    /// What happened since each sink's previous report, indexed like `report_sinks`.
+    report_windows: Vec<ReportWindow>,
+    /// What happened since each observer's previous `after_tick`, indexed
+    /// like `observers`.
+    observer_windows: Vec<ReportWindow>,
+    /// The seed all randomness in the Simulation derives from.
+    pub seed: u64,
+    /// The engine's own random stream, e.g. for channel faults.
+    rng: StdRng,
+    /// The counter `next_correlation_id` draws from while the Simulation runs.
+    correlation_ids: CorrelationIds,
+    /// Whether the Agents' random draws are mirrored; see `random::rng()`.
+    pub antithetic: bool,
+    /// Whether to process the Agents of a tick in parallel across all cores.
+    pub enable_parallel_agents: bool,
+    /// Whether Agents can peek at the others; see `view`.
+    pub enable_agent_views: bool,
+    /// What to do when an Agent fails; see `failure`.
+    pub error_policy: ErrorPolicy,
+    /// Whether an Agent's panic is a failure; see `failure`.
+    pub enable_panic_isolation: bool,
+    /// What to do once nothing can happen anymore, and since when; see
+    /// `quiescence`.
+    pub quiescence_policy: QuiescencePolicy,
+    quiescent_since: Option<DiscreteTime>,
+    agent_views: Option<Arc<AgentViews>>,
+    /// Agents running candidate logic on copies of another Agent's messages.
+    pub shadow_agents: Vec<ShadowAgent>,
+    /// The metadata of every shadow, indexed like `shadow_agents`.
+    shadow_metadata: Vec<ShadowMetadata>,
     /// Maps from agent.state().id => a handle for indexing the Agent in the vec.
-    agent_metadata_hash_table: HashMap<String, AgentMetadata>,
+    agent_handles: HashMap<String, usize>,
+    /// The children of every parent; see `hierarchy`.
+    children: HashMap<String, Vec<String>>,
+    /// The metadata of every Agent, indexed by the same handle as `agents`.
+    agent_metadata: Vec<AgentMetadata>,
 }
 
 /// The parameters to create a Simulation.
@@ -71,10 +376,118 @@ pub struct SimulationParameters {
     /// The discrete time at which the simulation should begin.
     /// For the vast majority of simulations, 0 is the correct default.
     pub starting_time: DiscreteTime,
+    /// The discrete time at which the simulation ends, halting as if the
+    /// `halt_check` returned true. For runs of a fixed length, which the
+    /// `halt_check` can't be told as it captures nothing. None only halts by
+    /// the `halt_check`.
+    pub ending_time: Option<DiscreteTime>,
+    /// Halts the run after this many ticks from the starting time, whatever
+    /// the `halt_check`, with `HaltReason::LimitReached`.
+    pub max_ticks: Option<DiscreteTime>,
+    /// Halts the run once it took this long in real time, whatever the
+    /// `halt_check`, with `HaltReason::LimitReached`.
+    pub max_wall_clock: Option<Duration>,
+    /// The simulated duration of a tick, and the timestamp of tick 0, with
+    /// which logs, plots and reports show times; see `TimeScale`.
+    pub time_scale: Option<TimeScale>,
+    /// The end of the warm-up period, whose data statistics exclude. See
+    /// `Simulation::detect_warm_up` to find it automatically.
+    pub warm_up: Option<DiscreteTime>,
     /// Whether to record metrics on queue depths at every tick of the simulation.
     pub enable_queue_depth_metrics: bool,
     /// Records a metric on the number of cycles an agent was asleep for.
     pub enable_agent_asleep_cycles_metric: bool,
+    /// Keeps the count, mean, variance, min and max of every Agent's queue
+    /// depth online, in constant space, unlike `enable_queue_depth_metrics`.
+    pub enable_queue_depth_stats: bool,
+    /// Records a run trace of message arrivals and services, for
+    /// cross-validating against other simulators. See `Simulation::trace_json`.
+    pub enable_trace: bool,
+    /// Records the spans of time every Agent spent processing, idle, asleep or
+    /// dead. Takes space proportional to the number of changes of activity.
+    pub enable_activity_metrics: bool,
+    /// Records the number of messages every Agent consumed per window of
+    /// this many ticks, e.g. to spot throughput degrading over a run.
+    pub throughput_window: Option<DiscreteTime>,
+    /// The queue growth, in messages per tick, at or above which an Agent is
+    /// flagged unstable. Defaults to `stats::UNSTABLE_GROWTH_RATE`.
+    pub unstable_growth_rate: f64,
+    /// The initial values of the shared environment variables.
+    pub environment: Environment,
+    /// The background processes that update the environment every tick.
+    pub world_dynamics: Vec<Box<dyn WorldDynamics>>,
+    /// The shared resources Agents acquire and release; see `resource`.
+    pub resources: Vec<Resource>,
+    /// The stores and containers Agents put to and get from; see `store`.
+    pub stores: Vec<Store>,
+    pub containers: Vec<Container>,
+    /// The pools of consumers sharing a queue; see `pool`.
+    pub pools: Vec<ConsumerPool>,
+    /// The controllers that grow and shrink groups of workers; see `autoscale`.
+    pub autoscalers: Vec<Autoscaler>,
+    /// The named groups of Agents; see `group`.
+    pub groups: Vec<AgentGroup>,
+    /// The grid Agents have cells on, if any; see `grid`.
+    pub grid: Option<Grid>,
+    /// The continuous space Agents have bodies in, if any; see `space`.
+    pub space: Option<Space>,
+    /// The states of the cells, if the Simulation is a cellular automaton;
+    /// see `automaton`.
+    pub cells: Option<Cells>,
+    /// The values on the blackboard to begin with; see `blackboard`.
+    pub blackboard: Blackboard,
+    /// Records every environment variable as a linearly interpolated Series.
+    pub enable_environment_metrics: bool,
+    /// The faults of unreliable channels between Agents. Reliable by default.
+    pub channel_model: ChannelModel,
+    /// The links between Agents, and how they change over time. Fully connected by default.
+    pub topology: Topology,
+    /// The middleware overheads, e.g. encryption, applied to every message on
+    /// every hop. Each delays the message by its cost in ticks.
+    pub message_transforms: Vec<Box<dyn MessageTransform>>,
+    /// The middleware every command, i.e. message with an interrupt, passes
+    /// through before the engine acts on it, in order. Each may change the
+    /// command, or veto it; see `CommandMiddleware`.
+    pub command_middleware: Vec<Box<dyn CommandMiddleware>>,
+    /// The handlers of `Interrupt::Custom` commands, by the name of the
+    /// commands they handle; see `CommandHandler`.
+    pub command_handlers: Vec<Box<dyn CommandHandler>>,
+    /// The hops a message without a `ttl` may take before it is dead-lettered,
+    /// which catches routing loops. None means unlimited.
+    pub default_ttl: Option<u32>,
+    /// The sinks that receive report snapshots at their cadence and on completion.
+    pub report_sinks: Vec<Box<dyn ReportSink>>,
+    /// The observers called before every tick, after ticks at their cadence,
+    /// and on every delivery; see `SimulationObserver`.
+    pub observers: Vec<Box<dyn SimulationObserver>>,
+    /// The seed all randomness in the Simulation derives from, making runs
+    /// reproducible. None picks a random seed, recorded in `Simulation::seed`.
+    pub seed: Option<u64>,
+    /// Mirrors every random draw of the Agents, for an antithetic replication
+    /// of the run with the same seed. See `experiment::evaluate_antithetic`.
+    pub antithetic: bool,
+    /// Whether to process the Agents of a tick in parallel across all cores.
+    /// Agents only see the messages of previous ticks, so this gives the same
+    /// results as processing them in order; it pays off with many Agents.
+    /// Agents that aren't `Send` (see `AgentCommon::as_send`) are processed on
+    /// the thread running the Simulation. Only which of a tick's requests gets
+    /// which correlation id can differ between parallel runs.
+    pub enable_parallel_agents: bool,
+    /// Snapshots every Agent at the start of each tick, for the others to
+    /// peek at with `SimulationState::peek_agent`.
+    pub enable_agent_views: bool,
+    /// What to do when an Agent fails to process a message, besides
+    /// recording it; see `failure`.
+    pub error_policy: ErrorPolicy,
+    /// Catches the panics of Agents, recording them as failures and killing
+    /// the Agents, rather than aborting the run; see `failure`.
+    pub enable_panic_isolation: bool,
+    /// What to do once nothing can happen anymore, e.g. on a deadlock; see
+    /// `quiescence`.
+    pub quiescence_policy: QuiescencePolicy,
+    /// Agents that receive copies of another Agent's messages, and whose
+    /// produced messages are recorded but never delivered. See `ShadowAgent`.
+    pub shadow_agents: Vec<ShadowAgent>,
 }
 
 impl Default for SimulationParameters {
@@ -83,41 +496,526 @@ impl Default for SimulationParameters {
             agents: vec![],
             halt_check: |_| true,
             starting_time: 0,
+            ending_time: None,
+            max_ticks: None,
+            max_wall_clock: None,
+            time_scale: None,
+            warm_up: None,
             enable_queue_depth_metrics: false,
             enable_agent_asleep_cycles_metric: false,
+            enable_queue_depth_stats: false,
+            enable_trace: false,
+            enable_activity_metrics: false,
+            throughput_window: None,
+            unstable_growth_rate: stats::UNSTABLE_GROWTH_RATE,
+            environment: Environment::new(),
+            world_dynamics: vec![],
+            resources: vec![],
+            stores: vec![],
+            containers: vec![],
+            pools: vec![],
+            autoscalers: vec![],
+            groups: vec![],
+            grid: None,
+            space: None,
+            cells: None,
+            blackboard: Blackboard::default(),
+            enable_environment_metrics: false,
+            channel_model: ChannelModel::default(),
+            topology: Topology::default(),
+            message_transforms: vec![],
+            command_middleware: vec![],
+            command_handlers: vec![],
+            default_ttl: None,
+            report_sinks: vec![],
+            observers: vec![],
+            seed: None,
+            antithetic: false,
+            enable_parallel_agents: false,
+            enable_agent_views: false,
+            error_policy: ErrorPolicy::default(),
+            enable_panic_isolation: false,
+            quiescence_policy: QuiescencePolicy::default(),
+            shadow_agents: vec![],
+        }
+    }
+}
+
+/// Running totals over all Agents of a Simulation, kept up to date as
+/// messages are queued, consumed and produced, so they can be read in O(1),
+/// e.g. from `halt_check`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Totals {
+    /// The number of messages Agents consumed.
+    pub consumed: usize,
+    /// The number of messages Agents produced.
+    pub produced: usize,
+    /// The number of messages on the queues of Agents.
+    pub queued: usize,
+}
+
+/// The metrics a Simulation can record, each behind an `enable_*` option,
+/// which can be toggled while it runs; see `Simulation::set_metric`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Metric {
+    /// `enable_queue_depth_metrics`.
+    QueueDepth,
+    /// `enable_queue_depth_stats`.
+    QueueDepthStats,
+    /// `enable_agent_asleep_cycles_metric`.
+    AgentAsleepCycles,
+    /// `enable_environment_metrics`.
+    Environment,
+    /// `enable_trace`.
+    Trace,
+    /// `enable_activity_metrics`.
+    Activity,
+}
+
+/// The Simulation's options that affect how a single Agent is processed.
+#[derive(Clone, Copy, Debug)]
+struct StepOptions {
+    enable_queue_depth_metric: bool,
+    enable_agent_asleep_cycles_metric: bool,
+    enable_queue_depth_stats: bool,
+    enable_trace: bool,
+    enable_activity_metrics: bool,
+    throughput_window: Option<DiscreteTime>,
+    warm_up: Option<DiscreteTime>,
+    antithetic: bool,
+    error_policy: ErrorPolicy,
+    enable_panic_isolation: bool,
+}
+
+/// Processes one Agent for a tick, returning the messages it produced.
+/// What an Agent did in a step: the messages it produced, and how many
+/// messages it took off its queue and consumed.
+struct Step {
+    produced: Vec<Message>,
+    dequeued: usize,
+    consumed: usize,
+}
+
+impl Totals {
+    /// Counts what an Agent did in a step.
+    fn record(&mut self, step: &Step) {
+        self.queued = self.queued.saturating_sub(step.dequeued);
+        self.consumed += step.consumed;
+    }
+}
+
+fn step_agent(
+    agent: &mut dyn Agent,
+    metadata: &mut AgentMetadata,
+    simulation_state: &SimulationState,
+    tick_message: &Message,
+    options: StepOptions,
+) -> Step {
+    let span = spans::agent(&agent.state().id, simulation_state.time);
+    let _agent = span.enter();
+    if options.enable_queue_depth_metric {
+        metadata.record_queue_depth(simulation_state.time, agent.state().queue.len());
+    }
+
+    if options.enable_queue_depth_stats
+        && options.warm_up.map_or(true, |w| simulation_state.time >= w)
+    {
+        metadata
+            .queue_depth_stats
+            .push(agent.state().queue.len() as f64);
+    }
+
+    let mode = agent.state().mode;
+    // Agents may also drain their queue themselves while processing.
+    let queue_len = agent.state().queue.len();
+    let consumed_len = agent.state().consumed.len();
+
+    let (served, produced) = match agent.state().options.concurrency {
+        0 | 1 => {
+            let (served, produced) =
+                serve(agent, metadata, simulation_state, tick_message, options);
+            (usize::from(served), produced)
+        }
+        concurrency => serve_concurrently(
+            agent,
+            metadata,
+            simulation_state,
+            tick_message,
+            options,
+            concurrency,
+        ),
+    };
+    let dequeued = queue_len.saturating_sub(agent.state().queue.len());
+    let consumed = agent.state().consumed.len().saturating_sub(consumed_len);
+    metadata.processed += dequeued;
+
+    if options.enable_activity_metrics {
+        let activity = Activity::of(mode, served > 0);
+        activity::record_activity(&mut metadata.activity, simulation_state.time, activity);
+    }
+
+    if let (AgentMode::AsleepUntil(_), true) = (mode, options.enable_agent_asleep_cycles_metric) {
+        metadata.asleep_cycle_count += 1
+    }
+
+    if let Some(window) = options.throughput_window {
+        let from = simulation_state.time - simulation_state.time % window.max(1);
+        if metadata.throughput.last().map_or(true, |(f, _)| *f != from) {
+            metadata.throughput.push((from, 0));
+        }
+        if let Some((_, window_consumed)) = metadata.throughput.last_mut() {
+            *window_consumed += consumed;
+        }
+    }
+
+    Step {
+        produced,
+        dequeued,
+        consumed,
+    }
+}
+
+/// Takes the next message off an Agent's queue, if it is active, and processes
+/// it. Returns whether a message was taken, and the messages produced.
+fn serve(
+    agent: &mut dyn Agent,
+    metadata: &mut AgentMetadata,
+    simulation_state: &SimulationState,
+    tick_message: &Message,
+    options: StepOptions,
+) -> (bool, Vec<Message>) {
+    // Sleeping and dead Agents leave their queue untouched, so the messages
+    // they receive are processed once they wake up.
+    let queued_msg = match agent.state().mode {
+        AgentMode::Proactive | AgentMode::Reactive => agent.state_mut().queue.pop_front(),
+        AgentMode::AsleepUntil(_) | AgentMode::Dead => None,
+    };
+
+    if let (true, Some(msg)) = (options.enable_trace, &queued_msg) {
+        metadata.trace.push(TraceEvent::new(
+            simulation_state.time,
+            TraceEventKind::ServiceStart,
+            &agent.state().id,
+            msg,
+            Some(agent.state().queue.len()),
+        ));
+    }
+
+    let isolate = options.enable_panic_isolation;
+    let processed = match agent.state().mode {
+        AgentMode::Proactive => random::with_rng(&mut metadata.rng, options.antithetic, || {
+            failure::isolate(isolate, || {
+                agent.try_process(
+                    simulation_state.clone(),
+                    queued_msg.as_ref().unwrap_or(tick_message),
+                )
+            })
+        }),
+        AgentMode::Reactive => match &queued_msg {
+            Some(msg) => random::with_rng(&mut metadata.rng, options.antithetic, || {
+                failure::isolate(isolate, || agent.try_process(simulation_state.clone(), msg))
+            }),
+            None => Ok(Ok(None)),
+        },
+        AgentMode::AsleepUntil(_) | AgentMode::Dead => Ok(Ok(None)),
+    };
+
+    let time = simulation_state.time;
+    let produced = match processed {
+        Ok(Ok(produced)) => produced.unwrap_or_default(),
+        Ok(Err(error)) => {
+            record_failure(agent, metadata, time, error, false, options);
+            vec![]
+        }
+        Err(panic) => {
+            record_failure(agent, metadata, time, panic, true, options);
+            vec![]
+        }
+    };
+
+    (queued_msg.is_some(), produced)
+}
+
+/// Records an Agent's failure, and kills it if it panicked or per the policy.
+fn record_failure(
+    agent: &mut dyn Agent,
+    metadata: &mut AgentMetadata,
+    time: DiscreteTime,
+    error: AgentError,
+    panicked: bool,
+    options: StepOptions,
+) {
+    warn!("{} failed: {}", agent.state().id, error);
+    metadata.failures.push(AgentFailure {
+        time,
+        agent: agent.state().id.clone(),
+        error,
+        panicked,
+    });
+    if panicked || options.error_policy == ErrorPolicy::KillAgent {
+        agent.state_mut().mode = AgentMode::Dead;
+    }
+}
+
+/// Serves an Agent with several slots, i.e. identical servers: every slot
+/// that is free takes the next message. When the Agent puts itself to sleep
+/// after processing a message, only that slot is busy until it wakes up, and
+/// the Agent sleeps once all of its slots are busy. Returns the number of
+/// messages taken, and the messages produced.
+fn serve_concurrently(
+    agent: &mut dyn Agent,
+    metadata: &mut AgentMetadata,
+    simulation_state: &SimulationState,
+    tick_message: &Message,
+    options: StepOptions,
+    concurrency: usize,
+) -> (usize, Vec<Message>) {
+    let time = simulation_state.time;
+    metadata.slots.resize(concurrency, Slot::default());
+
+    let mut served = 0;
+    let mut produced = vec![];
+    for slot in 0..concurrency {
+        let mode = agent.state().mode;
+        let idle = agent.state().queue.is_empty();
+        // Proactive Agents process the tick once if there's nothing queued.
+        if metadata.slots[slot].busy_until > time
+            || (idle && (mode == AgentMode::Reactive || served > 0))
+        {
+            continue;
+        }
+        if !matches!(mode, AgentMode::Proactive | AgentMode::Reactive) {
+            break;
+        }
+
+        let (taken, messages) = serve(agent, metadata, simulation_state, tick_message, options);
+        served += usize::from(taken);
+        produced.extend(messages);
+
+        let busy_until = match agent.state().mode {
+            AgentMode::AsleepUntil(until) => {
+                agent.state_mut().mode = mode;
+                until.max(time + 1)
+            }
+            _ => time + 1,
+        };
+        metadata.slots[slot].busy_ticks += busy_until - time;
+        metadata.slots[slot].busy_until = busy_until;
+    }
+
+    let free_at = metadata.slots.iter().map(|s| s.busy_until).min();
+    if let (Some(free_at), true) = (
+        free_at,
+        matches!(
+            agent.state().mode,
+            AgentMode::Proactive | AgentMode::Reactive
+        ),
+    ) {
+        if free_at > time + 1 {
+            agent.state_mut().mode = AgentMode::AsleepUntil(free_at);
         }
     }
+
+    (served, produced)
+}
+
+/// A server of an Agent with `AgentOptions::concurrency` above 1.
+#[derive(Clone, Copy, Debug, Default)]
+struct Slot {
+    /// The first tick at which the slot is free.
+    busy_until: DiscreteTime,
+    /// The ticks the slot was busy, including those after the run.
+    busy_ticks: DiscreteTime,
 }
 
 #[derive(Clone, Debug)]
 struct AgentMetadata {
     queue_depth_metrics: Vec<usize>,
+    /// The (time, index into `queue_depth_metrics`) at which every contiguous
+    /// run of queue depths starts, as recording can be toggled mid-run.
+    queue_depth_segments: Vec<(DiscreteTime, usize)>,
     asleep_cycle_count: DiscreteTime,
+    queue_depth_stats: StreamingStats,
+    /// The service start events of the run trace.
+    trace: Vec<TraceEvent>,
+    /// What the Agent did over time.
+    activity: Vec<ActivitySpan>,
+    /// The (window start, messages consumed) of every throughput window.
+    throughput: Vec<(DiscreteTime, usize)>,
+    /// The slots of an Agent that processes messages concurrently.
+    slots: Vec<Slot>,
+    /// The Agent's own random stream; see `random::rng()`.
+    rng: StdRng,
+    /// The number of messages taken off the Agent's queue.
+    processed: usize,
+    /// The length of the Agent's queue when the Simulation was constructed.
+    initial_queue_len: usize,
+    /// The Agent's failures; see `failure`.
+    failures: Vec<AgentFailure>,
+}
+
+impl AgentMetadata {
+    fn new(rng_seed: u64) -> Self {
+        AgentMetadata {
+            queue_depth_metrics: vec![],
+            queue_depth_segments: vec![],
+            asleep_cycle_count: 0,
+            queue_depth_stats: StreamingStats::default(),
+            trace: vec![],
+            activity: vec![],
+            throughput: vec![],
+            slots: vec![],
+            rng: StdRng::seed_from_u64(rng_seed),
+            processed: 0,
+            initial_queue_len: 0,
+            failures: vec![],
+        }
+    }
+
+    fn record_queue_depth(&mut self, time: DiscreteTime, depth: usize) {
+        let recorded = self.queue_depth_metrics.len();
+        let contiguous = self
+            .queue_depth_segments
+            .last()
+            .map_or(false, |(start, index)| {
+                start + (recorded - index) as DiscreteTime == time
+            });
+        if !contiguous {
+            self.queue_depth_segments.push((time, recorded));
+        }
+        self.queue_depth_metrics.push(depth);
+    }
 }
 
 impl Simulation {
     pub fn new(parameters: SimulationParameters) -> Simulation {
-        Simulation {
+        let seed = parameters.seed.unwrap_or_else(rand::random);
+        let agent_handles: HashMap<String, usize> = parameters
+            .agents
+            .iter()
+            .enumerate()
+            .map(|(handle, a)| (a.state().id.to_owned(), handle))
+            .collect();
+        let initially_queued = parameters
+            .agents
+            .iter()
+            .map(|a| a.state().queue.len())
+            .sum();
+        let initially_consumed = parameters
+            .agents
+            .iter()
+            .map(|a| a.state().consumed.len())
+            .sum();
+        let children = hierarchy::children_of(&parameters.agents);
+
+        let simulation = Simulation {
             mode: SimulationMode::Constructed,
-            agent_metadata_hash_table: parameters
+            halt_reason: None,
+            agent_metadata: parameters
                 .agents
                 .iter()
-                .map(|a| {
-                    (
-                        a.state().id.to_owned(),
-                        AgentMetadata {
-                            queue_depth_metrics: vec![],
-                            asleep_cycle_count: 0,
-                        },
-                    )
+                .map(|a| AgentMetadata {
+                    initial_queue_len: a.state().queue.len(),
+                    ..AgentMetadata::new(random::agent_seed(seed, &a.state().id))
                 })
                 .collect(),
             agents: parameters.agents,
             halt_check: parameters.halt_check,
             time: parameters.starting_time,
+            starting_time: parameters.starting_time,
+            ending_time: parameters.ending_time,
+            max_ticks: parameters.max_ticks,
+            max_wall_clock: parameters.max_wall_clock,
+            time_scale: parameters.time_scale,
+            step_budget: None,
+            warm_up: parameters.warm_up,
             enable_queue_depth_metric: parameters.enable_queue_depth_metrics,
             enable_agent_asleep_cycles_metric: parameters.enable_agent_asleep_cycles_metric,
+            enable_queue_depth_stats: parameters.enable_queue_depth_stats,
+            enable_trace: parameters.enable_trace,
+            enable_activity_metrics: parameters.enable_activity_metrics,
+            throughput_window: parameters.throughput_window,
+            unstable_growth_rate: parameters.unstable_growth_rate,
+            trace_arrivals: vec![],
+            environment: parameters.environment,
+            world_dynamics: parameters.world_dynamics,
+            resources: parameters.resources,
+            stores: parameters.stores,
+            containers: parameters.containers,
+            pools: parameters.pools,
+            autoscalers: parameters.autoscalers,
+            groups: parameters.groups,
+            group_turns: HashMap::new(),
+            open_forks: HashMap::new(),
+            completed_forks: vec![],
+            grid: parameters.grid.map(Arc::new),
+            grid_moves: vec![],
+            space: parameters.space.map(Arc::new),
+            space_steers: vec![],
+            cells: parameters.cells.map(Arc::new),
+            next_cells: vec![],
+            blackboard: Arc::new(parameters.blackboard),
+            blackboard_writes: vec![],
+            enable_environment_metrics: parameters.enable_environment_metrics,
+            environment_series: HashMap::new(),
+            channel_model: parameters.channel_model,
+            channel_metrics: HashMap::new(),
+            topology: parameters.topology,
+            message_transforms: parameters.message_transforms,
+            command_middleware: parameters.command_middleware,
+            command_handlers: parameters.command_handlers,
+            in_flight: vec![],
+            default_ttl: parameters.default_ttl,
+            dead_letters: vec![],
+            ledger: MessageLedger {
+                initially_queued,
+                ..Default::default()
+            },
+            interleaving: None,
+            totals: Totals {
+                consumed: initially_consumed,
+                queued: initially_queued,
+                ..Default::default()
+            },
+            topology_events: vec![],
+            edge_load: HashMap::new(),
+            report_sinks: parameters.report_sinks,
+            observers: parameters.observers,
+            pause_requested: None,
+            checkpoint_requested: false,
+            checkpoints: vec![],
+            alarms: vec![],
+            timers: BTreeMap::new(),
+            report_windows: vec![],
+            observer_windows: vec![],
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            correlation_ids: CorrelationIds::default(),
+            antithetic: parameters.antithetic,
+            enable_parallel_agents: parameters.enable_parallel_agents,
+            enable_agent_views: parameters.enable_agent_views,
+            error_policy: parameters.error_policy,
+            enable_panic_isolation: parameters.enable_panic_isolation,
+            quiescence_policy: parameters.quiescence_policy,
+            quiescent_since: None,
+            agent_views: None,
+            shadow_metadata: parameters
+                .shadow_agents
+                .iter()
+                .map(|s| ShadowMetadata::new(s, agent_handles.get(&s.shadowed).copied(), seed))
+                .collect(),
+            shadow_agents: parameters.shadow_agents,
+            agent_handles,
+            children,
+        };
+
+        for diagnostic in simulation.validate() {
+            match diagnostic.severity() {
+                Severity::Warning => warn!("{}", diagnostic),
+                Severity::Error => error!("{}", diagnostic),
+            }
         }
+        simulation
     }
 
     /// Returns the consumed messages for a given Agent during the Simulation.
@@ -132,109 +1030,474 @@ impl Simulation {
         Some(agent.state().produced.clone())
     }
 
-    /// Returns the queue depth timeseries for a given Agent during the Simulation.
+    /// Returns the number of messages an Agent consumed, in O(1).
+    pub fn consumed_count(&self, id: &str) -> Option<usize> {
+        Some(self.agent(id)?.state().consumed.len())
+    }
+
+    /// Returns the number of messages an Agent produced, in O(1).
+    pub fn produced_count(&self, id: &str) -> Option<usize> {
+        Some(self.agent(id)?.state().produced.len())
+    }
+
+    /// Returns the length of an Agent's queue, in O(1).
+    pub fn queue_len(&self, id: &str) -> Option<usize> {
+        Some(self.agent(id)?.state().queue.len())
+    }
+
+    /// Returns how many slots of a concurrent Agent are busy, as of the end
+    /// of the last tick; see `AgentOptions::concurrency`.
+    pub fn busy_slots(&self, id: &str) -> Option<usize> {
+        let slots = &self.metadata_for_agent(id)?.slots;
+        Some(slots.iter().filter(|s| s.busy_until > self.time).count())
+    }
+
+    /// Returns the fraction of ticks each slot of a concurrent Agent was
+    /// busy; see `AgentOptions::concurrency`. None for other Agents.
+    pub fn slot_utilization(&self, id: &str) -> Option<Vec<f64>> {
+        let concurrency = self.agent(id)?.state().options.concurrency;
+        if concurrency <= 1 {
+            return None;
+        }
+        let ticks = self.time.saturating_sub(self.starting_time).max(1);
+        let mut slots = self.metadata_for_agent(id)?.slots.clone();
+        slots.resize(concurrency, Slot::default());
+        Some(
+            slots
+                .iter()
+                .map(|s| {
+                    let after_run = s.busy_until.saturating_sub(self.time);
+                    s.busy_ticks.saturating_sub(after_run) as f64 / ticks as f64
+                })
+                .collect(),
+        )
+    }
+
+    /// Returns the running totals over all Agents, as of the end of the last tick.
+    pub fn totals(&self) -> &Totals {
+        &self.totals
+    }
+
+    pub(crate) fn agent(&self, id: &str) -> Option<&dyn Agent> {
+        Some(self.agents.get(*self.agent_handles.get(id)?)?.as_ref())
+    }
+
+    /// Returns the queue depth timeseries for a given Agent during the
+    /// Simulation, from the end of the warm-up period. Ticks in which the
+    /// metric was disabled are skipped; see `queue_depth_timeline`.
     pub fn queue_depth_metrics(&self, id: &str) -> Option<Vec<usize>> {
         // TODO(?): Return non option here.
+        let timeline = self.queue_depth_timeline(id)?;
         Some(
-            self.agent_metadata_hash_table
-                .get(id)?
-                .queue_depth_metrics
-                .clone(),
+            timeline
+                .iter()
+                .filter(|(time, _)| self.is_after_warm_up(*time))
+                .map(|(_, depth)| *depth)
+                .collect(),
         )
     }
 
+    /// Returns the (time, queue depth) of every tick in which an Agent's queue
+    /// depth was recorded, including the warm-up period.
+    pub fn queue_depth_timeline(&self, id: &str) -> Option<Vec<(DiscreteTime, usize)>> {
+        let metadata = self.metadata_for_agent(id)?;
+        let mut segments = metadata.queue_depth_segments.iter().peekable();
+        let mut timeline = Vec::with_capacity(metadata.queue_depth_metrics.len());
+        let mut segment = (self.starting_time, 0);
+        for (index, depth) in metadata.queue_depth_metrics.iter().enumerate() {
+            if let Some(next) = segments.next_if(|(_, start)| *start == index) {
+                segment = *next;
+            }
+            timeline.push((segment.0 + (index - segment.1) as DiscreteTime, *depth));
+        }
+        Some(timeline)
+    }
+
+    /// Enables or disables recording a metric from now on, e.g. to record
+    /// expensive telemetry only around an interesting interval of a long run.
+    /// Agents do the same by sending an `Interrupt::SetMetric`.
+    pub fn set_metric(&mut self, metric: Metric, enabled: bool) {
+        match metric {
+            Metric::QueueDepth => self.enable_queue_depth_metric = enabled,
+            Metric::QueueDepthStats => self.enable_queue_depth_stats = enabled,
+            Metric::AgentAsleepCycles => self.enable_agent_asleep_cycles_metric = enabled,
+            Metric::Environment => self.enable_environment_metrics = enabled,
+            Metric::Trace => self.enable_trace = enabled,
+            Metric::Activity => self.enable_activity_metrics = enabled,
+        }
+    }
+
+    /// Returns the (window start, messages consumed) of every throughput
+    /// window of an Agent, in order. Requires `throughput_window`.
+    pub fn throughput(&self, id: &str) -> Option<&[(DiscreteTime, usize)]> {
+        Some(&self.metadata_for_agent(id)?.throughput)
+    }
+
     /// Returns the asleep cycle count for a given Agent during the Simulation.
     pub fn asleep_cycle_count(&self, id: &str) -> Option<DiscreteTime> {
         // TODO(?): Return non option here.
-        Some(self.agent_metadata_hash_table.get(id)?.asleep_cycle_count)
+        Some(self.metadata_for_agent(id)?.asleep_cycle_count)
+    }
+
+    /// Returns the streaming statistics of an Agent's queue depth, which can
+    /// be read while the Simulation runs, e.g. from `halt_check`. Requires
+    /// `enable_queue_depth_stats`.
+    pub fn queue_depth_stats(&self, id: &str) -> Option<&StreamingStats> {
+        Some(&self.metadata_for_agent(id)?.queue_depth_stats)
+    }
+
+    fn metadata_for_agent(&self, id: &str) -> Option<&AgentMetadata> {
+        self.agent_metadata.get(*self.agent_handles.get(id)?)
+    }
+
+    /// Returns the messages the engine refused to deliver, e.g. because they
+    /// exceeded their hops, in order.
+    pub fn dead_letters(&self) -> &[DeadLetter] {
+        &self.dead_letters
+    }
+
+    /// Returns the recorded Series of an environment variable during the Simulation.
+    pub fn environment_series(&self, name: &str) -> Option<&Series> {
+        self.environment_series.get(name)
     }
 
     /// Runs the simulation. This should only be called after adding all the beginning state.
+    ///
+    /// Agents pushed onto `agents` since the last run join it. Panics if more
+    /// than one Agent has the same id.
     pub fn run(&mut self) {
+        self.register_agents();
+        let _correlation_ids = self.correlation_ids.enter();
+        // A paused run resumes its report windows.
+        if self.mode == SimulationMode::Paused {
+            self.halt_reason = None;
+        } else {
+            self.reset_report_windows();
+        }
         self.mode = SimulationMode::Running;
 
-        while !(self.halt_check)(self) {
-            debug!("Running next tick of simulation at time {}", self.time);
+        // Reused across ticks, to not allocate for them on every tick.
+        let mut tick_message = Message::new(self.time, "SIM_SRC", "ANY");
+        let mut environment = Arc::new(self.environment.clone());
+        let started = Instant::now();
+
+        while self.mode == SimulationMode::Running {
+            let ended = self.ending_time.map_or(false, |end| self.time >= end);
+            if ended || (self.halt_check)(self) {
+                self.halt_reason = Some(HaltReason::HaltCheck);
+                break;
+            }
+            if let Some(limit) = self.limit_reached(started) {
+                warn!("The simulation reached its limit {:?}", limit);
+                self.halt_reason = Some(HaltReason::LimitReached(limit));
+                break;
+            }
+            if self.step_budget == Some(0) {
+                self.mode = SimulationMode::Paused;
+                break;
+            }
+
+            let tick_span = spans::tick(self.time);
+            let _tick = tick_span.enter();
+            debug!(
+                "Running next tick of simulation at time {}",
+                self.format_time(self.time)
+            );
             let mut message_bus = vec![];
             self.wakeup_agents_scheduled_to_wakeup_now();
+            if !self.timers.is_empty() {
+                self.fire_timers();
+            }
+            if !self.children.is_empty() {
+                self.propagate_terminations();
+            }
+            self.apply_scheduled_link_changes();
+            if !self.grid_moves.is_empty() {
+                self.apply_grid_moves();
+            }
+            if self.space.is_some() {
+                self.advance_space();
+            }
+            if !self.autoscalers.is_empty() {
+                self.autoscale();
+            }
+            if !self.pools.is_empty() {
+                self.dispatch_pools();
+            }
+            if self.quiescence_policy != QuiescencePolicy::Ignore && self.check_quiescence() {
+                break;
+            }
+            if !self.observers.is_empty() {
+                self.notify_observers(|o, s| o.before_tick(s));
+            }
+
+            for dynamics in self.world_dynamics.iter_mut() {
+                dynamics.update(self.time, &mut self.environment);
+            }
+
+            if self.enable_environment_metrics {
+                for (name, value) in self.environment.iter() {
+                    self.environment_series
+                        .entry(name.clone())
+                        .or_insert_with(|| Series::new(Interpolation::Linear))
+                        .record(self.time, *value);
+                }
+            }
+
+            if !self.world_dynamics.is_empty() {
+                environment = Arc::new(self.environment.clone());
+            }
 
-            let tick_message = Message::new(self.time, "SIM_SRC".to_string(), "ANY".to_string());
+            if self.enable_agent_views {
+                self.refresh_agent_views();
+            }
+
+            tick_message.queued_time = self.time;
             let simulation_state = SimulationState {
                 time: self.time,
                 mode: self.mode.clone(),
+                environment: environment.clone(),
+                grid: self.grid.clone(),
+                space: self.space.clone(),
+                cells: self.cells.clone(),
+                blackboard: self.blackboard.clone(),
+                agent_views: self.agent_views.clone(),
+            };
+
+            let options = StepOptions {
+                enable_queue_depth_metric: self.enable_queue_depth_metric,
+                enable_agent_asleep_cycles_metric: self.enable_agent_asleep_cycles_metric,
+                enable_queue_depth_stats: self.enable_queue_depth_stats,
+                enable_trace: self.enable_trace,
+                enable_activity_metrics: self.enable_activity_metrics,
+                throughput_window: self.throughput_window,
+                warm_up: self.warm_up,
+                antithetic: self.antithetic,
+                error_policy: self.error_policy,
+                enable_panic_isolation: self.enable_panic_isolation,
             };
 
-            for agent in self.agents.iter_mut() {
-                if self.enable_queue_depth_metric {
-                    self.agent_metadata_hash_table
-                        .get_mut(&agent.state().id)
-                        .expect("Failed to find agent in metrics")
-                        .queue_depth_metrics
-                        .push(agent.state().queue.len());
+            if self.enable_parallel_agents {
+                // Agents that are Send are processed on worker threads, the
+                // rest on this one.
+                let mut parallel = vec![];
+                let mut sequential = vec![];
+                for (handle, (agent, metadata)) in self
+                    .agents
+                    .iter_mut()
+                    .zip(self.agent_metadata.iter_mut())
+                    .enumerate()
+                {
+                    if agent.as_send().is_some() {
+                        parallel.push((handle, agent.as_send().unwrap(), metadata));
+                    } else {
+                        sequential.push((handle, agent, metadata));
+                    }
                 }
+                let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+                let chunk_size = ((parallel.len() + threads - 1) / threads).max(1);
 
-                let queued_msg = agent.state_mut().queue.pop_front();
+                let mut steps: Vec<(usize, Step)> = std::thread::scope(|scope| {
+                    let (state, tick, span) = (&simulation_state, &tick_message, &tick_span);
+                    let correlation_ids = &self.correlation_ids;
+                    let workers: Vec<_> = parallel
+                        .chunks_mut(chunk_size)
+                        .map(|chunk| {
+                            scope.spawn(move || {
+                                let _tick = span.enter();
+                                let _correlation_ids = correlation_ids.enter();
+                                chunk
+                                    .iter_mut()
+                                    .map(|(handle, a, m)| {
+                                        (*handle, step_agent(*a, m, state, tick, options))
+                                    })
+                                    .collect::<Vec<_>>()
+                            })
+                        })
+                        .collect();
 
-                match agent.state().mode {
-                    AgentMode::Proactive => {
-                        if let Some(messages) = agent.as_mut().process(
-                            simulation_state.clone(),
-                            queued_msg.as_ref().unwrap_or(&tick_message),
-                        ) {
-                            message_bus.extend(messages);
-                        }
-                    }
-                    AgentMode::Reactive => {
-                        if queued_msg.is_some() {
-                            if let Some(new_msgs) = agent
-                                .as_mut()
-                                .process(simulation_state.clone(), &queued_msg.unwrap())
-                            {
-                                message_bus.extend(new_msgs);
-                            }
-                        }
-                    }
-                    AgentMode::AsleepUntil(_) => {
-                        if self.enable_agent_asleep_cycles_metric {
-                            self.agent_metadata_hash_table
-                                .get_mut(&agent.state().id)
-                                .expect("Failed to find agent in metrics")
-                                .asleep_cycle_count += 1
-                        }
+                    let mut produced: Vec<_> = sequential
+                        .into_iter()
+                        .map(|(handle, a, m)| {
+                            (handle, step_agent(a.as_mut(), m, state, tick, options))
+                        })
+                        .collect();
+                    for worker in workers {
+                        produced.extend(worker.join().expect("Agent worker thread panicked"));
                     }
-                    AgentMode::Dead => {}
+                    produced
+                });
+
+                // The messages are merged in agent order, so the message bus
+                // is the same as if processed sequentially.
+                steps.sort_by_key(|(handle, _)| *handle);
+                for (_, step) in steps {
+                    self.totals.record(&step);
+                    message_bus.extend(step.produced);
                 }
+            } else {
+                for (agent, metadata) in self.agents.iter_mut().zip(self.agent_metadata.iter_mut())
+                {
+                    let step = step_agent(
+                        agent.as_mut(),
+                        metadata,
+                        &simulation_state,
+                        &tick_message,
+                        options,
+                    );
+                    self.totals.record(&step);
+                    message_bus.extend(step.produced);
+                }
+            }
+
+            if !self.shadow_agents.is_empty() {
+                self.step_shadows(&simulation_state, &tick_message, options);
             }
 
             // Consume all the new messages in the bus and deliver to agents.
-            self.process_message_bus(message_bus);
+            let messages_delivered = self.process_message_bus(message_bus);
+            // Nothing reads this tick's state anymore, so the updates below
+            // needn't copy what it shares with them.
+            drop(simulation_state);
+            if !self.next_cells.is_empty() {
+                self.swap_cells();
+            }
+            if !self.blackboard_writes.is_empty() {
+                self.apply_blackboard_writes();
+            }
+            if self.error_policy == ErrorPolicy::FailSimulation {
+                if let Some(failure) = self.failure_now() {
+                    let reason =
+                        HaltReason::AgentFailed(failure.agent.clone(), failure.error.clone());
+                    self.mode = SimulationMode::Failed;
+                    self.halt_reason = Some(reason);
+                }
+            }
+            self.observe_tick_for_reports(messages_delivered);
+            if !self.observers.is_empty() {
+                self.observe_tick_for_observers(messages_delivered);
+            }
 
             debug!("Finished this tick; incrementing time.");
             self.time += 1;
+            if let Some(budget) = &mut self.step_budget {
+                *budget -= 1;
+            }
+            self.apply_controls();
+        }
+
+        if self.mode == SimulationMode::Paused {
+            info!("Paused the simulation at {}", self.format_time(self.time));
+            return;
+        }
+        if self.mode != SimulationMode::Failed {
+            self.mode = SimulationMode::Completed;
+        }
+        for discrepancy in self.message_ledger().discrepancies() {
+            warn!("The message ledger doesn't balance: {}", discrepancy);
         }
 
-        self.mode = SimulationMode::Completed;
+        self.write_completion_reports();
+        if !self.observers.is_empty() {
+            self.notify_observers_on_completion();
+        }
         self.emit_completed_simulation_debug_logging();
     }
 
+    /// Runs the simulation for at most `ticks` ticks, then pauses it unless
+    /// it halted first. It resumes on the next `run`, `run_for` or `step`.
+    pub fn run_for(&mut self, ticks: DiscreteTime) {
+        self.step_budget = Some(ticks);
+        self.run();
+        self.step_budget = None;
+    }
+
+    /// Runs a single tick of the simulation; see `run_for`.
+    pub fn step(&mut self) {
+        self.run_for(1);
+    }
+
+    /// Registers the Agent at `handle`, which is in `agents` but not yet in
+    /// the Simulation's metadata or handles.
+    pub(crate) fn register_agent(&mut self, handle: usize) {
+        let state = self.agents[handle].state();
+        let id = state.id.clone();
+        self.ledger.initially_queued += state.queue.len();
+        self.totals.queued += state.queue.len();
+        self.totals.consumed += state.consumed.len();
+        for id in state.queue.iter().filter_map(|m| m.correlation_id) {
+            self.correlation_ids.skip_past(id);
+        }
+        self.agent_metadata.push(AgentMetadata {
+            initial_queue_len: state.queue.len(),
+            ..AgentMetadata::new(random::agent_seed(self.seed, &id))
+        });
+        if let Some(parent) = &state.options.parent {
+            let children = self.children.entry(parent.clone()).or_default();
+            children.push(id.clone());
+        }
+        self.agent_handles.insert(id, handle);
+    }
+
+    /// Registers the Agents pushed onto `agents` since the Simulation was
+    /// created or last ran, and checks every id has exactly one Agent.
+    fn register_agents(&mut self) {
+        for handle in self.agent_metadata.len()..self.agents.len() {
+            self.register_agent(handle);
+        }
+        let registered = self.agent_handles.len() == self.agents.len()
+            && self
+                .agents
+                .iter()
+                .enumerate()
+                .all(|(handle, a)| self.agent_handles.get(&a.state().id) == Some(&handle));
+        if registered {
+            return;
+        }
+
+        self.agent_handles.clear();
+        for (handle, agent) in self.agents.iter().enumerate() {
+            let id = &agent.state().id;
+            if self.agent_handles.insert(id.clone(), handle).is_some() {
+                panic!("More than one agent has the id {:?}", id);
+            }
+        }
+    }
+
+    /// Returns the safety limit the run reached, if any.
+    fn limit_reached(&self, started: Instant) -> Option<Limit> {
+        let ticks = self.time.saturating_sub(self.starting_time);
+        if self.max_ticks.map_or(false, |max| ticks >= max) {
+            return Some(Limit::MaxTicks);
+        }
+        if self
+            .max_wall_clock
+            .map_or(false, |max| started.elapsed() >= max)
+        {
+            return Some(Limit::MaxWallClock);
+        }
+        None
+    }
+
     /// A helper to calculate the average waiting time to process items.
     /// Note: This function will likely go away; it is an artifact of prototyping.
     pub fn calc_avg_wait_statistics(&self) -> HashMap<String, usize> {
         let mut data = HashMap::new();
-        for agent in self
-            .agents
-            .iter()
-            .filter(|a| !a.state().consumed.is_empty())
-        {
-            let mut sum_of_times: u64 = 0;
-            for completed in agent.state().consumed.iter() {
-                sum_of_times += completed.completed_time.unwrap() - completed.queued_time;
-            }
+        for agent in self.agents.iter() {
+            let waits: Vec<u64> = agent
+                .state()
+                .consumed
+                .iter()
+                .filter(|m| self.is_after_warm_up(m.queued_time))
+                .map(|m| m.completed_time.unwrap() - m.queued_time)
+                .collect();
 
-            data.insert(
-                agent.state().id.clone(),
-                sum_of_times as usize / agent.state().consumed.len(),
-            );
+            if !waits.is_empty() {
+                data.insert(
+                    agent.state().id.clone(),
+                    waits.iter().sum::<u64>() as usize / waits.len(),
+                );
+            }
         }
 
         data
@@ -257,7 +1520,10 @@ impl Simulation {
         let mut data = HashMap::new();
 
         for agent in self.agents.iter() {
-            data.insert(agent.state().id.clone(), agent.state().consumed.len());
+            let consumed = agent.state().consumed.iter();
+            let consumed = consumed
+                .filter(|m| self.is_after_warm_up(m.completed_time.unwrap_or(m.queued_time)));
+            data.insert(agent.state().id.clone(), consumed.count());
         }
 
         data
@@ -268,7 +1534,9 @@ impl Simulation {
         let mut data = HashMap::new();
 
         for agent in self.agents.iter() {
-            data.insert(agent.state().id.clone(), agent.state().produced.len());
+            let produced = agent.state().produced.iter();
+            let produced = produced.filter(|m| self.is_after_warm_up(m.queued_time));
+            data.insert(agent.state().id.clone(), produced.count());
         }
 
         data
@@ -288,74 +1556,1752 @@ impl Simulation {
 
     /// Consume a message_bus of messages and disperse those messages to the agents.
     /// If there are any interrupts, process those immediately.
-    fn process_message_bus(&mut self, mut message_bus: Vec<Message>) {
-        while let Some(message) = message_bus.pop() {
-            for agent in self.agents.iter_mut() {
-                if agent.state().id == message.clone().destination {
-                    agent.push_message(message.clone());
-                }
+    /// Returns the number of messages delivered to Agents.
+    fn process_message_bus(&mut self, mut message_bus: Vec<Message>) -> usize {
+        let mut messages_delivered = 0;
 
-                if agent.state().id == message.clone().source {
-                    agent.state_mut().produced.push(message.clone());
-                }
+        if let Some(interleaving) = &mut self.interleaving {
+            interleaving.reorder(&mut message_bus);
+        }
+
+        while let Some(mut message) = message_bus.pop() {
+            if message.interrupt.is_some()
+                && !self.command_middleware.is_empty()
+                && !self.apply_command_middleware(&mut message)
+            {
+                continue;
+            }
+            self.resolve_destination(&mut message);
+            self.resolve_shortest_queue(&mut message);
+            let copies = self
+                .expand_within(&message)
+                .or_else(|| self.expand_tag(&message))
+                .or_else(|| self.expand_group(&message));
+            if let Some(copies) = copies {
+                message_bus.extend(copies);
+                continue;
+            }
+            self.ledger.produced += 1;
+
+            if let Some(source) = self.agent_handles.get(&message.source) {
+                self.agents[*source]
+                    .state_mut()
+                    .produced
+                    .push(message.clone());
+                self.totals.produced += 1;
             }
 
-            if let Some(Interrupt::HaltSimulation(reason)) = message.interrupt {
+            if let Some(Interrupt::HaltSimulation(reason)) = &message.interrupt {
                 info!("Received a halt interrupt: {:?}", reason);
                 self.mode = SimulationMode::Completed;
+                self.halt_reason = Some(HaltReason::Interrupt(reason.clone()));
             }
-        }
-    }
 
-    /// An internal function used to wakeup sleeping Agents due to wake.
-    fn wakeup_agents_scheduled_to_wakeup_now(&mut self) {
-        for agent in self.agents.iter_mut() {
-            if let AgentMode::AsleepUntil(wakeup_at) = agent.state().mode {
-                if self.time >= wakeup_at {
-                    agent.state_mut().mode = agent.state().wake_mode;
-                }
+            if let Some(Interrupt::SetMetric { metric, enabled }) = &message.interrupt {
+                self.set_metric(*metric, *enabled);
             }
-        }
-    }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use rand_distr::Poisson;
-    use simul_macro::agent;
+            if let Some(Interrupt::SetLink {
+                source,
+                destination,
+                up,
+            }) = &message.interrupt
+            {
+                self.set_link(source, destination, *up);
+            }
 
-    fn init() {
-        let _ = env_logger::builder().is_test(true).try_init();
-    }
+            if self.handle_control_interrupt(&message) {
+                self.ledger.to_resources += 1;
+                continue;
+            }
+            if self.handle_timer_interrupt(&mut message) {
+                continue;
+            }
 
-    #[test]
-    fn basic_periodic_test() {
-        init();
-        let mut simulation = Simulation::new(SimulationParameters {
-            agents: vec![
-                periodic_producing_agent("producer".to_string(), 1, "consumer".to_string()),
-                periodic_consuming_agent("consumer".to_string(), 1),
-            ],
-            halt_check: |s: &Simulation| s.time == 5,
-            ..Default::default()
-        });
-        simulation.run();
-        let produced_stats = simulation.calc_produced_len_statistics();
-        assert_eq!(produced_stats.get("producer"), Some(&5));
-        assert_eq!(produced_stats.get("consumer"), Some(&0));
+            self.open_fork(&mut message);
 
-        let consumed_stats = simulation.calc_consumed_len_statistics();
-        assert_eq!(consumed_stats.get("producer"), Some(&0));
-        assert_eq!(consumed_stats.get("consumer"), Some(&4));
-    }
+            if let Some(Interrupt::MoveTo { x, y }) = message.interrupt {
+                self.grid_moves.push((message.source.clone(), x, y));
+                self.ledger.to_resources += 1;
+                continue;
+            }
+            if self.steer_body(&message) {
+                self.ledger.to_resources += 1;
+                continue;
+            }
+            if let Some(Interrupt::Blackboard { key, value }) = message.interrupt {
+                self.blackboard_writes.push((key, value));
+                self.ledger.to_resources += 1;
+                continue;
+            }
+            if let Some(Interrupt::SetCell(state)) = message.interrupt {
+                self.next_cells.push((message.source, state));
+                self.ledger.to_resources += 1;
+                continue;
+            }
+            if let Some(replies) = self.handle_custom_command(&message) {
+                message_bus.extend(replies);
+                continue;
+            }
 
-    #[test]
-    fn starbucks_clerk() {
-        init();
+            // Replies are delivered like any other message, in this tick.
+            let replies = self
+                .handle_resource_message(&message)
+                .or_else(|| self.handle_store_message(&message));
+            if let Some(replies) = replies {
+                message_bus.extend(replies);
+                self.ledger.to_resources += 1;
+                continue;
+            }
 
-        #[agent]
-        struct Clerk {}
+            let Some(destination) = self.agent_handles.get(&message.destination).copied() else {
+                if self.enqueue_in_pool(message).is_some() {
+                    self.ledger.unroutable += 1;
+                }
+                continue;
+            };
+
+            // Only messages bound for an Agent travel over a channel.
+            let delivery = self.channel_delivery(&message);
+            if delivery.copies == 0 {
+                self.ledger.lost += 1;
+                continue;
+            }
+
+            message.hops += 1;
+            if let Some(ttl) = message.ttl.or(self.default_ttl) {
+                if ttl == 0 {
+                    warn!(
+                        "Dead-lettering a message that exceeded its hops: {:?}",
+                        message
+                    );
+                    self.dead_letters.push(DeadLetter {
+                        time: self.time,
+                        reason: DeadLetterReason::HopLimitExceeded,
+                        message,
+                    });
+                    self.ledger.dead_lettered += 1;
+                    continue;
+                }
+                message.ttl = Some(ttl - 1);
+            }
+
+            // Replies to forks are delivered once all are in, each once.
+            let mut message = match self.gather(message) {
+                fork::Gathered::Pass(message) => message,
+                fork::Gathered::Held => continue,
+                fork::Gathered::Joined(join, replies) => {
+                    match self.agent_handles.get(&join).copied() {
+                        Some(handle) => {
+                            messages_delivered += replies.len();
+                            for reply in replies {
+                                self.deliver(handle, reply, false);
+                            }
+                        }
+                        None => self.ledger.unroutable += replies.len(),
+                    }
+                    continue;
+                }
+            };
+
+            self.ledger.duplicated += delivery.copies - 1;
+
+            let mut delay = self.edge_delay(&message);
+            if !self.message_transforms.is_empty() {
+                delay += self.apply_message_transforms(&mut message);
+            }
+            if delay > 0 {
+                for _ in 0..delivery.copies {
+                    self.in_flight.push(InFlightMessage {
+                        due: self.time + delay,
+                        handle: destination,
+                        message: message.clone(),
+                        reorder: delivery.reorder,
+                    });
+                }
+                continue;
+            }
+
+            messages_delivered += delivery.copies;
+
+            // The last copy is moved rather than cloned, so the common case of
+            // a single copy delivers without allocating.
+            for _ in 1..delivery.copies {
+                self.deliver(destination, message.clone(), delivery.reorder);
+            }
+
+            self.deliver(destination, message, delivery.reorder);
+        }
+
+        messages_delivered + self.deliver_due_in_flight_messages()
+    }
+
+    /// Puts a message onto the queue of the Agent with the given handle.
+    fn deliver(&mut self, handle: usize, message: Message, reorder: bool) {
+        if !self.shadow_agents.is_empty() {
+            self.deliver_to_shadows(handle, &message);
+        }
+
+        self.ledger.delivered += 1;
+        for observer in self.observers.iter_mut() {
+            observer.on_message_delivered(self.time, &message);
+        }
+
+        // Recorded of the message itself, which reordering or a custom
+        // push_message needn't put at the back of the queue.
+        let arrival = self.enable_trace.then(|| {
+            TraceEvent::new(
+                self.time,
+                TraceEventKind::Arrival,
+                &self.agents[handle].state().id,
+                &message,
+                None,
+            )
+        });
+
+        let agent = &mut self.agents[handle];
+        let queue_len = agent.state().queue.len();
+        if reorder {
+            let queue = &mut agent.state_mut().queue;
+            let position = self.rng.gen_range(0..=queue.len());
+            queue.insert(position, message);
+        } else {
+            agent.push_message(message);
+        }
+        self.totals.queued += agent.state().queue.len().saturating_sub(queue_len);
+
+        if let Some(mut arrival) = arrival {
+            arrival.queue_length = Some(agent.state().queue.len());
+            self.trace_arrivals.push(arrival);
+        }
+    }
+
+    /// An internal function used to wakeup sleeping Agents due to wake.
+    fn wakeup_agents_scheduled_to_wakeup_now(&mut self) {
+        let shadows = self.shadow_agents.iter_mut().map(|s| &mut s.agent);
+        for agent in self.agents.iter_mut().chain(shadows) {
+            if let AgentMode::AsleepUntil(wakeup_at) = agent.state().mode {
+                if self.time >= wakeup_at {
+                    agent.state_mut().mode = agent.state().wake_mode;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand_distr::Poisson;
+    use simul_macro::agent;
+
+    fn init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    #[test]
+    fn basic_periodic_test() {
+        init();
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer".to_string(), 1, "consumer".to_string()),
+                periodic_consuming_agent("consumer".to_string(), 1),
+            ],
+            halt_check: |s: &Simulation| s.time == 5,
+            ..Default::default()
+        });
+        simulation.run();
+        let produced_stats = simulation.calc_produced_len_statistics();
+        assert_eq!(produced_stats.get("producer"), Some(&5));
+        assert_eq!(produced_stats.get("consumer"), Some(&0));
+
+        let consumed_stats = simulation.calc_consumed_len_statistics();
+        assert_eq!(consumed_stats.get("producer"), Some(&0));
+        assert_eq!(consumed_stats.get("consumer"), Some(&4));
+
+        assert_consumed!(simulation, "consumer", >= 4);
+        assert_produced!(simulation, "producer", == 5);
+        assert_queue_len!(simulation, "consumer", <= 1);
+        assert_queue_empty!(simulation, "producer");
+        assert_halted_by!(simulation, HaltCheck);
+    }
+
+    #[test]
+    fn world_dynamics_test() {
+        init();
+
+        #[agent]
+        struct Thermometer {}
+
+        impl Agent for Thermometer {
+            fn process(&mut self, state: SimulationState, _: &Message) -> Option<Vec<Message>> {
+                // The dynamics have already run once this tick.
+                assert!(state.environment["temperature"] < 100.0);
+                None
+            }
+        }
+
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![Box::new(Thermometer {
+                state: AgentState {
+                    mode: AgentMode::Proactive,
+                    wake_mode: AgentMode::Proactive,
+                    id: "thermometer".to_string(),
+                    ..Default::default()
+                },
+            })],
+            environment: [("temperature".to_string(), 100.0)].into(),
+            world_dynamics: vec![exponential_decay("temperature", 0.5, 20.0)],
+            enable_environment_metrics: true,
+            halt_check: |s: &Simulation| s.time == 3,
+            ..Default::default()
+        });
+        simulation.run();
+
+        assert_eq!(simulation.environment["temperature"], 30.0);
+        let series = simulation.environment_series("temperature").unwrap();
+        assert_eq!(series.points, vec![(0, 60.0), (1, 40.0), (2, 30.0)]);
+        assert_eq!(series.crossings(50.0), vec![0.5]);
+    }
+
+    #[test]
+    fn channel_faults_test() {
+        init();
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer".to_string(), 1, "consumer".to_string()),
+                periodic_consuming_agent("consumer".to_string(), 1),
+            ],
+            channel_model: ChannelModel::default().with_channel(
+                "producer",
+                "consumer",
+                ChannelFaults {
+                    loss_probability: 1.0,
+                    ..Default::default()
+                },
+            ),
+            halt_check: |s: &Simulation| s.time == 5,
+            ..Default::default()
+        });
+        simulation.run();
+
+        assert_produced!(simulation, "producer", == 5);
+        assert_consumed!(simulation, "consumer", == 0);
+        let metrics = simulation.channel_metrics("producer", "consumer").unwrap();
+        assert_eq!(metrics.dropped, 5);
+    }
+
+    #[test]
+    fn shadow_agents_test() {
+        init();
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer".to_string(), 1, "consumer".to_string()),
+                periodic_consuming_agent("consumer".to_string(), 1),
+            ],
+            shadow_agents: vec![
+                ShadowAgent::new(
+                    "consumer",
+                    periodic_consuming_agent("candidate consumer".to_string(), 2),
+                ),
+                ShadowAgent::new(
+                    "producer",
+                    periodic_producing_agent(
+                        "candidate producer".to_string(),
+                        1,
+                        "consumer".to_string(),
+                    ),
+                ),
+            ],
+            halt_check: |s: &Simulation| s.time == 5,
+            ..Default::default()
+        });
+        simulation.run();
+
+        // The shadows' messages are recorded, but never reach the consumer.
+        assert_consumed!(simulation, "consumer", == 4);
+        let candidate = simulation.shadow("candidate consumer").unwrap();
+        assert_eq!(candidate.state().consumed.len(), 2);
+        let candidate = simulation.shadow("candidate producer").unwrap();
+        assert_eq!(candidate.state().produced.len(), 5);
+    }
+
+    #[test]
+    fn message_transforms_test() {
+        init();
+
+        #[derive(Clone, Debug)]
+        struct Encryption;
+        impl MessageTransform for Encryption {
+            fn name(&self) -> &'static str {
+                "encryption"
+            }
+
+            fn apply(&mut self, _message: &mut Message) -> DiscreteTime {
+                2
+            }
+        }
+
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer".to_string(), 1, "consumer".to_string()),
+                periodic_consuming_agent("consumer".to_string(), 1),
+            ],
+            message_transforms: vec![Box::new(Encryption)],
+            halt_check: |s: &Simulation| s.time == 10,
+            ..Default::default()
+        });
+        simulation.run();
+
+        assert_consumed!(simulation, "consumer", == 7);
+        let latency = simulation.latency_decomposition("consumer").unwrap();
+        assert_eq!(latency.mean_latency, 3.0);
+        assert_eq!(latency.mean_queueing, 1.0);
+        assert_eq!(latency.mean_transforms["encryption"], 2.0);
+
+        let mut per_kilobyte = PayloadSizeCost {
+            name: "compression",
+            ticks_per_kilobyte: 2,
+        };
+        let mut message = Message {
+            custom_payload: Some(vec![0; 1500]),
+            ..Message::new(0, "producer", "consumer")
+        };
+        assert_eq!(per_kilobyte.apply(&mut message), 4);
+    }
+
+    #[test]
+    fn hop_limit_test() {
+        init();
+
+        // Two routers with a routing bug: each forwards everything to the other.
+        #[agent]
+        struct Router {
+            next: String,
+        }
+
+        impl Agent for Router {
+            fn process(&mut self, state: SimulationState, msg: &Message) -> Option<Vec<Message>> {
+                Some(vec![msg.forward(state.time, self.next.as_str())])
+            }
+        }
+
+        let router = |id: &str, next: &str, queue: Vec<Message>| -> Box<dyn Agent> {
+            Box::new(Router {
+                next: next.to_string(),
+                state: AgentState {
+                    mode: AgentMode::Reactive,
+                    wake_mode: AgentMode::Reactive,
+                    id: id.to_string(),
+                    queue: queue.into(),
+                    ..Default::default()
+                },
+            })
+        };
+
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                router("a", "b", vec![Message::new(0, "client", "a")]),
+                router("b", "a", vec![]),
+            ],
+            default_ttl: Some(5),
+            halt_check: |s: &Simulation| s.time == 100,
+            ..Default::default()
+        });
+        simulation.run();
+
+        let dead_letters = simulation.dead_letters();
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0].reason, DeadLetterReason::HopLimitExceeded);
+        assert_eq!(dead_letters[0].time, 5);
+        assert_eq!(dead_letters[0].message.hops, 6);
+        assert_queue_empty!(simulation, "a");
+        assert_queue_empty!(simulation, "b");
+    }
+
+    #[test]
+    fn warm_up_test() {
+        init();
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer".to_string(), 1, "consumer".to_string()),
+                periodic_consuming_agent("consumer".to_string(), 1),
+            ],
+            warm_up: Some(5),
+            enable_queue_depth_metrics: true,
+            halt_check: |s: &Simulation| s.time == 10,
+            ..Default::default()
+        });
+        simulation.run();
+
+        // Statistics exclude the warm-up, while the histories are kept whole.
+        assert_produced!(simulation, "producer", == 10);
+        assert_eq!(simulation.calc_produced_len_statistics()["producer"], 5);
+        assert_eq!(simulation.calc_consumed_len_statistics()["consumer"], 5);
+        assert_eq!(simulation.queue_depth_metrics("consumer").unwrap().len(), 5);
+
+        simulation.warm_up = simulation.detect_warm_up("consumer");
+        assert_eq!(simulation.warm_up, Some(5));
+    }
+
+    #[test]
+    fn workload_test() {
+        init();
+        let run = |workload| {
+            let mut simulation = Simulation::new(SimulationParameters {
+                agents: vec![
+                    workload_agent("users", workload, "server"),
+                    serving_agent("server", 4),
+                ],
+                seed: Some(1),
+                halt_check: |s: &Simulation| s.time == 200,
+                ..Default::default()
+            });
+            simulation.run();
+            simulation
+        };
+
+        // Two users share the server, so neither waits long for the other.
+        let closed = run(Workload::Closed {
+            population: 2,
+            think_time: Poisson::new(10.0).unwrap(),
+        });
+        assert_queue_len!(closed, "server", <= 2);
+        assert_consumed!(closed, "users", >= 10);
+        let served = closed.consumed_for_agent("server").unwrap().len();
+        let replied = closed.consumed_for_agent("users").unwrap().len();
+        assert!(served - replied <= 2);
+
+        let sessions = user_sessions(&closed, "users").unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(
+            sessions.values().map(|s| s.requests).sum::<usize>(),
+            replied
+        );
+        assert!(sessions.values().all(|s| s.mean_response_time >= 1.0));
+
+        // Open arrivals faster than the server keep coming regardless.
+        let open = run(Workload::Open {
+            interarrival: Poisson::new(2.0).unwrap(),
+        });
+        assert_queue_len!(open, "server", >= 20);
+    }
+
+    #[test]
+    fn queue_depth_stats_test() {
+        init();
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer".to_string(), 1, "consumer".to_string()),
+                periodic_consuming_agent("consumer".to_string(), 2),
+            ],
+            enable_queue_depth_stats: true,
+            // The statistics are readable mid-run.
+            halt_check: |s: &Simulation| s.queue_depth_stats("consumer").unwrap().count == 10,
+            ..Default::default()
+        });
+        simulation.run();
+
+        let stats = simulation.queue_depth_stats("consumer").unwrap();
+        assert_eq!(simulation.time, 10);
+        assert_eq!(stats.min, 0.0);
+        assert_eq!(stats.max, 5.0);
+        assert!(stats.mean > 0.0 && stats.variance() > 0.0);
+
+        // The consumer falls behind, so later messages wait longer.
+        let p50 = simulation.wait_time_percentile("consumer", 50.0).unwrap();
+        let p99 = simulation.wait_time_percentile("consumer", 99.0).unwrap();
+        assert!(p50 < p99);
+    }
+
+    #[test]
+    fn trace_test() {
+        init();
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer".to_string(), 1, "consumer".to_string()),
+                periodic_consuming_agent("consumer".to_string(), 1),
+            ],
+            enable_trace: true,
+            halt_check: |s: &Simulation| s.time == 3,
+            ..Default::default()
+        });
+        simulation.run();
+
+        let kinds: Vec<(DiscreteTime, TraceEventKind)> = simulation
+            .trace()
+            .iter()
+            .map(|e| (e.time, e.kind))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (0, TraceEventKind::Arrival),
+                (1, TraceEventKind::ServiceStart),
+                (1, TraceEventKind::ServiceEnd),
+                (1, TraceEventKind::Arrival),
+                (2, TraceEventKind::ServiceStart),
+                (2, TraceEventKind::ServiceEnd),
+                (2, TraceEventKind::Arrival),
+            ]
+        );
+
+        let json = simulation.trace_json();
+        assert!(json.starts_with(r#"{"format":"simul-trace","version":1,"events":[{"time":0,"event":"arrival","agent":"consumer","source":"producer","queued_time":0,"queue_length":1}"#));
+    }
+
+    #[test]
+    fn trace_reordered_arrivals_test() {
+        init();
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer", 1, "consumer"),
+                periodic_consuming_agent("consumer", 5),
+            ],
+            channel_model: ChannelModel::default().with_default_faults(ChannelFaults {
+                loss_probability: 0.0,
+                duplication_probability: 0.0,
+                reorder_probability: 1.0,
+            }),
+            enable_trace: true,
+            halt_check: |s: &Simulation| s.time == 20,
+            seed: Some(7),
+            ..Default::default()
+        });
+        simulation.run();
+
+        // Without latency, every message arrives in the tick it was sent,
+        // wherever in the queue it lands.
+        let arrivals: Vec<TraceEvent> = simulation
+            .trace()
+            .into_iter()
+            .filter(|e| e.kind == TraceEventKind::Arrival)
+            .collect();
+        assert_eq!(arrivals.len(), 20);
+        assert!(arrivals.iter().all(|e| e.queued_time == e.time));
+    }
+
+    #[test]
+    fn replay_agent_test() {
+        init();
+        let parameters = SimulationParameters {
+            agents: vec![
+                poisson_distributed_producing_agent(
+                    "producer".to_string(),
+                    Poisson::new(3.0).unwrap(),
+                    "consumer".to_string(),
+                ),
+                periodic_consuming_agent("consumer".to_string(), 1),
+            ],
+            halt_check: |s: &Simulation| s.time == 50,
+            ..Default::default()
+        };
+        let mut recorded = Simulation::new(parameters.clone());
+        recorded.run();
+
+        // The producer's behavior is frozen, while the consumer changes.
+        let producer = replay_agent(&recorded, "producer", ReplayMode::ByTime).unwrap();
+        let mut replayed = Simulation::new(SimulationParameters {
+            agents: vec![
+                producer,
+                periodic_consuming_agent("consumer".to_string(), 2),
+            ],
+            ..parameters
+        });
+        replayed.run();
+
+        let sent_times = |s: &Simulation| -> Vec<DiscreteTime> {
+            s.produced_for_agent("producer")
+                .unwrap()
+                .iter()
+                .map(|m| m.queued_time)
+                .collect()
+        };
+        assert_eq!(sent_times(&recorded), sent_times(&replayed));
+    }
+
+    #[test]
+    fn export_csv_test() {
+        init();
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer".to_string(), 1, "consumer".to_string()),
+                periodic_consuming_agent("consumer".to_string(), 1),
+            ],
+            enable_queue_depth_metrics: true,
+            halt_check: |s: &Simulation| s.time == 3,
+            ..Default::default()
+        });
+        simulation.run();
+
+        let dir = std::env::temp_dir().join("simul-export-csv-test");
+        simulation.export_csv(&dir).unwrap();
+
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(
+            read("summary.csv"),
+            "agent,queue_length,consumed,produced,average_wait\n\
+             producer,0,0,3,\n\
+             consumer,1,2,0,1\n"
+        );
+        assert_eq!(
+            read("consumer_queue_depth.csv"),
+            "time,queue_depth\n0,0\n1,1\n2,1\n"
+        );
+        assert_eq!(read("producer_produced.csv").lines().count(), 4);
+        assert!(read("consumer_consumed.csv").contains("producer,consumer,0,1,,1"));
+
+        // Ids needing quotes in CSV, or encoding in file names.
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("a,b", 1, "a b"),
+                periodic_consuming_agent("a b", 1),
+                periodic_consuming_agent("a_b", 1),
+            ],
+            halt_check: |s: &Simulation| s.time == 2,
+            ..Default::default()
+        });
+        simulation.run();
+        simulation.export_csv(&dir).unwrap();
+        assert!(read("summary.csv").contains("\n\"a,b\",0,0,2,\n"));
+        assert!(read("a%2Cb_produced.csv").contains("\"a,b\",a b,0,"));
+        assert_eq!(read("a%20b_consumed.csv").lines().count(), 2);
+        assert_eq!(read("a_b_consumed.csv").lines().count(), 1);
+    }
+
+    #[test]
+    fn sequence_diagram_test() {
+        init();
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer", 2, "consumer"),
+                periodic_consuming_agent("consumer", 1),
+            ],
+            halt_check: |s: &Simulation| s.time == 6,
+            ..Default::default()
+        });
+        simulation.run();
+
+        assert_eq!(
+            simulation.sequence_diagram(2..5),
+            "sequenceDiagram\n    \
+             participant p0 as producer\n    \
+             participant p1 as consumer\n    \
+             Note over p0,p1: 2\n    \
+             p0->>p1: message\n    \
+             Note over p0,p1: 4\n    \
+             p0->>p1: message\n"
+        );
+        assert_eq!(simulation.sequence_diagram(..).matches("->>").count(), 3);
+    }
+
+    #[test]
+    fn chrome_trace_test() {
+        init();
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer", 2, "consumer"),
+                periodic_consuming_agent("consumer", 1),
+            ],
+            enable_activity_metrics: true,
+            time_scale: Some(TimeScale::new(Duration::from_millis(1))),
+            halt_check: |s: &Simulation| s.time == 4,
+            ..Default::default()
+        });
+        simulation.run();
+
+        let trace = simulation.chrome_trace();
+        assert!(trace.starts_with("{\"traceEvents\":[{\"name\":\"process_name\""));
+        assert!(trace.contains(
+            "{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":0,\"tid\":1,\"args\":{\"name\":\"consumer\"}}"
+        ));
+        assert!(trace.contains(
+            "{\"name\":\"send\",\"ph\":\"i\",\"s\":\"t\",\"pid\":0,\"tid\":0,\"ts\":2000,\"args\":{\"source\":\"producer\",\"destination\":\"consumer\",\"queued_time\":2}}"
+        ));
+        assert_eq!(trace.matches("\"name\":\"send\"").count(), 2);
+        // Idle spans are gaps in the tracks.
+        let spans = ["producer", "consumer"]
+            .iter()
+            .flat_map(|id| simulation.activity(id).unwrap())
+            .filter(|s| s.activity != Activity::Idle)
+            .count();
+        assert_eq!(trace.matches("\"ph\":\"X\"").count(), spans);
+    }
+
+    #[test]
+    fn message_ledger_test() {
+        init();
+
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer", 1, "consumer"),
+                periodic_producing_agent("stray", 5, "nobody"),
+                periodic_consuming_agent("consumer", 2),
+            ],
+            channel_model: ChannelModel::default().with_default_faults(ChannelFaults {
+                loss_probability: 0.2,
+                duplication_probability: 0.2,
+                reorder_probability: 0.0,
+            }),
+            message_transforms: vec![Box::new(FixedLatency {
+                name: "network",
+                ticks: 3,
+            })],
+            halt_check: |s: &Simulation| s.time == 50,
+            seed: Some(7),
+            ..Default::default()
+        });
+        simulation.run();
+
+        let ledger = simulation.message_ledger();
+        assert_eq!(ledger.produced, 60);
+        assert_eq!(ledger.unroutable, 10);
+        assert!(ledger.lost > 0 && ledger.duplicated > 0);
+        assert!(ledger.in_flight > 0 && ledger.queued > 0);
+        assert_consumed!(simulation, "consumer", == ledger.processed);
+        assert_messages_conserved!(simulation);
+    }
+
+    #[test]
+    fn queue_stability_test() {
+        init();
+
+        let run = |producer_period, enable_queue_depth_metrics| {
+            let mut simulation = Simulation::new(SimulationParameters {
+                agents: vec![
+                    periodic_producing_agent("producer", producer_period, "consumer"),
+                    periodic_consuming_agent("consumer", 2),
+                ],
+                enable_queue_depth_metrics,
+                halt_check: |s: &Simulation| s.time == 100,
+                ..Default::default()
+            });
+            simulation.run();
+            simulation
+        };
+
+        for enable_queue_depth_metrics in [false, true] {
+            let overloaded = run(1, enable_queue_depth_metrics);
+            let stability = overloaded.queue_stability("consumer").unwrap();
+            assert!((stability.growth_rate - 0.5).abs() < 0.02);
+            assert_eq!(overloaded.unstable_agents(), vec!["consumer"]);
+            assert_eq!(overloaded.report().unstable_agents, vec!["consumer"]);
+
+            let underloaded = run(3, enable_queue_depth_metrics);
+            assert!(underloaded.unstable_agents().is_empty());
+
+            let mut tolerant = overloaded;
+            tolerant.unstable_growth_rate = 0.6;
+            assert!(tolerant.unstable_agents().is_empty());
+        }
+    }
+
+    #[test]
+    fn littles_law_test() {
+        init();
+
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer", 3, "consumer"),
+                periodic_consuming_agent("consumer", 2),
+            ],
+            enable_queue_depth_metrics: true,
+            halt_check: |s: &Simulation| s.time == 600,
+            ..Default::default()
+        });
+        simulation.run();
+
+        let consumer = simulation.littles_law("consumer").unwrap();
+        assert!((consumer.arrival_rate - 1.0 / 3.0).abs() < 0.01);
+        assert!(consumer.mean_wait > 0.0);
+        assert!(consumer.relative_error() < 0.05, "{:?}", consumer);
+
+        // The producer never receives anything.
+        let report = simulation.littles_law_report();
+        assert_eq!(report.len(), 2);
+        assert_eq!(report[0].1.relative_error(), 0.0);
+        assert!(simulation.littles_law("missing").is_none());
+    }
+
+    #[test]
+    fn activity_test() {
+        init();
+
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer", 4, "consumer"),
+                periodic_consuming_agent("consumer", 2),
+            ],
+            enable_activity_metrics: true,
+            halt_check: |s: &Simulation| s.time == 8,
+            ..Default::default()
+        });
+        simulation.run();
+
+        // The consumer starts asleep, then consumes each message the tick
+        // after it was sent, and sleeps until its next period.
+        let span = |from, until, activity| ActivitySpan {
+            from,
+            until,
+            activity,
+        };
+        assert_eq!(
+            simulation.activity("consumer").unwrap(),
+            &[
+                span(0, 2, Activity::Asleep),
+                span(2, 3, Activity::Processing),
+                span(3, 4, Activity::Asleep),
+                span(4, 5, Activity::Idle),
+                span(5, 6, Activity::Processing),
+                span(6, 7, Activity::Asleep),
+                span(7, 8, Activity::Idle),
+            ]
+        );
+        assert_eq!(simulation.utilization("consumer"), Some(0.25));
+    }
+
+    #[test]
+    fn wait_time_histogram_test() {
+        init();
+
+        // E.g. replayed from a trace whose clocks were skewed.
+        let mut consumer = periodic_consuming_agent("consumer", 1);
+        consumer.state_mut().consumed.push(Message {
+            completed_time: Some(3),
+            ..Message::new(5, "producer", "consumer")
+        });
+        let simulation = Simulation::new(SimulationParameters {
+            agents: vec![consumer],
+            ..Default::default()
+        });
+
+        let histogram = simulation.wait_time_histogram("consumer").unwrap();
+        assert_eq!(histogram.percentile(100.0), Some(0));
+        assert!(simulation.wait_time_histogram("unknown").is_none());
+    }
+
+    #[test]
+    fn rolling_wait_time_percentiles_test() {
+        init();
+
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer", 1, "consumer"),
+                periodic_consuming_agent("consumer", 2),
+            ],
+            halt_check: |s: &Simulation| s.time == 100,
+            ..Default::default()
+        });
+        simulation.run();
+
+        let rolling = simulation
+            .rolling_wait_time_percentiles("consumer", 10, &[50.0, 99.0])
+            .unwrap();
+        assert_eq!(rolling.first().unwrap().0, 2);
+        assert_eq!(rolling.last().unwrap().0, 99);
+
+        // The consumer is saturated, so waits grow over the run.
+        let (early, late) = (&rolling[10].1, &rolling[90].1);
+        assert!(early[0] <= early[1] && late[0] <= late[1]);
+        assert!(late[0] > early[0] + 30);
+        assert!(simulation
+            .rolling_wait_time_percentiles("unknown", 10, &[50.0])
+            .is_none());
+    }
+
+    #[test]
+    fn totals_test() {
+        init();
+
+        // Halts once 5 messages were consumed, without scanning any Agent.
+        for enable_parallel_agents in [false, true] {
+            let mut server = workload::serving_agent("server", 1);
+            server.push_message(Message::new(0, "producer", "server"));
+            let mut simulation = Simulation::new(SimulationParameters {
+                agents: vec![
+                    periodic_producing_agent("producer", 1, "consumer"),
+                    periodic_consuming_agent("consumer", 2),
+                    server,
+                ],
+                enable_parallel_agents,
+                halt_check: |s: &Simulation| s.totals().consumed == 5,
+                ..Default::default()
+            });
+            simulation.run();
+
+            let totals = *simulation.totals();
+            assert_eq!(totals.consumed, 5);
+            assert_eq!(simulation.consumed_count("consumer"), Some(4));
+            assert_eq!(simulation.consumed_count("server"), Some(1));
+            let produced = ["producer", "server"].map(|id| simulation.produced_count(id));
+            assert_eq!(totals.produced, produced.iter().flatten().sum());
+            assert_eq!(Some(totals.queued), simulation.queue_len("consumer"));
+        }
+        assert_eq!(
+            Simulation::new(SimulationParameters::default()).consumed_count("unknown"),
+            None
+        );
+    }
+
+    #[test]
+    fn set_metric_test() {
+        init();
+
+        // Records queue depths only from tick 5 to 7, and from tick 10 on.
+        #[agent]
+        struct Monitor {}
+
+        impl Agent for Monitor {
+            fn process(&mut self, state: SimulationState, _msg: &Message) -> Option<Vec<Message>> {
+                let enabled = match state.time {
+                    4 | 9 => true,
+                    7 => false,
+                    _ => return None,
+                };
+                Some(vec![Message {
+                    source: self.state.id.clone(),
+                    destination: "engine".to_string(),
+                    interrupt: Some(Interrupt::SetMetric {
+                        metric: Metric::QueueDepth,
+                        enabled,
+                    }),
+                    ..Default::default()
+                }])
+            }
+        }
+
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                Box::new(Monitor {
+                    state: AgentState {
+                        mode: AgentMode::Proactive,
+                        wake_mode: AgentMode::Proactive,
+                        id: "monitor".to_string(),
+                        ..Default::default()
+                    },
+                }),
+                periodic_producing_agent("producer", 1, "consumer"),
+                periodic_consuming_agent("consumer", 2),
+            ],
+            halt_check: |s: &Simulation| s.time == 12,
+            ..Default::default()
+        });
+        simulation.run();
+
+        let times: Vec<DiscreteTime> = simulation
+            .queue_depth_timeline("consumer")
+            .unwrap()
+            .iter()
+            .map(|(time, _)| *time)
+            .collect();
+        assert_eq!(times, vec![5, 6, 7, 10, 11]);
+
+        simulation.warm_up = Some(7);
+        assert_eq!(simulation.queue_depth_metrics("consumer").unwrap().len(), 3);
+    }
+
+    #[test]
+    fn throughput_test() {
+        init();
+
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer", 1, "consumer"),
+                periodic_consuming_agent("consumer", 1),
+            ],
+            throughput_window: Some(10),
+            halt_check: |s: &Simulation| s.time == 25,
+            ..Default::default()
+        });
+        simulation.run();
+
+        // The consumer keeps up with the producer once its first message arrives.
+        assert_eq!(
+            simulation.throughput("consumer").unwrap(),
+            &[(0, 9), (10, 10), (20, 5)]
+        );
+        let total: usize = simulation
+            .throughput("consumer")
+            .unwrap()
+            .iter()
+            .map(|(_, c)| c)
+            .sum();
+        assert_eq!(simulation.consumed_count("consumer"), Some(total));
+    }
+
+    #[test]
+    fn concurrency_test() {
+        init();
+
+        let run = |concurrency| {
+            let mut consumer = periodic_consuming_agent("consumer", 4);
+            consumer.state_mut().options.concurrency = concurrency;
+            let mut simulation = Simulation::new(SimulationParameters {
+                agents: vec![
+                    periodic_producing_agent("producer", 1, "consumer"),
+                    consumer,
+                ],
+                halt_check: |s: &Simulation| s.time == 100,
+                ..Default::default()
+            });
+            simulation.run();
+            simulation
+        };
+
+        // One server takes 4 ticks per message; four keep up with a message
+        // per tick, serving them in batches as the consumer starts asleep.
+        let single = run(1);
+        let pool = run(4);
+        assert_consumed!(single, "consumer", == 24);
+        assert_consumed!(pool, "consumer", == 96);
+        assert!(pool.queue_len("consumer").unwrap() <= 4);
+        assert!(single.slot_utilization("consumer").is_none());
+
+        let utilization = pool.slot_utilization("consumer").unwrap();
+        assert_eq!(utilization.len(), 4);
+        assert!(utilization.iter().all(|u| *u > 0.9), "{:?}", utilization);
+        // The last batch is done as the run ends.
+        assert_eq!(pool.busy_slots("consumer"), Some(0));
+        assert!(pool.message_ledger().is_balanced());
+    }
+
+    #[test]
+    fn topology_partition_test() {
+        init();
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer".to_string(), 1, "consumer".to_string()),
+                periodic_consuming_agent("consumer".to_string(), 1),
+            ],
+            topology: Topology::default().with_partition(1, 3, &["producer"], &["consumer"]),
+            halt_check: |s: &Simulation| s.time == 6,
+            ..Default::default()
+        });
+        simulation.run();
+
+        assert_produced!(simulation, "producer", == 6);
+        assert_consumed!(simulation, "consumer", == 3);
+        let metrics = simulation.channel_metrics("producer", "consumer").unwrap();
+        assert_eq!(metrics.partitioned, 2);
+
+        let events = simulation.topology_events();
+        assert_eq!(events.len(), 4);
+        assert!(events[..2].iter().all(|e| e.time == 1 && !e.up));
+        assert!(events[2..].iter().all(|e| e.time == 3 && e.up));
+        assert!(simulation.topology.is_linked("producer", "consumer"));
+    }
+
+    #[test]
+    fn topology_edges_test() {
+        init();
+        /// Sends three messages to the consumer at once, at the start.
+        #[agent]
+        struct Burst {}
+
+        impl Agent for Burst {
+            fn process(&mut self, state: SimulationState, _: &Message) -> Option<Vec<Message>> {
+                self.state.mode = AgentMode::Dead;
+                Some(vec![Message::new(state.time, "burst", "consumer"); 3])
+            }
+        }
+
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                Box::new(Burst {
+                    state: AgentState {
+                        mode: AgentMode::Proactive,
+                        id: "burst".to_string(),
+                        ..Default::default()
+                    },
+                }),
+                periodic_producing_agent("stranger".to_string(), 1, "consumer".to_string()),
+                periodic_consuming_agent("consumer".to_string(), 1),
+            ],
+            topology: Topology::default()
+                .with_edge(
+                    "burst",
+                    "consumer",
+                    Edge {
+                        latency: 2,
+                        capacity: Some(1),
+                    },
+                )
+                .with_edges_only(),
+            halt_check: |s: &Simulation| s.time == 10,
+            ..Default::default()
+        });
+        simulation.run();
+
+        // One message departs per tick, and each takes 2 ticks to arrive.
+        let completed: Vec<_> = simulation
+            .consumed_for_agent("consumer")
+            .unwrap()
+            .iter()
+            .map(|m| m.completed_time.unwrap())
+            .collect();
+        assert_eq!(completed, [3, 4, 5]);
+        let metrics = simulation.channel_metrics("burst", "consumer").unwrap();
+        assert_eq!(metrics.congested, 2);
+
+        // The stranger has no edge to the consumer.
+        let metrics = simulation.channel_metrics("stranger", "consumer").unwrap();
+        assert_eq!(metrics.partitioned, 10);
+        assert!(simulation.message_ledger().is_balanced());
+    }
+
+    #[test]
+    fn topology_dot_test() {
+        init();
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer".to_string(), 2, "consumer".to_string()),
+                periodic_consuming_agent("consumer".to_string(), 1),
+            ],
+            topology: Topology::default().with_edge("consumer", "producer", Edge::default()),
+            halt_check: |s: &Simulation| s.time == 6,
+            ..Default::default()
+        });
+        simulation.run();
+
+        assert_eq!(
+            simulation.topology_dot(),
+            "digraph simulation {\n  \"producer\";\n  \"consumer\";\n  \"producer\" -> \"consumer\" [label=\"3\", weight=3];\n  \"consumer\" -> \"producer\" [style=dashed];\n}\n"
+        );
+    }
+
+    #[test]
+    fn contract_net_test() {
+        init();
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("tasks".to_string(), 5, "manager".to_string()),
+                contract::contract_net_manager(
+                    "manager",
+                    vec!["expensive".to_string(), "cheap".to_string()],
+                    2,
+                ),
+                contract::contract_net_contractor("expensive", |_| Some(10), 1),
+                contract::contract_net_contractor("cheap", |_| Some(5), 1),
+            ],
+            halt_check: |s: &Simulation| s.time == 22,
+            ..Default::default()
+        });
+        simulation.run();
+
+        // The last task is still out for bids when the simulation halts.
+        let outcomes = contract::contract_net_outcomes(&simulation, "manager").unwrap();
+        assert_eq!(outcomes.announced, 5);
+        assert_eq!(outcomes.awarded, 4);
+        assert_eq!(outcomes.completed, 4);
+        assert_eq!(outcomes.mean_completion_time, Some(6.0));
+        assert_consumed!(simulation, "cheap", == 4);
+        assert_consumed!(simulation, "expensive", == 0);
+    }
+
+    #[test]
+    fn contract_net_correlated_task_test() {
+        init();
+        let mut manager = contract::contract_net_manager("manager", vec!["cheap".to_string()], 3);
+        for id in [7, 3] {
+            manager.push_message(Message {
+                correlation_id: Some(id),
+                ..Message::new(0, "client", "manager")
+            });
+        }
+
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                manager,
+                contract::contract_net_contractor("cheap", |_| Some(5), 1),
+            ],
+            halt_check: |s: &Simulation| s.time == 10,
+            ..Default::default()
+        });
+        simulation.run();
+
+        // Tasks carrying their own correlation id are contracted out like any other.
+        let outcomes = contract::contract_net_outcomes(&simulation, "manager").unwrap();
+        assert_eq!(outcomes.announced, 2);
+        assert_eq!(outcomes.awarded, 2);
+        assert_eq!(outcomes.completed, 2);
+
+        // Contracts that expire together are awarded in the order they opened.
+        let correlation_ids = |f: fn(&contract::ContractMessage) -> bool| {
+            let manager = simulation.agent("manager").unwrap();
+            manager
+                .state()
+                .produced
+                .iter()
+                .filter(|m| contract::ContractMessage::from_message(m).is_some_and(|c| f(&c)))
+                .filter_map(|m| m.correlation_id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            correlation_ids(|c| matches!(c, contract::ContractMessage::Award(_))),
+            correlation_ids(|c| matches!(c, contract::ContractMessage::CallForProposals(_))),
+        );
+    }
+
+    #[test]
+    fn request_reply_test() {
+        init();
+
+        #[agent]
+        struct Client {
+            pending: Option<RequestHandle>,
+            replies: u32,
+        }
+
+        impl Agent for Client {
+            fn process(&mut self, state: SimulationState, msg: &Message) -> Option<Vec<Message>> {
+                if self.pending.is_some_and(|p| p.matches(msg)) {
+                    self.pending = None;
+                    self.replies += 1;
+                }
+
+                if self.pending.is_some() {
+                    return None;
+                }
+
+                let (request, handle) =
+                    Message::request(state.time, self.state.id.as_str(), "server", None);
+                self.pending = Some(handle);
+                Some(vec![request])
+            }
+        }
+
+        #[agent]
+        struct Server {}
+
+        impl Agent for Server {
+            fn process(&mut self, state: SimulationState, msg: &Message) -> Option<Vec<Message>> {
+                Some(vec![msg.reply(state.time, msg.custom_payload.clone())])
+            }
+        }
+
+        let parameters = SimulationParameters {
+            agents: vec![
+                Box::new(Client {
+                    pending: None,
+                    replies: 0,
+                    state: AgentState {
+                        mode: AgentMode::Proactive,
+                        wake_mode: AgentMode::Proactive,
+                        id: "client".to_string(),
+                        ..Default::default()
+                    },
+                }),
+                Box::new(Server {
+                    state: AgentState {
+                        mode: AgentMode::Reactive,
+                        wake_mode: AgentMode::Reactive,
+                        id: "server".to_string(),
+                        ..Default::default()
+                    },
+                }),
+            ],
+            halt_check: |s: &Simulation| s.time == 6,
+            ..Default::default()
+        };
+        let mut simulation = Simulation::new(parameters.clone());
+        simulation.run();
+        let correlation_ids = |s: &Simulation| {
+            s.produced_for_agent("client")
+                .unwrap()
+                .iter()
+                .map(|m| m.correlation_id)
+                .collect::<Vec<_>>()
+        };
+
+        // One round trip every two ticks, each reply correlated to its request.
+        assert_produced!(simulation, "client", == 3);
+        let requests = simulation.produced_for_agent("client").unwrap();
+        let replies = simulation.produced_for_agent("server").unwrap();
+        assert_eq!(replies.len(), 3);
+        for (request, reply) in requests.iter().zip(replies.iter()) {
+            assert_eq!(request.correlation_id, reply.correlation_id);
+        }
+
+        // Every run numbers its requests from 0, whatever ran before it.
+        assert_eq!(correlation_ids(&simulation), [Some(0), Some(1), Some(2)]);
+        let mut rerun = Simulation::new(parameters);
+        rerun.run();
+        assert_eq!(correlation_ids(&rerun), correlation_ids(&simulation));
+    }
+
+    #[test]
+    fn report_sink_test() {
+        init();
+
+        #[derive(Clone, Debug)]
+        struct Recorder {
+            reports: std::sync::Arc<std::sync::Mutex<Vec<Report>>>,
+        }
+
+        impl ReportSink for Recorder {
+            fn write(&mut self, report: &Report) -> std::io::Result<()> {
+                self.reports.lock().unwrap().push(report.clone());
+                Ok(())
+            }
+
+            fn cadence(&self) -> Cadence {
+                Cadence::EveryNTicks(2)
+            }
+        }
+
+        let reports = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer".to_string(), 1, "consumer".to_string()),
+                periodic_consuming_agent("consumer".to_string(), 1),
+            ],
+            report_sinks: vec![Box::new(Recorder {
+                reports: reports.clone(),
+            })],
+            halt_check: |s: &Simulation| s.time == 5,
+            ..Default::default()
+        });
+        simulation.run();
+
+        let reports = reports.lock().unwrap();
+        let times: Vec<DiscreteTime> = reports.iter().map(|r| r.time).collect();
+        assert_eq!(times, vec![1, 3, 5]);
+        assert_eq!(
+            reports[1].window,
+            Some(ReportWindow {
+                from: 2,
+                ticks: 2,
+                messages_delivered: 2,
+            })
+        );
+        assert_eq!(reports.last().unwrap().mode, SimulationMode::Completed);
+        assert_eq!(reports.last().unwrap().produced["producer"], 5);
+
+        let mut report = reports[0].clone();
+        report.unstable_agents = vec!["a\u{1b}\"b".to_string()];
+        assert!(report
+            .to_json()
+            .contains(r#""unstable_agents":["a\u001b\"b"]"#));
+    }
+
+    #[test]
+    fn webhook_report_sink_test() {
+        use std::io::{BufRead, Read, Write};
+
+        // Answers each report with the next status, after reading all of it.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut sink = WebhookReportSink {
+            url: format!("http://{}/reports", listener.local_addr().unwrap()),
+            cadence: Cadence::OnCompletion,
+        };
+        let server = std::thread::spawn(move || {
+            for status in ["204 No Content", "500 Internal Server Error"] {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = std::io::BufReader::new(stream);
+                let mut content_length = 0;
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    if let Some(length) = line.strip_prefix("Content-Length: ") {
+                        content_length = length.trim().parse().unwrap();
+                    }
+                    line.clear();
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                write!(reader.get_mut(), "HTTP/1.1 {}\r\n\r\n", status).unwrap();
+            }
+        });
+
+        let report = Simulation::new(SimulationParameters::default()).report();
+        assert!(sink.write(&report).is_ok());
+        let error = sink.write(&report).unwrap_err();
+        assert!(error.to_string().contains("500"), "{}", error);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn parallel_agents_test() {
+        init();
+        let parameters = SimulationParameters {
+            agents: (0..16)
+                .flat_map(|i| {
+                    [
+                        poisson_distributed_producing_agent(
+                            format!("producer{}", i),
+                            Poisson::new(3.0).unwrap(),
+                            format!("consumer{}", i),
+                        ),
+                        periodic_consuming_agent(format!("consumer{}", i), 2),
+                    ]
+                })
+                .collect(),
+            halt_check: |s: &Simulation| s.time == 100,
+            seed: Some(7),
+            ..Default::default()
+        };
+
+        let mut sequential = Simulation::new(parameters.clone());
+        sequential.run();
+        let mut parallel = Simulation::new(SimulationParameters {
+            enable_parallel_agents: true,
+            ..parameters
+        });
+        parallel.run();
+
+        assert_eq!(
+            sequential.calc_produced_len_statistics(),
+            parallel.calc_produced_len_statistics()
+        );
+        assert_eq!(
+            sequential.calc_avg_wait_statistics(),
+            parallel.calc_avg_wait_statistics()
+        );
+    }
+
+    #[test]
+    fn non_send_agents_test() {
+        init();
+        /// Counts the messages it gets in a counter it shares, so isn't Send.
+        #[agent]
+        struct Counter {
+            count: std::rc::Rc<std::cell::Cell<usize>>,
+        }
+
+        impl Agent for Counter {
+            fn process(&mut self, state: SimulationState, msg: &Message) -> Option<Vec<Message>> {
+                self.count.set(self.count.get() + 1);
+                self.state.consumed.push(Message {
+                    completed_time: Some(state.time),
+                    ..msg.clone()
+                });
+                None
+            }
+        }
+
+        let count = std::rc::Rc::new(std::cell::Cell::new(0));
+        let mut counter: Box<dyn Agent> = Box::new(Counter {
+            count: count.clone(),
+            state: AgentState {
+                mode: AgentMode::Reactive,
+                wake_mode: AgentMode::Reactive,
+                id: "counter".to_string(),
+                ..Default::default()
+            },
+        });
+        let mut producer = periodic_producing_agent("producer", 1, "counter");
+        assert!(counter.as_send().is_none() && counter.clone_send().is_none());
+        assert!(producer.as_send().is_some() && producer.clone_send().is_some());
+
+        let parameters = SimulationParameters {
+            agents: vec![producer, counter],
+            halt_check: |s: &Simulation| s.time == 10,
+            enable_parallel_agents: true,
+            ..Default::default()
+        };
+        let mut simulation = Simulation::new(parameters.clone());
+        simulation.run();
+        assert_eq!(count.get(), 9);
+        assert!(simulation.message_ledger().is_balanced());
+
+        // The ensemble runs them one after another on this thread instead.
+        let report = experiment::run_ensemble(
+            &parameters,
+            &[1, 2],
+            &[("consumed", |s| s.consumed_count("counter").unwrap() as f64)],
+        );
+        assert_eq!(report.rows, [(1, vec![9.0]), (2, vec![9.0])]);
+        assert_eq!(count.get(), 27);
+    }
+
+    #[test]
+    fn simulation_macro_test() {
+        init();
+        let mut simulation = Simulation::new(simul_macro::simulation! {
+            agents {
+                producer: periodic_producing_agent(@producer, 1, @consumer),
+                consumer: periodic_consuming_agent(@consumer, 1),
+            }
+            halt_check: |s: &Simulation| s.time == 5,
+            seed: Some(1)
+        });
+        simulation.run();
+
+        assert_produced!(simulation, "producer", == 5);
+        assert_consumed!(simulation, "consumer", == 4);
+    }
+
+    #[test]
+    fn halt_interrupt_test() {
+        init();
+
+        #[agent]
+        struct Quitter {}
+
+        impl Agent for Quitter {
+            fn process(&mut self, _: SimulationState, _: &Message) -> Option<Vec<Message>> {
+                Some(vec![Message {
+                    source: self.state().id.clone(),
+                    interrupt: Some(Interrupt::HaltSimulation("done".to_string())),
+                    ..Default::default()
+                }])
+            }
+        }
+
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![Box::new(Quitter {
+                state: AgentState {
+                    mode: AgentMode::Proactive,
+                    wake_mode: AgentMode::Proactive,
+                    id: "quitter".to_string(),
+                    ..Default::default()
+                },
+            })],
+            halt_check: |s: &Simulation| s.time == 5,
+            ..Default::default()
+        });
+        simulation.run();
+
+        assert_eq!(simulation.time, 1);
+        assert_halted_by!(simulation, Interrupt(_));
+        assert_eq!(
+            simulation.halt_reason,
+            Some(HaltReason::Interrupt("done".to_string()))
+        );
+    }
+
+    #[test]
+    fn limits_test() {
+        init();
+        let run = |max_ticks, max_wall_clock| {
+            let mut simulation = Simulation::new(SimulationParameters {
+                agents: vec![periodic_consuming_agent("consumer".to_string(), 1)],
+                halt_check: |_| false,
+                starting_time: 3,
+                max_ticks,
+                max_wall_clock,
+                ..Default::default()
+            });
+            simulation.run();
+            simulation
+        };
+
+        let simulation = run(Some(5), None);
+        assert_eq!(simulation.time, 8);
+        assert_halted_by!(simulation, MaxTicks);
+
+        let simulation = run(None, Some(Duration::ZERO));
+        assert_eq!(simulation.time, 3);
+        assert_halted_by!(simulation, MaxWallClock);
+    }
+
+    #[test]
+    fn step_test() {
+        init();
+        let simulation = || {
+            Simulation::new(SimulationParameters {
+                agents: vec![
+                    periodic_producing_agent("producer".to_string(), 1, "consumer".to_string()),
+                    periodic_consuming_agent("consumer".to_string(), 1),
+                ],
+                halt_check: |s: &Simulation| s.time == 5,
+                ..Default::default()
+            })
+        };
+        let mut run = simulation();
+        run.run();
+        let mut simulation = simulation();
+
+        simulation.step();
+        simulation.step();
+        assert_eq!(simulation.time, 2);
+        assert_eq!(simulation.mode, SimulationMode::Paused);
+        assert_eq!(simulation.halt_reason, None);
+
+        // It halts rather than pauses, if it gets there first.
+        simulation.run_for(10);
+        assert_eq!(simulation.time, 5);
+        assert_halted_by!(simulation, HaltCheck);
+        assert_eq!(
+            simulation.consumed_count("consumer"),
+            run.consumed_count("consumer")
+        );
+    }
+
+    #[test]
+    fn late_agents_test() {
+        init();
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![periodic_producing_agent(
+                "producer".to_string(),
+                1,
+                "consumer".to_string(),
+            )],
+            halt_check: |s: &Simulation| s.time == 5,
+            ..Default::default()
+        });
+        simulation
+            .agents
+            .push(periodic_consuming_agent("consumer".to_string(), 1));
+        simulation.run();
+
+        assert_consumed!(simulation, "consumer", == 4);
+        assert!(simulation.message_ledger().is_balanced());
+    }
+
+    #[test]
+    #[should_panic(expected = "More than one agent has the id \"consumer\"")]
+    fn duplicate_agents_test() {
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer".to_string(), 1, "consumer".to_string()),
+                periodic_consuming_agent("consumer".to_string(), 1),
+                periodic_consuming_agent("consumer".to_string(), 1),
+            ],
+            halt_check: |s: &Simulation| s.time == 5,
+            ..Default::default()
+        });
+        simulation.run();
+    }
+
+    #[test]
+    fn message_matrix_test() {
+        init();
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer".to_string(), 1, "consumer".to_string()),
+                periodic_consuming_agent("consumer".to_string(), 1),
+            ],
+            halt_check: |s: &Simulation| s.time == 5,
+            ..Default::default()
+        });
+        simulation.run();
+
+        let matrix = simulation.message_matrix();
+        assert_eq!(matrix.count("producer", "consumer"), Some(5));
+        assert_eq!(matrix.count("consumer", "producer"), Some(0));
+        assert_eq!(matrix.mean_latency("producer", "consumer"), Some(1.0));
+        assert_eq!(
+            matrix.to_csv(),
+            "source,destination,count,mean_latency\nproducer,consumer,5,1\n"
+        );
+
+        let matrix = stats::MessageMatrix {
+            agent_ids: vec!["a,b".to_string(), "c".to_string()],
+            counts: vec![vec![0, 1], vec![0, 0]],
+            mean_latencies: vec![vec![None; 2]; 2],
+        };
+        assert_eq!(
+            matrix.to_csv(),
+            "source,destination,count,mean_latency\n\"a,b\",c,1,\n"
+        );
+    }
+
+    #[test]
+    fn starbucks_clerk() {
+        init();
+
+        #[agent]
+        struct Clerk {}
 
         impl Agent for Clerk {
             fn process(
@@ -409,6 +3355,7 @@ mod tests {
                     },
                 }),
             ],
+            ..Default::default()
         });
 
         simulation.run();
@@ -0,0 +1,227 @@
+//! The `simul` binary: runs a scenario file and writes its metrics, and its
+//! plots with the `plot` feature, to an output directory.
+//!
+//! ```text
+//! simul <scenario> [--out <dir>] [--replications <n>] [--seed <seed>]
+//! simul ensemble <scenario> --seeds <from>..<to> [--out <csv>]
+//! ```
+//!
+//! One run writes `report.json`, `topology.dot` and the CSV files of
+//! `export_csv`. With more than one replication, `replications.csv` has the
+//! metrics of every run, and their estimates are printed. See `simul::scenario` for the format.
+//!
+//! `ensemble` runs the scenario once per seed of the range, which excludes
+//! `<to>` unless written `<from>..=<to>`, writes the metrics of every run as
+//! CSV (`ensemble.csv` by default), and prints their summary.
+
+use simul::experiment::{self, completion_time, mean_queue_length, mean_wait_time, replicate, Kpi};
+use simul::scenario::Scenario;
+use simul::{Severity, Simulation};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: simul <scenario> [--out <dir>] [--replications <n>] [--seed <seed>]
+       simul ensemble <scenario> --seeds <from>..<to> [--out <csv>]";
+
+/// The metrics measured of every run of replications and ensembles.
+const METRICS: [(&str, Kpi); 3] = [
+    ("completion_time", completion_time),
+    ("mean_wait_time", mean_wait_time),
+    ("mean_queue_length", mean_queue_length),
+];
+
+/// The command-line arguments of a run.
+struct Args {
+    scenario: PathBuf,
+    out: PathBuf,
+    replications: Option<usize>,
+    seed: Option<u64>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut scenario = None;
+    let mut out = PathBuf::from("out");
+    let mut replications = None;
+    let mut seed = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| args.next().ok_or_else(|| format!("{} needs a value", flag));
+        match arg.as_str() {
+            "--out" => out = PathBuf::from(value("--out")?),
+            "--replications" => {
+                let n = value("--replications")?;
+                replications = Some(n.parse().map_err(|_| format!("bad --replications {}", n))?);
+            }
+            "--seed" => {
+                let s = value("--seed")?;
+                seed = Some(s.parse().map_err(|_| format!("bad --seed {}", s))?);
+            }
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            flag if flag.starts_with('-') => return Err(format!("unknown flag {}", flag)),
+            _ if scenario.is_some() => return Err(USAGE.to_string()),
+            _ => scenario = Some(PathBuf::from(arg)),
+        }
+    }
+
+    Ok(Args {
+        scenario: scenario.ok_or_else(|| USAGE.to_string())?,
+        out,
+        replications,
+        seed,
+    })
+}
+
+/// The command-line arguments of an ensemble.
+struct EnsembleArgs {
+    scenario: PathBuf,
+    out: PathBuf,
+    seeds: Vec<u64>,
+}
+
+/// Parses a range of seeds, `<from>..<to>` or `<from>..=<to>`.
+fn parse_seeds(range: &str) -> Option<Vec<u64>> {
+    let (from, to) = range.split_once("..")?;
+    let from: u64 = from.parse().ok()?;
+    match to.strip_prefix('=') {
+        Some(to) => Some((from..=to.parse().ok()?).collect()),
+        None => Some((from..to.parse().ok()?).collect()),
+    }
+}
+
+fn parse_ensemble_args(mut args: impl Iterator<Item = String>) -> Result<EnsembleArgs, String> {
+    let mut scenario = None;
+    let mut out = PathBuf::from("ensemble.csv");
+    let mut seeds = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| args.next().ok_or_else(|| format!("{} needs a value", flag));
+        match arg.as_str() {
+            "--out" => out = PathBuf::from(value("--out")?),
+            "--seeds" => {
+                let range = value("--seeds")?;
+                seeds = Some(parse_seeds(&range).ok_or_else(|| format!("bad --seeds {}", range))?);
+            }
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            flag if flag.starts_with('-') => return Err(format!("unknown flag {}", flag)),
+            _ if scenario.is_some() => return Err(USAGE.to_string()),
+            _ => scenario = Some(PathBuf::from(arg)),
+        }
+    }
+
+    let seeds = seeds.ok_or_else(|| "ensemble needs --seeds".to_string())?;
+    if seeds.is_empty() {
+        return Err("--seeds is an empty range".to_string());
+    }
+    Ok(EnsembleArgs {
+        scenario: scenario.ok_or_else(|| USAGE.to_string())?,
+        out,
+        seeds,
+    })
+}
+
+fn run_ensemble(args: EnsembleArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let scenario = Scenario::load(&args.scenario)?;
+    let parameters = scenario.parameters();
+    let diagnostics = Simulation::new(parameters.clone()).validate();
+    for diagnostic in diagnostics.iter() {
+        eprintln!("simul: {:?}: {}", diagnostic.severity(), diagnostic);
+    }
+    if diagnostics.iter().any(|d| d.severity() == Severity::Error) {
+        return Err("the scenario isn't valid".into());
+    }
+
+    let report = experiment::run_ensemble(&parameters, &args.seeds, &METRICS);
+    report.write_csv(&args.out)?;
+    println!(
+        "{}: ran {} seeds, wrote {}",
+        scenario.name.as_deref().unwrap_or("scenario"),
+        args.seeds.len(),
+        args.out.display()
+    );
+    for kpi in report.summary() {
+        println!(
+            "  {}: mean {:.3}, std dev {:.3}, min {:.3}, max {:.3}",
+            kpi.name, kpi.mean, kpi.std_dev, kpi.min, kpi.max
+        );
+    }
+    Ok(())
+}
+
+fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    let mut scenario = Scenario::load(&args.scenario)?;
+    if let Some(n) = args.replications {
+        scenario.replications = n.max(1);
+    }
+    if args.seed.is_some() {
+        scenario.config.seed = args.seed;
+    }
+    let parameters = scenario.parameters();
+
+    let mut simulation = Simulation::new(parameters.clone());
+    let diagnostics = simulation.validate();
+    for diagnostic in diagnostics.iter() {
+        eprintln!("simul: {:?}: {}", diagnostic.severity(), diagnostic);
+    }
+    if diagnostics.iter().any(|d| d.severity() == Severity::Error) {
+        return Err("the scenario isn't valid".into());
+    }
+    std::fs::create_dir_all(&args.out)?;
+    simulation.run();
+    std::fs::write(args.out.join("report.json"), simulation.report().to_json())?;
+    simulation.export_csv(&args.out)?;
+    std::fs::write(args.out.join("topology.dot"), simulation.topology_dot())?;
+    #[cfg(feature = "plot")]
+    for id in scenario.config.agents.iter().map(|a| &a.state().id) {
+        let path = args.out.join(format!("{}.svg", id));
+        simul::plot::agent_plot(&simulation, id, path, &Default::default())?;
+    }
+    println!(
+        "{}: ran {} ticks, wrote {}",
+        scenario.name.as_deref().unwrap_or("scenario"),
+        simulation.time,
+        args.out.display()
+    );
+
+    if scenario.replications > 1 {
+        let report = replicate(
+            &parameters,
+            scenario.replications,
+            scenario.config.seed.unwrap_or_default(),
+            &METRICS,
+        );
+        report.runs.write_csv(args.out.join("replications.csv"))?;
+        println!("{} replications:", scenario.replications);
+        for metric in report.metrics.iter() {
+            let (low, high) = metric.confidence_interval;
+            println!(
+                "  {}: {:.3} (95% CI {:.3} to {:.3})",
+                metric.name, metric.mean, low, high
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1).peekable();
+    let result = if args.peek().map(String::as_str) == Some("ensemble") {
+        parse_ensemble_args(args.skip(1)).map(run_ensemble)
+    } else {
+        parse_args(args).map(run)
+    };
+    let result = match result {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("simul: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -1,9 +1,80 @@
-use crate::DiscreteTime;
+use crate::{BlackboardValue, CustomCommand, DiscreteTime, GroupDelivery, Metric};
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 
 #[derive(Clone, Debug)]
 pub enum Interrupt {
     /// Immediately halt the simulation (with some reason why).
     HaltSimulation(String),
+    /// Pause the simulation at the end of this tick (with some reason why),
+    /// until it is run again.
+    PauseSimulation(String),
+    /// Take a checkpoint of the simulation at the end of this tick; see
+    /// `Simulation::checkpoints`.
+    CheckpointNow,
+    /// Raise an alarm (with some reason why); see `Simulation::alarms`.
+    RaiseAlarm(String),
+    /// Deliver the message back to its source at the given tick; see
+    /// `Message::schedule_at`.
+    ScheduleAt(DiscreteTime),
+    /// Deliver a copy of the message back to its source every period of
+    /// ticks; see `Message::set_interval`.
+    SetInterval(DiscreteTime),
+    /// Cancel the repeating timer with the correlation id; see
+    /// `TimerHandle::cancel`.
+    CancelTimer(u64),
+    /// Take the link from source to destination up or down, e.g. to close a road.
+    SetLink {
+        source: String,
+        destination: String,
+        up: bool,
+    },
+    /// Enable or disable recording a metric from the next tick on.
+    SetMetric { metric: Metric, enabled: bool },
+    /// Acquire some of a resource; see `Message::acquire`.
+    Acquire { resource: String, amount: u64 },
+    /// Give back some of a resource; see `Message::release`.
+    Release { resource: String, amount: u64 },
+    /// Put into a store or container; see `Message::put`.
+    Put { target: String, amount: f64 },
+    /// Get from a store or container; see `Message::get`.
+    Get { target: String, amount: f64 },
+    /// Deliver the message to whichever of targets has the shortest queue at
+    /// delivery, rather than to its destination; see `shortest_queue_router`.
+    ShortestQueue { targets: Vec<String> },
+    /// Open a fork of `branches` children, whose replies the engine joins at
+    /// `join`; see `Message::fork`.
+    Fork { join: String, branches: usize },
+    /// Move the source to a cell of the grid; see `Message::move_to`.
+    MoveTo { x: i64, y: i64 },
+    /// Set the velocity of the source's body; see `Message::set_velocity`.
+    SetVelocity { vx: f64, vy: f64 },
+    /// Put the source's body at a point; see `Message::place_at`.
+    PlaceAt { x: f64, y: f64 },
+    /// Deliver a copy of the message to every Agent within the radius of the
+    /// source, rather than to its destination; see `Message::within`.
+    Within { radius: f64 },
+    /// Set the next state of the source's cell; see `Message::set_cell`.
+    SetCell(Vec<u8>),
+    /// Deliver a copy of the message to every other Agent with the tag,
+    /// rather than to its destination; see `Message::to_tag`.
+    ToTag(String),
+    /// Deliver copies of the message to the members of a group, per the
+    /// delivery, rather than to its destination; see `Message::to_group`.
+    ToGroup {
+        group: String,
+        delivery: GroupDelivery,
+    },
+    /// Put a value at key on the blackboard, or take it off if None; see
+    /// `Message::post`.
+    Blackboard {
+        key: String,
+        value: Option<BlackboardValue>,
+    },
+    /// A command defined outside the crate, handled by the handler registered
+    /// for its name; see `command`.
+    Custom(Box<dyn CustomCommand>),
 }
 
 /// A Message represents an interaction between Agents.
@@ -20,6 +91,89 @@ pub struct Message {
     pub custom_payload: Option<Vec<u8>>,
     /// A control interrupt to bubble up to the Simulation engine.
     pub interrupt: Option<Interrupt>,
+    /// Correlates the messages of one interaction, e.g. a request and its reply.
+    pub correlation_id: Option<u64>,
+    /// The (transform name, ticks) costs of the MessageTransforms applied to
+    /// this message on its way; see `Simulation::latency_decomposition`.
+    pub transform_costs: Vec<(&'static str, DiscreteTime)>,
+    /// How many more hops the message may take. Every delivery decrements it,
+    /// and a message delivered with no hops left goes to the dead-letter queue
+    /// instead. None falls back to `Simulation::default_ttl`.
+    pub ttl: Option<u32>,
+    /// How many times the message has been delivered, including forwards.
+    pub hops: u32,
+}
+
+/// Why a message ended up in the dead-letter queue.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum DeadLetterReason {
+    /// The message ran out of hops, likely because it was caught in a routing loop.
+    HopLimitExceeded,
+    /// The custom command of this name has no handler.
+    UnhandledCommand(String),
+}
+
+/// A message the engine refused to deliver, and why.
+#[derive(Clone, Debug)]
+pub struct DeadLetter {
+    /// When the message was dead-lettered.
+    pub time: DiscreteTime,
+    pub reason: DeadLetterReason,
+    pub message: Message,
+}
+
+thread_local! {
+    /// The correlation ids of the Simulation running on this thread, if any.
+    static CURRENT_CORRELATION_IDS: RefCell<Option<Arc<AtomicU64>>> = const { RefCell::new(None) };
+}
+
+/// Returns a fresh correlation id. While a Simulation runs, it comes from the
+/// Simulation's own counter, so every run numbers its requests from 0 and
+/// runs with the same seed get the same ids. Outside of a run, it comes from
+/// a counter of the process.
+pub fn next_correlation_id() -> u64 {
+    static NEXT_CORRELATION_ID: AtomicU64 = AtomicU64::new(0);
+    CURRENT_CORRELATION_IDS.with(|ids| match &*ids.borrow() {
+        Some(ids) => ids.fetch_add(1, Ordering::Relaxed),
+        None => NEXT_CORRELATION_ID.fetch_add(1, Ordering::Relaxed),
+    })
+}
+
+/// The counter of a Simulation's correlation ids. Shared with the threads
+/// running its Agents in parallel; clones count on from the same id, apart.
+#[derive(Debug, Default)]
+pub(crate) struct CorrelationIds(Arc<AtomicU64>);
+
+impl Clone for CorrelationIds {
+    fn clone(&self) -> Self {
+        CorrelationIds(Arc::new(AtomicU64::new(self.0.load(Ordering::Relaxed))))
+    }
+}
+
+impl CorrelationIds {
+    /// Makes `next_correlation_id` draw from this counter on this thread,
+    /// until the returned guard is dropped.
+    pub(crate) fn enter(&self) -> EnteredCorrelationIds {
+        let outer = CURRENT_CORRELATION_IDS.with(|ids| ids.replace(Some(self.0.clone())));
+        EnteredCorrelationIds { outer }
+    }
+
+    /// Counts on from past the id, e.g. of a request queued before the run.
+    pub(crate) fn skip_past(&self, id: u64) {
+        self.0.fetch_max(id.saturating_add(1), Ordering::Relaxed);
+    }
+}
+
+/// Restores the correlation ids that were current before `enter`.
+pub(crate) struct EnteredCorrelationIds {
+    outer: Option<Arc<AtomicU64>>,
+}
+
+impl Drop for EnteredCorrelationIds {
+    fn drop(&mut self) {
+        let outer = self.outer.take();
+        CURRENT_CORRELATION_IDS.with(|ids| *ids.borrow_mut() = outer);
+    }
 }
 
 impl Message {
@@ -35,4 +189,67 @@ impl Message {
             ..Default::default()
         }
     }
+
+    /// Creates a request from src to dst with a fresh correlation id. The
+    /// returned RequestHandle recognizes the replies to the request.
+    pub fn request<S>(
+        time: DiscreteTime,
+        src: S,
+        dst: S,
+        payload: Option<Vec<u8>>,
+    ) -> (Message, RequestHandle)
+    where
+        S: Into<String>,
+    {
+        let handle = RequestHandle {
+            correlation_id: next_correlation_id(),
+        };
+
+        let request = Message {
+            custom_payload: payload,
+            correlation_id: Some(handle.correlation_id),
+            ..Message::new(time, src, dst)
+        };
+
+        (request, handle)
+    }
+
+    /// Forwards this message from its destination to the next one, keeping its
+    /// payload, correlation id and hop count, so routing loops are detected.
+    pub fn forward<S>(&self, time: DiscreteTime, dst: S) -> Message
+    where
+        S: Into<String>,
+    {
+        Message {
+            queued_time: time,
+            completed_time: None,
+            source: self.destination.clone(),
+            destination: dst.into(),
+            interrupt: None,
+            ..self.clone()
+        }
+    }
+
+    /// Creates a reply to this message: from its destination back to its
+    /// source, carrying the same correlation id.
+    pub fn reply(&self, time: DiscreteTime, payload: Option<Vec<u8>>) -> Message {
+        Message {
+            custom_payload: payload,
+            correlation_id: self.correlation_id,
+            ..Message::new(time, self.destination.as_str(), self.source.as_str())
+        }
+    }
+}
+
+/// A handle to an outstanding request, used to match its replies.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct RequestHandle {
+    pub correlation_id: u64,
+}
+
+impl RequestHandle {
+    /// Whether the message belongs to this request, e.g. is a reply to it.
+    pub fn matches(&self, msg: &Message) -> bool {
+        msg.correlation_id == Some(self.correlation_id)
+    }
 }
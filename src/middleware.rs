@@ -0,0 +1,127 @@
+//! Command middleware: cross-cutting policies on the commands Agents send
+//! the engine, i.e. messages with an interrupt, e.g. a global rate limit, an
+//! audit log or fault injection, in one place rather than in every Agent.
+
+use crate::{DiscreteTime, Message, Simulation};
+use dyn_clone::DynClone;
+
+/// A CommandMiddleware sees every command before the engine acts on it, in
+/// the order of `command_middleware`, and can change it or veto it. A vetoed
+/// command is dropped, and counted as vetoed in the message ledger.
+pub trait CommandMiddleware: std::fmt::Debug + DynClone + Send {
+    /// Handles a command sent at time, changing it in place if need be.
+    /// Returns whether to let it through.
+    fn on_command(&mut self, time: DiscreteTime, message: &mut Message) -> bool;
+}
+
+dyn_clone::clone_trait_object!(CommandMiddleware);
+
+impl Simulation {
+    /// Passes a command through every middleware. Returns whether it got
+    /// through; a vetoed command stops at the middleware that vetoed it.
+    pub(crate) fn apply_command_middleware(&mut self, message: &mut Message) -> bool {
+        let time = self.time;
+        let passed = self
+            .command_middleware
+            .iter_mut()
+            .all(|m| m.on_command(time, message));
+        if !passed {
+            self.ledger.produced += 1;
+            self.ledger.vetoed += 1;
+        }
+        passed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use simul_macro::agent;
+    use std::sync::{Arc, Mutex};
+
+    /// Posts its id's counter every tick, and asks to halt at tick 2.
+    fn poster(id: &str) -> Box<dyn Agent> {
+        #[agent]
+        struct Poster {}
+
+        impl Agent for Poster {
+            fn process(&mut self, state: SimulationState, _: &Message) -> Option<Vec<Message>> {
+                let id = self.state.id.as_str();
+                let count = state.blackboard.int(id).unwrap_or(0);
+                let mut messages = vec![Message::post(state.time, id, id, count + 1)];
+                if state.time == 2 {
+                    messages.push(Message {
+                        interrupt: Some(Interrupt::HaltSimulation("done".to_string())),
+                        ..Message::new(state.time, id, id)
+                    });
+                }
+                Some(messages)
+            }
+        }
+
+        Box::new(Poster {
+            state: AgentState {
+                mode: AgentMode::Proactive,
+                wake_mode: AgentMode::Proactive,
+                id: id.to_string(),
+                ..Default::default()
+            },
+        })
+    }
+
+    /// Lets through one command per tick.
+    #[derive(Clone, Debug, Default)]
+    struct RateLimit {
+        last: Option<DiscreteTime>,
+    }
+
+    impl CommandMiddleware for RateLimit {
+        fn on_command(&mut self, time: DiscreteTime, _: &mut Message) -> bool {
+            let allowed = self.last != Some(time);
+            self.last = Some(time);
+            allowed
+        }
+    }
+
+    /// Turns halts into posts of the halt reason, recording every command.
+    #[derive(Clone, Debug, Default)]
+    struct Audit {
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl CommandMiddleware for Audit {
+        fn on_command(&mut self, time: DiscreteTime, message: &mut Message) -> bool {
+            let entry = format!("{} {}", time, message.source);
+            self.log.lock().unwrap().push(entry);
+            if let Some(Interrupt::HaltSimulation(reason)) = &message.interrupt {
+                *message = Message::post(time, message.source.as_str(), "halted", reason.as_str());
+            }
+            true
+        }
+    }
+
+    #[test]
+    fn command_middleware_test() {
+        let audit = Audit::default();
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![poster("a"), poster("b")],
+            command_middleware: vec![Box::new(audit.clone()), Box::<RateLimit>::default()],
+            halt_check: |s: &Simulation| s.time == 5,
+            ..Default::default()
+        });
+        simulation.run();
+
+        // The halts were posted instead, and ran to the halt check.
+        assert_eq!(simulation.halt_reason, Some(HaltReason::HaltCheck));
+        let blackboard = simulation.blackboard();
+        assert_eq!(blackboard.text("halted"), Some("done"));
+
+        // One command per tick got through.
+        let posts = blackboard.int("a").unwrap_or(0) + blackboard.int("b").unwrap_or(0);
+        assert_eq!(posts, 4);
+        assert_eq!(audit.log.lock().unwrap().len(), 12);
+        let ledger = simulation.message_ledger();
+        assert_eq!(ledger.vetoed, 7);
+        assert!(ledger.is_balanced());
+    }
+}
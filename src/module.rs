@@ -0,0 +1,297 @@
+//! Composing Simulations from reusable modules.
+//!
+//! A `SimModule` bundles the Agents of a subsystem, e.g. a kitchen, with the
+//! links between them, its KPIs and the options it needs. Instances of a
+//! module live under a namespace: the Agent with role "cook" of the instance
+//! "kitchen" has the id "kitchen::cook", so a model can hold many instances
+//! of a module, developed and tested on its own, without collisions.
+//!
+//! Agents of a module address each other by role: a message from
+//! "kitchen::cook" to "waiter" is delivered to "kitchen::waiter", unless an
+//! Agent with the id "waiter" exists. So Agents written without namespaces in
+//! mind, like the built-in ones, work unchanged within modules.
+
+use crate::{Agent, LinkChange, Message, Simulation, SimulationParameters, Topology};
+use std::sync::Arc;
+
+/// Separates the namespace of an Agent id from its role.
+pub const NAMESPACE_SEPARATOR: &str = "::";
+
+/// The namespace of an instance of a module.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Namespace {
+    pub name: String,
+}
+
+impl Namespace {
+    pub fn new<T>(name: T) -> Namespace
+    where
+        T: Into<String>,
+    {
+        Namespace { name: name.into() }
+    }
+
+    /// Returns the id of the Agent with the given role in this namespace.
+    pub fn id(&self, role: &str) -> String {
+        format!("{}{}{}", self.name, NAMESPACE_SEPARATOR, role)
+    }
+
+    /// Returns the role of an Agent id in this namespace, if it is in it.
+    pub fn role<'a>(&self, id: &'a str) -> Option<&'a str> {
+        id.strip_prefix(self.name.as_str())?
+            .strip_prefix(NAMESPACE_SEPARATOR)
+    }
+}
+
+/// Builds the Agent with the given id, in the given namespace. Targets within
+/// the module may be addressed by role, or by `Namespace::id`.
+pub type AgentInitializer = Arc<dyn Fn(String, &Namespace) -> Box<dyn Agent> + Send + Sync>;
+
+/// A KPI of an instance of a module.
+pub type ModuleKpi = Arc<dyn Fn(&Simulation, &Namespace) -> f64 + Send + Sync>;
+
+/// A reusable bundle of Agents, links, KPIs and options; see the module docs.
+/// Agents and links are given by role, and are namespaced when instantiated.
+#[derive(Clone, Default)]
+pub struct SimModule {
+    /// The name of the module, and the default namespace of its instances.
+    pub name: String,
+    agents: Vec<(String, AgentInitializer)>,
+    topology: Topology,
+    kpis: Vec<(String, ModuleKpi)>,
+    options: Vec<fn(&mut SimulationParameters)>,
+}
+
+impl std::fmt::Debug for SimModule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let roles: Vec<&String> = self.agents.iter().map(|(role, _)| role).collect();
+        let kpis: Vec<&String> = self.kpis.iter().map(|(name, _)| name).collect();
+        f.debug_struct("SimModule")
+            .field("name", &self.name)
+            .field("agents", &roles)
+            .field("topology", &self.topology)
+            .field("kpis", &kpis)
+            .finish()
+    }
+}
+
+impl SimModule {
+    pub fn new<T>(name: T) -> SimModule
+    where
+        T: Into<String>,
+    {
+        SimModule {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Adds an Agent with the given role.
+    pub fn with_agent<T, F>(mut self, role: T, initializer: F) -> Self
+    where
+        T: Into<String>,
+        F: Fn(String, &Namespace) -> Box<dyn Agent> + Send + Sync + 'static,
+    {
+        self.agents.push((role.into(), Arc::new(initializer)));
+        self
+    }
+
+    /// Adds the topology between the module's Agents, by role.
+    pub fn with_topology(mut self, topology: Topology) -> Self {
+        self.topology.down.extend(topology.down);
+        self.topology.schedule.extend(topology.schedule);
+        self
+    }
+
+    /// Adds a KPI, evaluated per instance; see `kpis`.
+    pub fn with_kpi<T, F>(mut self, name: T, kpi: F) -> Self
+    where
+        T: Into<String>,
+        F: Fn(&Simulation, &Namespace) -> f64 + Send + Sync + 'static,
+    {
+        self.kpis.push((name.into(), Arc::new(kpi)));
+        self
+    }
+
+    /// Adds options the module needs, e.g. metrics its KPIs read, which are
+    /// applied to the parameters it is installed into.
+    pub fn with_options(mut self, options: fn(&mut SimulationParameters)) -> Self {
+        self.options.push(options);
+        self
+    }
+
+    /// Returns a copy of the module under another name, so it installs into
+    /// another namespace by default.
+    pub fn renamed<T>(&self, name: T) -> SimModule
+    where
+        T: Into<String>,
+    {
+        SimModule {
+            name: name.into(),
+            ..self.clone()
+        }
+    }
+
+    /// Installs an instance of the module into the parameters, under the
+    /// namespace `instance`.
+    pub fn install(&self, parameters: &mut SimulationParameters, instance: &str) {
+        let namespace = Namespace::new(instance);
+
+        for (role, initializer) in self.agents.iter() {
+            let mut agent = initializer(namespace.id(role), &namespace);
+            agent.state_mut().id = namespace.id(role);
+            parameters.agents.push(agent);
+        }
+
+        for (source, destination) in self.topology.down.iter() {
+            parameters
+                .topology
+                .down
+                .insert((namespace.id(source), namespace.id(destination)));
+        }
+        for change in self.topology.schedule.iter() {
+            parameters.topology.schedule.push(LinkChange {
+                source: namespace.id(&change.source),
+                destination: namespace.id(&change.destination),
+                ..change.clone()
+            });
+        }
+
+        for options in self.options.iter() {
+            options(parameters);
+        }
+    }
+
+    /// Evaluates the module's KPIs for an instance, named like its Agents,
+    /// e.g. "kitchen::throughput".
+    pub fn kpis(&self, simulation: &Simulation, instance: &str) -> Vec<(String, f64)> {
+        let namespace = Namespace::new(instance);
+        self.kpis
+            .iter()
+            .map(|(name, kpi)| (namespace.id(name), kpi(simulation, &namespace)))
+            .collect()
+    }
+}
+
+impl Simulation {
+    /// Returns the ids of every instance of a role, e.g. "north::cashier" and
+    /// "south::cashier" for "cashier", in Agent order.
+    pub fn ids_of_role(&self, role: &str) -> Vec<&str> {
+        self.agents
+            .iter()
+            .map(|a| a.state().id.as_str())
+            .filter(|id| {
+                id.rsplit_once(NAMESPACE_SEPARATOR)
+                    .map_or(false, |(_, r)| r == role)
+            })
+            .collect()
+    }
+
+    /// Returns the ids of every Agent in an instance, in Agent order.
+    pub fn ids_in_namespace(&self, instance: &str) -> Vec<&str> {
+        let namespace = Namespace::new(instance);
+        self.agents
+            .iter()
+            .map(|a| a.state().id.as_str())
+            .filter(|id| namespace.role(id).is_some())
+            .collect()
+    }
+
+    /// Qualifies the destination of a message sent by role within a namespace
+    /// with the namespace of its source, if no Agent has the destination as
+    /// its id. Nested namespaces resolve from the innermost.
+    pub(crate) fn resolve_destination(&self, message: &mut Message) {
+        if self.agent_handles.contains_key(&message.destination) {
+            return;
+        }
+
+        let Some((namespace, _)) = message.source.rsplit_once(NAMESPACE_SEPARATOR) else {
+            return;
+        };
+        let qualified = Namespace::new(namespace).id(&message.destination);
+        if self.agent_handles.contains_key(&qualified) {
+            message.destination = qualified;
+        }
+    }
+}
+
+impl SimulationParameters {
+    /// Installs an instance of a module under its own name; see `SimModule::install`.
+    pub fn with_module(mut self, module: &SimModule) -> Self {
+        module.install(&mut self, &module.name);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    fn store() -> SimModule {
+        SimModule::new("store")
+            .with_agent("customers", |id, namespace| {
+                periodic_producing_agent(id, 1, namespace.id("cashier"))
+            })
+            .with_agent("greeter", |id, _| {
+                periodic_producing_agent(id, 2, "cashier".to_string())
+            })
+            .with_agent("cashier", |id, _| periodic_consuming_agent(id, 1))
+            .with_topology(Topology::default().with_link_change(5, "customers", "cashier", false))
+            .with_kpi("served", |simulation, namespace| {
+                let consumed = simulation.consumed_for_agent(&namespace.id("cashier"));
+                consumed.map_or(0.0, |c| c.len() as f64)
+            })
+            .with_options(|parameters| parameters.enable_queue_depth_metrics = true)
+    }
+
+    #[test]
+    fn namespace_test() {
+        let namespace = Namespace::new("north");
+        assert_eq!(namespace.id("cashier"), "north::cashier");
+        assert_eq!(namespace.role("north::cashier"), Some("cashier"));
+        assert_eq!(namespace.role("northeast::cashier"), None);
+    }
+
+    #[test]
+    fn sim_module_test() {
+        let store = store();
+        let mut parameters = SimulationParameters {
+            halt_check: |s: &Simulation| s.time == 10,
+            ..Default::default()
+        }
+        .with_module(&store.renamed("north"));
+        store.install(&mut parameters, "south");
+        assert!(parameters.enable_queue_depth_metrics);
+
+        let mut simulation = Simulation::new(parameters);
+        simulation.run();
+
+        // Each instance's customers reach only their own cashier, until the
+        // link goes down at tick 5.
+        for instance in ["north", "south"] {
+            let consumed = simulation
+                .consumed_for_agent(&format!("{}::cashier", instance))
+                .unwrap();
+            assert!(consumed
+                .iter()
+                .all(|m| m.source.starts_with(&format!("{}::", instance))));
+            assert!(consumed
+                .iter()
+                .any(|m| m.source == format!("{}::greeter", instance)));
+            assert_eq!(
+                store.kpis(&simulation, instance),
+                vec![(format!("{}::served", instance), consumed.len() as f64)]
+            );
+        }
+        assert_eq!(simulation.topology_events().len(), 2);
+        assert_eq!(
+            simulation.ids_of_role("cashier"),
+            vec!["north::cashier", "south::cashier"]
+        );
+        assert_eq!(
+            simulation.ids_in_namespace("south"),
+            vec!["south::customers", "south::greeter", "south::cashier"]
+        );
+    }
+}
@@ -0,0 +1,406 @@
+//! Queueing networks: stations of parallel servers with exponential service,
+//! fed by Poisson arrivals, that route the messages they served to each other
+//! with fixed probabilities.
+//!
+//! A `QueueingNetwork` describes a network by station, and builds into a
+//! `SimModule`, so a classic model takes a few lines:
+//!
+//! ```
+//! use simul::network::QueueingNetwork;
+//! use simul::{Simulation, SimulationParameters};
+//!
+//! // Jobs use the CPU, then the disk 30% of the time, and come back.
+//! let network = QueueingNetwork::new()
+//!     .with_arrivals("jobs", 0.01, "cpu")
+//!     .with_station("cpu", 2, 0.02)
+//!     .with_station("disk", 1, 0.05)
+//!     .with_route("cpu", "disk", 0.3)
+//!     .with_route("disk", "cpu", 1.0);
+//!
+//! let mut simulation = Simulation::new(
+//!     SimulationParameters {
+//!         halt_check: |s: &Simulation| s.time == 1_000,
+//!         ..Default::default()
+//!     }
+//!     .with_module(&network.module("server")),
+//! );
+//! simulation.run();
+//! ```
+//!
+//! Rates are per tick; see `validate` for the error the discrete engine adds.
+
+use crate::module::SimModule;
+use crate::{Agent, AgentMode, AgentState, DiscreteTime, Message, SimulationState};
+use rand::Rng;
+use rand_distr::{Distribution, Exp};
+use simul_macro::agent;
+
+/// Returns an Agent that sends messages to target as a Poisson process with
+/// `rate` messages per tick. Arrivals are binned into the tick they fall in.
+pub fn poisson_arrivals<T>(id: T, rate: f64, target: T) -> Box<dyn Agent>
+where
+    T: Into<String>,
+{
+    #[agent]
+    struct PoissonArrivals {
+        interarrival: Exp<f64>,
+        next_arrival: Option<f64>,
+        target: String,
+    }
+
+    impl Agent for PoissonArrivals {
+        fn process(
+            &mut self,
+            simulation_state: SimulationState,
+            _msg: &Message,
+        ) -> Option<Vec<Message>> {
+            let time = simulation_state.time;
+            let mut next_arrival = self
+                .next_arrival
+                .unwrap_or_else(|| time as f64 + self.interarrival.sample(&mut crate::rng()));
+
+            let mut arrivals = vec![];
+            while next_arrival < (time + 1) as f64 {
+                arrivals.push(Message::new(time, self.state.id.as_str(), &self.target));
+                next_arrival += self.interarrival.sample(&mut crate::rng());
+            }
+
+            self.next_arrival = Some(next_arrival);
+            self.state.mode = AgentMode::AsleepUntil(next_arrival as DiscreteTime);
+            Some(arrivals)
+        }
+    }
+
+    Box::new(PoissonArrivals {
+        interarrival: Exp::new(rate).expect("the arrival rate must be positive"),
+        next_arrival: None,
+        target: target.into(),
+        state: AgentState {
+            mode: AgentMode::Proactive,
+            wake_mode: AgentMode::Proactive,
+            id: id.into(),
+            ..Default::default()
+        },
+    })
+}
+
+/// Returns an Agent with `servers` identical servers sharing its queue, each
+/// serving a message in an exponentially distributed time, with `rate`
+/// messages per tick. A message is consumed at the tick its service starts, so
+/// its wait is the time it spent queued, rounded up to a tick.
+///
+/// Once served, a message is forwarded to the destination of one of the
+/// (destination, probability) routes, drawn at random, or leaves the network
+/// with the remaining probability.
+pub fn exponential_station<T>(
+    id: T,
+    servers: usize,
+    rate: f64,
+    routes: Vec<(String, f64)>,
+) -> Box<dyn Agent>
+where
+    T: Into<String>,
+{
+    #[derive(Clone, Debug, Default)]
+    struct Server {
+        /// When the server is done with its last message.
+        busy_until: f64,
+        serving: Option<Message>,
+    }
+
+    #[agent]
+    struct ExponentialStation {
+        service_time: Exp<f64>,
+        servers: Vec<Server>,
+        routes: Vec<(String, f64)>,
+    }
+
+    impl ExponentialStation {
+        fn route(&self) -> Option<&str> {
+            if self.routes.is_empty() {
+                return None;
+            }
+            let mut draw: f64 = crate::rng().gen();
+            for (destination, probability) in self.routes.iter() {
+                if draw < *probability {
+                    return Some(destination);
+                }
+                draw -= probability;
+            }
+            None
+        }
+    }
+
+    impl Agent for ExponentialStation {
+        fn process(
+            &mut self,
+            simulation_state: SimulationState,
+            msg: &Message,
+        ) -> Option<Vec<Message>> {
+            let time = simulation_state.time;
+
+            let mut served = vec![];
+            for server in self.servers.iter_mut() {
+                if server.busy_until <= time as f64 {
+                    served.extend(server.serving.take());
+                }
+            }
+            let forwarded = served
+                .iter()
+                .filter_map(|m| Some(m.forward(time, self.route()?)))
+                .collect();
+
+            // The station is only awake while a server is free, so it serves
+            // msg and as many queued messages as it has free servers.
+            if msg.source != "SIM_SRC" {
+                self.state.queue.push_front(msg.clone());
+            }
+            for server in self.servers.iter_mut().filter(|s| s.serving.is_none()) {
+                let Some(msg) = self.state.queue.pop_front() else {
+                    break;
+                };
+
+                // Services run on a continuous clock, so a server starts on
+                // its next message as soon as it is free, or the message has
+                // arrived, rather than at the next tick. A message arrived
+                // in the middle of the tick it is binned into, on average.
+                let start = server.busy_until.max(msg.queued_time as f64 + 0.5);
+                server.busy_until = start + self.service_time.sample(&mut crate::rng());
+                server.serving = Some(msg.clone());
+                self.state.consumed.push(Message {
+                    completed_time: Some(time),
+                    ..msg
+                });
+            }
+
+            // Wake up for the next service to complete, and for arrivals
+            // while a server is free.
+            let busy = self.servers.iter().filter(|s| s.serving.is_some());
+            let done_at = busy.clone().map(|s| s.busy_until).fold(f64::MAX, f64::min);
+            (self.state.mode, self.state.wake_mode) = match busy.count() {
+                0 => (AgentMode::Reactive, AgentMode::Reactive),
+                n if n == self.servers.len() => (
+                    AgentMode::AsleepUntil(done_at.ceil() as DiscreteTime),
+                    AgentMode::Proactive,
+                ),
+                _ => (AgentMode::Proactive, AgentMode::Proactive),
+            };
+
+            Some(forwarded)
+        }
+    }
+
+    Box::new(ExponentialStation {
+        service_time: Exp::new(rate).expect("the service rate must be positive"),
+        servers: vec![Server::default(); servers.max(1)],
+        routes,
+        state: AgentState {
+            mode: AgentMode::Reactive,
+            wake_mode: AgentMode::Reactive,
+            id: id.into(),
+            ..Default::default()
+        },
+    })
+}
+
+/// A station of a `QueueingNetwork`.
+#[derive(Clone, Debug)]
+struct Station {
+    role: String,
+    servers: usize,
+    service_rate: f64,
+    routes: Vec<(String, f64)>,
+}
+
+/// An open queueing network of M/M/c stations; see the module docs.
+#[derive(Clone, Debug, Default)]
+pub struct QueueingNetwork {
+    /// Every (role, rate, station) of the external arrivals.
+    arrivals: Vec<(String, f64, String)>,
+    stations: Vec<Station>,
+}
+
+impl QueueingNetwork {
+    pub fn new() -> QueueingNetwork {
+        QueueingNetwork::default()
+    }
+
+    /// Adds Poisson arrivals of `rate` messages per tick from outside the
+    /// network to a station.
+    pub fn with_arrivals<T>(mut self, role: T, rate: f64, station: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.arrivals.push((role.into(), rate, station.into()));
+        self
+    }
+
+    /// Adds a station of `servers` parallel servers, each serving `rate`
+    /// messages per tick on average.
+    pub fn with_station<T>(mut self, role: T, servers: usize, rate: f64) -> Self
+    where
+        T: Into<String>,
+    {
+        self.stations.push(Station {
+            role: role.into(),
+            servers,
+            service_rate: rate,
+            routes: vec![],
+        });
+        self
+    }
+
+    /// Routes the given fraction of the messages served at a station to
+    /// another. Messages not routed anywhere leave the network.
+    ///
+    /// # Panics
+    ///
+    /// If the station doesn't exist, or its routes add up to more than 1.
+    pub fn with_route<T>(mut self, from: T, to: T, probability: f64) -> Self
+    where
+        T: Into<String>,
+    {
+        let from = from.into();
+        let station = self
+            .stations
+            .iter_mut()
+            .find(|s| s.role == from)
+            .unwrap_or_else(|| panic!("no station {} to route from", from));
+        station.routes.push((to.into(), probability));
+        assert!(
+            station.routes.iter().map(|(_, p)| p).sum::<f64>() <= 1.0 + 1e-9,
+            "the routes from {} add up to more than 1",
+            from
+        );
+        self
+    }
+
+    /// Solves the traffic equations of the network: the mean rate at which
+    /// messages arrive at each station, from outside or from other stations,
+    /// in station order. Stations with an arrival rate at or above their
+    /// capacity are unstable.
+    pub fn arrival_rates(&self) -> Vec<(String, f64)> {
+        let external: Vec<f64> = self
+            .stations
+            .iter()
+            .map(|s| {
+                let arrivals = self.arrivals.iter().filter(|(_, _, to)| *to == s.role);
+                arrivals.map(|(_, rate, _)| rate).sum()
+            })
+            .collect();
+
+        // Fixed-point iteration converges for open networks, where every
+        // message eventually leaves.
+        let mut rates = external.clone();
+        for _ in 0..10_000 {
+            let mut next = external.clone();
+            for (station, rate) in self.stations.iter().zip(rates.iter()) {
+                for (to, probability) in station.routes.iter() {
+                    if let Some(i) = self.stations.iter().position(|s| s.role == *to) {
+                        next[i] += rate * probability;
+                    }
+                }
+            }
+            let converged = next
+                .iter()
+                .zip(rates.iter())
+                .all(|(a, b)| (a - b).abs() < 1e-12);
+            rates = next;
+            if converged {
+                break;
+            }
+        }
+
+        self.stations
+            .iter()
+            .map(|s| s.role.clone())
+            .zip(rates)
+            .collect()
+    }
+
+    /// Builds a module with an Agent per arrival process and per station,
+    /// addressing each other by role. Enables the queue depth metrics that
+    /// the stations' statistics need.
+    pub fn module<T>(&self, name: T) -> SimModule
+    where
+        T: Into<String>,
+    {
+        let mut module = SimModule::new(name)
+            .with_options(|parameters| parameters.enable_queue_depth_metrics = true);
+
+        for (role, rate, station) in self.arrivals.iter().cloned() {
+            module = module.with_agent(role, move |id, _| {
+                poisson_arrivals(id, rate, station.clone())
+            });
+        }
+        for station in self.stations.iter().cloned() {
+            module = module.with_agent(station.role.clone(), move |id, _| {
+                exponential_station(
+                    id,
+                    station.servers,
+                    station.service_rate,
+                    station.routes.clone(),
+                )
+            });
+        }
+
+        module
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    fn tandem() -> QueueingNetwork {
+        // Half of the messages served at "b" are served again at "a".
+        QueueingNetwork::new()
+            .with_arrivals("arrivals", 0.01, "a")
+            .with_station("a", 2, 0.02)
+            .with_station("b", 1, 0.04)
+            .with_route("a", "b", 1.0)
+            .with_route("b", "a", 0.5)
+    }
+
+    #[test]
+    fn arrival_rates_test() {
+        let rates = tandem().arrival_rates();
+        assert_eq!(rates[0].0, "a");
+        assert!((rates[0].1 - 0.02).abs() < 1e-9);
+        assert!((rates[1].1 - 0.02).abs() < 1e-9);
+    }
+
+    #[test]
+    #[should_panic(expected = "add up to more than 1")]
+    fn with_route_test() {
+        tandem().with_route("b", "b", 0.6);
+    }
+
+    #[test]
+    fn queueing_network_test() {
+        let network = tandem();
+        let mut simulation = Simulation::new(
+            SimulationParameters {
+                halt_check: |s: &Simulation| s.time == 200_000,
+                seed: Some(3),
+                ..Default::default()
+            }
+            .with_module(&network.module("shop")),
+        );
+        simulation.run();
+
+        for (station, rate) in network.arrival_rates() {
+            let id = format!("shop::{}", station);
+            let observed = simulation.littles_law(&id).unwrap();
+            assert!(
+                (observed.arrival_rate - rate).abs() / rate < 0.05,
+                "{}: {:?}",
+                id,
+                observed
+            );
+            assert!(observed.relative_error() < 0.05, "{}: {:?}", id, observed);
+        }
+        assert!(simulation.message_ledger().is_balanced());
+    }
+}
@@ -0,0 +1,172 @@
+//! Observers: callbacks on the progress of a Simulation, for progress bars,
+//! custom logging or streaming exporters, without changing the engine.
+
+use crate::report::{Cadence, ReportWindow};
+use crate::{DiscreteTime, Message, Simulation};
+use dyn_clone::DynClone;
+
+/// A SimulationObserver is called before every tick, after ticks at its
+/// cadence, and on every message delivered to an Agent's queue. Every
+/// callback does nothing by default, so observers implement only what they
+/// need.
+pub trait SimulationObserver: std::fmt::Debug + DynClone + Send {
+    /// Called at the start of every tick, before any Agent processes.
+    fn before_tick(&mut self, _simulation: &Simulation) {}
+
+    /// Called at the end of ticks at the observer's cadence, before time
+    /// advances, and once more when the Simulation completes if ticks went by
+    /// unobserved since.
+    fn after_tick(&mut self, _simulation: &Simulation) {}
+
+    /// Called as a message is put on the queue of its destination.
+    fn on_message_delivered(&mut self, _time: DiscreteTime, _message: &Message) {}
+
+    /// How often `after_tick` is called while the Simulation runs.
+    fn cadence(&self) -> Cadence {
+        Cadence::EveryTick
+    }
+}
+
+dyn_clone::clone_trait_object!(SimulationObserver);
+
+impl Simulation {
+    /// Calls every observer with the Simulation.
+    pub(crate) fn notify_observers(&mut self, call: fn(&mut dyn SimulationObserver, &Simulation)) {
+        // Taken out for the calls, so they can see the rest of the Simulation.
+        let mut observers = std::mem::take(&mut self.observers);
+        for observer in observers.iter_mut() {
+            call(observer.as_mut(), self);
+        }
+        self.observers = observers;
+    }
+
+    /// Aggregates a finished tick into the window of every observer, and
+    /// calls `after_tick` on those that are due at their cadence.
+    pub(crate) fn observe_tick_for_observers(&mut self, messages_delivered: usize) {
+        // Observers pushed mid-run start observing from this tick.
+        let window = ReportWindow {
+            from: self.time,
+            ..Default::default()
+        };
+        self.observer_windows.resize(self.observers.len(), window);
+
+        let next_window = ReportWindow {
+            from: self.time + 1,
+            ..Default::default()
+        };
+        let due: Vec<bool> = self
+            .observers
+            .iter()
+            .zip(self.observer_windows.iter_mut())
+            .map(|(observer, window)| {
+                let due = observer.cadence().observe_tick(window, messages_delivered);
+                if due {
+                    *window = next_window.clone();
+                }
+                due
+            })
+            .collect();
+        self.notify_due_observers(&due);
+    }
+
+    /// Calls `after_tick` once more on the observers with unobserved ticks.
+    pub(crate) fn notify_observers_on_completion(&mut self) {
+        let due: Vec<bool> = (0..self.observers.len())
+            .map(|i| self.observer_windows.get(i).map_or(false, |w| w.ticks > 0))
+            .collect();
+        self.notify_due_observers(&due);
+    }
+
+    fn notify_due_observers(&mut self, due: &[bool]) {
+        let mut observers = std::mem::take(&mut self.observers);
+        for (observer, _) in observers.iter_mut().zip(due).filter(|(_, due)| **due) {
+            observer.after_tick(self);
+        }
+        self.observers = observers;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Records what it observed, shared with the test.
+    #[derive(Clone, Debug, Default)]
+    struct Recorder {
+        events: Arc<Mutex<Vec<String>>>,
+        cadence: Option<Cadence>,
+    }
+
+    impl SimulationObserver for Recorder {
+        fn before_tick(&mut self, simulation: &Simulation) {
+            let event = format!("before {}", simulation.time);
+            self.events.lock().unwrap().push(event);
+        }
+
+        fn after_tick(&mut self, simulation: &Simulation) {
+            let event = format!("after {}", simulation.time);
+            self.events.lock().unwrap().push(event);
+        }
+
+        fn on_message_delivered(&mut self, time: DiscreteTime, message: &Message) {
+            let event = format!("delivered {} to {}", time, message.destination);
+            self.events.lock().unwrap().push(event);
+        }
+
+        fn cadence(&self) -> Cadence {
+            self.cadence.unwrap_or(Cadence::EveryTick)
+        }
+    }
+
+    #[test]
+    fn observer_test() {
+        let recorder = Recorder::default();
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer", 1, "consumer"),
+                periodic_consuming_agent("consumer", 1),
+            ],
+            observers: vec![Box::new(recorder.clone())],
+            halt_check: |s: &Simulation| s.time == 2,
+            ..Default::default()
+        });
+        simulation.run();
+
+        assert_eq!(
+            *recorder.events.lock().unwrap(),
+            [
+                "before 0",
+                "delivered 0 to consumer",
+                "after 0",
+                "before 1",
+                "delivered 1 to consumer",
+                "after 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn observer_cadence_test() {
+        let recorder = Recorder {
+            cadence: Some(Cadence::EveryNTicks(2)),
+            ..Default::default()
+        };
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer", 1, "consumer"),
+                periodic_consuming_agent("consumer", 1),
+            ],
+            observers: vec![Box::new(recorder.clone())],
+            halt_check: |s: &Simulation| s.time == 5,
+            ..Default::default()
+        });
+        simulation.run();
+
+        // Every other tick, then once more for the tick left at completion.
+        let events = recorder.events.lock().unwrap();
+        let after: Vec<&String> = events.iter().filter(|e| e.starts_with("after")).collect();
+        assert_eq!(after, ["after 1", "after 3", "after 5"]);
+    }
+}
@@ -0,0 +1,420 @@
+//! Petri nets: places holding tokens, and transitions that fire by taking
+//! tokens from their input places and putting tokens into their output
+//! places, compiled down to Agents and messages.
+//!
+//! Every place and every transition is an Agent. A transition asks each of
+//! its input places for the tokens it needs, and a place hands them out in
+//! the order it is asked, as long as it has enough. A transition that got
+//! all it asked for fires, depositing tokens into its output places;
+//! otherwise it gives back what it got and asks again on the next tick. So a
+//! firing takes a couple of ticks, and transitions competing for tokens are
+//! served first come, first served. Transitions without input places fire
+//! every tick.
+
+use crate::{
+    Agent, AgentMode, AgentState, DiscreteTime, Interpolation, Message, Series, Simulation,
+    SimulationState,
+};
+use simul_macro::agent;
+
+/// The messages between places and transitions.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+enum Token {
+    /// A transition asks a place for tokens.
+    Request(u64),
+    /// A place hands out the tokens asked for.
+    Grant(u64),
+    /// A place doesn't have the tokens asked for.
+    Deny,
+    /// A transition puts tokens into a place.
+    Deposit(u64),
+}
+
+impl Token {
+    fn encode(self) -> Vec<u8> {
+        let (kind, count) = match self {
+            Token::Request(n) => (0, n),
+            Token::Grant(n) => (1, n),
+            Token::Deny => (2, 0),
+            Token::Deposit(n) => (3, n),
+        };
+        let mut payload = vec![kind];
+        payload.extend(count.to_le_bytes());
+        payload
+    }
+
+    fn decode(msg: &Message) -> Option<Token> {
+        let payload = msg.custom_payload.as_ref()?;
+        let count = u64::from_le_bytes(payload.get(1..9)?.try_into().ok()?);
+        match payload.first()? {
+            0 => Some(Token::Request(count)),
+            1 => Some(Token::Grant(count)),
+            2 => Some(Token::Deny),
+            3 => Some(Token::Deposit(count)),
+            _ => None,
+        }
+    }
+
+    fn message(self, time: DiscreteTime, src: &str, dst: &str) -> Message {
+        Message {
+            custom_payload: Some(self.encode()),
+            ..Message::new(time, src, dst)
+        }
+    }
+}
+
+/// A transition of a PetriNet, with the (place, tokens) it takes and puts.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Transition {
+    pub name: String,
+    pub inputs: Vec<(String, u64)>,
+    pub outputs: Vec<(String, u64)>,
+}
+
+/// A Petri net; see the module docs.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct PetriNet {
+    /// The (name, initial tokens) of every place.
+    pub places: Vec<(String, u64)>,
+    pub transitions: Vec<Transition>,
+}
+
+/// The tokens of a place of a PetriNet over a Simulation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlaceStats {
+    pub name: String,
+    /// The tokens over time.
+    pub tokens: Series,
+    pub max_tokens: u64,
+    /// The time-weighted mean of the tokens over the run.
+    pub mean_tokens: f64,
+}
+
+/// The token metrics of a PetriNet over a Simulation.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PetriReport {
+    /// The places, in the order they were added.
+    pub places: Vec<PlaceStats>,
+    /// The (transition, times fired), in the order they were added.
+    pub firings: Vec<(String, usize)>,
+}
+
+impl PetriReport {
+    /// The tokens of a place at the end of the run.
+    pub fn tokens(&self, place: &str) -> Option<u64> {
+        let place = self.places.iter().find(|p| p.name == place)?;
+        Some(place.tokens.points.last()?.1 as u64)
+    }
+
+    /// The times a transition fired.
+    pub fn fired(&self, transition: &str) -> Option<usize> {
+        let (_, fired) = self.firings.iter().find(|(t, _)| t == transition)?;
+        Some(*fired)
+    }
+}
+
+impl PetriNet {
+    pub fn new() -> PetriNet {
+        PetriNet::default()
+    }
+
+    /// Adds a place holding `tokens` tokens to begin with.
+    pub fn place<T>(mut self, name: T, tokens: u64) -> Self
+    where
+        T: Into<String>,
+    {
+        self.places.push((name.into(), tokens));
+        self
+    }
+
+    /// Adds a transition that takes tokens from the input places, and puts
+    /// tokens into the output places, by (place, tokens).
+    ///
+    /// # Panics
+    ///
+    /// If a place isn't added before.
+    pub fn transition<T>(mut self, name: T, inputs: &[(&str, u64)], outputs: &[(&str, u64)]) -> Self
+    where
+        T: Into<String>,
+    {
+        let name = name.into();
+        let arcs = |arcs: &[(&str, u64)]| -> Vec<(String, u64)> {
+            arcs.iter()
+                .map(|(place, tokens)| {
+                    assert!(
+                        self.places.iter().any(|(p, _)| p == place),
+                        "{} connects to {}, which must be added before it",
+                        name,
+                        place
+                    );
+                    (place.to_string(), *tokens)
+                })
+                .collect()
+        };
+
+        let transition = Transition {
+            inputs: arcs(inputs),
+            outputs: arcs(outputs),
+            name,
+        };
+        self.transitions.push(transition);
+        self
+    }
+
+    /// Returns the Agents of the places and transitions, with their names as ids.
+    pub fn agents(&self) -> Vec<Box<dyn Agent>> {
+        let places = self
+            .places
+            .iter()
+            .map(|(name, tokens)| place_agent(name, *tokens));
+        let transitions = self.transitions.iter().map(transition_agent);
+        places.chain(transitions).collect()
+    }
+
+    /// Returns the token metrics of a Simulation of the net.
+    pub fn report(&self, simulation: &Simulation) -> PetriReport {
+        let start = simulation.starting_time;
+        let places = self
+            .places
+            .iter()
+            .map(|(name, initial)| {
+                let mut tokens = Series::new(Interpolation::Step);
+                tokens.record(start, *initial as f64);
+                let mut max_tokens = *initial;
+                for change in simulation.consumed_for_agent(name).unwrap_or_default() {
+                    let count = match Token::decode(&change) {
+                        Some(Token::Deposit(count)) => count,
+                        _ => continue,
+                    };
+                    max_tokens = max_tokens.max(count);
+                    let time = change.completed_time.unwrap_or(change.queued_time);
+                    tokens.record(time, count as f64);
+                }
+
+                let end = simulation.time.max(start + 1) as f64;
+                PlaceStats {
+                    name: name.clone(),
+                    mean_tokens: tokens.mean(start as f64, end).unwrap_or(0.0),
+                    tokens,
+                    max_tokens,
+                }
+            })
+            .collect();
+
+        let firings = self
+            .transitions
+            .iter()
+            .map(|t| {
+                (
+                    t.name.clone(),
+                    simulation.consumed_count(&t.name).unwrap_or(0),
+                )
+            })
+            .collect();
+
+        PetriReport { places, firings }
+    }
+}
+
+/// Returns the Agent of a place. It records the tokens it holds after every
+/// change as a consumed message with a deposit of that many tokens.
+fn place_agent(name: &str, tokens: u64) -> Box<dyn Agent> {
+    #[agent]
+    struct Place {
+        tokens: u64,
+    }
+
+    impl Agent for Place {
+        fn process(
+            &mut self,
+            simulation_state: SimulationState,
+            msg: &Message,
+        ) -> Option<Vec<Message>> {
+            let time = simulation_state.time;
+            let incoming = std::iter::once(msg.clone()).chain(self.state.queue.drain(..));
+            let mut replies = vec![];
+            for msg in incoming.collect::<Vec<_>>() {
+                let id = self.state.id.as_str();
+                let before = self.tokens;
+                match Token::decode(&msg) {
+                    Some(Token::Request(n)) if n <= self.tokens => {
+                        self.tokens -= n;
+                        replies.push(Token::Grant(n).message(time, id, &msg.source));
+                    }
+                    Some(Token::Request(_)) => {
+                        replies.push(Token::Deny.message(time, id, &msg.source));
+                    }
+                    Some(Token::Deposit(n)) => self.tokens += n,
+                    _ => {}
+                }
+
+                if self.tokens != before {
+                    let change = Token::Deposit(self.tokens).message(time, id, id);
+                    self.state.consumed.push(Message {
+                        completed_time: Some(time),
+                        ..change
+                    });
+                }
+            }
+            Some(replies)
+        }
+    }
+
+    Box::new(Place {
+        tokens,
+        state: AgentState {
+            mode: AgentMode::Reactive,
+            wake_mode: AgentMode::Reactive,
+            id: name.to_string(),
+            ..Default::default()
+        },
+    })
+}
+
+/// Returns the Agent of a transition. It records every firing as a consumed
+/// message.
+fn transition_agent(transition: &Transition) -> Box<dyn Agent> {
+    #[agent]
+    struct TransitionAgent {
+        inputs: Vec<(String, u64)>,
+        outputs: Vec<(String, u64)>,
+        /// The (place, tokens) granted so far of the current attempt.
+        granted: Vec<(String, u64)>,
+        /// The replies still due of the current attempt.
+        awaiting: usize,
+        denied: bool,
+    }
+
+    impl TransitionAgent {
+        /// Deposits tokens into places.
+        fn deposit(&self, time: DiscreteTime, arcs: &[(String, u64)]) -> Vec<Message> {
+            arcs.iter()
+                .map(|(place, n)| Token::Deposit(*n).message(time, &self.state.id, place))
+                .collect()
+        }
+
+        /// Fires, or gives back the tokens, once every input place replied.
+        fn settle(&mut self, time: DiscreteTime) -> Vec<Message> {
+            // Retries on the next tick, if denied.
+            self.state.mode = AgentMode::Proactive;
+            let granted = std::mem::take(&mut self.granted);
+            if self.denied {
+                return self.deposit(time, &granted);
+            }
+
+            self.state.consumed.push(Message {
+                completed_time: Some(time),
+                ..Message::new(time, self.state.id.as_str(), self.state.id.as_str())
+            });
+            self.deposit(time, &self.outputs)
+        }
+    }
+
+    impl Agent for TransitionAgent {
+        fn process(
+            &mut self,
+            simulation_state: SimulationState,
+            msg: &Message,
+        ) -> Option<Vec<Message>> {
+            let time = simulation_state.time;
+            if self.awaiting == 0 {
+                if self.inputs.is_empty() {
+                    return Some(self.settle(time));
+                }
+
+                // Asks for the tokens, and waits for the replies.
+                self.awaiting = self.inputs.len();
+                self.denied = false;
+                self.state.mode = AgentMode::Reactive;
+                let requests = self
+                    .inputs
+                    .iter()
+                    .map(|(place, n)| Token::Request(*n).message(time, &self.state.id, place))
+                    .collect();
+                return Some(requests);
+            }
+
+            let incoming = std::iter::once(msg.clone()).chain(self.state.queue.drain(..));
+            for msg in incoming.collect::<Vec<_>>() {
+                match Token::decode(&msg) {
+                    Some(Token::Grant(n)) => self.granted.push((msg.source.clone(), n)),
+                    Some(Token::Deny) => self.denied = true,
+                    _ => continue,
+                }
+                self.awaiting -= 1;
+            }
+
+            if self.awaiting > 0 {
+                return None;
+            }
+            Some(self.settle(time))
+        }
+    }
+
+    Box::new(TransitionAgent {
+        inputs: transition.inputs.clone(),
+        outputs: transition.outputs.clone(),
+        granted: vec![],
+        awaiting: 0,
+        denied: false,
+        state: AgentState {
+            mode: AgentMode::Proactive,
+            wake_mode: AgentMode::Reactive,
+            id: transition.name.clone(),
+            ..Default::default()
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn token_test() {
+        for token in [
+            Token::Request(3),
+            Token::Grant(2),
+            Token::Deny,
+            Token::Deposit(7),
+        ] {
+            let msg = token.message(0, "a", "b");
+            assert_eq!(Token::decode(&msg), Some(token));
+        }
+    }
+
+    #[test]
+    fn petri_net_test() {
+        // Two machines share a single operator, so at most one is ever busy,
+        // and the 5 parts are all finished.
+        let net = PetriNet::new()
+            .place("parts", 5)
+            .place("operator", 1)
+            .place("busy", 0)
+            .place("done", 0)
+            .transition("start", &[("parts", 1), ("operator", 1)], &[("busy", 1)])
+            .transition("finish", &[("busy", 1)], &[("operator", 1), ("done", 1)]);
+
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: net.agents(),
+            halt_check: |s: &Simulation| s.time == 100,
+            ..Default::default()
+        });
+        simulation.run();
+
+        let report = net.report(&simulation);
+        assert_eq!(report.tokens("done"), Some(5));
+        assert_eq!(report.tokens("parts"), Some(0));
+        assert_eq!(report.tokens("operator"), Some(1));
+        assert_eq!(
+            (report.fired("start"), report.fired("finish")),
+            (Some(5), Some(5))
+        );
+
+        let busy = &report.places[2];
+        assert_eq!(busy.max_tokens, 1);
+        assert!(busy.mean_tokens > 0.0 && busy.mean_tokens < 1.0);
+        assert!(simulation.message_ledger().is_balanced());
+    }
+}
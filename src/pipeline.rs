@@ -0,0 +1,186 @@
+//! Pipelines: chains of stages, like an assembly line, each of which sends
+//! its work on to the next, without hand-wiring the destinations.
+
+use crate::module::SimModule;
+use crate::{Agent, AgentMode, AgentState, DiscreteTime, Message, SimulationState};
+use simul_macro::agent;
+use std::sync::Arc;
+
+/// Builds the Agent of a stage with the given id, which sends its work on to
+/// the id of the next stage, None for the last one.
+pub type StageInitializer = Arc<dyn Fn(String, Option<&str>) -> Box<dyn Agent> + Send + Sync>;
+
+/// A chain of stages, in order; see the module docs.
+#[derive(Clone, Default)]
+pub struct Pipeline {
+    stages: Vec<(String, StageInitializer)>,
+}
+
+impl std::fmt::Debug for Pipeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pipeline")
+            .field("stages", &self.ids())
+            .finish()
+    }
+}
+
+impl Pipeline {
+    pub fn new() -> Pipeline {
+        Pipeline::default()
+    }
+
+    /// Appends a stage with the given id, which the previous stage sends to.
+    pub fn stage<T, F>(mut self, id: T, initializer: F) -> Self
+    where
+        T: Into<String>,
+        F: Fn(String, Option<&str>) -> Box<dyn Agent> + Send + Sync + 'static,
+    {
+        self.stages.push((id.into(), Arc::new(initializer)));
+        self
+    }
+
+    /// The ids of the stages, in order.
+    pub fn ids(&self) -> Vec<&str> {
+        self.stages.iter().map(|(id, _)| id.as_str()).collect()
+    }
+
+    /// Returns the Agents of the stages, in order, each wired to the next.
+    pub fn build(&self) -> Vec<Box<dyn Agent>> {
+        let ids = self.ids();
+        self.stages
+            .iter()
+            .enumerate()
+            .map(|(i, (id, initializer))| initializer(id.clone(), ids.get(i + 1).copied()))
+            .collect()
+    }
+
+    /// Returns a module of the stages, with the ids as roles, so the
+    /// pipeline can be installed many times, each instance in its own
+    /// namespace.
+    pub fn module<T>(&self, name: T) -> SimModule
+    where
+        T: Into<String>,
+    {
+        let ids: Vec<String> = self.ids().into_iter().map(String::from).collect();
+        let mut module = SimModule::new(name);
+        for (i, (role, initializer)) in self.stages.iter().enumerate() {
+            let initializer = initializer.clone();
+            let next = ids.get(i + 1).cloned();
+            module =
+                module.with_agent(role.as_str(), move |id, _| initializer(id, next.as_deref()));
+        }
+        module
+    }
+}
+
+/// Returns an Agent that works on each message it receives for
+/// `service_period` ticks, in the order they arrived, and then sends it on to
+/// next, if any; otherwise it consumes it.
+pub fn stage_agent<T>(id: T, service_period: DiscreteTime, next: Option<&str>) -> Box<dyn Agent>
+where
+    T: Into<String>,
+{
+    #[agent]
+    struct Stage {
+        service_period: DiscreteTime,
+        next: Option<String>,
+        /// The message being worked on, sent on once the work is done.
+        working_on: Option<Message>,
+    }
+
+    impl Agent for Stage {
+        fn process(
+            &mut self,
+            simulation_state: SimulationState,
+            msg: &Message,
+        ) -> Option<Vec<Message>> {
+            let time = simulation_state.time;
+            let done = self.working_on.take();
+            let mut sent = vec![];
+            if let Some(done) = done {
+                match &self.next {
+                    Some(next) => sent.push(done.forward(time, next.as_str())),
+                    None => self.state.consumed.push(Message {
+                        completed_time: Some(time),
+                        ..done
+                    }),
+                }
+            }
+
+            if msg.source == "SIM_SRC" {
+                self.state.mode = AgentMode::Reactive;
+            } else {
+                self.working_on = Some(msg.clone());
+                self.state.mode = AgentMode::AsleepUntil(time + self.service_period);
+                self.state.wake_mode = AgentMode::Proactive;
+            }
+            Some(sent)
+        }
+    }
+
+    Box::new(Stage {
+        service_period,
+        next: next.map(String::from),
+        working_on: None,
+        state: AgentState {
+            mode: AgentMode::Reactive,
+            wake_mode: AgentMode::Reactive,
+            id: id.into(),
+            ..Default::default()
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    fn assembly_line() -> Pipeline {
+        Pipeline::new()
+            .stage("orders", |id, next| {
+                periodic_producing_agent(id, 3, next.unwrap().to_string())
+            })
+            .stage("cut", |id, next| stage_agent(id, 2, next))
+            .stage("weld", |id, next| stage_agent(id, 3, next))
+            .stage("paint", |id, next| stage_agent(id, 1, next))
+    }
+
+    #[test]
+    fn pipeline_test() {
+        let pipeline = assembly_line();
+        assert_eq!(pipeline.ids(), ["orders", "cut", "weld", "paint"]);
+
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: pipeline.build(),
+            halt_check: |s: &Simulation| s.time == 60,
+            ..Default::default()
+        });
+        simulation.run();
+
+        // Every order is delivered to cut, weld and paint in turn.
+        let painted = simulation.consumed_for_agent("paint").unwrap();
+        assert!(!painted.is_empty());
+        assert!(painted.iter().all(|m| m.hops == 3));
+        let sent = simulation.produced_count("weld").unwrap();
+        assert!(sent >= painted.len());
+        assert!(simulation.message_ledger().is_balanced());
+    }
+
+    #[test]
+    fn pipeline_module_test() {
+        let module = assembly_line().module("line");
+        let parameters = SimulationParameters {
+            halt_check: |s: &Simulation| s.time == 60,
+            ..Default::default()
+        }
+        .with_module(&module)
+        .with_module(&module.renamed("backup"));
+        let mut simulation = Simulation::new(parameters);
+        simulation.run();
+
+        let painted = |id| simulation.consumed_for_agent(id).unwrap().len();
+        assert!(painted("line::paint") > 0);
+        assert_eq!(painted("line::paint"), painted("backup::paint"));
+    }
+}
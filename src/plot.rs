@@ -0,0 +1,930 @@
+//! Renders figures of Simulations and experiments. Requires the `plot` feature.
+//!
+//! Every figure has a `draw_*` function that draws onto any plotters drawing
+//! area, e.g. a `BitMapBackend` with plotters' bitmap feature enabled, and a
+//! convenience function that renders it to an SVG file.
+
+use crate::chaos::{ChaosSweepReport, LATENCY, LOSS_PROBABILITY};
+use crate::experiment::{ExperimentReport, GridSearchReport, ParameterPoint};
+use crate::{Activity, DiscreteTime, Interpolation, Series, Simulation, TimeScale};
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// The error of rendering a figure.
+pub type PlotError = Box<dyn std::error::Error>;
+
+/// How a figure is rendered.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlotConfig {
+    /// The (width, height) of SVG files, in pixels.
+    pub size: (u32, u32),
+    /// Replaces the figure's default title.
+    pub title: Option<String>,
+    /// The names of the series to draw, e.g. the configurations of a box plot
+    /// or the lines of an agent plot. None draws them all.
+    pub series: Option<Vec<String>>,
+}
+
+impl Default for PlotConfig {
+    fn default() -> Self {
+        PlotConfig {
+            size: (800, 600),
+            title: None,
+            series: None,
+        }
+    }
+}
+
+impl PlotConfig {
+    fn title<'a>(&'a self, default: &'a str) -> &'a str {
+        self.title.as_deref().unwrap_or(default)
+    }
+
+    fn includes(&self, series: &str) -> bool {
+        self.series
+            .as_ref()
+            .map_or(true, |s| s.iter().any(|name| name == series))
+    }
+
+    /// Renders a figure to an SVG file at the configured size.
+    fn render_svg<P, F>(&self, path: P, draw: F) -> Result<(), PlotError>
+    where
+        P: AsRef<Path>,
+        F: FnOnce(&DrawingArea<SVGBackend, Shift>) -> Result<(), PlotError>,
+    {
+        let root = SVGBackend::new(path.as_ref(), self.size).into_drawing_area();
+        draw(&root)?;
+        root.present()?;
+        Ok(())
+    }
+}
+
+/// Renders a box plot of a KPI for every configuration of an experiment, one
+/// box per configuration label, to an SVG file.
+pub fn kpi_box_plot<P: AsRef<Path>>(
+    report: &ExperimentReport,
+    kpi: &str,
+    path: P,
+    config: &PlotConfig,
+) -> Result<(), PlotError> {
+    config.render_svg(path, |area| draw_kpi_box_plot(area, report, kpi, config))
+}
+
+/// Draws a box plot of a KPI for every configuration of an experiment.
+pub fn draw_kpi_box_plot<DB>(
+    area: &DrawingArea<DB, Shift>,
+    report: &ExperimentReport,
+    kpi: &str,
+    config: &PlotConfig,
+) -> Result<(), PlotError>
+where
+    DB: DrawingBackend,
+    DB::ErrorType: 'static,
+{
+    let mut series = vec![];
+    for (label, _) in report.configurations.iter() {
+        let values = report
+            .kpi_values(label, kpi)
+            .ok_or_else(|| format!("unknown KPI {:?}", kpi))?;
+        if config.includes(label) {
+            series.push((label.clone(), values));
+        }
+    }
+
+    let all_values = series.iter().flat_map(|(_, values)| values.iter());
+    let (min, max) = all_values.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| {
+        (min.min(*v), max.max(*v))
+    });
+    if !min.is_finite() {
+        return Err("the experiment has no runs".into());
+    }
+    let margin = ((max - min) * 0.1).max(1.0);
+    let labels: Vec<String> = series.iter().map(|(label, _)| label.clone()).collect();
+
+    area.fill(&WHITE)?;
+
+    let mut chart = ChartBuilder::on(area)
+        .caption(config.title(kpi), ("sans-serif", 24))
+        .margin(20)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d(
+            labels.into_segmented(),
+            (min - margin) as f32..(max + margin) as f32,
+        )?;
+
+    chart.configure_mesh().disable_x_mesh().y_desc(kpi).draw()?;
+
+    chart.draw_series(
+        series
+            .iter()
+            .zip(labels.iter())
+            .map(|((_, values), label)| {
+                Boxplot::new_vertical(SegmentValue::CenterOf(label), &Quartiles::new(values))
+            }),
+    )?;
+
+    Ok(())
+}
+
+/// Renders the degradation surface of a KPI from a chaos sweep as a heatmap
+/// over latency and loss probability, to an SVG file.
+pub fn chaos_heatmap<P: AsRef<Path>>(
+    report: &ChaosSweepReport,
+    kpi: &str,
+    path: P,
+    config: &PlotConfig,
+) -> Result<(), PlotError> {
+    config.render_svg(path, |area| draw_chaos_heatmap(area, report, kpi, config))
+}
+
+/// Draws the degradation surface of a KPI from a chaos sweep as a heatmap.
+pub fn draw_chaos_heatmap<DB>(
+    area: &DrawingArea<DB, Shift>,
+    report: &ChaosSweepReport,
+    kpi: &str,
+    config: &PlotConfig,
+) -> Result<(), PlotError>
+where
+    DB: DrawingBackend,
+    DB::ErrorType: 'static,
+{
+    let degradation = report
+        .degradation(kpi)
+        .ok_or_else(|| format!("unknown KPI {:?}", kpi))?;
+    let title = format!("{} degradation", kpi);
+    draw_heatmap(
+        area,
+        &degradation,
+        LATENCY,
+        LOSS_PROBABILITY,
+        config.title(&title),
+    )
+}
+
+/// Renders the scores of a grid search as a heatmap over two of its
+/// dimensions, to an SVG file. Scores are averaged over any other dimensions.
+pub fn grid_search_heatmap<P: AsRef<Path>>(
+    report: &GridSearchReport,
+    x_dimension: &str,
+    y_dimension: &str,
+    path: P,
+    config: &PlotConfig,
+) -> Result<(), PlotError> {
+    let scores: Vec<(ParameterPoint, f64)> = report
+        .results
+        .iter()
+        .map(|(point, score)| (point.clone(), *score as f64))
+        .collect();
+    config.render_svg(path, |area| {
+        draw_heatmap(
+            area,
+            &scores,
+            x_dimension,
+            y_dimension,
+            config.title("score"),
+        )
+    })
+}
+
+/// Renders values over two dimensions of a parameter space as a heatmap, to
+/// an SVG file; see `draw_heatmap`.
+pub fn heatmap<P: AsRef<Path>>(
+    values: &[(ParameterPoint, f64)],
+    x_dimension: &str,
+    y_dimension: &str,
+    path: P,
+    config: &PlotConfig,
+) -> Result<(), PlotError> {
+    config.render_svg(path, |area| {
+        draw_heatmap(
+            area,
+            values,
+            x_dimension,
+            y_dimension,
+            config.title("value"),
+        )
+    })
+}
+
+/// Draws values over two dimensions of a parameter space as a heatmap, one
+/// cell per distinct pair of values, from blue (lowest) to red (highest).
+/// Values of points that share a cell, differing only in other dimensions,
+/// are averaged.
+pub fn draw_heatmap<DB>(
+    area: &DrawingArea<DB, Shift>,
+    values: &[(ParameterPoint, f64)],
+    x_dimension: &str,
+    y_dimension: &str,
+    title: &str,
+) -> Result<(), PlotError>
+where
+    DB: DrawingBackend,
+    DB::ErrorType: 'static,
+{
+    let axis = |dimension: &str| -> Result<Vec<f64>, PlotError> {
+        let mut axis = vec![];
+        for (point, _) in values {
+            let value = *point
+                .get(dimension)
+                .ok_or_else(|| format!("unknown dimension {:?}", dimension))?;
+            if !axis.contains(&value) {
+                axis.push(value);
+            }
+        }
+        axis.sort_by(f64::total_cmp);
+        Ok(axis)
+    };
+    let (xs, ys) = (axis(x_dimension)?, axis(y_dimension)?);
+    if xs.is_empty() {
+        return Err("there are no values to plot".into());
+    }
+
+    // Maps from (x index, y index) => (sum, count) of the cell's values.
+    let mut cells: BTreeMap<(usize, usize), (f64, f64)> = BTreeMap::new();
+    for (point, value) in values {
+        let x = xs.iter().position(|x| *x == point[x_dimension]);
+        let y = ys.iter().position(|y| *y == point[y_dimension]);
+        let cell = cells.entry((x.unwrap_or(0), y.unwrap_or(0))).or_default();
+        *cell = (cell.0 + value, cell.1 + 1.0);
+    }
+    let cells: Vec<((usize, usize), f64)> = cells
+        .into_iter()
+        .map(|(xy, (sum, count))| (xy, sum / count))
+        .collect();
+
+    let (min, max) = cells
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), (_, v)| {
+            (min.min(*v), max.max(*v))
+        });
+    let color = |value: f64| {
+        let t = if max > min {
+            (value - min) / (max - min)
+        } else {
+            0.0
+        };
+        HSLColor(0.66 * (1.0 - t), 0.8, 0.5)
+    };
+
+    area.fill(&WHITE)?;
+
+    let mut chart = ChartBuilder::on(area)
+        .caption(title, ("sans-serif", 24))
+        .margin(20)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d(0.0..xs.len() as f64, 0.0..ys.len() as f64)?;
+
+    let label = |axis: &[f64], position: f64| {
+        axis.get(position as usize)
+            .filter(|_| position.fract() == 0.5)
+            .map(|v| v.to_string())
+            .unwrap_or_default()
+    };
+    chart
+        .configure_mesh()
+        .disable_mesh()
+        .x_desc(x_dimension)
+        .y_desc(y_dimension)
+        .x_labels(xs.len() * 2 + 1)
+        .y_labels(ys.len() * 2 + 1)
+        .x_label_formatter(&|x| label(&xs, *x))
+        .y_label_formatter(&|y| label(&ys, *y))
+        .draw()?;
+
+    chart.draw_series(cells.iter().map(|((x, y), value)| {
+        let (x, y) = (*x as f64, *y as f64);
+        Rectangle::new([(x, y), (x + 1.0, y + 1.0)], color(*value).filled())
+    }))?;
+
+    Ok(())
+}
+
+/// Renders the timeseries of an Agent as lines over time, to an SVG file:
+/// its queue depth, if `enable_queue_depth_metrics` was set, and every series
+/// it exposes through `Agent::plot_series`.
+pub fn agent_plot<P: AsRef<Path>>(
+    simulation: &Simulation,
+    id: &str,
+    path: P,
+    config: &PlotConfig,
+) -> Result<(), PlotError> {
+    config.render_svg(path, |area| draw_agent_plot(area, simulation, id, config))
+}
+
+/// Draws the timeseries of an Agent as lines over time.
+pub fn draw_agent_plot<DB>(
+    area: &DrawingArea<DB, Shift>,
+    simulation: &Simulation,
+    id: &str,
+    config: &PlotConfig,
+) -> Result<(), PlotError>
+where
+    DB: DrawingBackend,
+    DB::ErrorType: 'static,
+{
+    let agent = simulation
+        .agents
+        .iter()
+        .find(|a| a.state().id == id)
+        .ok_or_else(|| format!("unknown agent {:?}", id))?;
+
+    let mut lines: Vec<(String, Vec<(f64, f64)>)> = vec![];
+    let depths = queue_depth_points(simulation, id);
+    if !depths.is_empty() {
+        lines.push(("queue depth".to_string(), depths));
+    }
+    for (name, series) in agent.plot_series() {
+        lines.push((name.to_string(), line_points(series)));
+    }
+    lines.retain(|(name, _)| config.includes(name));
+
+    if lines.is_empty() {
+        return Err(format!("agent {:?} has no series to plot", id).into());
+    }
+    draw_lines(area, config.title(id), simulation.time_scale(), lines)
+}
+
+/// Renders rolling p50, p95 and p99 wait times of an Agent over time, to an
+/// SVG file; see `draw_wait_percentile_plot`.
+pub fn wait_percentile_plot<P: AsRef<Path>>(
+    simulation: &Simulation,
+    id: &str,
+    window: DiscreteTime,
+    path: P,
+    config: &PlotConfig,
+) -> Result<(), PlotError> {
+    config.render_svg(path, |area| {
+        draw_wait_percentile_plot(area, simulation, id, window, config)
+    })
+}
+
+/// Draws rolling p50, p95 and p99 wait times of an Agent over time, over
+/// windows of `window` ticks, which makes saturation trends visible. See
+/// `Simulation::rolling_wait_time_percentiles`.
+pub fn draw_wait_percentile_plot<DB>(
+    area: &DrawingArea<DB, Shift>,
+    simulation: &Simulation,
+    id: &str,
+    window: DiscreteTime,
+    config: &PlotConfig,
+) -> Result<(), PlotError>
+where
+    DB: DrawingBackend,
+    DB::ErrorType: 'static,
+{
+    let percentiles = [("p50", 50.0), ("p95", 95.0), ("p99", 99.0)];
+    let rolling = simulation
+        .rolling_wait_time_percentiles(id, window, &percentiles.map(|(_, p)| p))
+        .ok_or_else(|| format!("unknown agent {:?}", id))?;
+    if rolling.is_empty() {
+        return Err(format!("agent {:?} consumed no messages", id).into());
+    }
+
+    let lines = percentiles
+        .iter()
+        .enumerate()
+        .filter(|(_, (name, _))| config.includes(name))
+        .map(|(index, (name, _))| {
+            let points = rolling
+                .iter()
+                .map(|(time, values)| (*time as f64, values[index] as f64));
+            (name.to_string(), points.collect())
+        })
+        .collect();
+
+    let title = format!("{} wait time", id);
+    draw_lines(area, config.title(&title), simulation.time_scale(), lines)
+}
+
+/// A timeseries of a Simulation, to compare across runs.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum RunMetric<'a> {
+    /// An Agent's queue depth. Requires `enable_queue_depth_metrics`.
+    QueueDepth(&'a str),
+    /// The number of messages an Agent consumed so far.
+    Consumed(&'a str),
+    /// An environment variable. Requires `enable_environment_metrics`.
+    Environment(&'a str),
+    /// The messages an Agent consumed per window. Requires `throughput_window`.
+    Throughput(&'a str),
+}
+
+impl RunMetric<'_> {
+    fn points(&self, simulation: &Simulation) -> Vec<(f64, f64)> {
+        match self {
+            RunMetric::QueueDepth(id) => queue_depth_points(simulation, id),
+            RunMetric::Consumed(id) => {
+                let mut completions: Vec<DiscreteTime> = simulation
+                    .agents
+                    .iter()
+                    .find(|a| a.state().id == *id)
+                    .map(|a| a.state().consumed.iter())
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|m| m.completed_time)
+                    .collect();
+                completions.sort_unstable();
+
+                let mut series = Series::new(Interpolation::Step);
+                series.record(simulation.starting_time, 0.0);
+                for (count, time) in completions.iter().enumerate() {
+                    series.record(*time, (count + 1) as f64);
+                }
+                series.record(simulation.time, completions.len() as f64);
+                line_points(&series)
+            }
+            RunMetric::Environment(name) => simulation
+                .environment_series(name)
+                .map(line_points)
+                .unwrap_or_default(),
+            RunMetric::Throughput(id) => simulation
+                .throughput(id)
+                .unwrap_or_default()
+                .iter()
+                .map(|(from, consumed)| (*from as f64, *consumed as f64))
+                .collect(),
+        }
+    }
+}
+
+impl std::fmt::Display for RunMetric<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RunMetric::QueueDepth(id) => write!(f, "{} queue depth", id),
+            RunMetric::Consumed(id) => write!(f, "{} consumed", id),
+            RunMetric::Environment(name) => write!(f, "{}", name),
+            RunMetric::Throughput(id) => write!(f, "{} throughput", id),
+        }
+    }
+}
+
+/// Renders the same metric from several runs as overlaid lines, one per run
+/// labelled in the legend, to an SVG file; see `draw_comparison`.
+pub fn plot_comparison<P: AsRef<Path>>(
+    runs: &[(&str, &Simulation)],
+    metric: RunMetric,
+    path: P,
+    config: &PlotConfig,
+) -> Result<(), PlotError> {
+    config.render_svg(path, |area| draw_comparison(area, runs, metric, config))
+}
+
+/// Draws the same metric from several runs, e.g. with different consumer
+/// periods or seeds, as overlaid lines over time.
+pub fn draw_comparison<DB>(
+    area: &DrawingArea<DB, Shift>,
+    runs: &[(&str, &Simulation)],
+    metric: RunMetric,
+    config: &PlotConfig,
+) -> Result<(), PlotError>
+where
+    DB: DrawingBackend,
+    DB::ErrorType: 'static,
+{
+    let lines: Vec<(String, Vec<(f64, f64)>)> = runs
+        .iter()
+        .filter(|(label, _)| config.includes(label))
+        .map(|(label, simulation)| (label.to_string(), metric.points(simulation)))
+        .collect();
+
+    let title = metric.to_string();
+    let time_scale = runs.first().and_then(|(_, s)| s.time_scale());
+    draw_lines(area, config.title(&title), time_scale, lines)
+}
+
+/// The queue depths of an Agent after the warm-up, as points over time.
+fn queue_depth_points(simulation: &Simulation, id: &str) -> Vec<(f64, f64)> {
+    simulation
+        .queue_depth_timeline(id)
+        .unwrap_or_default()
+        .iter()
+        .filter(|(time, _)| simulation.is_after_warm_up(*time))
+        .map(|(time, depth)| (*time as f64, *depth as f64))
+        .collect()
+}
+
+/// Draws named lines of (x, y) points on one chart, with a legend.
+fn draw_lines<DB>(
+    area: &DrawingArea<DB, Shift>,
+    title: &str,
+    time_scale: Option<&TimeScale>,
+    lines: Vec<(String, Vec<(f64, f64)>)>,
+) -> Result<(), PlotError>
+where
+    DB: DrawingBackend,
+    DB::ErrorType: 'static,
+{
+    let points = lines.iter().flat_map(|(_, points)| points.iter());
+    let (min_x, max_x, min_y, max_y) = points.fold(
+        (
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+        ),
+        |(min_x, max_x, min_y, max_y), (x, y)| {
+            (min_x.min(*x), max_x.max(*x), min_y.min(*y), max_y.max(*y))
+        },
+    );
+    if !min_x.is_finite() {
+        return Err("there are no points to plot".into());
+    }
+
+    area.fill(&WHITE)?;
+
+    let mut chart = ChartBuilder::on(area)
+        .caption(title, ("sans-serif", 24))
+        .margin(20)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d(min_x..max_x.max(min_x + 1.0), min_y..max_y.max(min_y + 1.0))?;
+
+    // With a time scale, ticks are labeled with the times they stand for.
+    let time_label = |x: &f64| {
+        let time = x.max(0.0).round() as DiscreteTime;
+        time_scale.map_or_else(|| x.to_string(), |scale| scale.format(time))
+    };
+    let mut mesh = chart.configure_mesh();
+    mesh.x_desc("time");
+    if time_scale.is_some() {
+        mesh.x_label_formatter(&time_label);
+    }
+    mesh.draw()?;
+
+    for (index, (name, points)) in lines.into_iter().enumerate() {
+        let color = Palette99::pick(index).to_rgba();
+        chart
+            .draw_series(LineSeries::new(points, color.stroke_width(2)))?
+            .label(name)
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+    }
+
+    chart
+        .configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    Ok(())
+}
+
+/// Renders a Gantt chart of what every Agent did over time, to an SVG file;
+/// see `draw_gantt_chart`.
+pub fn gantt_chart<P: AsRef<Path>>(
+    simulation: &Simulation,
+    path: P,
+    config: &PlotConfig,
+) -> Result<(), PlotError> {
+    config.render_svg(path, |area| draw_gantt_chart(area, simulation, config))
+}
+
+/// Draws a Gantt chart with one row per Agent, banded by the spans of time
+/// it spent processing, idle, asleep or dead, making utilization and idle
+/// periods visible at a glance. Requires `enable_activity_metrics`.
+pub fn draw_gantt_chart<DB>(
+    area: &DrawingArea<DB, Shift>,
+    simulation: &Simulation,
+    config: &PlotConfig,
+) -> Result<(), PlotError>
+where
+    DB: DrawingBackend,
+    DB::ErrorType: 'static,
+{
+    let rows: Vec<(&str, &[crate::ActivitySpan])> = simulation
+        .agents
+        .iter()
+        .map(|a| a.state().id.as_str())
+        .filter(|id| config.includes(id))
+        .filter_map(|id| Some((id, simulation.activity(id)?)))
+        .filter(|(_, spans)| !spans.is_empty())
+        .collect();
+    if rows.is_empty() {
+        return Err("no activity was recorded; set enable_activity_metrics".into());
+    }
+
+    let from = rows
+        .iter()
+        .map(|(_, spans)| spans[0].from)
+        .min()
+        .unwrap_or(0);
+    let until = rows
+        .iter()
+        .flat_map(|(_, spans)| spans.last())
+        .map(|s| s.until);
+    let until = until.max().unwrap_or(from + 1);
+    let color = |activity: Activity| match activity {
+        Activity::Processing => RGBColor(46, 139, 87),
+        Activity::Idle => RGBColor(211, 211, 211),
+        Activity::Asleep => RGBColor(100, 149, 237),
+        Activity::Dead => RGBColor(40, 40, 40),
+    };
+
+    area.fill(&WHITE)?;
+
+    let mut chart = ChartBuilder::on(area)
+        .caption(config.title("activity"), ("sans-serif", 24))
+        .margin(20)
+        .x_label_area_size(40)
+        .y_label_area_size(100)
+        .build_cartesian_2d(from as f64..until as f64, 0.0..rows.len() as f64)?;
+
+    let label = |position: f64| {
+        rows.get(position as usize)
+            .filter(|_| position.fract() == 0.5)
+            .map(|(id, _)| id.to_string())
+            .unwrap_or_default()
+    };
+    chart
+        .configure_mesh()
+        .disable_y_mesh()
+        .x_desc("time")
+        .y_labels(rows.len() * 2 + 1)
+        .y_label_formatter(&|y| label(*y))
+        .draw()?;
+
+    chart.draw_series(rows.iter().enumerate().flat_map(|(row, (_, spans))| {
+        spans.iter().map(move |span| {
+            let (x0, x1) = (span.from as f64, span.until as f64);
+            let (y0, y1) = (row as f64 + 0.1, row as f64 + 0.9);
+            Rectangle::new([(x0, y0), (x1, y1)], color(span.activity).filled())
+        })
+    }))?;
+
+    for activity in [
+        Activity::Processing,
+        Activity::Idle,
+        Activity::Asleep,
+        Activity::Dead,
+    ] {
+        chart
+            .draw_series(std::iter::empty::<Rectangle<(f64, f64)>>())?
+            .label(format!("{:?}", activity))
+            .legend(move |(x, y)| {
+                Rectangle::new([(x, y - 5), (x + 10, y + 5)], color(activity).filled())
+            });
+    }
+
+    chart
+        .configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    Ok(())
+}
+
+/// The points of the line of a Series, with steps drawn as such.
+fn line_points(series: &Series) -> Vec<(f64, f64)> {
+    let mut points = vec![];
+    for (time, value) in series.points.iter() {
+        if let (Interpolation::Step, Some((_, previous))) = (series.interpolation, points.last()) {
+            points.push((*time as f64, *previous));
+        }
+        points.push((*time as f64, *value));
+    }
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::experiment::{run_experiment, Kpi};
+    use crate::*;
+
+    #[test]
+    fn kpi_box_plot_test() {
+        let parameters = |period| SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer", period, "consumer"),
+                periodic_consuming_agent("consumer", 1),
+            ],
+            halt_check: |s: &Simulation| s.time == 20,
+            ..Default::default()
+        };
+        let kpis: &[(&str, Kpi)] = &[("produced", |s| {
+            s.calc_produced_len_statistics()["producer"] as f64
+        })];
+        let report = run_experiment(
+            &[("fast", parameters(1)), ("slow", parameters(4))],
+            &[1, 2, 3],
+            kpis,
+        );
+
+        let path = std::env::temp_dir().join("simul-kpi-box-plot-test.svg");
+        kpi_box_plot(&report, "produced", &path, &PlotConfig::default()).unwrap();
+        let svg = std::fs::read_to_string(&path).unwrap();
+        assert!(svg.contains("<svg") && svg.contains("slow"));
+        assert!(kpi_box_plot(&report, "unknown", &path, &PlotConfig::default()).is_err());
+    }
+
+    #[test]
+    fn agent_plot_test() {
+        #[simul_macro::agent]
+        struct Thermostat {
+            temperature: Series,
+        }
+
+        impl Agent for Thermostat {
+            fn process(&mut self, state: SimulationState, _msg: &Message) -> Option<Vec<Message>> {
+                self.temperature
+                    .record(state.time, 20.0 + (state.time % 3) as f64);
+                None
+            }
+
+            fn plot_series(&self) -> Vec<(&str, &Series)> {
+                vec![("temperature", &self.temperature)]
+            }
+        }
+
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![Box::new(Thermostat {
+                temperature: Series::default(),
+                state: AgentState {
+                    mode: AgentMode::Proactive,
+                    wake_mode: AgentMode::Proactive,
+                    id: "thermostat".to_string(),
+                    ..Default::default()
+                },
+            })],
+            enable_queue_depth_metrics: true,
+            halt_check: |s: &Simulation| s.time == 20,
+            ..Default::default()
+        });
+        simulation.run();
+
+        let path = std::env::temp_dir().join("simul-agent-plot-test.svg");
+        agent_plot(&simulation, "thermostat", &path, &PlotConfig::default()).unwrap();
+        let svg = std::fs::read_to_string(&path).unwrap();
+        assert!(svg.contains("queue depth") && svg.contains("temperature"));
+        assert!(agent_plot(&simulation, "unknown", &path, &PlotConfig::default()).is_err());
+
+        let config = PlotConfig {
+            size: (400, 300),
+            title: Some("Thermostat readings".to_string()),
+            series: Some(vec!["temperature".to_string()]),
+        };
+        agent_plot(&simulation, "thermostat", &path, &config).unwrap();
+        let svg = std::fs::read_to_string(&path).unwrap();
+        assert!(svg.contains("width=\"400\"") && svg.contains("Thermostat readings"));
+        assert!(svg.contains("temperature") && !svg.contains("queue depth"));
+    }
+
+    #[test]
+    fn gantt_chart_test() {
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer", 4, "consumer"),
+                periodic_consuming_agent("consumer", 2),
+            ],
+            enable_activity_metrics: true,
+            halt_check: |s: &Simulation| s.time == 8,
+            ..Default::default()
+        });
+        simulation.run();
+
+        let path = std::env::temp_dir().join("simul-gantt-chart-test.svg");
+        gantt_chart(&simulation, &path, &PlotConfig::default()).unwrap();
+        let svg = std::fs::read_to_string(&path).unwrap();
+        assert!(svg.contains("consumer") && svg.contains("Asleep"));
+
+        let only_unknown = PlotConfig {
+            series: Some(vec!["unknown".to_string()]),
+            ..Default::default()
+        };
+        assert!(gantt_chart(&simulation, &path, &only_unknown).is_err());
+    }
+
+    #[test]
+    fn wait_percentile_plot_test() {
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer", 1, "consumer"),
+                periodic_consuming_agent("consumer", 2),
+            ],
+            halt_check: |s: &Simulation| s.time == 50,
+            ..Default::default()
+        });
+        simulation.run();
+
+        let path = std::env::temp_dir().join("simul-wait-percentile-plot-test.svg");
+        wait_percentile_plot(&simulation, "consumer", 10, &path, &PlotConfig::default()).unwrap();
+        let svg = std::fs::read_to_string(&path).unwrap();
+        assert!(svg.contains("p50") && svg.contains("p99"));
+        assert!(
+            wait_percentile_plot(&simulation, "producer", 10, &path, &PlotConfig::default())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn plot_comparison_test() {
+        let run = |consumer_period| {
+            let mut simulation = Simulation::new(SimulationParameters {
+                agents: vec![
+                    periodic_producing_agent("producer", 2, "consumer"),
+                    periodic_consuming_agent("consumer", consumer_period),
+                ],
+                enable_queue_depth_metrics: true,
+                throughput_window: Some(5),
+                halt_check: |s: &Simulation| s.time == 30,
+                ..Default::default()
+            });
+            simulation.run();
+            simulation
+        };
+        let (fast, slow) = (run(1), run(4));
+        let runs = [("fast consumer", &fast), ("slow consumer", &slow)];
+
+        let path = std::env::temp_dir().join("simul-plot-comparison-test.svg");
+        for metric in [
+            RunMetric::QueueDepth("consumer"),
+            RunMetric::Consumed("consumer"),
+            RunMetric::Throughput("consumer"),
+        ] {
+            plot_comparison(&runs, metric, &path, &PlotConfig::default()).unwrap();
+            let svg = std::fs::read_to_string(&path).unwrap();
+            assert!(svg.contains("fast consumer") && svg.contains("slow consumer"));
+        }
+
+        let metric = RunMetric::Environment("unknown");
+        assert!(plot_comparison(&runs, metric, &path, &PlotConfig::default()).is_err());
+        assert_eq!(
+            RunMetric::Consumed("consumer").points(&slow).last(),
+            Some(&(30.0, slow.consumed_count("consumer").unwrap() as f64))
+        );
+    }
+
+    #[test]
+    fn chaos_heatmap_test() {
+        let parameters = SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer", 1, "consumer"),
+                periodic_consuming_agent("consumer", 1),
+            ],
+            halt_check: |s: &Simulation| s.time == 20,
+            ..Default::default()
+        };
+        let sweep = crate::chaos::ChaosSweep {
+            latencies: vec![0, 2, 4],
+            loss_probabilities: vec![0.0, 0.5],
+            seeds: vec![1],
+        };
+        let kpis: &[(&str, Kpi)] = &[("wait", crate::experiment::mean_wait_time)];
+        let report = crate::chaos::chaos_sweep(&parameters, &sweep, kpis);
+
+        let path = std::env::temp_dir().join("simul-chaos-heatmap-test.svg");
+        chaos_heatmap(&report, "wait", &path, &PlotConfig::default()).unwrap();
+        let svg = std::fs::read_to_string(&path).unwrap();
+        assert!(svg.contains("<svg") && svg.contains("loss_probability"));
+        assert!(chaos_heatmap(&report, "unknown", &path, &PlotConfig::default()).is_err());
+    }
+
+    #[test]
+    fn grid_search_heatmap_test() {
+        let space = crate::experiment::ParameterSpace::default()
+            .with_dimension("producer_period", [1.0, 2.0, 3.0])
+            .with_dimension("consumer_period", [1.0, 2.0])
+            .with_dimension("unused", [0.0, 1.0]);
+        let report = crate::experiment::grid_search(
+            &space,
+            |point| SimulationParameters {
+                agents: vec![
+                    periodic_producing_agent(
+                        "producer",
+                        point["producer_period"] as u64,
+                        "consumer",
+                    ),
+                    periodic_consuming_agent("consumer", point["consumer_period"] as u64),
+                ],
+                halt_check: |s: &Simulation| s.time == 20,
+                ..Default::default()
+            },
+            |s| s.calc_consumed_len_statistics()["consumer"] as i64,
+        );
+
+        let path = std::env::temp_dir().join("simul-grid-search-heatmap-test.svg");
+        grid_search_heatmap(
+            &report,
+            "producer_period",
+            "consumer_period",
+            &path,
+            &PlotConfig::default(),
+        )
+        .unwrap();
+        let svg = std::fs::read_to_string(&path).unwrap();
+        // The background, and one cell per (producer, consumer) period.
+        assert_eq!(svg.matches("<rect").count(), 7);
+        assert!(grid_search_heatmap(
+            &report,
+            "producer_period",
+            "unknown",
+            &path,
+            &PlotConfig::default()
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,176 @@
+//! Pools of consumers that share a single queue, from which idle members
+//! pull work, rather than each having work pushed onto its own queue.
+//!
+//! Messages addressed to a pool wait in its shared queue. At the start of
+//! every tick, each member that is awake with nothing queued pulls the next
+//! one, so slow members never hold up work a fast member could be doing.
+
+use crate::{AgentMode, Message, Simulation};
+use std::collections::VecDeque;
+
+/// A set of consumers sharing a queue; see the module docs.
+#[derive(Clone, Debug)]
+pub struct ConsumerPool {
+    /// The name messages to the pool are addressed to.
+    pub name: String,
+    /// The ids of the member Agents.
+    pub members: Vec<String>,
+    queue: VecDeque<Message>,
+    /// The messages every member pulled, in member order.
+    pulls: Vec<usize>,
+    /// The member offered the first message at the next dispatch, rotated so
+    /// no member is always first in line.
+    next: usize,
+}
+
+impl ConsumerPool {
+    pub fn new<T>(name: T, members: Vec<String>) -> ConsumerPool
+    where
+        T: Into<String>,
+    {
+        ConsumerPool {
+            name: name.into(),
+            pulls: vec![0; members.len()],
+            members,
+            queue: VecDeque::new(),
+            next: 0,
+        }
+    }
+
+    /// Adds a member, which pulls from the next dispatch on.
+    pub fn add_member<T>(&mut self, member: T)
+    where
+        T: Into<String>,
+    {
+        self.members.push(member.into());
+        self.pulls.push(0);
+    }
+
+    /// Removes a member, and the count of messages it pulled.
+    pub fn remove_member(&mut self, member: &str) {
+        if let Some(index) = self.members.iter().position(|m| m == member) {
+            self.members.remove(index);
+            self.pulls.remove(index);
+            self.next %= self.members.len().max(1);
+        }
+    }
+
+    /// The number of messages waiting in the shared queue.
+    pub fn queue_len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Returns the (member, messages pulled) of every member.
+    pub fn pulls(&self) -> Vec<(&str, usize)> {
+        self.members
+            .iter()
+            .map(|m| m.as_str())
+            .zip(self.pulls.iter().copied())
+            .collect()
+    }
+
+    /// Jain's fairness index of the messages pulled by the members: 1 if all
+    /// pulled as many, down to 1/n if one pulled everything.
+    pub fn fairness(&self) -> f64 {
+        let sum: usize = self.pulls.iter().sum();
+        let sum_of_squares: usize = self.pulls.iter().map(|p| p * p).sum();
+        if sum_of_squares == 0 {
+            return 1.0;
+        }
+        (sum * sum) as f64 / (self.pulls.len() * sum_of_squares) as f64
+    }
+}
+
+impl Simulation {
+    /// Returns a consumer pool of the Simulation, as of now.
+    pub fn pool(&self, name: &str) -> Option<&ConsumerPool> {
+        self.pools.iter().find(|p| p.name == name)
+    }
+
+    /// Puts a message addressed to a pool onto its shared queue. Returns the
+    /// message back if it isn't addressed to a pool.
+    pub(crate) fn enqueue_in_pool(&mut self, message: Message) -> Option<Message> {
+        match self
+            .pools
+            .iter_mut()
+            .find(|p| p.name == message.destination)
+        {
+            Some(pool) => {
+                pool.queue.push_back(message);
+                None
+            }
+            None => Some(message),
+        }
+    }
+
+    /// Hands the next messages of every pool to its idle members.
+    pub(crate) fn dispatch_pools(&mut self) {
+        for index in 0..self.pools.len() {
+            let pool = &mut self.pools[index];
+            let members = pool.members.len();
+            let mut handed = vec![];
+            for offset in 0..members {
+                if pool.queue.is_empty() {
+                    break;
+                }
+                let member = (pool.next + offset) % members;
+                let Some(handle) = self.agent_handles.get(&pool.members[member]).copied() else {
+                    continue;
+                };
+
+                let state = self.agents[handle].state();
+                let awake = matches!(state.mode, AgentMode::Reactive | AgentMode::Proactive);
+                if awake && state.queue.is_empty() {
+                    let message = pool.queue.pop_front().expect("The queue isn't empty");
+                    pool.pulls[member] += 1;
+                    handed.push((handle, message));
+                }
+            }
+            pool.next = (pool.next + 1) % members.max(1);
+
+            for (handle, message) in handed {
+                self.deliver(handle, message, false);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn consumer_pool_test() {
+        let members = vec!["slow".to_string(), "fast".to_string()];
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer", 1, "workers"),
+                periodic_consuming_agent("slow", 4),
+                periodic_consuming_agent("fast", 1),
+            ],
+            pools: vec![ConsumerPool::new("workers", members)],
+            halt_check: |s: &Simulation| s.time == 200,
+            ..Default::default()
+        });
+        simulation.run();
+
+        // The fast member pulls more, and nothing waits on the slow one.
+        let pool = simulation.pool("workers").unwrap();
+        let pulls = pool.pulls();
+        assert!(pulls[1].1 > 2 * pulls[0].1, "{:?}", pulls);
+        assert!(pool.fairness() < 0.9);
+        assert!(pool.queue_len() <= 1);
+        assert!(simulation.queue_len("slow").unwrap() <= 1);
+        assert!(simulation.message_ledger().is_balanced());
+    }
+
+    #[test]
+    fn fairness_test() {
+        let mut pool = ConsumerPool::new("pool", vec!["a".to_string(), "b".to_string()]);
+        pool.pulls = vec![5, 5];
+        assert_eq!(pool.fairness(), 1.0);
+        pool.pulls = vec![10, 0];
+        assert_eq!(pool.fairness(), 0.5);
+    }
+}
@@ -0,0 +1,190 @@
+//! Tick-indexed stochastic processes, for driving prices, demand or
+//! environmental signals with realistic autocorrelation rather than
+//! independent samples. Agents step them with `simul::rng()`; as world
+//! dynamics they draw from a seeded stream of their own, see `process_dynamics`.
+
+use crate::world::{Environment, WorldDynamics};
+use crate::DiscreteTime;
+use dyn_clone::DynClone;
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+use rand_distr::StandardNormal;
+
+/// A StochasticProcess gives the value of a signal at the next tick from its
+/// value at this tick.
+pub trait StochasticProcess: std::fmt::Debug + DynClone + Send {
+    fn next(&mut self, value: f64, rng: &mut dyn RngCore) -> f64;
+}
+
+dyn_clone::clone_trait_object!(StochasticProcess);
+
+/// Moves by `drift` plus normal noise with standard deviation `volatility`
+/// every tick.
+#[derive(Clone, Debug)]
+pub struct RandomWalk {
+    pub drift: f64,
+    pub volatility: f64,
+}
+
+impl StochasticProcess for RandomWalk {
+    fn next(&mut self, value: f64, rng: &mut dyn RngCore) -> f64 {
+        let noise: f64 = rng.sample(StandardNormal);
+        value + self.drift + self.volatility * noise
+    }
+}
+
+/// The Ornstein–Uhlenbeck process: reverts towards `mean` by a fraction
+/// `reversion` of the distance every tick, plus normal noise with standard
+/// deviation `volatility`. Values stay autocorrelated but don't wander off.
+#[derive(Clone, Debug)]
+pub struct OrnsteinUhlenbeck {
+    pub mean: f64,
+    pub reversion: f64,
+    pub volatility: f64,
+}
+
+impl StochasticProcess for OrnsteinUhlenbeck {
+    fn next(&mut self, value: f64, rng: &mut dyn RngCore) -> f64 {
+        let noise: f64 = rng.sample(StandardNormal);
+        value + self.reversion * (self.mean - value) + self.volatility * noise
+    }
+}
+
+/// Switches between regimes, e.g. calm and volatile markets, by a Markov
+/// chain: every tick it moves from regime i to regime j with probability
+/// `transitions[i][j]`, then steps the process of the new regime.
+#[derive(Clone, Debug)]
+pub struct RegimeSwitching {
+    pub regimes: Vec<Box<dyn StochasticProcess>>,
+    /// The transition matrix. Each row sums to 1.
+    pub transitions: Vec<Vec<f64>>,
+    /// The index of the current regime.
+    pub regime: usize,
+}
+
+impl StochasticProcess for RegimeSwitching {
+    fn next(&mut self, value: f64, rng: &mut dyn RngCore) -> f64 {
+        let draw: f64 = rng.gen();
+        let mut cumulative = 0.0;
+        for (regime, probability) in self.transitions[self.regime].iter().enumerate() {
+            cumulative += probability;
+            if draw < cumulative {
+                self.regime = regime;
+                break;
+            }
+        }
+
+        self.regimes[self.regime].next(value, rng)
+    }
+}
+
+/// Samples the values of a process over the given number of ticks, starting
+/// from `initial`, reproducibly from the seed.
+pub fn sample_path(
+    process: &mut dyn StochasticProcess,
+    initial: f64,
+    ticks: usize,
+    seed: u64,
+) -> Vec<f64> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    std::iter::successors(Some(initial), |value| Some(process.next(*value, &mut rng)))
+        .take(ticks)
+        .collect()
+}
+
+/// Drives an environment variable by a process, from its own stream seeded
+/// by `seed`, so runs are reproducible. Missing variables start at `initial`.
+pub fn process_dynamics<T>(
+    variable: T,
+    initial: f64,
+    process: Box<dyn StochasticProcess>,
+    seed: u64,
+) -> Box<dyn WorldDynamics>
+where
+    T: Into<String>,
+{
+    #[derive(Clone, Debug)]
+    struct ProcessDynamics {
+        variable: String,
+        initial: f64,
+        process: Box<dyn StochasticProcess>,
+        rng: StdRng,
+    }
+
+    impl WorldDynamics for ProcessDynamics {
+        fn update(&mut self, _time: DiscreteTime, environment: &mut Environment) {
+            let value = environment
+                .entry(self.variable.clone())
+                .or_insert(self.initial);
+            *value = self.process.next(*value, &mut self.rng);
+        }
+    }
+
+    Box::new(ProcessDynamics {
+        variable: variable.into(),
+        initial,
+        process,
+        rng: StdRng::seed_from_u64(seed),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::StreamingStats;
+
+    #[test]
+    fn random_walk_test() {
+        let mut walk = RandomWalk {
+            drift: 2.0,
+            volatility: 0.0,
+        };
+        assert_eq!(sample_path(&mut walk, 1.0, 4, 1), vec![1.0, 3.0, 5.0, 7.0]);
+    }
+
+    #[test]
+    fn ornstein_uhlenbeck_test() {
+        let mut process = OrnsteinUhlenbeck {
+            mean: 10.0,
+            reversion: 0.1,
+            volatility: 1.0,
+        };
+        let path = sample_path(&mut process, 10.0, 10_000, 1);
+        assert_eq!(path, sample_path(&mut process, 10.0, 10_000, 1));
+
+        let mut stats = StreamingStats::default();
+        path.iter().for_each(|v| stats.push(*v));
+        assert!((stats.mean - 10.0).abs() < 0.5);
+
+        // Consecutive values are strongly correlated, unlike i.i.d. samples.
+        let covariance: f64 = path
+            .windows(2)
+            .map(|w| (w[0] - stats.mean) * (w[1] - stats.mean))
+            .sum::<f64>()
+            / (path.len() - 1) as f64;
+        assert!(covariance / stats.variance() > 0.8);
+    }
+
+    #[test]
+    fn regime_switching_test() {
+        let mut process = RegimeSwitching {
+            regimes: vec![
+                Box::new(RandomWalk {
+                    drift: 1.0,
+                    volatility: 0.0,
+                }),
+                Box::new(RandomWalk {
+                    drift: -1.0,
+                    volatility: 0.0,
+                }),
+            ],
+            transitions: vec![vec![0.0, 1.0], vec![1.0, 0.0]],
+            regime: 0,
+        };
+        // Alternates between the regimes on every tick.
+        assert_eq!(
+            sample_path(&mut process, 0.0, 4, 1),
+            vec![0.0, -1.0, 0.0, -1.0]
+        );
+    }
+}
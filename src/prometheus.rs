@@ -0,0 +1,165 @@
+//! Serves the live metrics of a running Simulation over HTTP, in the
+//! Prometheus text format, so long runs can be watched from Prometheus or
+//! Grafana. Requires the `prometheus` feature.
+
+use crate::report::{Cadence, Report, ReportSink};
+use crate::SimulationMode;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::io::{Read as _, Write as _};
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+
+/// A ReportSink that serves its latest report at `http://<address>/metrics`.
+/// Scrapes see the Simulation as of the sink's last report, so the cadence
+/// sets how fresh the metrics are.
+#[derive(Clone, Debug)]
+pub struct PrometheusExporter {
+    pub cadence: Cadence,
+    address: SocketAddr,
+    /// The latest report, rendered in the Prometheus text format.
+    metrics: Arc<Mutex<String>>,
+}
+
+impl PrometheusExporter {
+    /// Binds the address and serves metrics from a background thread until
+    /// the process exits. Bind port 0 to pick any free port.
+    pub fn bind<A: ToSocketAddrs>(address: A, cadence: Cadence) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let exporter = PrometheusExporter {
+            cadence,
+            address: listener.local_addr()?,
+            metrics: Arc::new(Mutex::new(String::new())),
+        };
+
+        let metrics = exporter.metrics.clone();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                // Every request is answered with the metrics, whatever its path.
+                let _ = stream.read(&mut [0; 1024]);
+                let body = metrics.lock().map(|m| m.clone()).unwrap_or_default();
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+            }
+        });
+
+        Ok(exporter)
+    }
+
+    /// The address the metrics are served on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+impl ReportSink for PrometheusExporter {
+    fn cadence(&self) -> Cadence {
+        self.cadence
+    }
+
+    fn write(&mut self, report: &Report) -> std::io::Result<()> {
+        let rendered = to_prometheus(report);
+        let mut metrics = self
+            .metrics
+            .lock()
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "poisoned metrics"))?;
+        *metrics = rendered;
+        Ok(())
+    }
+}
+
+/// Renders a report in the Prometheus text format, with agents in sorted order.
+pub fn to_prometheus(report: &Report) -> String {
+    fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    }
+
+    fn per_agent(out: &mut String, name: &str, values: &HashMap<String, usize>) {
+        let sorted: BTreeMap<_, _> = values.iter().collect();
+        for (agent, value) in sorted {
+            let agent = agent
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            let _ = writeln!(out, "{}{{agent=\"{}\"}} {}", name, agent, value);
+        }
+    }
+
+    let mut out = String::new();
+    family(&mut out, "simul_tick", "gauge", "The current tick.");
+    let _ = writeln!(out, "simul_tick {}", report.time);
+
+    family(
+        &mut out,
+        "simul_completed",
+        "gauge",
+        "Whether the simulation has completed.",
+    );
+    let completed = matches!(report.mode, SimulationMode::Completed);
+    let _ = writeln!(out, "simul_completed {}", u8::from(completed));
+
+    family(
+        &mut out,
+        "simul_queue_depth",
+        "gauge",
+        "The number of messages queued on an agent.",
+    );
+    per_agent(&mut out, "simul_queue_depth", &report.queue_lengths);
+
+    family(
+        &mut out,
+        "simul_consumed_total",
+        "counter",
+        "The number of messages an agent consumed.",
+    );
+    per_agent(&mut out, "simul_consumed_total", &report.consumed);
+
+    family(
+        &mut out,
+        "simul_produced_total",
+        "counter",
+        "The number of messages an agent produced.",
+    );
+    per_agent(&mut out, "simul_produced_total", &report.produced);
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn prometheus_exporter_test() {
+        let exporter = PrometheusExporter::bind("127.0.0.1:0", Cadence::EveryNTicks(5)).unwrap();
+        let address = exporter.local_addr();
+
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer", 1, "consumer"),
+                periodic_consuming_agent("consumer", 2),
+            ],
+            halt_check: |s: &Simulation| s.time == 10,
+            report_sinks: vec![Box::new(exporter)],
+            ..Default::default()
+        });
+        simulation.run();
+
+        let mut stream = std::net::TcpStream::connect(address).unwrap();
+        write!(stream, "GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("simul_tick 10\n"));
+        assert!(response.contains("simul_completed 1\n"));
+        assert!(response.contains("simul_consumed_total{agent=\"consumer\"} 4\n"));
+        assert!(response.contains("# TYPE simul_queue_depth gauge\n"));
+    }
+}
@@ -0,0 +1,118 @@
+//! Messages to every Agent near the sender, so spatial interactions like
+//! infections or alarms don't need the sender to know who is around.
+
+use crate::{DiscreteTime, Interrupt, Message, Simulation};
+
+impl Message {
+    /// Creates a message from src to every other Agent within `radius` of
+    /// it: in the space, if src has a body there, or else on the grid, in
+    /// cells. The engine sends a copy to each, nearest first, in place of
+    /// this message, so nobody near means no message at all.
+    pub fn within<S>(time: DiscreteTime, src: S, radius: f64, payload: Option<Vec<u8>>) -> Message
+    where
+        S: Into<String>,
+    {
+        let src = src.into();
+        Message {
+            custom_payload: payload,
+            interrupt: Some(Interrupt::Within { radius }),
+            ..Message::new(time, src.as_str(), src.as_str())
+        }
+    }
+}
+
+impl Simulation {
+    /// Returns the copies of a message to every Agent within its radius of
+    /// its source. None if it isn't a message to those within a radius.
+    pub(crate) fn expand_within(&self, message: &Message) -> Option<Vec<Message>> {
+        let Some(Interrupt::Within { radius }) = message.interrupt else {
+            return None;
+        };
+
+        let source = message.source.as_str();
+        let near = match (self.space(), self.grid()) {
+            (Some(space), _) if space.body(source).is_some() => space.within(source, radius),
+            (_, Some(grid)) => grid.neighbors(source, radius.floor() as i64),
+            _ => vec![],
+        };
+
+        let copies = near
+            .into_iter()
+            .map(|destination| Message {
+                destination: destination.to_string(),
+                interrupt: None,
+                ..message.clone()
+            })
+            .collect();
+        Some(copies)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use simul_macro::agent;
+
+    /// Shouts to everyone within 2 once, at the start.
+    fn shouter() -> Box<dyn Agent> {
+        #[agent]
+        struct Shouter {}
+
+        impl Agent for Shouter {
+            fn process(&mut self, state: SimulationState, _: &Message) -> Option<Vec<Message>> {
+                self.state.mode = AgentMode::Dead;
+                Some(vec![Message::within(state.time, "shouter", 2.0, None)])
+            }
+        }
+
+        Box::new(Shouter {
+            state: AgentState {
+                mode: AgentMode::Proactive,
+                wake_mode: AgentMode::Reactive,
+                id: "shouter".to_string(),
+                ..Default::default()
+            },
+        })
+    }
+
+    fn heard(space: Option<Space>, grid: Option<Grid>) -> Vec<String> {
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                shouter(),
+                periodic_consuming_agent("near", 1),
+                periodic_consuming_agent("far", 1),
+            ],
+            space,
+            grid,
+            halt_check: |s: &Simulation| s.time == 5,
+            ..Default::default()
+        });
+        simulation.run();
+        assert!(simulation.message_ledger().is_balanced());
+
+        let mut heard = vec![];
+        for id in ["near", "far"] {
+            if simulation.consumed_count(id).unwrap() > 0 {
+                heard.push(id.to_string());
+            }
+        }
+        heard
+    }
+
+    #[test]
+    fn within_test() {
+        let space = Space::new(10.0, 10.0)
+            .with_body("shouter", (5.0, 5.0), (0.0, 0.0))
+            .with_body("near", (6.0, 6.0), (0.0, 0.0))
+            .with_body("far", (9.0, 9.0), (0.0, 0.0));
+        assert_eq!(heard(Some(space), None), ["near"]);
+
+        let grid = Grid::new(10, 10)
+            .with_agent("shouter", 0, 0)
+            .with_agent("near", 2, 2)
+            .with_agent("far", 3, 0);
+        assert_eq!(heard(None, Some(grid)), ["near"]);
+
+        assert!(heard(None, None).is_empty());
+    }
+}
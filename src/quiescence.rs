@@ -0,0 +1,138 @@
+//! Quiescence: the state in which nothing can happen anymore, because no
+//! Agent will process again unless sent a message, and no message is on its
+//! way. A run that reaches it, e.g. by a deadlock or by simply finishing its
+//! work, would otherwise spin ticks until its `halt_check`.
+
+use crate::{AgentMode, DiscreteTime, HaltReason, Simulation, SimulationMode};
+use log::warn;
+
+/// What the engine does once the Simulation is quiescent.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub enum QuiescencePolicy {
+    /// Carry on ticking.
+    #[default]
+    Ignore,
+    /// Log a warning when it becomes quiescent, and carry on ticking.
+    Warn,
+    /// Halt, with `HaltReason::Quiescent`.
+    Halt,
+    /// Fail, with `HaltReason::Quiescent`, e.g. when the run should never
+    /// deadlock before its `halt_check`.
+    Fail,
+}
+
+impl Simulation {
+    /// Returns whether the Simulation is quiescent: every Agent is dead, or
+    /// reactive with an empty queue, or asleep with an empty queue and to
+    /// wake up reactive, and no message waits in flight, in a pool or on a
+    /// timer.
+    pub fn is_quiescent(&self) -> bool {
+        let idle = self.agents.iter().map(|a| a.state()).all(|s| {
+            let reactive = match s.mode {
+                AgentMode::Dead => return true,
+                AgentMode::Reactive => true,
+                AgentMode::AsleepUntil(_) => s.wake_mode == AgentMode::Reactive,
+                AgentMode::Proactive => false,
+            };
+            reactive && s.queue.is_empty()
+        });
+        idle && self.in_flight.is_empty()
+            && self.timers.is_empty()
+            && self.pools.iter().all(|p| p.queue_len() == 0)
+    }
+
+    /// Applies the quiescence policy. Returns whether to halt.
+    pub(crate) fn check_quiescence(&mut self) -> bool {
+        if !self.is_quiescent() {
+            self.quiescent_since = None;
+            return false;
+        }
+        if self.quiescent_since.is_some() {
+            return false;
+        }
+        self.quiescent_since = Some(self.time);
+
+        match self.quiescence_policy {
+            QuiescencePolicy::Ignore => false,
+            QuiescencePolicy::Warn => {
+                warn!(
+                    "The simulation is quiescent at {}",
+                    self.format_time(self.time)
+                );
+                false
+            }
+            QuiescencePolicy::Halt | QuiescencePolicy::Fail => {
+                if self.quiescence_policy == QuiescencePolicy::Fail {
+                    self.mode = SimulationMode::Failed;
+                }
+                self.halt_reason = Some(HaltReason::Quiescent);
+                true
+            }
+        }
+    }
+
+    /// Returns when the Simulation last became quiescent, if it still is.
+    /// Only tracked with a quiescence policy other than `Ignore`.
+    pub fn quiescent_since(&self) -> Option<DiscreteTime> {
+        self.quiescent_since
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use simul_macro::agent;
+
+    /// Sends three messages to the consumer, then dies.
+    fn sender() -> Box<dyn Agent> {
+        #[agent]
+        struct Sender {}
+
+        impl Agent for Sender {
+            fn process(&mut self, state: SimulationState, _: &Message) -> Option<Vec<Message>> {
+                if state.time == 2 {
+                    self.state.mode = AgentMode::Dead;
+                }
+                Some(vec![Message::new(state.time, "sender", "consumer")])
+            }
+        }
+
+        Box::new(Sender {
+            state: AgentState {
+                mode: AgentMode::Proactive,
+                wake_mode: AgentMode::Proactive,
+                id: "sender".to_string(),
+                ..Default::default()
+            },
+        })
+    }
+
+    fn run(quiescence_policy: QuiescencePolicy) -> Simulation {
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![sender(), periodic_consuming_agent("consumer", 2)],
+            quiescence_policy,
+            halt_check: |s: &Simulation| s.time == 1000,
+            ..Default::default()
+        });
+        simulation.run();
+        simulation
+    }
+
+    #[test]
+    fn quiescence_test() {
+        // The consumer takes the last message at 6, and then sleeps with
+        // nothing left to wake up to.
+        let simulation = run(QuiescencePolicy::Halt);
+        assert_eq!(simulation.halt_reason, Some(HaltReason::Quiescent));
+        assert_eq!(simulation.mode, SimulationMode::Completed);
+        assert_eq!(simulation.time, 7);
+        assert_eq!(simulation.consumed_count("consumer"), Some(3));
+
+        let simulation = run(QuiescencePolicy::Fail);
+        assert_eq!(simulation.mode, SimulationMode::Failed);
+
+        let simulation = run(QuiescencePolicy::Warn);
+        assert_eq!(simulation.halt_reason, Some(HaltReason::HaltCheck));
+        assert_eq!(simulation.quiescent_since(), Some(7));
+    }
+}
@@ -0,0 +1,85 @@
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+use std::cell::{Cell, RefCell};
+
+thread_local! {
+    static CURRENT_RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+    /// Whether the current stream is mirrored; see `with_rng`.
+    static ANTITHETIC: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Returns the random number generator Agents should draw from.
+///
+/// While a Simulation runs an Agent, this is that Agent's own stream, seeded
+/// from the Simulation's seed and the Agent's id. Runs with the same seed are
+/// therefore reproducible, and an Agent sees the same random numbers no matter
+/// how the other Agents are configured. Outside of a Simulation it is seeded
+/// from entropy.
+///
+/// In an antithetic Simulation every draw is mirrored: each bit is flipped, so
+/// a uniform draw u in [0, 1) becomes (nearly) 1 - u.
+pub fn rng() -> SimulationRng {
+    SimulationRng { _private: () }
+}
+
+/// A handle to the current random number stream. See `rng()`.
+#[derive(Clone, Copy, Debug)]
+pub struct SimulationRng {
+    _private: (),
+}
+
+impl RngCore for SimulationRng {
+    fn next_u32(&mut self) -> u32 {
+        let draw = CURRENT_RNG.with(|r| r.borrow_mut().next_u32());
+        if ANTITHETIC.with(Cell::get) {
+            !draw
+        } else {
+            draw
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let draw = CURRENT_RNG.with(|r| r.borrow_mut().next_u64());
+        if ANTITHETIC.with(Cell::get) {
+            !draw
+        } else {
+            draw
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        CURRENT_RNG.with(|r| r.borrow_mut().fill_bytes(dest));
+        mirror_if_antithetic(dest);
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        CURRENT_RNG.with(|r| r.borrow_mut().try_fill_bytes(dest))?;
+        mirror_if_antithetic(dest);
+        Ok(())
+    }
+}
+
+fn mirror_if_antithetic(dest: &mut [u8]) {
+    if ANTITHETIC.with(Cell::get) {
+        dest.iter_mut().for_each(|b| *b = !*b);
+    }
+}
+
+/// Derives the seed of an Agent's stream from the Simulation seed and its id.
+/// This uses FNV-1a rather than std's hasher, which is not stable across runs.
+pub(crate) fn agent_seed(seed: u64, id: &str) -> u64 {
+    id.bytes().fold(0xcbf29ce484222325 ^ seed, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Runs f with the given generator installed as the current stream, mirrored
+/// if antithetic.
+pub(crate) fn with_rng<T>(rng: &mut StdRng, antithetic: bool, f: impl FnOnce() -> T) -> T {
+    CURRENT_RNG.with(|r| std::mem::swap(&mut *r.borrow_mut(), rng));
+    let outer_antithetic = ANTITHETIC.with(|a| a.replace(antithetic));
+    let result = f();
+    ANTITHETIC.with(|a| a.set(outer_antithetic));
+    CURRENT_RNG.with(|r| std::mem::swap(&mut *r.borrow_mut(), rng));
+    result
+}
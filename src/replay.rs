@@ -0,0 +1,112 @@
+//! Behavioral cloning: replays an Agent's recorded decisions from a previous
+//! run, to freeze its historical behavior while experimenting with changes to
+//! the rest of the system.
+
+use crate::{Agent, AgentMode, AgentState, DiscreteTime, Message, Simulation, SimulationState};
+use simul_macro::agent;
+use std::collections::BTreeMap;
+
+/// How a replay Agent decides when to send its recorded messages.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum ReplayMode {
+    /// Sends the messages at the same ticks the recorded Agent sent them,
+    /// whatever it receives.
+    ByTime,
+    /// On its n-th received message, sends what the recorded Agent sent in
+    /// the tick it consumed its n-th message. Messages the recorded Agent sent
+    /// in ticks it consumed nothing are not replayed.
+    ByInput,
+}
+
+/// Builds an Agent that replays what the Agent with the given id sent during
+/// a completed Simulation. The replay Agent has the same id, and records the
+/// messages it receives as consumed.
+pub fn replay_agent(simulation: &Simulation, id: &str, mode: ReplayMode) -> Option<Box<dyn Agent>> {
+    #[agent]
+    struct ReplayAgent {
+        mode: ReplayMode,
+        by_time: BTreeMap<DiscreteTime, Vec<Message>>,
+        by_input: Vec<Vec<Message>>,
+        inputs: usize,
+    }
+
+    impl ReplayAgent {
+        fn replay(messages: &[Message], time: DiscreteTime) -> Vec<Message> {
+            messages
+                .iter()
+                .map(|m| Message {
+                    queued_time: time,
+                    ..m.clone()
+                })
+                .collect()
+        }
+    }
+
+    impl Agent for ReplayAgent {
+        fn process(
+            &mut self,
+            simulation_state: SimulationState,
+            msg: &Message,
+        ) -> Option<Vec<Message>> {
+            let time = simulation_state.time;
+            let is_input = msg.source != "SIM_SRC";
+            if is_input {
+                self.state.consumed.push(Message {
+                    completed_time: Some(time),
+                    ..msg.clone()
+                });
+            }
+
+            match self.mode {
+                ReplayMode::ByTime => Some(Self::replay(self.by_time.get(&time)?, time)),
+                ReplayMode::ByInput if is_input => {
+                    self.inputs += 1;
+                    Some(Self::replay(self.by_input.get(self.inputs - 1)?, time))
+                }
+                ReplayMode::ByInput => None,
+            }
+        }
+    }
+
+    let recorded = simulation
+        .agents
+        .iter()
+        .find(|a| a.state().id == id)?
+        .state();
+
+    let mut by_time: BTreeMap<DiscreteTime, Vec<Message>> = BTreeMap::new();
+    for msg in recorded.produced.iter() {
+        by_time
+            .entry(msg.queued_time)
+            .or_default()
+            .push(msg.clone());
+    }
+
+    let mut by_input = vec![];
+    let mut last_input_tick = None;
+    for msg in recorded.consumed.iter() {
+        let tick = msg.completed_time;
+        let outputs = match tick {
+            Some(t) if last_input_tick != tick => by_time.get(&t).cloned().unwrap_or_default(),
+            _ => vec![],
+        };
+        last_input_tick = tick;
+        by_input.push(outputs);
+    }
+
+    Some(Box::new(ReplayAgent {
+        mode,
+        by_time,
+        by_input,
+        inputs: 0,
+        state: AgentState {
+            mode: match mode {
+                ReplayMode::ByTime => AgentMode::Proactive,
+                ReplayMode::ByInput => AgentMode::Reactive,
+            },
+            wake_mode: AgentMode::Reactive,
+            id: id.to_string(),
+            ..Default::default()
+        },
+    }))
+}
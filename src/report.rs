@@ -0,0 +1,319 @@
+use crate::{json, DiscreteTime, Simulation, SimulationMode};
+use dyn_clone::DynClone;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::io::{BufRead as _, Write as _};
+use std::net::{TcpStream, ToSocketAddrs as _};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// How long a webhook may take to accept a connection, take a report, or
+/// answer it, before the write fails.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A snapshot of the statistics of a Simulation at some point in time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    pub time: DiscreteTime,
+    /// The time in the Simulation's time scale, if it has one.
+    pub time_label: Option<String>,
+    pub mode: SimulationMode,
+    /// Maps from agent id => the length of its queue.
+    pub queue_lengths: HashMap<String, usize>,
+    /// Maps from agent id => the number of messages it consumed.
+    pub consumed: HashMap<String, usize>,
+    /// Maps from agent id => the number of messages it produced.
+    pub produced: HashMap<String, usize>,
+    /// Maps from agent id => the average waiting time of its consumed messages.
+    pub average_wait: HashMap<String, usize>,
+    /// The agents whose queues grow without bound; see `Simulation::unstable_agents`.
+    pub unstable_agents: Vec<String>,
+    /// What happened since the sink's previous report. None for reports that
+    /// weren't delivered to a sink, e.g. from `Simulation::report()`.
+    pub window: Option<ReportWindow>,
+}
+
+/// The data the engine aggregates for a ReportSink between its reports, so
+/// sinks that report rarely still learn what happened in the meantime.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct ReportWindow {
+    /// The first tick of the window.
+    pub from: DiscreteTime,
+    /// The number of ticks in the window.
+    pub ticks: DiscreteTime,
+    /// The number of messages delivered to Agents in the window.
+    pub messages_delivered: usize,
+}
+
+/// How often an observer, like a ReportSink, is called while a Simulation
+/// runs. Observers that only need occasional summaries shouldn't pay for a
+/// callback on every tick of a million-tick simulation.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub enum Cadence {
+    /// After every tick.
+    EveryTick,
+    /// After every N ticks.
+    EveryNTicks(DiscreteTime),
+    /// Only after ticks in which messages were delivered.
+    OnEvents,
+    /// Only when the Simulation completes.
+    #[default]
+    OnCompletion,
+}
+
+impl Cadence {
+    /// Aggregates a finished tick into the window, and tells whether the
+    /// observer of the window is due at this cadence.
+    pub(crate) fn observe_tick(self, window: &mut ReportWindow, messages_delivered: usize) -> bool {
+        window.ticks += 1;
+        window.messages_delivered += messages_delivered;
+
+        match self {
+            Cadence::EveryTick => true,
+            Cadence::EveryNTicks(n) => window.ticks >= n,
+            Cadence::OnEvents => messages_delivered > 0,
+            Cadence::OnCompletion => false,
+        }
+    }
+}
+
+impl Report {
+    /// Renders the report as a single line of JSON, with agents in sorted order.
+    pub fn to_json(&self) -> String {
+        fn json_object(map: &HashMap<String, usize>) -> String {
+            let sorted: BTreeMap<_, _> = map.iter().collect();
+            let fields: Vec<String> = sorted
+                .iter()
+                .map(|(k, v)| format!("{}:{}", json::string(k), v))
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        }
+
+        let mut json = String::new();
+        let _ = write!(
+            json,
+            "{{\"time\":{},\"mode\":\"{:?}\",\"queue_lengths\":{},\"consumed\":{},\"produced\":{},\"average_wait\":{}",
+            self.time,
+            self.mode,
+            json_object(&self.queue_lengths),
+            json_object(&self.consumed),
+            json_object(&self.produced),
+            json_object(&self.average_wait),
+        );
+
+        if let Some(label) = &self.time_label {
+            let _ = write!(json, ",\"time_label\":{}", json::string(label));
+        }
+
+        let unstable: Vec<String> = self
+            .unstable_agents
+            .iter()
+            .map(|id| json::string(id))
+            .collect();
+        let _ = write!(json, ",\"unstable_agents\":[{}]", unstable.join(","));
+
+        if let Some(window) = &self.window {
+            let _ = write!(
+                json,
+                ",\"window\":{{\"from\":{},\"ticks\":{},\"messages_delivered\":{}}}",
+                window.from, window.ticks, window.messages_delivered
+            );
+        }
+
+        json.push('}');
+        json
+    }
+}
+
+/// A ReportSink receives report snapshots periodically while a Simulation
+/// runs, at its cadence, and once more when it completes. This way long
+/// unattended runs leave behind progress artifacts, even if they are killed
+/// before completion.
+pub trait ReportSink: std::fmt::Debug + DynClone + Send {
+    fn write(&mut self, report: &Report) -> std::io::Result<()>;
+
+    /// How often the sink receives reports while the Simulation runs.
+    fn cadence(&self) -> Cadence {
+        Cadence::OnCompletion
+    }
+}
+
+dyn_clone::clone_trait_object!(ReportSink);
+
+/// Appends every report as a line of JSON to a file.
+#[derive(Clone, Debug)]
+pub struct FileReportSink {
+    pub path: PathBuf,
+    pub cadence: Cadence,
+}
+
+impl ReportSink for FileReportSink {
+    fn cadence(&self) -> Cadence {
+        self.cadence
+    }
+
+    fn write(&mut self, report: &Report) -> std::io::Result<()> {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", report.to_json())
+    }
+}
+
+/// POSTs every report as JSON to a webhook. Only plain `http://` URLs are
+/// supported, e.g. `http://localhost:8080/reports`. A write fails if the
+/// webhook answers with anything but a 2xx status, or takes over 5 seconds to
+/// connect, take the report or answer.
+#[derive(Clone, Debug)]
+pub struct WebhookReportSink {
+    pub url: String,
+    pub cadence: Cadence,
+}
+
+impl ReportSink for WebhookReportSink {
+    fn cadence(&self) -> Cadence {
+        self.cadence
+    }
+
+    fn write(&mut self, report: &Report) -> std::io::Result<()> {
+        let invalid_url = || std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid url");
+        let rest = self.url.strip_prefix("http://").ok_or_else(invalid_url)?;
+        let (host, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        let address = if host.contains(':') {
+            host.to_string()
+        } else {
+            format!("{}:80", host)
+        };
+
+        let mut stream = connect(&address)?;
+        stream.set_read_timeout(Some(WEBHOOK_TIMEOUT))?;
+        stream.set_write_timeout(Some(WEBHOOK_TIMEOUT))?;
+
+        let body = report.to_json();
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            if path.is_empty() { "/" } else { path },
+            host,
+            body.len(),
+            body
+        )?;
+
+        // The status line reads e.g. `HTTP/1.1 204 No Content`.
+        let mut status_line = String::new();
+        std::io::BufReader::new(stream).read_line(&mut status_line)?;
+        let status = status_line.split_whitespace().nth(1).unwrap_or_default();
+        if !(status.len() == 3 && status.starts_with('2')) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("webhook answered {:?}", status_line.trim_end()),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Connects to the first of the address's resolutions that accepts in time.
+fn connect(address: &str) -> std::io::Result<TcpStream> {
+    let mut error = None;
+    for address in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, WEBHOOK_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => error = Some(e),
+        }
+    }
+    Err(error.unwrap_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "unresolvable url")
+    }))
+}
+
+impl Simulation {
+    /// Returns a snapshot of the statistics of the Simulation as of now.
+    pub fn report(&self) -> Report {
+        Report {
+            time: self.time,
+            time_label: self.time_scale().map(|s| s.format(self.time)),
+            mode: self.mode.clone(),
+            queue_lengths: self.calc_queue_len_statistics(),
+            consumed: self.calc_consumed_len_statistics(),
+            produced: self.calc_produced_len_statistics(),
+            average_wait: self.calc_avg_wait_statistics(),
+            unstable_agents: self.unstable_agents(),
+            window: None,
+        }
+    }
+
+    /// Resets the report windows of every sink and observer, to start at the
+    /// current time.
+    pub(crate) fn reset_report_windows(&mut self) {
+        let window = ReportWindow {
+            from: self.time,
+            ..Default::default()
+        };
+        self.report_windows = vec![window.clone(); self.report_sinks.len()];
+        self.observer_windows = vec![window; self.observers.len()];
+    }
+
+    /// Aggregates a finished tick into the windows of every sink, and writes a
+    /// report to the sinks that are due at their cadence.
+    pub(crate) fn observe_tick_for_reports(&mut self, messages_delivered: usize) {
+        let due: Vec<bool> = self
+            .report_sinks
+            .iter()
+            .zip(self.report_windows.iter_mut())
+            .map(|(sink, window)| sink.cadence().observe_tick(window, messages_delivered))
+            .collect();
+
+        if !due.contains(&true) {
+            return;
+        }
+
+        let report = self.report();
+        for (handle, sink) in self.report_sinks.iter_mut().enumerate() {
+            if due[handle] {
+                write_report(
+                    sink,
+                    &report,
+                    &mut self.report_windows[handle],
+                    self.time + 1,
+                );
+            }
+        }
+    }
+
+    /// Writes a final report to every report sink.
+    pub(crate) fn write_completion_reports(&mut self) {
+        if self.report_sinks.is_empty() {
+            return;
+        }
+
+        let report = self.report();
+        for (handle, sink) in self.report_sinks.iter_mut().enumerate() {
+            write_report(sink, &report, &mut self.report_windows[handle], self.time);
+        }
+    }
+}
+
+/// Writes the report with the sink's window, then starts its next window.
+/// Failing sinks are logged and skipped.
+fn write_report(
+    sink: &mut Box<dyn ReportSink>,
+    report: &Report,
+    window: &mut ReportWindow,
+    next_window_from: DiscreteTime,
+) {
+    let report = Report {
+        window: Some(window.clone()),
+        ..report.clone()
+    };
+
+    if let Err(e) = sink.write(&report) {
+        log::warn!("Failed to write report to {:?}: {}", sink, e);
+    }
+
+    *window = ReportWindow {
+        from: next_window_from,
+        ..Default::default()
+    };
+}
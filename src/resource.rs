@@ -0,0 +1,287 @@
+//! Shared resources with a capacity, like machines, beds or licenses, that
+//! Agents acquire and release, waiting in line while not enough is free.
+//!
+//! An Agent acquires a resource by sending `Message::acquire`, and holds it
+//! from when the engine grants the request: a reply from the resource, which
+//! the returned RequestHandle matches. It gives the resource back by sending
+//! `Message::release`. Requests are granted first come, first served, so a
+//! large request blocks the smaller ones behind it rather than starving.
+
+use crate::{DiscreteTime, Interrupt, Message, RequestHandle, Simulation};
+use log::warn;
+use std::collections::VecDeque;
+
+/// A resource of which Agents hold parts; see the module docs.
+#[derive(Clone, Debug)]
+pub struct Resource {
+    pub name: String,
+    pub capacity: u64,
+    in_use: u64,
+    /// The requests waiting to be granted, in order.
+    waiting: VecDeque<Message>,
+    stats: ResourceStats,
+    /// When `in_use` or `waiting` last changed.
+    last_change: DiscreteTime,
+}
+
+/// The contention of a resource over a run.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ResourceStats {
+    /// The requests granted.
+    pub acquisitions: usize,
+    /// The requests granted that had to wait.
+    pub contended: usize,
+    /// The total ticks granted requests waited.
+    pub total_wait: DiscreteTime,
+    pub max_wait: DiscreteTime,
+    /// The most requests that waited at once.
+    pub max_waiting: usize,
+    /// The mean fraction of the capacity in use over the run.
+    pub utilization: f64,
+    /// The mean number of requests waiting over the run.
+    pub mean_waiting: f64,
+    /// The integral of the amount in use over time.
+    in_use_ticks: u64,
+    /// The integral of the number of waiting requests over time.
+    waiting_ticks: u64,
+}
+
+impl ResourceStats {
+    /// The mean ticks a granted request waited.
+    pub fn mean_wait(&self) -> f64 {
+        if self.acquisitions == 0 {
+            0.0
+        } else {
+            self.total_wait as f64 / self.acquisitions as f64
+        }
+    }
+}
+
+impl Resource {
+    pub fn new<T>(name: T, capacity: u64) -> Resource
+    where
+        T: Into<String>,
+    {
+        Resource {
+            name: name.into(),
+            capacity,
+            in_use: 0,
+            waiting: VecDeque::new(),
+            stats: ResourceStats::default(),
+            last_change: 0,
+        }
+    }
+
+    /// The amount held by Agents.
+    pub fn in_use(&self) -> u64 {
+        self.in_use
+    }
+
+    /// The amount free to acquire.
+    pub fn available(&self) -> u64 {
+        self.capacity.saturating_sub(self.in_use)
+    }
+
+    /// The number of requests waiting to be granted.
+    pub fn waiting(&self) -> usize {
+        self.waiting.len()
+    }
+
+    /// Accumulates the time-weighted statistics up to `time`.
+    fn advance(&mut self, time: DiscreteTime) {
+        let elapsed = time.saturating_sub(self.last_change);
+        self.stats.in_use_ticks += self.in_use * elapsed;
+        self.stats.waiting_ticks += self.waiting.len() as u64 * elapsed;
+        self.last_change = time;
+    }
+
+    /// Queues a request, granting what can be granted.
+    fn acquire(&mut self, time: DiscreteTime, request: Message) -> Vec<Message> {
+        self.advance(time);
+        self.waiting.push_back(request);
+        self.stats.max_waiting = self.stats.max_waiting.max(self.waiting.len());
+        self.grant(time)
+    }
+
+    fn release(&mut self, time: DiscreteTime, amount: u64) -> Vec<Message> {
+        self.advance(time);
+        if amount > self.in_use {
+            warn!(
+                "Releasing {} of {}, of which only {} is in use",
+                amount, self.name, self.in_use
+            );
+        }
+        self.in_use = self.in_use.saturating_sub(amount);
+        self.grant(time)
+    }
+
+    /// Grants the waiting requests in order, while they fit.
+    fn grant(&mut self, time: DiscreteTime) -> Vec<Message> {
+        let mut grants = vec![];
+        while let Some(request) = self.waiting.front() {
+            let amount = match request.interrupt {
+                Some(Interrupt::Acquire { amount, .. }) => amount,
+                _ => 0,
+            };
+            if amount > self.available() {
+                break;
+            }
+
+            let request = self
+                .waiting
+                .pop_front()
+                .expect("The request was just peeked");
+            let wait = time - request.queued_time;
+            self.in_use += amount;
+            self.stats.acquisitions += 1;
+            self.stats.contended += usize::from(wait > 0);
+            self.stats.total_wait += wait;
+            self.stats.max_wait = self.stats.max_wait.max(wait);
+            grants.push(request.reply(time, None));
+        }
+        grants
+    }
+}
+
+impl Message {
+    /// Creates a request of src for `amount` of a resource. The engine grants
+    /// it with a reply from the resource, which the RequestHandle matches.
+    pub fn acquire<S>(
+        time: DiscreteTime,
+        src: S,
+        resource: S,
+        amount: u64,
+    ) -> (Message, RequestHandle)
+    where
+        S: Into<String>,
+    {
+        let (mut request, handle) = Message::request(time, src, resource, None);
+        request.interrupt = Some(Interrupt::Acquire {
+            resource: request.destination.clone(),
+            amount,
+        });
+        (request, handle)
+    }
+
+    /// Creates a message that gives `amount` of a resource held by src back.
+    pub fn release<S>(time: DiscreteTime, src: S, resource: S, amount: u64) -> Message
+    where
+        S: Into<String>,
+    {
+        let mut release = Message::new(time, src, resource);
+        release.interrupt = Some(Interrupt::Release {
+            resource: release.destination.clone(),
+            amount,
+        });
+        release
+    }
+}
+
+impl Simulation {
+    /// Returns a resource of the Simulation, as of now.
+    pub fn resource(&self, name: &str) -> Option<&Resource> {
+        self.resources.iter().find(|r| r.name == name)
+    }
+
+    /// Returns the contention of a resource over the run so far.
+    pub fn resource_stats(&self, name: &str) -> Option<ResourceStats> {
+        let mut resource = self.resource(name)?.clone();
+        resource.advance(self.time);
+
+        let ticks = self.time.saturating_sub(self.starting_time).max(1) as f64;
+        let mut stats = resource.stats;
+        stats.utilization = stats.in_use_ticks as f64 / (resource.capacity.max(1) as f64 * ticks);
+        stats.mean_waiting = stats.waiting_ticks as f64 / ticks;
+        Some(stats)
+    }
+
+    /// Handles a message to a resource, returning the grants it causes. None
+    /// if the message isn't to a resource of the Simulation.
+    pub(crate) fn handle_resource_message(&mut self, message: &Message) -> Option<Vec<Message>> {
+        let (name, amount) = match &message.interrupt {
+            Some(Interrupt::Acquire { resource, amount }) => (resource, *amount),
+            Some(Interrupt::Release { resource, amount }) => (resource, *amount),
+            _ => return None,
+        };
+
+        let time = self.time;
+        let resource = self.resources.iter_mut().find(|r| r.name == *name)?;
+
+        Some(match message.interrupt {
+            Some(Interrupt::Acquire { .. }) => resource.acquire(time, message.clone()),
+            _ => resource.release(time, amount),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use simul_macro::agent;
+
+    /// Uses the machine for 3 ticks at a time, as soon as it's granted.
+    fn worker(id: &str) -> Box<dyn Agent> {
+        #[agent]
+        struct Worker {
+            pending: Option<RequestHandle>,
+        }
+
+        impl Agent for Worker {
+            fn process(&mut self, state: SimulationState, msg: &Message) -> Option<Vec<Message>> {
+                let id = self.state.id.clone();
+                if self.pending.is_some_and(|p| p.matches(msg)) {
+                    self.pending = None;
+                    self.state.mode = AgentMode::AsleepUntil(state.time + 3);
+                    self.state.wake_mode = AgentMode::Proactive;
+                    return None;
+                }
+
+                let mut messages = vec![];
+                if self.state.wake_mode == AgentMode::Proactive {
+                    messages.push(Message::release(state.time, id.as_str(), "machine", 1));
+                }
+                let (request, handle) = Message::acquire(state.time, id.as_str(), "machine", 1);
+                messages.push(request);
+                self.pending = Some(handle);
+                self.state.mode = AgentMode::Reactive;
+                self.state.wake_mode = AgentMode::Reactive;
+                Some(messages)
+            }
+        }
+
+        Box::new(Worker {
+            pending: None,
+            state: AgentState {
+                mode: AgentMode::Proactive,
+                wake_mode: AgentMode::Reactive,
+                id: id.to_string(),
+                ..Default::default()
+            },
+        })
+    }
+
+    #[test]
+    fn resource_test() {
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![worker("a"), worker("b")],
+            resources: vec![Resource::new("machine", 1)],
+            halt_check: |s: &Simulation| s.time == 60,
+            ..Default::default()
+        });
+        simulation.run();
+
+        // The workers take turns, so the machine is always in use and one of
+        // them always waits for it.
+        let stats = simulation.resource_stats("machine").unwrap();
+        // A worker holds the machine from the grant until it wakes up.
+        assert_eq!((stats.acquisitions, stats.contended), (15, 14));
+        assert_eq!(stats.max_wait, 4);
+        assert_eq!(stats.utilization, 1.0);
+        assert_eq!(stats.mean_waiting, 1.0);
+
+        let machine = simulation.resource("machine").unwrap();
+        assert_eq!((machine.in_use(), machine.waiting()), (1, 1));
+        assert!(simulation.message_ledger().is_balanced());
+    }
+}
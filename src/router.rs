@@ -0,0 +1,244 @@
+//! Agents that distribute incoming work over a set of downstream Agents.
+//!
+//! Routers forward every message they receive in the tick they take it off
+//! their queue, so they add no service time of their own.
+
+use crate::{Agent, AgentMode, AgentState, Interrupt, Message, Simulation, SimulationState};
+use rand::distributions::{Distribution, WeightedIndex};
+use simul_macro::agent;
+
+/// Returns an Agent that forwards each message it receives to the next of
+/// targets in turn.
+pub fn round_robin_router<T>(id: T, targets: Vec<String>) -> Box<dyn Agent>
+where
+    T: Into<String>,
+{
+    #[agent]
+    struct RoundRobinRouter {
+        targets: Vec<String>,
+        next: usize,
+    }
+
+    impl Agent for RoundRobinRouter {
+        fn process(
+            &mut self,
+            simulation_state: SimulationState,
+            msg: &Message,
+        ) -> Option<Vec<Message>> {
+            let incoming = std::iter::once(msg.clone()).chain(self.state.queue.drain(..));
+            let mut forwarded = vec![];
+            for msg in incoming.collect::<Vec<_>>() {
+                let target = self.targets.get(self.next)?;
+                forwarded.push(msg.forward(simulation_state.time, target.as_str()));
+                self.next = (self.next + 1) % self.targets.len();
+            }
+            Some(forwarded)
+        }
+    }
+
+    Box::new(RoundRobinRouter {
+        targets,
+        next: 0,
+        state: AgentState {
+            mode: AgentMode::Reactive,
+            wake_mode: AgentMode::Reactive,
+            id: id.into(),
+            ..Default::default()
+        },
+    })
+}
+
+/// Returns an Agent that forwards each message it receives to one of
+/// targets, drawn at random in proportion to its weight.
+///
+/// # Panics
+///
+/// If targets and weights differ in length, or the weights are invalid,
+/// e.g. all zero or negative.
+pub fn random_router<T>(id: T, targets: Vec<String>, weights: Vec<f64>) -> Box<dyn Agent>
+where
+    T: Into<String>,
+{
+    #[agent]
+    struct RandomRouter {
+        targets: Vec<String>,
+        weights: WeightedIndex<f64>,
+    }
+
+    impl Agent for RandomRouter {
+        fn process(
+            &mut self,
+            simulation_state: SimulationState,
+            msg: &Message,
+        ) -> Option<Vec<Message>> {
+            let incoming = std::iter::once(msg.clone()).chain(self.state.queue.drain(..));
+            let forwarded = incoming
+                .collect::<Vec<_>>()
+                .iter()
+                .map(|msg| {
+                    let target = &self.targets[self.weights.sample(&mut crate::rng())];
+                    msg.forward(simulation_state.time, target.as_str())
+                })
+                .collect();
+            Some(forwarded)
+        }
+    }
+
+    assert_eq!(
+        targets.len(),
+        weights.len(),
+        "every target of a random router needs a weight"
+    );
+    Box::new(RandomRouter {
+        targets,
+        weights: WeightedIndex::new(weights).expect("the weights must be valid"),
+        state: AgentState {
+            mode: AgentMode::Reactive,
+            wake_mode: AgentMode::Reactive,
+            id: id.into(),
+            ..Default::default()
+        },
+    })
+}
+
+/// Returns an Agent that forwards each message it receives to whichever of
+/// targets has the shortest queue, for join-the-shortest-queue models. Agents
+/// can't see each other's queues, so the engine picks the target when it
+/// delivers the message. Ties go to the earliest of targets.
+pub fn shortest_queue_router<T>(id: T, targets: Vec<String>) -> Box<dyn Agent>
+where
+    T: Into<String>,
+{
+    #[agent]
+    struct ShortestQueueRouter {
+        targets: Vec<String>,
+    }
+
+    impl Agent for ShortestQueueRouter {
+        fn process(
+            &mut self,
+            simulation_state: SimulationState,
+            msg: &Message,
+        ) -> Option<Vec<Message>> {
+            let incoming = std::iter::once(msg.clone()).chain(self.state.queue.drain(..));
+            let forwarded = incoming
+                .collect::<Vec<_>>()
+                .iter()
+                .map(|msg| Message {
+                    interrupt: Some(Interrupt::ShortestQueue {
+                        targets: self.targets.clone(),
+                    }),
+                    ..msg.forward(simulation_state.time, self.targets[0].as_str())
+                })
+                .collect();
+            Some(forwarded)
+        }
+    }
+
+    assert!(!targets.is_empty(), "a router needs targets");
+    Box::new(ShortestQueueRouter {
+        targets,
+        state: AgentState {
+            mode: AgentMode::Reactive,
+            wake_mode: AgentMode::Reactive,
+            id: id.into(),
+            ..Default::default()
+        },
+    })
+}
+
+impl Simulation {
+    /// Addresses a message to route to the shortest queue to the Agent of
+    /// its targets with the shortest queue, as of now.
+    pub(crate) fn resolve_shortest_queue(&self, message: &mut Message) {
+        let Some(Interrupt::ShortestQueue { targets }) = &message.interrupt else {
+            return;
+        };
+
+        let shortest = targets
+            .iter()
+            .filter_map(|t| Some((self.queue_len(t)?, t)))
+            .min_by_key(|(len, _)| *len);
+        if let Some((_, target)) = shortest {
+            message.destination = target.clone();
+        }
+        message.interrupt = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    fn run(router: Box<dyn Agent>) -> Simulation {
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer", 1, "router"),
+                router,
+                periodic_consuming_agent("a", 1),
+                periodic_consuming_agent("b", 1),
+            ],
+            halt_check: |s: &Simulation| s.time == 400,
+            seed: Some(1),
+            ..Default::default()
+        });
+        simulation.run();
+        simulation
+    }
+
+    #[test]
+    fn round_robin_router_test() {
+        let targets = vec!["a".to_string(), "b".to_string()];
+        let simulation = run(round_robin_router("router", targets));
+
+        let routed_to = |id| {
+            simulation
+                .produced_for_agent("router")
+                .unwrap()
+                .iter()
+                .filter(|m| m.destination == id)
+                .count()
+        };
+        // The producer's 399 messages alternate, starting with "a".
+        assert_eq!((routed_to("a"), routed_to("b")), (200, 199));
+    }
+
+    #[test]
+    fn shortest_queue_router_test() {
+        // "b" is three times as fast as "a", so it gets most of the messages
+        // and neither queue builds up.
+        let targets = vec!["a".to_string(), "b".to_string()];
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer", 1, "router"),
+                shortest_queue_router("router", targets),
+                periodic_consuming_agent("a", 3),
+                periodic_consuming_agent("b", 1),
+            ],
+            halt_check: |s: &Simulation| s.time == 300,
+            ..Default::default()
+        });
+        simulation.run();
+
+        let consumed = |id| simulation.consumed_for_agent(id).unwrap().len();
+        assert!(
+            consumed("b") > consumed("a"),
+            "{} {}",
+            consumed("a"),
+            consumed("b")
+        );
+        assert!(simulation.queue_len("a").unwrap() <= 2);
+        assert!(simulation.queue_len("b").unwrap() <= 2);
+    }
+
+    #[test]
+    fn random_router_test() {
+        let targets = vec!["a".to_string(), "b".to_string()];
+        let simulation = run(random_router("router", targets, vec![3.0, 1.0]));
+
+        let produced = simulation.produced_for_agent("router").unwrap();
+        let to_a = produced.iter().filter(|m| m.destination == "a").count();
+        assert!((250..350).contains(&to_a), "{}", to_a);
+    }
+}
@@ -0,0 +1,109 @@
+//! Scenario files: a Simulation configured in TOML or YAML, see `config`,
+//! plus how to run it, so models can be packaged and run by the `simul`
+//! binary without writing Rust. For example:
+//!
+//! ```toml
+//! # An M/M/1 queue.
+//! name = "mm1"
+//! ticks = 10000
+//! seed = 7
+//! replications = 30
+//!
+//! [agent.arrivals]
+//! kind = "poisson_arrivals"
+//! rate = 0.5
+//! target = "server"
+//!
+//! [agent.server]
+//! kind = "exponential_station"
+//! servers = 1
+//! rate = 0.8
+//! ```
+//!
+//! Besides the keys of `config`, a scenario has the optional top-level keys
+//! `name` and `replications`.
+
+use crate::config::{AgentRegistry, ConfigDocument, ConfigError, ConfigFormat, SimulationConfig};
+use crate::SimulationParameters;
+use std::path::Path;
+
+/// A Simulation defined by a scenario file.
+#[derive(Clone, Debug)]
+pub struct Scenario {
+    pub name: Option<String>,
+    /// The number of replications to run, at least 1.
+    pub replications: usize,
+    pub config: SimulationConfig,
+}
+
+impl Scenario {
+    /// Reads and parses a scenario file of the built-in kinds of agents, in
+    /// the format of its extension.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Scenario, ConfigError> {
+        Scenario::from_document(ConfigDocument::load(path)?, &AgentRegistry::default())
+    }
+
+    /// Parses a scenario of the built-in kinds of agents.
+    pub fn parse(text: &str, format: ConfigFormat) -> Result<Scenario, ConfigError> {
+        let document = ConfigDocument::parse(text, format)?;
+        Scenario::from_document(document, &AgentRegistry::default())
+    }
+
+    /// Reads a parsed scenario, creating its agents with the registry.
+    pub fn from_document(
+        mut document: ConfigDocument,
+        registry: &AgentRegistry,
+    ) -> Result<Scenario, ConfigError> {
+        let name = document.top.string("name")?;
+        let replications = document.top.count("replications")?.unwrap_or(1).max(1) as usize;
+        Ok(Scenario {
+            name,
+            replications,
+            config: SimulationConfig::from_document(document, registry)?,
+        })
+    }
+
+    /// The SimulationParameters of one run of the scenario.
+    pub fn parameters(&self) -> SimulationParameters {
+        self.config.parameters()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    const SCENARIO: &str = r#"
+# A producer and a consumer.
+name = "pair"   # Named for the output.
+ticks = 100
+seed = 7
+replications = 3
+
+[agent.producer]
+kind = "periodic_producer"
+period = 2
+target = "consumer"
+
+[agent.consumer]
+kind = "periodic_consumer"
+period = 1
+"#;
+
+    #[test]
+    fn scenario_test() {
+        let scenario = Scenario::parse(SCENARIO, ConfigFormat::Toml).unwrap();
+        assert_eq!(scenario.name.as_deref(), Some("pair"));
+        assert_eq!(scenario.replications, 3);
+        assert_eq!(scenario.config.seed, Some(7));
+
+        let mut simulation = Simulation::new(scenario.parameters());
+        simulation.run();
+        assert_eq!(simulation.time, 100);
+        assert_eq!(simulation.consumed_count("consumer"), Some(50));
+
+        let error = Scenario::parse("name = 1\nticks = 1", ConfigFormat::Toml).unwrap_err();
+        assert_eq!(error.to_string(), "name must be a string");
+    }
+}
@@ -0,0 +1,151 @@
+use crate::DiscreteTime;
+
+/// How a Series is valued between its recorded points.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub enum Interpolation {
+    /// The value holds until the next recorded point, as for discrete events.
+    #[default]
+    Step,
+    /// The value moves linearly between recorded points, as for flows.
+    Linear,
+}
+
+/// A piecewise series of a variable recorded over Simulation time, e.g. an
+/// environment variable or an Agent's inventory level. With interpolation a
+/// Series can be treated as a continuous signal for post-run analysis.
+///
+/// Values hold at the last recorded point after it; before the first point
+/// the Series is undefined.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Series {
+    /// The recorded (time, value) points, in time order.
+    pub points: Vec<(DiscreteTime, f64)>,
+    pub interpolation: Interpolation,
+}
+
+impl Series {
+    pub fn new(interpolation: Interpolation) -> Series {
+        Series {
+            points: vec![],
+            interpolation,
+        }
+    }
+
+    /// Records the value at the given time. Times must not go backwards.
+    /// Runs of an unchanged value only keep their first and last points, so
+    /// recording every tick costs space proportional to the number of changes.
+    pub fn record(&mut self, time: DiscreteTime, value: f64) {
+        let n = self.points.len();
+        if n >= 2 && self.points[n - 1].1 == value && self.points[n - 2].1 == value {
+            self.points[n - 1].0 = time;
+        } else {
+            self.points.push((time, value));
+        }
+    }
+
+    /// Returns the interpolated value at the given time.
+    pub fn value_at(&self, time: f64) -> Option<f64> {
+        let first = self.points.first()?;
+        if time < first.0 as f64 {
+            return None;
+        }
+
+        let next = self.points.partition_point(|(t, _)| (*t as f64) <= time);
+        let (t0, v0) = self.points[next - 1];
+        match (self.interpolation, self.points.get(next)) {
+            (Interpolation::Linear, Some((t1, v1))) => {
+                Some(v0 + (v1 - v0) * (time - t0 as f64) / (*t1 - t0) as f64)
+            }
+            _ => Some(v0),
+        }
+    }
+
+    /// Returns the area under the Series between two times.
+    pub fn integral(&self, from: f64, to: f64) -> f64 {
+        let Some(last) = self.points.last() else {
+            return 0.0;
+        };
+
+        let mut area = 0.0;
+        let segments = self
+            .points
+            .windows(2)
+            .map(|w| (w[0].0 as f64, w[1].0 as f64))
+            .chain(std::iter::once((last.0 as f64, f64::INFINITY)));
+
+        for (start, end) in segments {
+            let (a, b) = (start.max(from), end.min(to));
+            if a >= b {
+                continue;
+            }
+
+            // Both interpolations are linear within a segment, so the
+            // trapezoid rule is exact.
+            let value_at_b = match self.interpolation {
+                Interpolation::Step => self.value_at(a),
+                Interpolation::Linear => self.value_at(b),
+            };
+            area += (b - a) * (self.value_at(a).unwrap_or(0.0) + value_at_b.unwrap_or(0.0)) / 2.0;
+        }
+
+        area
+    }
+
+    /// Returns the time-weighted mean of the Series between two times.
+    pub fn mean(&self, from: f64, to: f64) -> Option<f64> {
+        let start = from.max(self.points.first()?.0 as f64);
+        (to > start).then(|| self.integral(start, to) / (to - start))
+    }
+
+    /// Returns the times at which the Series crosses the threshold, in either
+    /// direction. A Step series crosses at the time of the step.
+    pub fn crossings(&self, threshold: f64) -> Vec<f64> {
+        self.points
+            .windows(2)
+            .filter_map(|w| {
+                let ((t0, v0), (t1, v1)) = (w[0], w[1]);
+                let crosses =
+                    (v0 < threshold && v1 >= threshold) || (v0 > threshold && v1 <= threshold);
+                if !crosses {
+                    return None;
+                }
+
+                Some(match self.interpolation {
+                    Interpolation::Step => t1 as f64,
+                    Interpolation::Linear => {
+                        t0 as f64 + (threshold - v0) / (v1 - v0) * (t1 - t0) as f64
+                    }
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpolation_test() {
+        let mut linear = Series::new(Interpolation::Linear);
+        for (t, v) in [(0, 0.0), (1, 2.0), (2, 2.0), (3, 2.0), (4, 0.0)] {
+            linear.record(t, v);
+        }
+
+        assert_eq!(linear.points, vec![(0, 0.0), (1, 2.0), (3, 2.0), (4, 0.0)]);
+        assert_eq!(linear.value_at(0.5), Some(1.0));
+        assert_eq!(linear.value_at(3.5), Some(1.0));
+        assert_eq!(linear.value_at(10.0), Some(0.0));
+        assert_eq!(linear.integral(0.0, 4.0), 6.0);
+        assert_eq!(linear.crossings(1.0), vec![0.5, 3.5]);
+
+        let step = Series {
+            interpolation: Interpolation::Step,
+            ..linear
+        };
+        assert_eq!(step.value_at(0.5), Some(0.0));
+        assert_eq!(step.integral(0.0, 6.0), 6.0);
+        assert_eq!(step.mean(0.0, 6.0), Some(1.0));
+        assert_eq!(step.crossings(1.0), vec![1.0, 4.0]);
+    }
+}
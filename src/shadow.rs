@@ -0,0 +1,91 @@
+use crate::StepOptions;
+use crate::{random, step_agent, Agent, AgentMetadata, Message, Simulation, SimulationState};
+
+/// A shadow runs candidate logic alongside the Agent it shadows: it receives a
+/// copy of every message delivered to that Agent, but the messages it produces
+/// are only recorded, never delivered. This compares a new policy against the
+/// incumbent within one run, without affecting the Simulation.
+#[derive(Clone, Debug)]
+pub struct ShadowAgent {
+    /// The id of the Agent whose inbound messages the shadow receives.
+    pub shadowed: String,
+    /// The candidate logic. Its produced messages are the decisions it would have made.
+    pub agent: Box<dyn Agent>,
+}
+
+impl ShadowAgent {
+    pub fn new<T>(shadowed: T, agent: Box<dyn Agent>) -> ShadowAgent
+    where
+        T: Into<String>,
+    {
+        ShadowAgent {
+            shadowed: shadowed.into(),
+            agent,
+        }
+    }
+}
+
+/// The engine's bookkeeping for a shadow.
+#[derive(Clone, Debug)]
+pub(crate) struct ShadowMetadata {
+    /// The handle of the shadowed Agent, if it exists.
+    pub shadowed_handle: Option<usize>,
+    pub agent_metadata: AgentMetadata,
+}
+
+impl ShadowMetadata {
+    /// The shadow draws from the same random stream as the Agent it shadows,
+    /// so differences in their decisions come from their logic alone.
+    pub fn new(shadow: &ShadowAgent, shadowed_handle: Option<usize>, seed: u64) -> Self {
+        ShadowMetadata {
+            shadowed_handle,
+            agent_metadata: AgentMetadata::new(random::agent_seed(seed, &shadow.shadowed)),
+        }
+    }
+}
+
+impl Simulation {
+    /// Returns the shadow Agent with the given id.
+    pub fn shadow(&self, id: &str) -> Option<&dyn Agent> {
+        self.shadow_agents
+            .iter()
+            .find(|s| s.agent.state().id == id)
+            .map(|s| s.agent.as_ref())
+    }
+
+    /// Processes every shadow for a tick, recording what they produced.
+    pub(crate) fn step_shadows(
+        &mut self,
+        simulation_state: &SimulationState,
+        tick_message: &Message,
+        options: StepOptions,
+    ) {
+        for (shadow, metadata) in self
+            .shadow_agents
+            .iter_mut()
+            .zip(self.shadow_metadata.iter_mut())
+        {
+            let step = step_agent(
+                shadow.agent.as_mut(),
+                &mut metadata.agent_metadata,
+                simulation_state,
+                tick_message,
+                options,
+            );
+            shadow.agent.state_mut().produced.extend(step.produced);
+        }
+    }
+
+    /// Delivers a copy of a message to the shadows of the Agent with the given handle.
+    pub(crate) fn deliver_to_shadows(&mut self, handle: usize, message: &Message) {
+        for (shadow, metadata) in self
+            .shadow_agents
+            .iter_mut()
+            .zip(self.shadow_metadata.iter())
+        {
+            if metadata.shadowed_handle == Some(handle) {
+                shadow.agent.push_message(message.clone());
+            }
+        }
+    }
+}
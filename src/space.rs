@@ -0,0 +1,382 @@
+//! A continuous 2D space in which Agents are bodies with positions and
+//! velocities, for models like flocking, crowds or vehicles.
+//!
+//! Agents see the space as of the start of the tick in their
+//! SimulationState, e.g. to find the bodies near them, and steer by sending
+//! `Message::set_velocity` or `Message::place_at`. At the start of every tick
+//! the engine first moves every body by its velocity, and then applies the
+//! messages of the previous tick, in the order they were sent. Proximity
+//! queries go through a spatial hash of square cells, so they only look at
+//! the bodies in the cells around the query.
+
+use crate::{DiscreteTime, Interrupt, Message, Simulation};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// A body in a Space.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Body {
+    pub position: (f64, f64),
+    /// The distance moved per tick along each axis.
+    pub velocity: (f64, f64),
+}
+
+/// A `width` by `height` space of bodies; see the module docs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Space {
+    pub width: f64,
+    pub height: f64,
+    /// Whether the edges wrap around, making the space a torus. Otherwise
+    /// bodies stop at the edges, losing their velocity across them.
+    pub torus: bool,
+    /// The side of the cells of the spatial hash. Queries are fastest with
+    /// cells about as large as their radius.
+    pub cell_size: f64,
+    bodies: BTreeMap<String, Body>,
+    /// The ids of the bodies in every occupied cell.
+    index: HashMap<(i64, i64), Vec<String>>,
+}
+
+/// An update of a body sent by its Agent.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Steer {
+    Velocity(f64, f64),
+    Position(f64, f64),
+}
+
+impl Space {
+    pub fn new(width: f64, height: f64) -> Space {
+        Space {
+            width,
+            height,
+            torus: false,
+            cell_size: 1.0,
+            bodies: BTreeMap::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    pub fn with_torus(self) -> Space {
+        Space {
+            torus: true,
+            ..self
+        }
+    }
+
+    /// Sets the side of the cells of the spatial hash.
+    ///
+    /// # Panics
+    ///
+    /// If the cell size isn't positive.
+    pub fn with_cell_size(mut self, cell_size: f64) -> Space {
+        assert!(
+            cell_size > 0.0,
+            "the cell size {} isn't positive",
+            cell_size
+        );
+        self.cell_size = cell_size;
+        self.reindex();
+        self
+    }
+
+    /// Adds a body for an Agent to begin with.
+    pub fn with_body<T>(mut self, id: T, position: (f64, f64), velocity: (f64, f64)) -> Space
+    where
+        T: Into<String>,
+    {
+        let id = id.into();
+        let position = self.confine(position);
+        self.index
+            .entry(self.cell(position))
+            .or_default()
+            .push(id.clone());
+        self.bodies.insert(id, Body { position, velocity });
+        self
+    }
+
+    pub fn body(&self, id: &str) -> Option<&Body> {
+        self.bodies.get(id)
+    }
+
+    /// The bodies, in id order.
+    pub fn bodies(&self) -> impl Iterator<Item = (&str, &Body)> {
+        self.bodies.iter().map(|(id, body)| (id.as_str(), body))
+    }
+
+    /// The distance between two points, the shortest way around on a torus.
+    pub fn distance(&self, a: (f64, f64), b: (f64, f64)) -> f64 {
+        let axis = |a: f64, b: f64, size: f64| {
+            let d = (a - b).abs();
+            if self.torus {
+                d.min(size - d)
+            } else {
+                d
+            }
+        };
+        axis(a.0, b.0, self.width).hypot(axis(a.1, b.1, self.height))
+    }
+
+    /// The bodies within `radius` of a point, nearest first, then by id.
+    pub fn near(&self, point: (f64, f64), radius: f64) -> Vec<&str> {
+        let (cx, cy) = self.cell(point);
+        let (columns, rows) = self.cells();
+        // Reaching past every cell finds nothing more.
+        let reach = (radius / self.cell_size)
+            .ceil()
+            .clamp(0.0, columns.max(rows) as f64) as i64;
+        let span = |c: i64, count: i64| {
+            let (from, to) = (c.saturating_sub(reach), c.saturating_add(reach));
+            if !self.torus {
+                (from.max(0), to.min(count - 1))
+            } else if reach.saturating_mul(2) >= count - 1 {
+                (0, count - 1)
+            } else {
+                (from, to)
+            }
+        };
+        let ((x0, x1), (y0, y1)) = (span(cx, columns), span(cy, rows));
+
+        // Large reaches look at the occupied cells instead of every one.
+        let mut cells = vec![];
+        let reached = (x1 - x0 + 1).saturating_mul(y1 - y0 + 1);
+        if reached > self.index.len() as i64 {
+            cells.extend(self.index.keys().copied());
+        } else {
+            for x in x0..=x1 {
+                for y in y0..=y1 {
+                    if self.torus {
+                        cells.push((x.rem_euclid(columns), y.rem_euclid(rows)));
+                    } else {
+                        cells.push((x, y));
+                    }
+                }
+            }
+        }
+        // A small torus wraps several offsets onto the same cell.
+        cells.sort_unstable();
+        cells.dedup();
+
+        let mut near: Vec<(f64, &str)> = cells
+            .iter()
+            .filter_map(|cell| self.index.get(cell))
+            .flatten()
+            .filter_map(|id| {
+                let distance = self.distance(point, self.bodies[id].position);
+                (distance <= radius).then_some((distance, id.as_str()))
+            })
+            .collect();
+        near.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(b.1)));
+        near.into_iter().map(|(_, id)| id).collect()
+    }
+
+    /// The other bodies within `radius` of an Agent's body, nearest first.
+    /// Empty if the Agent has no body.
+    pub fn within(&self, id: &str, radius: f64) -> Vec<&str> {
+        let Some(body) = self.bodies.get(id) else {
+            return vec![];
+        };
+        let mut near = self.near(body.position, radius);
+        near.retain(|n| *n != id);
+        near
+    }
+
+    /// The number of (columns, rows) of cells.
+    fn cells(&self) -> (i64, i64) {
+        let count = |size: f64| ((size / self.cell_size).ceil() as i64).max(1);
+        (count(self.width), count(self.height))
+    }
+
+    fn cell(&self, position: (f64, f64)) -> (i64, i64) {
+        let (columns, rows) = self.cells();
+        let cell = |p: f64, count: i64| ((p / self.cell_size).floor() as i64).min(count - 1);
+        (cell(position.0, columns), cell(position.1, rows))
+    }
+
+    /// Wraps a position around a torus, or clamps it to the space.
+    fn confine(&self, position: (f64, f64)) -> (f64, f64) {
+        if self.torus {
+            (
+                position.0.rem_euclid(self.width),
+                position.1.rem_euclid(self.height),
+            )
+        } else {
+            (
+                position.0.clamp(0.0, self.width),
+                position.1.clamp(0.0, self.height),
+            )
+        }
+    }
+
+    /// Moves every body by its velocity.
+    fn step(&mut self) {
+        let mut bodies = std::mem::take(&mut self.bodies);
+        for body in bodies.values_mut() {
+            let moved = (
+                body.position.0 + body.velocity.0,
+                body.position.1 + body.velocity.1,
+            );
+            if !self.torus {
+                if !(0.0..=self.width).contains(&moved.0) {
+                    body.velocity.0 = 0.0;
+                }
+                if !(0.0..=self.height).contains(&moved.1) {
+                    body.velocity.1 = 0.0;
+                }
+            }
+            body.position = self.confine(moved);
+        }
+        self.bodies = bodies;
+    }
+
+    fn steer(&mut self, id: &str, steer: Steer) {
+        let confined = match steer {
+            Steer::Position(x, y) => self.confine((x, y)),
+            Steer::Velocity(..) => (0.0, 0.0),
+        };
+        let Some(body) = self.bodies.get_mut(id) else {
+            return;
+        };
+        match steer {
+            Steer::Velocity(vx, vy) => body.velocity = (vx, vy),
+            Steer::Position(..) => body.position = confined,
+        }
+    }
+
+    /// Rebuilds the spatial hash from the positions of the bodies.
+    fn reindex(&mut self) {
+        let mut index: HashMap<(i64, i64), Vec<String>> = HashMap::new();
+        for (id, body) in &self.bodies {
+            index
+                .entry(self.cell(body.position))
+                .or_default()
+                .push(id.clone());
+        }
+        self.index = index;
+    }
+}
+
+impl Message {
+    /// Creates a message that sets the velocity of src's body from the next
+    /// tick on.
+    pub fn set_velocity<S>(time: DiscreteTime, src: S, vx: f64, vy: f64) -> Message
+    where
+        S: Into<String>,
+    {
+        let src = src.into();
+        Message {
+            interrupt: Some(Interrupt::SetVelocity { vx, vy }),
+            ..Message::new(time, src.as_str(), src.as_str())
+        }
+    }
+
+    /// Creates a message that puts src's body at (x, y) in the next tick.
+    pub fn place_at<S>(time: DiscreteTime, src: S, x: f64, y: f64) -> Message
+    where
+        S: Into<String>,
+    {
+        let src = src.into();
+        Message {
+            interrupt: Some(Interrupt::PlaceAt { x, y }),
+            ..Message::new(time, src.as_str(), src.as_str())
+        }
+    }
+}
+
+impl Simulation {
+    /// Returns the space, as of now.
+    pub fn space(&self) -> Option<&Space> {
+        self.space.as_deref()
+    }
+
+    /// Queues the update of a body a message sends, if it sends one.
+    pub(crate) fn steer_body(&mut self, message: &Message) -> bool {
+        let steer = match message.interrupt {
+            Some(Interrupt::SetVelocity { vx, vy }) => Steer::Velocity(vx, vy),
+            Some(Interrupt::PlaceAt { x, y }) => Steer::Position(x, y),
+            _ => return false,
+        };
+        self.space_steers.push((message.source.clone(), steer));
+        true
+    }
+
+    /// Moves the bodies by a tick, and applies the updates of the previous one.
+    pub(crate) fn advance_space(&mut self) {
+        let Some(space) = self.space.as_mut() else {
+            return;
+        };
+        let space = Arc::make_mut(space);
+        if self.time > self.starting_time {
+            space.step();
+        }
+        for (id, steer) in self.space_steers.drain(..) {
+            space.steer(&id, steer);
+        }
+        space.reindex();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use rand::prelude::*;
+
+    #[test]
+    fn near_test() {
+        // The spatial hash finds the same bodies as checking them all.
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut space = Space::new(100.0, 100.0).with_torus();
+        for i in 0..2000 {
+            let position = (rng.gen_range(0.0..100.0), rng.gen_range(0.0..100.0));
+            space = space.with_body(format!("{}", i), position, (0.0, 0.0));
+        }
+        let space = space.with_cell_size(5.0);
+
+        for center in [(50.0, 50.0), (1.0, 99.0)] {
+            let mut expected: Vec<&str> = space
+                .bodies()
+                .filter(|(_, b)| space.distance(center, b.position) <= 4.0)
+                .map(|(id, _)| id)
+                .collect();
+            let mut found = space.near(center, 4.0);
+            assert!(!found.is_empty());
+            expected.sort();
+            found.sort();
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn near_large_radius_test() {
+        // Checks the two bodies, not the trillion cells within the radius.
+        let space = Space::new(1e6, 1e6)
+            .with_cell_size(1.0)
+            .with_body("a", (0.0, 0.0), (0.0, 0.0))
+            .with_body("b", (1e6, 1e6), (0.0, 0.0));
+        assert_eq!(space.near((0.0, 0.0), 2e6), ["a", "b"]);
+        assert_eq!(space.near((0.0, 0.0), f64::INFINITY), ["a", "b"]);
+        assert_eq!(space.near((5e5, 5e5), 1.0), Vec::<&str>::new());
+    }
+
+    #[test]
+    #[should_panic(expected = "the cell size NaN isn't positive")]
+    fn nan_cell_size_test() {
+        Space::new(10.0, 10.0).with_cell_size(f64::NAN);
+    }
+
+    #[test]
+    fn movement_test() {
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![periodic_consuming_agent("boat", 1)],
+            space: Some(Space::new(10.0, 10.0).with_body("boat", (1.0, 1.0), (2.0, 0.5))),
+            halt_check: |s: &Simulation| s.time == 6,
+            ..Default::default()
+        });
+        simulation.run();
+
+        // 5 ticks of movement, stopping at the edge after 4.
+        let boat = simulation.space().unwrap().body("boat").unwrap();
+        assert_eq!(boat.position, (10.0, 3.5));
+        assert_eq!(boat.velocity, (0.0, 0.5));
+    }
+}
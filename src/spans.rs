@@ -0,0 +1,103 @@
+//! Structured `tracing` spans of a run, with the `tracing` feature: a `tick`
+//! span per tick, with its `time`, and within it an `agent` span per Agent
+//! invocation, with the `agent` id, so runs can be inspected with
+//! `tracing-subscriber` or exported to other backends. Without the feature
+//! the spans are no-ops.
+
+use crate::DiscreteTime;
+
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::Span;
+
+/// Stands in for `tracing::Span` without the `tracing` feature.
+#[cfg(not(feature = "tracing"))]
+#[derive(Clone, Debug)]
+pub(crate) struct Span;
+
+/// Stands in for the guard of an entered `tracing::Span`.
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct Entered;
+
+#[cfg(not(feature = "tracing"))]
+impl Span {
+    pub(crate) fn enter(&self) -> Entered {
+        Entered
+    }
+}
+
+/// The span of the tick at `time`.
+pub(crate) fn tick(time: DiscreteTime) -> Span {
+    #[cfg(feature = "tracing")]
+    return tracing::debug_span!("tick", time);
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = time;
+        Span
+    }
+}
+
+/// The span of an Agent's invocation at `time`.
+pub(crate) fn agent(id: &str, time: DiscreteTime) -> Span {
+    #[cfg(feature = "tracing")]
+    return tracing::trace_span!("agent", agent = id, time);
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = (id, time);
+        Span
+    }
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use crate::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata};
+
+    /// Counts the spans created, by name.
+    #[derive(Default)]
+    struct SpanCounter {
+        spans: Mutex<HashMap<&'static str, usize>>,
+    }
+
+    impl tracing::Subscriber for SpanCounter {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut spans = self.spans.lock().unwrap();
+            *spans.entry(span.metadata().name()).or_default() += 1;
+            Id::from_u64(spans.values().sum::<usize>() as u64)
+        }
+
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, _: &Event<'_>) {}
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn spans_test() {
+        let counter = std::sync::Arc::new(SpanCounter::default());
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer", 1, "consumer"),
+                periodic_consuming_agent("consumer", 1),
+            ],
+            halt_check: |s: &Simulation| s.time == 5,
+            ..Default::default()
+        });
+        tracing::subscriber::with_default(counter.clone(), || simulation.run());
+
+        let spans = counter.spans.lock().unwrap();
+        assert_eq!(spans.get("tick"), Some(&5));
+        assert_eq!(spans.get("agent"), Some(&10));
+    }
+}
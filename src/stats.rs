@@ -0,0 +1,668 @@
+use crate::{csv, DiscreteTime, Simulation};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// An N×N matrix of the messages exchanged between Agents during a Simulation.
+///
+/// Rows are the source Agent and columns are the destination Agent, both
+/// indexed by the Agent's handle (its index in `Simulation::agents`).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MessageMatrix {
+    /// The Agent ids, in handle order. `agent_ids[i]` labels row and column `i`.
+    pub agent_ids: Vec<String>,
+    /// `counts[src][dst]` is the number of messages `src` produced to `dst`.
+    pub counts: Vec<Vec<usize>>,
+    /// `mean_latencies[src][dst]` is the mean of `completed_time - queued_time`
+    /// over the messages from `src` that `dst` consumed, if it consumed any.
+    pub mean_latencies: Vec<Vec<Option<f64>>>,
+}
+
+impl MessageMatrix {
+    /// Returns the handle of the Agent with the given id.
+    pub fn handle(&self, id: &str) -> Option<usize> {
+        self.agent_ids.iter().position(|a| a == id)
+    }
+
+    /// Returns the number of messages produced from `src` to `dst`.
+    pub fn count(&self, src: &str, dst: &str) -> Option<usize> {
+        Some(self.counts[self.handle(src)?][self.handle(dst)?])
+    }
+
+    /// Returns the mean latency of messages consumed by `dst` that came from `src`.
+    pub fn mean_latency(&self, src: &str, dst: &str) -> Option<f64> {
+        self.mean_latencies[self.handle(src)?][self.handle(dst)?]
+    }
+
+    /// Renders the matrix as CSV, one row per (source, destination) pair that
+    /// exchanged at least one message. Pairs with no consumed messages have an
+    /// empty mean_latency column.
+    pub fn to_csv(&self) -> String {
+        let mut csv = "source,destination,count,mean_latency\n".to_string();
+
+        for (src, row) in self.counts.iter().enumerate() {
+            for (dst, count) in row.iter().enumerate() {
+                let latency = self.mean_latencies[src][dst];
+                if *count == 0 && latency.is_none() {
+                    continue;
+                }
+
+                let _ = writeln!(
+                    csv,
+                    "{},{},{},{}",
+                    csv::field(&self.agent_ids[src]),
+                    csv::field(&self.agent_ids[dst]),
+                    count,
+                    latency.map(|l| l.to_string()).unwrap_or_default()
+                );
+            }
+        }
+
+        csv
+    }
+
+    /// Writes the CSV rendering of the matrix to `path`.
+    pub fn write_csv<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        std::fs::write(path, self.to_csv())
+    }
+}
+
+impl Simulation {
+    /// Returns the matrix of message counts and mean latencies between every
+    /// pair of Agents. Messages addressed to unknown Agents are not counted.
+    pub fn message_matrix(&self) -> MessageMatrix {
+        let agent_ids: Vec<String> = self.agents.iter().map(|a| a.state().id.clone()).collect();
+        let handle = |id: &str| self.agent_handles.get(id).copied();
+        let n = agent_ids.len();
+
+        let mut counts = vec![vec![0; n]; n];
+        let mut latency_sums = vec![vec![0u64; n]; n];
+        let mut latency_counts = vec![vec![0u64; n]; n];
+
+        for (src, agent) in self.agents.iter().enumerate() {
+            for msg in agent.state().produced.iter() {
+                if let Some(dst) = handle(&msg.destination) {
+                    counts[src][dst] += 1;
+                }
+            }
+        }
+
+        for (dst, agent) in self.agents.iter().enumerate() {
+            for msg in agent.state().consumed.iter() {
+                if let (Some(src), Some(completed)) = (handle(&msg.source), msg.completed_time) {
+                    latency_sums[src][dst] += completed.saturating_sub(msg.queued_time);
+                    latency_counts[src][dst] += 1;
+                }
+            }
+        }
+
+        let mean_latencies = latency_sums
+            .iter()
+            .zip(latency_counts.iter())
+            .map(|(sums, counts)| {
+                sums.iter()
+                    .zip(counts.iter())
+                    .map(|(sum, count)| (*count > 0).then(|| *sum as f64 / *count as f64))
+                    .collect()
+            })
+            .collect();
+
+        MessageMatrix {
+            agent_ids,
+            counts,
+            mean_latencies,
+        }
+    }
+}
+
+/// The count, mean, variance, min and max of a metric, kept online with
+/// Welford's algorithm, so memory stays constant however long a Simulation runs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StreamingStats {
+    pub count: u64,
+    pub mean: f64,
+    /// The sum of squared differences from the mean.
+    m2: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Default for StreamingStats {
+    fn default() -> Self {
+        StreamingStats {
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+}
+
+impl StreamingStats {
+    /// Adds an observation.
+    pub fn push(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    /// The sample variance; 0 with fewer than two observations.
+    pub fn variance(&self) -> f64 {
+        if self.count < 2 {
+            return 0.0;
+        }
+        self.m2 / (self.count - 1) as f64
+    }
+
+    /// The sample standard deviation.
+    pub fn std_dev(&self) -> f64 {
+        self.variance().sqrt()
+    }
+}
+
+/// A histogram of durations in ticks. Durations are integers, so every
+/// distinct value gets its own bucket and percentiles are exact.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Histogram {
+    /// Maps from a duration => how often it was recorded.
+    pub buckets: BTreeMap<DiscreteTime, u64>,
+    count: u64,
+}
+
+impl Histogram {
+    pub fn record(&mut self, value: DiscreteTime) {
+        *self.buckets.entry(value).or_default() += 1;
+        self.count += 1;
+    }
+
+    /// Takes back one recording of the value, if there is one, e.g. as it
+    /// slides out of a rolling window.
+    pub(crate) fn forget(&mut self, value: DiscreteTime) {
+        if let Some(count) = self.buckets.get_mut(&value) {
+            *count -= 1;
+            self.count -= 1;
+            if *count == 0 {
+                self.buckets.remove(&value);
+            }
+        }
+    }
+
+    /// The number of recorded values.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The p-th percentile (0 to 100) by the nearest-rank method, e.g. 99.0
+    /// for p99. None if nothing was recorded.
+    pub fn percentile(&self, p: f64) -> Option<DiscreteTime> {
+        if self.count == 0 {
+            return None;
+        }
+
+        let rank = ((p.clamp(0.0, 100.0) / 100.0 * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        self.buckets.iter().find_map(|(value, count)| {
+            seen += count;
+            (seen >= rank).then_some(*value)
+        })
+    }
+}
+
+/// Finds how many initial observations of a time series to truncate as
+/// warm-up, with the MSER-5 rule: the series is averaged in batches of 5, and
+/// the truncation point minimizes the standard error of the mean of the
+/// remaining batches. Only truncation of up to half the series is considered.
+pub fn mser5_truncation(series: &[f64]) -> usize {
+    let batches: Vec<f64> = series
+        .chunks_exact(5)
+        .map(|batch| batch.iter().sum::<f64>() / 5.0)
+        .collect();
+
+    let mut best = (f64::INFINITY, 0);
+    for d in 0..=batches.len() / 2 {
+        let rest = &batches[d..];
+        if rest.is_empty() {
+            break;
+        }
+
+        let n = rest.len() as f64;
+        let mean = rest.iter().sum::<f64>() / n;
+        let mser = rest.iter().map(|y| (y - mean).powi(2)).sum::<f64>() / (n * n);
+        if mser < best.0 {
+            best = (mser, d);
+        }
+    }
+
+    best.1 * 5
+}
+
+/// The two-sided 95% critical value of Student's t-distribution with the
+/// given degrees of freedom, falling back to the normal's beyond 30.
+pub(crate) fn t_critical_value_95(degrees_of_freedom: usize) -> f64 {
+    const TABLE: [f64; 30] = [
+        12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+        2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+        2.052, 2.048, 2.045, 2.042,
+    ];
+
+    match degrees_of_freedom {
+        0 => f64::INFINITY,
+        df if df <= TABLE.len() => TABLE[df - 1],
+        _ => 1.96,
+    }
+}
+
+/// The batch-means analysis of a single long run's time series: the series
+/// is split into equal batches, whose means are approximately independent
+/// even when the observations themselves are autocorrelated.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatchMeans {
+    /// The mean of every batch, in order.
+    pub batch_means: Vec<f64>,
+    /// The grand mean of the batches.
+    pub mean: f64,
+    /// The lag-1 autocorrelation of the batch means. Close to zero when the
+    /// batches are large enough to be treated as independent.
+    pub lag1_autocorrelation: f64,
+    /// The 95% confidence interval of the mean, from Student's t-distribution.
+    pub confidence_interval: (f64, f64),
+}
+
+/// Analyzes a time series with the given number of batches. Trailing
+/// observations that don't fill a batch are ignored. None if the series is
+/// shorter than two batches of one observation.
+pub fn batch_means(series: &[f64], batches: usize) -> Option<BatchMeans> {
+    if batches < 2 || series.len() < batches {
+        return None;
+    }
+
+    let batch_size = series.len() / batches;
+    let batch_means: Vec<f64> = series
+        .chunks_exact(batch_size)
+        .take(batches)
+        .map(|batch| batch.iter().sum::<f64>() / batch_size as f64)
+        .collect();
+
+    let k = batch_means.len() as f64;
+    let mean = batch_means.iter().sum::<f64>() / k;
+    let squares: f64 = batch_means.iter().map(|m| (m - mean).powi(2)).sum();
+    let lag1: f64 = batch_means
+        .windows(2)
+        .map(|pair| (pair[0] - mean) * (pair[1] - mean))
+        .sum();
+
+    let half_width = t_critical_value_95(batches - 1) * (squares / (k - 1.0) / k).sqrt();
+
+    Some(BatchMeans {
+        mean,
+        lag1_autocorrelation: if squares > 0.0 { lag1 / squares } else { 0.0 },
+        confidence_interval: (mean - half_width, mean + half_width),
+        batch_means,
+    })
+}
+
+/// The default `unstable_growth_rate`: queues that grow by this many messages
+/// per tick or more are flagged unstable.
+pub const UNSTABLE_GROWTH_RATE: f64 = 0.05;
+
+/// Whether an Agent keeps up with its arrivals over a run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueueStability {
+    /// The average growth of the queue, in messages per tick.
+    pub growth_rate: f64,
+    /// Whether the queue grew by the Simulation's `unstable_growth_rate` or
+    /// more, a sign that messages arrive faster than the Agent processes them
+    /// (ρ ≥ 1) and statistics of the run describe no steady state. Queues
+    /// near ρ = 1 may grow slower than that and go unflagged.
+    pub unstable: bool,
+}
+
+/// The slope of the least-squares line through a series, per observation.
+fn least_squares_slope(series: &[f64]) -> f64 {
+    let n = series.len() as f64;
+    let mean_x = (n - 1.0) / 2.0;
+    let mean_y = series.iter().sum::<f64>() / n;
+    let (covariance, variance) = series
+        .iter()
+        .enumerate()
+        .fold((0.0, 0.0), |(c, v), (x, y)| {
+            let dx = x as f64 - mean_x;
+            (c + dx * (y - mean_y), v + dx * dx)
+        });
+
+    if variance == 0.0 {
+        0.0
+    } else {
+        covariance / variance
+    }
+}
+
+/// The terms of Little's Law, L = λW, measured for an Agent over a run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LittlesLaw {
+    /// L: the mean queue length, in messages.
+    pub mean_queue_length: f64,
+    /// λ: the rate at which messages arrived, in messages per tick.
+    pub arrival_rate: f64,
+    /// W: the mean time a message spent in the queue, in ticks.
+    pub mean_wait: f64,
+}
+
+impl LittlesLaw {
+    /// λW, the mean queue length Little's Law predicts.
+    pub fn predicted_queue_length(&self) -> f64 {
+        self.arrival_rate * self.mean_wait
+    }
+
+    /// |L - λW| / L. It's 0 if the queue was always empty and nothing waited,
+    /// and infinite if the queue was always empty but messages waited.
+    pub fn relative_error(&self) -> f64 {
+        let difference = (self.mean_queue_length - self.predicted_queue_length()).abs();
+        if difference == 0.0 {
+            0.0
+        } else {
+            difference / self.mean_queue_length
+        }
+    }
+}
+
+impl Simulation {
+    /// Measures the terms of Little's Law for an Agent after the warm-up, as
+    /// a sanity check of the model and its metrics: a large `relative_error`
+    /// means messages are queued or consumed in ways the metrics don't see.
+    /// Messages still queued count as waiting until the end of the run.
+    /// Requires `enable_queue_depth_metrics`; None if no depth was recorded.
+    pub fn littles_law(&self, id: &str) -> Option<LittlesLaw> {
+        let depths = self.queue_depth_metrics(id)?;
+        if depths.is_empty() {
+            return None;
+        }
+
+        let state = self.agent(id)?.state();
+        let waits: Vec<DiscreteTime> = state
+            .consumed
+            .iter()
+            .map(|m| (m.queued_time, m.completed_time.unwrap_or(self.time)))
+            .chain(state.queue.iter().map(|m| (m.queued_time, self.time)))
+            .filter(|(queued, _)| self.is_after_warm_up(*queued))
+            .map(|(queued, completed)| completed.saturating_sub(queued))
+            .collect();
+
+        let ticks = depths.len() as f64;
+        let mean_wait = if waits.is_empty() {
+            0.0
+        } else {
+            waits.iter().sum::<DiscreteTime>() as f64 / waits.len() as f64
+        };
+
+        Some(LittlesLaw {
+            mean_queue_length: depths.iter().sum::<usize>() as f64 / ticks,
+            arrival_rate: waits.len() as f64 / ticks,
+            mean_wait,
+        })
+    }
+
+    /// Measures Little's Law for every Agent with queue depth metrics, in
+    /// Agent order; see `littles_law`.
+    pub fn littles_law_report(&self) -> Vec<(String, LittlesLaw)> {
+        self.agents
+            .iter()
+            .filter_map(|a| Some((a.state().id.clone(), self.littles_law(&a.state().id)?)))
+            .collect()
+    }
+
+    /// Estimates whether an Agent's queue is stable. The growth rate is the
+    /// trend of its queue depths after the warm-up if `enable_queue_depth_metrics`
+    /// is set, else the net change of its queue length over the run.
+    pub fn queue_stability(&self, id: &str) -> Option<QueueStability> {
+        let depths: Vec<f64> = self
+            .queue_depth_metrics(id)?
+            .iter()
+            .map(|d| *d as f64)
+            .collect();
+
+        let growth_rate = if depths.len() >= 2 {
+            least_squares_slope(&depths)
+        } else {
+            let ticks = self.time.saturating_sub(self.starting_time);
+            let agent = &self.agents[*self.agent_handles.get(id)?];
+            let initial = self.metadata_for_agent(id)?.initial_queue_len;
+            if ticks == 0 {
+                0.0
+            } else {
+                (agent.state().queue.len() as f64 - initial as f64) / ticks as f64
+            }
+        };
+
+        Some(QueueStability {
+            growth_rate,
+            unstable: growth_rate >= self.unstable_growth_rate,
+        })
+    }
+
+    /// Returns the ids of the Agents whose queues are unstable, sorted. Steady
+    /// state conclusions drawn from a run with unstable Agents are unsound.
+    pub fn unstable_agents(&self) -> Vec<String> {
+        let mut unstable: Vec<String> = self
+            .agents
+            .iter()
+            .map(|a| &a.state().id)
+            .filter(|id| self.queue_stability(id).map_or(false, |s| s.unstable))
+            .cloned()
+            .collect();
+        unstable.sort();
+        unstable
+    }
+
+    /// Batch-means analysis of an Agent's queue depths after the warm-up.
+    /// Requires `enable_queue_depth_metrics`.
+    pub fn queue_depth_batch_means(&self, id: &str, batches: usize) -> Option<BatchMeans> {
+        let depths: Vec<f64> = self
+            .queue_depth_metrics(id)?
+            .iter()
+            .map(|d| *d as f64)
+            .collect();
+        batch_means(&depths, batches)
+    }
+
+    /// Batch-means analysis of the wait times of the messages an Agent
+    /// consumed after the warm-up, in the order it consumed them.
+    pub fn wait_time_batch_means(&self, id: &str, batches: usize) -> Option<BatchMeans> {
+        let waits: Vec<f64> = self
+            .agent(id)?
+            .state()
+            .consumed
+            .iter()
+            .filter(|m| self.is_after_warm_up(m.queued_time))
+            .filter_map(|m| Some(m.completed_time?.saturating_sub(m.queued_time) as f64))
+            .collect();
+        batch_means(&waits, batches)
+    }
+
+    /// Returns the histogram of the wait times of the messages an Agent
+    /// consumed after the warm-up.
+    pub fn wait_time_histogram(&self, id: &str) -> Option<Histogram> {
+        let mut histogram = Histogram::default();
+        for msg in self.agent(id)?.state().consumed.iter() {
+            if let (Some(completed), true) =
+                (msg.completed_time, self.is_after_warm_up(msg.queued_time))
+            {
+                histogram.record(completed.saturating_sub(msg.queued_time));
+            }
+        }
+        Some(histogram)
+    }
+
+    /// The p-th percentile (0 to 100) of an Agent's wait times, e.g. 95.0 for
+    /// p95. None if the Agent doesn't exist or consumed nothing.
+    pub fn wait_time_percentile(&self, id: &str, p: f64) -> Option<DiscreteTime> {
+        self.wait_time_histogram(id)?.percentile(p)
+    }
+
+    /// Returns rolling percentiles (0 to 100) of an Agent's wait times over
+    /// the run: for every tick at which messages completed in the window of
+    /// `window` ticks ending with it, the (time, percentiles) of their waits.
+    /// Shows trends like saturation that a single percentile over the run hides.
+    pub fn rolling_wait_time_percentiles(
+        &self,
+        id: &str,
+        window: DiscreteTime,
+        percentiles: &[f64],
+    ) -> Option<Vec<(DiscreteTime, Vec<DiscreteTime>)>> {
+        let mut completions: Vec<(DiscreteTime, DiscreteTime)> = self
+            .agent(id)?
+            .state()
+            .consumed
+            .iter()
+            .filter(|m| self.is_after_warm_up(m.queued_time))
+            .filter_map(|m| {
+                let completed = m.completed_time?;
+                Some((completed, completed.saturating_sub(m.queued_time)))
+            })
+            .collect();
+        completions.sort_unstable();
+
+        let Some(first) = completions.first().map(|(time, _)| *time) else {
+            return Some(vec![]);
+        };
+        let last = self.time.max(first + 1);
+
+        // The histogram slides along with the window.
+        let (mut start, mut end) = (0, 0);
+        let mut histogram = Histogram::default();
+        let mut rolling = vec![];
+        for time in first..last {
+            while end < completions.len() && completions[end].0 <= time {
+                histogram.record(completions[end].1);
+                end += 1;
+            }
+            while start < end && completions[start].0 + window <= time {
+                histogram.forget(completions[start].1);
+                start += 1;
+            }
+            if start == end {
+                continue;
+            }
+
+            let values = percentiles
+                .iter()
+                .filter_map(|p| histogram.percentile(*p))
+                .collect();
+            rolling.push((time, values));
+        }
+
+        Some(rolling)
+    }
+
+    /// Returns whether something that happened at `time` is past the warm-up period.
+    pub(crate) fn is_after_warm_up(&self, time: DiscreteTime) -> bool {
+        self.warm_up.map_or(true, |w| time >= w)
+    }
+
+    /// Detects the end of the warm-up period from an Agent's queue depths with
+    /// MSER-5. Requires `enable_queue_depth_metrics`. Assign the result to
+    /// `Simulation::warm_up` to exclude the warm-up from statistics.
+    pub fn detect_warm_up(&self, id: &str) -> Option<DiscreteTime> {
+        let timeline = self.queue_depth_timeline(id)?;
+        let depths: Vec<f64> = timeline.iter().map(|(_, d)| *d as f64).collect();
+        Some(timeline.get(mser5_truncation(&depths))?.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn littles_law_relative_error_test() {
+        let law = |mean_queue_length, mean_wait| LittlesLaw {
+            mean_queue_length,
+            arrival_rate: 0.5,
+            mean_wait,
+        };
+        assert_eq!(law(2.0, 3.0).relative_error(), 0.25);
+        assert_eq!(law(0.0, 0.0).relative_error(), 0.0);
+        assert_eq!(law(0.0, 3.0).relative_error(), f64::INFINITY);
+    }
+
+    #[test]
+    fn histogram_forget_test() {
+        let mut histogram = Histogram::default();
+        [1, 2, 2, 5].into_iter().for_each(|v| histogram.record(v));
+        histogram.forget(2);
+        histogram.forget(5);
+        histogram.forget(7);
+        assert_eq!(histogram.count(), 2);
+        assert_eq!(histogram.buckets, BTreeMap::from([(1, 1), (2, 1)]));
+        assert_eq!(histogram.percentile(100.0), Some(2));
+    }
+
+    #[test]
+    fn least_squares_slope_test() {
+        assert_eq!(least_squares_slope(&[1.0, 3.0, 5.0, 7.0]), 2.0);
+        assert_eq!(least_squares_slope(&[4.0, 4.0, 4.0]), 0.0);
+        assert_eq!(least_squares_slope(&[4.0]), 0.0);
+    }
+
+    #[test]
+    fn mser5_truncation_test() {
+        // A transient ramp of 50 observations, then a noisy steady state.
+        let series: Vec<f64> = (0..50)
+            .map(|i| i as f64)
+            .chain((0..250).map(|i| 100.0 + (i % 7) as f64))
+            .collect();
+
+        let truncation = mser5_truncation(&series);
+        assert!((45..=60).contains(&truncation), "{}", truncation);
+        assert_eq!(mser5_truncation(&[1.0; 100]), 0);
+    }
+
+    #[test]
+    fn streaming_stats_test() {
+        let mut stats = StreamingStats::default();
+        for value in [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] {
+            stats.push(value);
+        }
+
+        assert_eq!(stats.count, 8);
+        assert_eq!(stats.mean, 5.0);
+        assert!((stats.variance() - 32.0 / 7.0).abs() < 1e-12);
+        assert_eq!((stats.min, stats.max), (2.0, 9.0));
+    }
+
+    #[test]
+    fn histogram_test() {
+        let mut histogram = Histogram::default();
+        for value in 1..=100 {
+            histogram.record(value);
+        }
+
+        assert_eq!(histogram.count(), 100);
+        assert_eq!(histogram.percentile(50.0), Some(50));
+        assert_eq!(histogram.percentile(99.0), Some(99));
+        assert_eq!(histogram.percentile(0.0), Some(1));
+        assert_eq!(histogram.percentile(100.0), Some(100));
+        assert_eq!(Histogram::default().percentile(50.0), None);
+    }
+
+    #[test]
+    fn batch_means_test() {
+        let series: Vec<f64> = (0..1000).map(|i| (i % 10) as f64).collect();
+
+        let analysis = batch_means(&series, 10).unwrap();
+        assert_eq!(analysis.batch_means, vec![4.5; 10]);
+        assert_eq!(analysis.mean, 4.5);
+        assert_eq!(analysis.confidence_interval, (4.5, 4.5));
+
+        let trending: Vec<f64> = (0..1000).map(|i| i as f64).collect();
+        let analysis = batch_means(&trending, 10).unwrap();
+        assert!(analysis.lag1_autocorrelation > 0.5);
+        assert!(analysis.confidence_interval.0 < 499.5 && 499.5 < analysis.confidence_interval.1);
+
+        assert!(batch_means(&series[..5], 10).is_none());
+    }
+}
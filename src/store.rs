@@ -0,0 +1,315 @@
+//! Stores of discrete items and containers of a continuous level, for
+//! material flow like inventories, tanks and buffers.
+//!
+//! Agents put to and get from them with messages, like resources: the engine
+//! replies to a request once it's done, which the returned RequestHandle
+//! matches. Puts block while a store or container is full, and gets while it
+//! is empty, each in first come, first served order.
+
+use crate::{DiscreteTime, Interrupt, Message, RequestHandle, Simulation};
+use std::collections::VecDeque;
+
+/// The flow through a store or container over a run.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FlowStats {
+    /// The puts done.
+    pub puts: usize,
+    /// The gets done.
+    pub gets: usize,
+    /// The total ticks puts were blocked.
+    pub put_wait: DiscreteTime,
+    /// The total ticks gets were blocked.
+    pub get_wait: DiscreteTime,
+    /// The mean level over the run, in items for a store.
+    pub mean_level: f64,
+    /// The integral of the level over time.
+    level_ticks: f64,
+}
+
+/// The requests blocked on a store or container, and its statistics.
+#[derive(Clone, Debug, Default)]
+struct Flow {
+    puts: VecDeque<Message>,
+    gets: VecDeque<Message>,
+    stats: FlowStats,
+    /// When the level last changed.
+    last_change: DiscreteTime,
+}
+
+impl Flow {
+    /// Accumulates the time-weighted statistics up to `time`.
+    fn advance(&mut self, time: DiscreteTime, level: f64) {
+        self.stats.level_ticks += level * time.saturating_sub(self.last_change) as f64;
+        self.last_change = time;
+    }
+
+    /// The stats as of `time`, given the current level.
+    fn stats(&self, time: DiscreteTime, level: f64, starting_time: DiscreteTime) -> FlowStats {
+        let mut flow = self.clone();
+        flow.advance(time, level);
+        let ticks = time.saturating_sub(starting_time).max(1) as f64;
+        FlowStats {
+            mean_level: flow.stats.level_ticks / ticks,
+            ..flow.stats
+        }
+    }
+
+    /// Unblocks the request at the front of a line, returning its reply.
+    fn done(stats: &mut FlowStats, line: &mut VecDeque<Message>, time: DiscreteTime) -> Message {
+        let request = line.pop_front().expect("A blocked request was just peeked");
+        let wait = time - request.queued_time;
+        if let Some(Interrupt::Put { .. }) = request.interrupt {
+            stats.puts += 1;
+            stats.put_wait += wait;
+        } else {
+            stats.gets += 1;
+            stats.get_wait += wait;
+        }
+        request.reply(time, None)
+    }
+}
+
+/// The amount a put or get moves.
+fn amount(request: &Message) -> f64 {
+    match request.interrupt {
+        Some(Interrupt::Put { amount, .. } | Interrupt::Get { amount, .. }) => amount,
+        _ => 0.0,
+    }
+}
+
+/// A store of up to `capacity` discrete items, each the payload of the
+/// message that put it. Gets reply with the items in the order they were put.
+#[derive(Clone, Debug)]
+pub struct Store {
+    pub name: String,
+    pub capacity: usize,
+    items: VecDeque<Option<Vec<u8>>>,
+    flow: Flow,
+}
+
+impl Store {
+    pub fn new<T>(name: T, capacity: usize) -> Store
+    where
+        T: Into<String>,
+    {
+        Store {
+            name: name.into(),
+            capacity,
+            items: VecDeque::new(),
+            flow: Flow::default(),
+        }
+    }
+
+    /// The number of items in the store.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Unblocks every request that can proceed, returning their replies.
+    fn flow(&mut self, time: DiscreteTime) -> Vec<Message> {
+        let mut replies = vec![];
+        loop {
+            if !self.flow.puts.is_empty() && self.items.len() < self.capacity {
+                self.flow.advance(time, self.items.len() as f64);
+                let item = self.flow.puts[0].custom_payload.clone();
+                self.items.push_back(item);
+                replies.push(Flow::done(&mut self.flow.stats, &mut self.flow.puts, time));
+            } else if !self.flow.gets.is_empty() && !self.items.is_empty() {
+                self.flow.advance(time, self.items.len() as f64);
+                let mut reply = Flow::done(&mut self.flow.stats, &mut self.flow.gets, time);
+                reply.custom_payload = self.items.pop_front().flatten();
+                replies.push(reply);
+            } else {
+                return replies;
+            }
+        }
+    }
+}
+
+/// A container of a continuous level of up to `capacity`, e.g. a tank.
+#[derive(Clone, Debug)]
+pub struct Container {
+    pub name: String,
+    pub capacity: f64,
+    level: f64,
+    flow: Flow,
+}
+
+impl Container {
+    pub fn new<T>(name: T, capacity: f64, level: f64) -> Container
+    where
+        T: Into<String>,
+    {
+        Container {
+            name: name.into(),
+            capacity,
+            level,
+            flow: Flow::default(),
+        }
+    }
+
+    pub fn level(&self) -> f64 {
+        self.level
+    }
+
+    /// Unblocks every request that can proceed, returning their replies.
+    fn flow(&mut self, time: DiscreteTime) -> Vec<Message> {
+        let mut replies = vec![];
+        loop {
+            let put = self.flow.puts.front().map(amount);
+            let get = self.flow.gets.front().map(amount);
+            if let Some(put) = put.filter(|p| self.level + p <= self.capacity) {
+                self.flow.advance(time, self.level);
+                self.level += put;
+                replies.push(Flow::done(&mut self.flow.stats, &mut self.flow.puts, time));
+            } else if let Some(get) = get.filter(|g| *g <= self.level) {
+                self.flow.advance(time, self.level);
+                self.level -= get;
+                replies.push(Flow::done(&mut self.flow.stats, &mut self.flow.gets, time));
+            } else {
+                return replies;
+            }
+        }
+    }
+}
+
+impl Message {
+    /// Creates a request of src to put `amount` into a container, or its
+    /// payload as an item into a store, in which case `amount` is ignored.
+    /// The engine replies once it's done, which the RequestHandle matches.
+    pub fn put<S>(
+        time: DiscreteTime,
+        src: S,
+        target: S,
+        amount: f64,
+        payload: Option<Vec<u8>>,
+    ) -> (Message, RequestHandle)
+    where
+        S: Into<String>,
+    {
+        let (mut request, handle) = Message::request(time, src, target, payload);
+        request.interrupt = Some(Interrupt::Put {
+            target: request.destination.clone(),
+            amount,
+        });
+        (request, handle)
+    }
+
+    /// Creates a request of src to get `amount` from a container, or an item
+    /// from a store, in which case `amount` is ignored. The engine replies
+    /// once it's done, with the item as payload for a store.
+    pub fn get<S>(time: DiscreteTime, src: S, target: S, amount: f64) -> (Message, RequestHandle)
+    where
+        S: Into<String>,
+    {
+        let (mut request, handle) = Message::request(time, src, target, None);
+        request.interrupt = Some(Interrupt::Get {
+            target: request.destination.clone(),
+            amount,
+        });
+        (request, handle)
+    }
+}
+
+impl Simulation {
+    /// Returns a store of the Simulation, as of now.
+    pub fn store(&self, name: &str) -> Option<&Store> {
+        self.stores.iter().find(|s| s.name == name)
+    }
+
+    /// Returns a container of the Simulation, as of now.
+    pub fn container(&self, name: &str) -> Option<&Container> {
+        self.containers.iter().find(|c| c.name == name)
+    }
+
+    /// Returns the flow through a store or container over the run so far.
+    pub fn flow_stats(&self, name: &str) -> Option<FlowStats> {
+        if let Some(store) = self.store(name) {
+            let level = store.items.len() as f64;
+            return Some(store.flow.stats(self.time, level, self.starting_time));
+        }
+        let container = self.container(name)?;
+        Some(
+            container
+                .flow
+                .stats(self.time, container.level, self.starting_time),
+        )
+    }
+
+    /// Handles a put or get, returning the replies to the requests it
+    /// unblocks. None if the message isn't to a store or container of the
+    /// Simulation.
+    pub(crate) fn handle_store_message(&mut self, message: &Message) -> Option<Vec<Message>> {
+        let (Some(Interrupt::Put { target, .. }) | Some(Interrupt::Get { target, .. })) =
+            &message.interrupt
+        else {
+            return None;
+        };
+        let line = |flow: &mut Flow| {
+            if let Some(Interrupt::Put { .. }) = message.interrupt {
+                flow.puts.push_back(message.clone());
+            } else {
+                flow.gets.push_back(message.clone());
+            }
+        };
+
+        let time = self.time;
+        if let Some(store) = self.stores.iter_mut().find(|s| s.name == *target) {
+            line(&mut store.flow);
+            return Some(store.flow(time));
+        }
+        let container = self.containers.iter_mut().find(|c| c.name == *target)?;
+        line(&mut container.flow);
+        Some(container.flow(time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn store_test() {
+        let mut store = Store::new("shelf", 1);
+        let (first, _) = Message::put(0, "maker", "shelf", 0.0, Some(vec![1]));
+        let (second, _) = Message::put(0, "maker", "shelf", 0.0, Some(vec![2]));
+        let (get, handle) = Message::get(1, "taker", "shelf", 0.0);
+
+        store.flow.puts.extend([first, second]);
+        assert_eq!(store.flow(0).len(), 1);
+        assert_eq!(store.len(), 1);
+
+        // Getting the first item unblocks the second put.
+        store.flow.gets.push_back(get);
+        let replies = store.flow(3);
+        assert_eq!(replies.len(), 2);
+        assert!(handle.matches(&replies[0]));
+        assert_eq!(replies[0].custom_payload, Some(vec![1]));
+        assert_eq!(store.items, vec![Some(vec![2])]);
+        assert_eq!(
+            (store.flow.stats.put_wait, store.flow.stats.get_wait),
+            (3, 2)
+        );
+    }
+
+    #[test]
+    fn container_test() {
+        let mut tank = Container::new("tank", 10.0, 4.0);
+        let (drain, _) = Message::get(0, "pump", "tank", 6.0);
+        let (fill, _) = Message::put(2, "truck", "tank", 5.0, None);
+
+        tank.flow.gets.push_back(drain);
+        assert!(tank.flow(0).is_empty());
+        tank.flow.puts.push_back(fill);
+        assert_eq!(tank.flow(2).len(), 2);
+        assert_eq!(tank.level(), 3.0);
+
+        let stats = tank.flow.stats(4, tank.level(), 0);
+        assert_eq!((stats.puts, stats.gets, stats.get_wait), (1, 1, 2));
+        assert_eq!(stats.mean_level, (4.0 * 2.0 + 3.0 * 2.0) / 4.0);
+    }
+}
@@ -0,0 +1,105 @@
+//! Tags: roles of Agents, e.g. "barista" or "cashier", so Agents can find
+//! and message the ones with a role rather than knowing their ids.
+
+use crate::{DiscreteTime, Interrupt, Message, Simulation};
+
+impl Message {
+    /// Creates a message from src to every other Agent with the tag, as of
+    /// delivery. The engine sends a copy to each, in the order of the Agents,
+    /// in place of this message, so no such Agent means no message at all.
+    pub fn to_tag<S>(time: DiscreteTime, src: S, tag: S, payload: Option<Vec<u8>>) -> Message
+    where
+        S: Into<String>,
+    {
+        let src = src.into();
+        Message {
+            custom_payload: payload,
+            interrupt: Some(Interrupt::ToTag(tag.into())),
+            ..Message::new(time, src.as_str(), src.as_str())
+        }
+    }
+}
+
+impl Simulation {
+    /// Returns the ids of the Agents with the tag, in the order of the Agents.
+    pub fn agents_with_tag(&self, tag: &str) -> Vec<&str> {
+        self.agents
+            .iter()
+            .map(|a| a.state())
+            .filter(|s| s.options.tags.iter().any(|t| t == tag))
+            .map(|s| s.id.as_str())
+            .collect()
+    }
+
+    /// Returns the copies of a message to every Agent with its tag but its
+    /// source. None if it isn't a message to a tag.
+    pub(crate) fn expand_tag(&self, message: &Message) -> Option<Vec<Message>> {
+        let Some(Interrupt::ToTag(tag)) = &message.interrupt else {
+            return None;
+        };
+
+        let copies = self
+            .agents_with_tag(tag)
+            .into_iter()
+            .filter(|id| *id != message.source)
+            .map(|destination| Message {
+                destination: destination.to_string(),
+                interrupt: None,
+                ..message.clone()
+            })
+            .collect();
+        Some(copies)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use simul_macro::agent;
+
+    fn tagged(mut agent: Box<dyn Agent>, tag: &str) -> Box<dyn Agent> {
+        agent.state_mut().options.tags.push(tag.to_string());
+        agent
+    }
+
+    #[test]
+    fn to_tag_test() {
+        #[agent]
+        struct Customer {}
+
+        impl Agent for Customer {
+            fn process(&mut self, state: SimulationState, _: &Message) -> Option<Vec<Message>> {
+                self.state.mode = AgentMode::Dead;
+                Some(vec![Message::to_tag(
+                    state.time, "customer", "barista", None,
+                )])
+            }
+        }
+
+        let customer = Box::new(Customer {
+            state: AgentState {
+                mode: AgentMode::Proactive,
+                id: "customer".to_string(),
+                ..Default::default()
+            },
+        });
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                customer,
+                tagged(periodic_consuming_agent("alice", 1), "barista"),
+                tagged(periodic_consuming_agent("bob", 1), "cashier"),
+                tagged(periodic_consuming_agent("carol", 1), "barista"),
+            ],
+            halt_check: |s: &Simulation| s.time == 3,
+            ..Default::default()
+        });
+        simulation.run();
+
+        assert_eq!(simulation.agents_with_tag("barista"), ["alice", "carol"]);
+        assert!(simulation.agents_with_tag("manager").is_empty());
+        assert_eq!(simulation.consumed_count("alice"), Some(1));
+        assert_eq!(simulation.consumed_count("bob"), Some(0));
+        assert_eq!(simulation.consumed_count("carol"), Some(1));
+        assert!(simulation.message_ledger().is_balanced());
+    }
+}
@@ -0,0 +1,222 @@
+//! A time scale: the simulated duration of a tick, and optionally the
+//! timestamp of tick 0, so logs, plots and reports can show human-readable
+//! times, e.g. "2024-01-01 09:30:00" or "1d 02:00:00", rather than ticks.
+
+use crate::calendar::{civil_from_days, Calendar};
+use crate::{DiscreteTime, Simulation};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Maps ticks onto durations, and onto timestamps if it has an epoch.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct TimeScale {
+    /// The simulated duration of a tick.
+    pub tick: Duration,
+    /// The timestamp of tick 0, if any.
+    pub epoch: Option<SystemTime>,
+}
+
+impl TimeScale {
+    pub fn new(tick: Duration) -> TimeScale {
+        TimeScale { tick, epoch: None }
+    }
+
+    /// Starts tick 0 at the timestamp.
+    pub fn with_epoch(mut self, epoch: SystemTime) -> TimeScale {
+        self.epoch = Some(epoch);
+        self
+    }
+
+    /// The simulated duration of a number of ticks.
+    pub fn duration(&self, ticks: DiscreteTime) -> Duration {
+        let nanos = u64::from(self.tick.subsec_nanos()).saturating_mul(ticks);
+        let seconds = self.tick.as_secs().saturating_mul(ticks);
+        Duration::new(
+            seconds.saturating_add(nanos / 1_000_000_000),
+            (nanos % 1_000_000_000) as u32,
+        )
+    }
+
+    /// The number of whole ticks in a duration.
+    pub fn ticks(&self, duration: Duration) -> DiscreteTime {
+        if self.tick.is_zero() {
+            return 0;
+        }
+        (duration.as_nanos() / self.tick.as_nanos()) as DiscreteTime
+    }
+
+    /// The timestamp of a tick. None without an epoch.
+    pub fn timestamp(&self, time: DiscreteTime) -> Option<SystemTime> {
+        Some(self.epoch? + self.duration(time))
+    }
+
+    /// The tick a timestamp falls in. None without an epoch, or for a
+    /// timestamp before it.
+    pub fn time_at(&self, timestamp: SystemTime) -> Option<DiscreteTime> {
+        let since_epoch = timestamp.duration_since(self.epoch?).ok()?;
+        Some(self.ticks(since_epoch))
+    }
+
+    /// The calendar of this time scale, for recurring events. None without
+    /// an epoch, or with ticks that aren't whole seconds.
+    pub fn calendar(&self) -> Option<Calendar> {
+        if self.tick.subsec_nanos() != 0 || self.tick.is_zero() {
+            return None;
+        }
+        Some(Calendar {
+            epoch: unix_millis(self.epoch?).div_euclid(1000) as i64,
+            seconds_per_tick: self.tick.as_secs(),
+        })
+    }
+
+    /// Formats a tick as its UTC timestamp, e.g. "2024-01-01 09:30:00", or
+    /// without an epoch as its duration, e.g. "1d 02:00:00". Milliseconds
+    /// are shown with ticks shorter than a second.
+    pub fn format(&self, time: DiscreteTime) -> String {
+        let millis = self.tick.subsec_nanos() != 0 || self.tick.is_zero();
+        let (date, elapsed) = match self.epoch {
+            Some(epoch) => {
+                let timestamp = unix_millis(epoch) + self.duration(time).as_millis() as i128;
+                let days = timestamp.div_euclid(86_400_000) as i64;
+                let (year, month, day) = civil_from_days(days);
+                let date = format!("{:04}-{:02}-{:02} ", year, month, day);
+                (date, timestamp.rem_euclid(86_400_000) as u128)
+            }
+            None => {
+                let elapsed = self.duration(time).as_millis();
+                let days = elapsed / 86_400_000;
+                let date = if days > 0 {
+                    format!("{}d ", days)
+                } else {
+                    String::new()
+                };
+                (date, elapsed % 86_400_000)
+            }
+        };
+
+        let seconds = elapsed / 1000;
+        let mut formatted = format!(
+            "{}{:02}:{:02}:{:02}",
+            date,
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        );
+        if millis {
+            formatted.push_str(&format!(".{:03}", elapsed % 1000));
+        }
+        formatted
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl TimeScale {
+    /// The timestamp of a tick, in UTC. None without an epoch.
+    pub fn to_chrono(&self, time: DiscreteTime) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.timestamp(time).map(chrono::DateTime::from)
+    }
+
+    /// The tick a timestamp falls in. None without an epoch, or for a
+    /// timestamp before it.
+    pub fn from_chrono<Tz: chrono::TimeZone>(
+        &self,
+        timestamp: chrono::DateTime<Tz>,
+    ) -> Option<DiscreteTime> {
+        self.time_at(timestamp.into())
+    }
+}
+
+/// The milliseconds since 1970-01-01 00:00 of a timestamp.
+fn unix_millis(timestamp: SystemTime) -> i128 {
+    match timestamp.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_millis() as i128,
+        Err(before) => -(before.duration().as_millis() as i128),
+    }
+}
+
+impl Simulation {
+    /// Returns the time scale of the Simulation, if it has one.
+    pub fn time_scale(&self) -> Option<&TimeScale> {
+        self.time_scale.as_ref()
+    }
+
+    /// Formats a tick in the Simulation's time scale, or as the bare tick
+    /// without one.
+    pub fn format_time(&self, time: DiscreteTime) -> String {
+        match &self.time_scale {
+            Some(scale) => scale.format(time),
+            None => time.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn time_scale_test() {
+        let minutes = TimeScale::new(Duration::from_secs(60));
+        assert_eq!(minutes.duration(90), Duration::from_secs(5400));
+        assert_eq!(minutes.ticks(Duration::from_secs(5459)), 90);
+        assert_eq!(minutes.format(90), "01:30:00");
+        assert_eq!(minutes.format(24 * 60 + 1), "1d 00:01:00");
+        assert_eq!(minutes.timestamp(1), None);
+
+        // 2024-01-01 00:00 UTC.
+        let epoch = UNIX_EPOCH + Duration::from_secs(1_704_067_200);
+        let minutes = minutes.with_epoch(epoch);
+        assert_eq!(minutes.format(9 * 60 + 30), "2024-01-01 09:30:00");
+        let timestamp = minutes.timestamp(60).unwrap();
+        assert_eq!(
+            minutes.time_at(timestamp + Duration::from_secs(59)),
+            Some(60)
+        );
+        assert_eq!(minutes.time_at(UNIX_EPOCH), None);
+        assert_eq!(minutes.calendar(), Some(Calendar::starting(2024, 1, 1, 60)));
+
+        let millis = TimeScale::new(Duration::from_millis(250)).with_epoch(UNIX_EPOCH);
+        assert_eq!(millis.format(7), "1970-01-01 00:00:01.750");
+        assert_eq!(millis.calendar(), None);
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn chrono_test() {
+        use chrono::{FixedOffset, TimeZone, Utc};
+
+        let epoch = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let minutes = TimeScale::new(Duration::from_secs(60)).with_epoch(epoch.into());
+        let half_past_nine = Utc.with_ymd_and_hms(2024, 1, 1, 9, 30, 0).unwrap();
+        assert_eq!(minutes.to_chrono(9 * 60 + 30), Some(half_past_nine));
+        assert_eq!(minutes.from_chrono(half_past_nine), Some(9 * 60 + 30));
+
+        // The same instant in another time zone is the same tick.
+        let tokyo = FixedOffset::east_opt(9 * 3600).unwrap();
+        assert_eq!(
+            minutes.from_chrono(half_past_nine.with_timezone(&tokyo)),
+            Some(9 * 60 + 30)
+        );
+        assert_eq!(
+            minutes.from_chrono(epoch - chrono::Duration::seconds(1)),
+            None
+        );
+        assert_eq!(TimeScale::new(Duration::from_secs(60)).to_chrono(0), None);
+    }
+
+    #[test]
+    fn format_time_test() {
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![periodic_producing_agent("producer", 1, "consumer")],
+            time_scale: Some(TimeScale::new(Duration::from_secs(3600))),
+            halt_check: |s: &Simulation| s.time == 30,
+            ..Default::default()
+        });
+        simulation.run();
+
+        assert_eq!(simulation.format_time(simulation.time), "1d 06:00:00");
+        assert_eq!(simulation.report().time_label.unwrap(), "1d 06:00:00");
+        let simulation = Simulation::new(SimulationParameters::default());
+        assert_eq!(simulation.format_time(30), "30");
+    }
+}
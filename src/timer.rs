@@ -0,0 +1,311 @@
+//! Timers: messages an Agent schedules to itself at a future tick, e.g. a
+//! timeout or the next round of periodic work, rather than emulating them by
+//! sleeping and waking up.
+//!
+//! An Agent sends `Message::schedule_at` or `Message::schedule_after`, and
+//! the engine holds the message until its tick, then delivers it onto the
+//! Agent's own queue before any Agent processes. An Agent asleep at the time
+//! processes it once it wakes up.
+//!
+//! A repeating timer, set by `Message::set_interval`, delivers a copy of its
+//! message every period instead, until cancelled by its `TimerHandle` or its
+//! Agent dies.
+
+use crate::{next_correlation_id, AgentMode, DiscreteTime, Interrupt, Message, Simulation};
+
+/// A message on the engine's timer wheel.
+#[derive(Clone, Debug)]
+pub(crate) struct Timer {
+    message: Message,
+    /// The period of a repeating timer.
+    period: Option<DiscreteTime>,
+}
+
+/// Recognizes the messages of a repeating timer, and cancels it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct TimerHandle {
+    pub correlation_id: u64,
+}
+
+impl TimerHandle {
+    /// Whether the message was delivered by this timer.
+    pub fn matches(&self, msg: &Message) -> bool {
+        msg.correlation_id == Some(self.correlation_id)
+    }
+
+    /// Creates a message that cancels this timer, from the Agent that set it.
+    pub fn cancel<S>(&self, time: DiscreteTime, src: S) -> Message
+    where
+        S: Into<String>,
+    {
+        let src = src.into();
+        Message {
+            interrupt: Some(Interrupt::CancelTimer(self.correlation_id)),
+            ..Message::new(time, src.as_str(), src.as_str())
+        }
+    }
+}
+
+impl Message {
+    /// Creates a message that src receives back at the tick `at`, or next
+    /// tick if `at` isn't in the future.
+    pub fn schedule_at<S>(
+        time: DiscreteTime,
+        src: S,
+        at: DiscreteTime,
+        payload: Option<Vec<u8>>,
+    ) -> Message
+    where
+        S: Into<String>,
+    {
+        let src = src.into();
+        Message {
+            custom_payload: payload,
+            interrupt: Some(Interrupt::ScheduleAt(at)),
+            ..Message::new(time, src.as_str(), src.as_str())
+        }
+    }
+
+    /// Creates a message that src receives back `delay` ticks from now.
+    pub fn schedule_after<S>(
+        time: DiscreteTime,
+        src: S,
+        delay: DiscreteTime,
+        payload: Option<Vec<u8>>,
+    ) -> Message
+    where
+        S: Into<String>,
+    {
+        Message::schedule_at(time, src, time + delay, payload)
+    }
+
+    /// Creates a message that src receives back every `period` ticks from
+    /// now, until the returned TimerHandle cancels it.
+    pub fn set_interval<S>(
+        time: DiscreteTime,
+        src: S,
+        period: DiscreteTime,
+        payload: Option<Vec<u8>>,
+    ) -> (Message, TimerHandle)
+    where
+        S: Into<String>,
+    {
+        let handle = TimerHandle {
+            correlation_id: next_correlation_id(),
+        };
+        let src = src.into();
+        let message = Message {
+            custom_payload: payload,
+            correlation_id: Some(handle.correlation_id),
+            interrupt: Some(Interrupt::SetInterval(period)),
+            ..Message::new(time, src.as_str(), src.as_str())
+        };
+        (message, handle)
+    }
+}
+
+impl Simulation {
+    /// Returns the number of timers not fired yet, including repeating ones.
+    pub fn pending_timers(&self) -> usize {
+        self.timers.values().map(Vec::len).sum()
+    }
+
+    /// Returns the number of messages waiting on one-off timers.
+    pub(crate) fn scheduled_messages(&self) -> usize {
+        let timers = self.timers.values().flatten();
+        timers.filter(|t| t.period.is_none()).count()
+    }
+
+    /// Sets or cancels a timer. Returns whether the message was a timer's.
+    pub(crate) fn handle_timer_interrupt(&mut self, message: &mut Message) -> bool {
+        let (due, period) = match message.interrupt {
+            Some(Interrupt::ScheduleAt(at)) => (at.max(self.time + 1), None),
+            Some(Interrupt::SetInterval(period)) => {
+                let period = period.max(1);
+                (self.time + period, Some(period))
+            }
+            Some(Interrupt::CancelTimer(id)) => {
+                for timers in self.timers.values_mut() {
+                    timers.retain(|t| t.period.is_none() || t.message.correlation_id != Some(id));
+                }
+                self.timers.retain(|_, timers| !timers.is_empty());
+                self.ledger.to_resources += 1;
+                return true;
+            }
+            _ => return false,
+        };
+
+        // A repeating timer sends copies, so the message itself ends here.
+        if period.is_some() {
+            self.ledger.to_resources += 1;
+        }
+        let message = Message {
+            destination: message.source.clone(),
+            interrupt: None,
+            ..std::mem::take(message)
+        };
+        self.timers
+            .entry(due)
+            .or_default()
+            .push(Timer { message, period });
+        true
+    }
+
+    /// Delivers the timers due now, in the order they were set, and sets
+    /// repeating ones again.
+    pub(crate) fn fire_timers(&mut self) {
+        let mut repeats = vec![];
+        while let Some(entry) = self.timers.first_entry() {
+            if *entry.key() > self.time {
+                break;
+            }
+            for timer in entry.remove() {
+                let handle = self.agent_handles.get(&timer.message.destination).copied();
+                let message = match timer.period {
+                    None => timer.message,
+                    Some(_) if handle.map_or(true, |h| self.is_dead(h)) => continue,
+                    Some(period) => {
+                        self.ledger.produced += 1;
+                        repeats.push((self.time + period, timer.clone()));
+                        Message {
+                            queued_time: self.time,
+                            ..timer.message
+                        }
+                    }
+                };
+                match handle {
+                    Some(handle) => self.deliver(handle, message, false),
+                    None => self.ledger.unroutable += 1,
+                }
+            }
+        }
+        for (due, timer) in repeats {
+            self.timers.entry(due).or_default().push(timer);
+        }
+    }
+
+    fn is_dead(&self, handle: usize) -> bool {
+        self.agents[handle].state().mode == AgentMode::Dead
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use simul_macro::agent;
+
+    /// Retries a request every 3 ticks until it's been sent 3 times, with a
+    /// timeout of 5 ticks on the last.
+    fn retrier() -> Box<dyn Agent> {
+        #[agent]
+        struct Retrier {}
+
+        impl Agent for Retrier {
+            fn process(&mut self, state: SimulationState, msg: &Message) -> Option<Vec<Message>> {
+                self.state.consumed.push(Message {
+                    completed_time: Some(state.time),
+                    ..msg.clone()
+                });
+                let attempts = self.state.consumed.len();
+                let timer = match attempts {
+                    3 => Message::schedule_at(state.time, "retrier", state.time + 5, None),
+                    4 => return None,
+                    _ => Message::schedule_after(state.time, "retrier", 3, None),
+                };
+                Some(vec![timer])
+            }
+        }
+
+        let mut queue = std::collections::VecDeque::new();
+        queue.push_back(Message::new(0, "", "retrier"));
+        Box::new(Retrier {
+            state: AgentState {
+                mode: AgentMode::Reactive,
+                wake_mode: AgentMode::Reactive,
+                id: "retrier".to_string(),
+                queue,
+                ..Default::default()
+            },
+        })
+    }
+
+    /// Sets a timer every 2 ticks, and cancels it on its third message.
+    fn ticker() -> Box<dyn Agent> {
+        #[agent]
+        struct Ticker {
+            timer: Option<TimerHandle>,
+        }
+
+        impl Agent for Ticker {
+            fn process(&mut self, state: SimulationState, msg: &Message) -> Option<Vec<Message>> {
+                self.state.consumed.push(Message {
+                    completed_time: Some(state.time),
+                    ..msg.clone()
+                });
+                match &self.timer {
+                    None => {
+                        let (message, timer) =
+                            Message::set_interval(state.time, "ticker", 2, Some(vec![7]));
+                        self.timer = Some(timer);
+                        Some(vec![message])
+                    }
+                    Some(timer) if self.state.consumed.len() == 4 => {
+                        assert!(timer.matches(msg));
+                        Some(vec![timer.cancel(state.time, "ticker")])
+                    }
+                    Some(_) => None,
+                }
+            }
+        }
+
+        let mut queue = std::collections::VecDeque::new();
+        queue.push_back(Message::new(0, "", "ticker"));
+        Box::new(Ticker {
+            timer: None,
+            state: AgentState {
+                mode: AgentMode::Reactive,
+                wake_mode: AgentMode::Reactive,
+                id: "ticker".to_string(),
+                queue,
+                ..Default::default()
+            },
+        })
+    }
+
+    #[test]
+    fn interval_test() {
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![ticker()],
+            halt_check: |s: &Simulation| s.time == 20,
+            ..Default::default()
+        });
+        simulation.run();
+
+        let consumed = simulation.consumed_for_agent("ticker").unwrap();
+        let times: Vec<_> = consumed.iter().map(|m| m.completed_time.unwrap()).collect();
+        assert_eq!(times, [0, 2, 4, 6]);
+        assert_eq!(consumed[3].custom_payload, Some(vec![7]));
+        assert_eq!(simulation.pending_timers(), 0);
+        assert!(simulation.message_ledger().is_balanced());
+    }
+
+    #[test]
+    fn timer_test() {
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![retrier()],
+            halt_check: |s: &Simulation| s.time == 20,
+            ..Default::default()
+        });
+        simulation.run();
+
+        let times: Vec<_> = simulation
+            .consumed_for_agent("retrier")
+            .unwrap()
+            .iter()
+            .map(|m| m.completed_time.unwrap())
+            .collect();
+        assert_eq!(times, [0, 3, 6, 11]);
+        assert_eq!(simulation.pending_timers(), 0);
+        assert!(simulation.message_ledger().is_balanced());
+    }
+}
@@ -0,0 +1,262 @@
+use crate::{DiscreteTime, Message, Simulation};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write as _;
+
+/// A change to the link from one Agent to another: it goes up or down.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct LinkChange {
+    /// When the change happens, at the start of the tick.
+    pub time: DiscreteTime,
+    pub source: String,
+    pub destination: String,
+    /// Whether the link comes up (true) or goes down (false).
+    pub up: bool,
+}
+
+/// The properties of the edge from one Agent to another, e.g. a network
+/// link or a shipping lane.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Edge {
+    /// The ticks a message takes to cross the edge.
+    pub latency: DiscreteTime,
+    /// How many messages the edge carries per tick, at least 1; the rest wait
+    /// for the next ticks, in the order they were sent. None is unlimited.
+    pub capacity: Option<usize>,
+}
+
+/// The connectivity between Agents, which can change over time, e.g. road
+/// closures or network partitions. Every link is up unless taken down, and
+/// messages sent over a link that is down are dropped.
+///
+/// Links can be edges with a latency and a capacity, which delay the messages
+/// over them. With `with_edges_only`, the edges are the only links, making
+/// the Topology a graph of which Agents are connected.
+#[derive(Clone, Debug, Default)]
+pub struct Topology {
+    /// The (source, destination) links that are currently down.
+    pub down: HashSet<(String, String)>,
+    /// The link changes to apply while the Simulation runs.
+    pub schedule: Vec<LinkChange>,
+    /// The (source, destination) edges, with their properties.
+    pub edges: HashMap<(String, String), Edge>,
+    /// Whether Agents without an edge between them aren't linked.
+    pub edges_only: bool,
+}
+
+impl Topology {
+    /// Takes the link from source to destination down from the start.
+    pub fn with_link_down<S>(mut self, source: S, destination: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.down.insert((source.into(), destination.into()));
+        self
+    }
+
+    /// Schedules the link from source to destination to come up or go down at `time`.
+    pub fn with_link_change<S>(
+        mut self,
+        time: DiscreteTime,
+        source: S,
+        destination: S,
+        up: bool,
+    ) -> Self
+    where
+        S: Into<String>,
+    {
+        self.schedule.push(LinkChange {
+            time,
+            source: source.into(),
+            destination: destination.into(),
+            up,
+        });
+        self
+    }
+
+    /// Schedules a partition between two groups of Agents: from `from` until
+    /// `until`, every link between the groups is down in both directions.
+    pub fn with_partition(
+        mut self,
+        from: DiscreteTime,
+        until: DiscreteTime,
+        group_a: &[&str],
+        group_b: &[&str],
+    ) -> Self {
+        for a in group_a {
+            for b in group_b {
+                for (source, destination) in [(a, b), (b, a)] {
+                    self = self
+                        .with_link_change(from, *source, *destination, false)
+                        .with_link_change(until, *source, *destination, true);
+                }
+            }
+        }
+        self
+    }
+
+    /// Adds the edge from source to destination.
+    pub fn with_edge<S>(mut self, source: S, destination: S, edge: Edge) -> Self
+    where
+        S: Into<String>,
+    {
+        self.edges.insert((source.into(), destination.into()), edge);
+        self
+    }
+
+    /// Adds the edges between two Agents in both directions.
+    pub fn with_undirected_edge<S>(self, a: S, b: S, edge: Edge) -> Self
+    where
+        S: Into<String>,
+    {
+        let (a, b) = (a.into(), b.into());
+        self.with_edge(a.clone(), b.clone(), edge)
+            .with_edge(b, a, edge)
+    }
+
+    /// Only links the Agents with an edge between them.
+    pub fn with_edges_only(self) -> Self {
+        Topology {
+            edges_only: true,
+            ..self
+        }
+    }
+
+    /// Returns the edge from source to destination.
+    pub fn edge(&self, source: &str, destination: &str) -> Option<&Edge> {
+        if self.edges.is_empty() {
+            return None;
+        }
+        self.edges
+            .get(&(source.to_string(), destination.to_string()))
+    }
+
+    /// Returns whether the link from source to destination is up.
+    pub fn is_linked(&self, source: &str, destination: &str) -> bool {
+        if self.edges_only && self.edge(source, destination).is_none() {
+            return false;
+        }
+        self.down.is_empty()
+            || !self
+                .down
+                .contains(&(source.to_string(), destination.to_string()))
+    }
+
+    fn apply(&mut self, change: &LinkChange) {
+        let link = (change.source.clone(), change.destination.clone());
+        if change.up {
+            self.down.remove(&link);
+        } else {
+            self.down.insert(link);
+        }
+    }
+}
+
+impl Simulation {
+    /// Takes the link from source to destination up or down as of now, and
+    /// records the change.
+    pub fn set_link(&mut self, source: &str, destination: &str, up: bool) {
+        let change = LinkChange {
+            time: self.time,
+            source: source.to_string(),
+            destination: destination.to_string(),
+            up,
+        };
+        self.topology.apply(&change);
+        self.topology_events.push(change);
+    }
+
+    /// Returns every link change that happened while the Simulation ran, in order.
+    pub fn topology_events(&self) -> &[LinkChange] {
+        &self.topology_events
+    }
+
+    /// Renders the Agents and the messages that flowed between them as a
+    /// Graphviz DOT graph, for reviewing a model's structure, e.g. with
+    /// `dot -Tsvg`. Every edge is labelled and weighted by the number of
+    /// messages its destination consumed from its source so far. Edges of
+    /// the Topology no message crossed are dashed.
+    pub fn topology_dot(&self) -> String {
+        let mut flows: BTreeMap<(&str, &str), usize> = BTreeMap::new();
+        for agent in self.agents.iter() {
+            let state = agent.state();
+            for message in state.consumed.iter() {
+                if self.agent_handles.contains_key(&message.source) {
+                    *flows.entry((&message.source, &state.id)).or_default() += 1;
+                }
+            }
+        }
+        let mut edges: Vec<&(String, String)> = self.topology.edges.keys().collect();
+        edges.sort();
+
+        let mut dot = "digraph simulation {\n".to_string();
+        for agent in self.agents.iter() {
+            let _ = writeln!(dot, "  {:?};", agent.state().id);
+        }
+        for ((source, destination), count) in flows.iter() {
+            let _ = writeln!(
+                dot,
+                "  {:?} -> {:?} [label=\"{}\", weight={}];",
+                source, destination, count, count
+            );
+        }
+        for (source, destination) in edges {
+            if !flows.contains_key(&(source.as_str(), destination.as_str())) {
+                let _ = writeln!(dot, "  {:?} -> {:?} [style=dashed];", source, destination);
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Returns the ticks a message waits for room on its edge and then takes
+    /// to cross it, and takes up its room. 0 if it's not sent over an edge.
+    pub(crate) fn edge_delay(&mut self, message: &Message) -> DiscreteTime {
+        let Some(edge) = self
+            .topology
+            .edge(&message.source, &message.destination)
+            .copied()
+        else {
+            return 0;
+        };
+        let Some(capacity) = edge.capacity else {
+            return edge.latency;
+        };
+
+        let link = (message.source.clone(), message.destination.clone());
+        let (departure, departing) = self.edge_load.entry(link.clone()).or_insert((self.time, 0));
+        if *departure < self.time {
+            (*departure, *departing) = (self.time, 0);
+        }
+        if *departing >= capacity.max(1) {
+            (*departure, *departing) = (*departure + 1, 0);
+        }
+        *departing += 1;
+
+        let waited = *departure - self.time;
+        if waited > 0 {
+            self.channel_metrics.entry(link).or_default().congested += 1;
+        }
+        waited + edge.latency
+    }
+
+    /// Applies the scheduled link changes that are due by now.
+    pub(crate) fn apply_scheduled_link_changes(&mut self) {
+        if self.topology.schedule.is_empty() {
+            return;
+        }
+
+        let mut due: Vec<LinkChange> = self
+            .topology
+            .schedule
+            .iter()
+            .filter(|c| c.time <= self.time)
+            .cloned()
+            .collect();
+        self.topology.schedule.retain(|c| c.time > self.time);
+        due.sort_by_key(|c| c.time);
+
+        for change in due {
+            self.set_link(&change.source, &change.destination, change.up);
+        }
+    }
+}
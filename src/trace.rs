@@ -0,0 +1,144 @@
+//! A versioned, machine-readable trace of a Simulation run, for
+//! cross-validating a model against other simulators, e.g. a SimPy
+//! implementation of the same model.
+//!
+//! Enable it with `SimulationParameters::enable_trace`, and export it with
+//! `Simulation::trace_json()`. Version 1 of the format is one JSON object:
+//!
+//! ```text
+//! {
+//!   "format": "simul-trace",
+//!   "version": 1,
+//!   "events": [
+//!     {
+//!       "time": <tick>,
+//!       "event": "arrival" | "service_start" | "service_end",
+//!       "agent": <id of the Agent the event happened at>,
+//!       "source": <id of the Agent that sent the message>,
+//!       "queued_time": <tick the message was sent>,
+//!       "queue_length": <length of the Agent's queue after the event>
+//!     },
+//!     ...
+//!   ]
+//! }
+//! ```
+//!
+//! Events are ordered by time. Within a tick, services start and end before
+//! the messages sent in that tick arrive. A message is identified by its
+//! (source, queued_time, agent). `service_end` events come from the messages
+//! Agents consumed, and have no `queue_length`.
+
+use crate::{json, DiscreteTime, Message, Simulation};
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// The version of the trace format `trace_json` writes.
+pub const TRACE_FORMAT_VERSION: u32 = 1;
+
+/// What happened to a message at an Agent.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum TraceEventKind {
+    /// The Agent started processing the message.
+    ServiceStart,
+    /// The Agent finished processing the message.
+    ServiceEnd,
+    /// The message was put on the Agent's queue.
+    Arrival,
+}
+
+/// One event of a run trace.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct TraceEvent {
+    pub time: DiscreteTime,
+    pub kind: TraceEventKind,
+    pub agent: String,
+    pub source: String,
+    pub queued_time: DiscreteTime,
+    /// The length of the Agent's queue after the event, if known.
+    pub queue_length: Option<usize>,
+}
+
+impl TraceEvent {
+    pub(crate) fn new(
+        time: DiscreteTime,
+        kind: TraceEventKind,
+        agent: &str,
+        message: &Message,
+        queue_length: Option<usize>,
+    ) -> TraceEvent {
+        TraceEvent {
+            time,
+            kind,
+            agent: agent.to_string(),
+            source: message.source.clone(),
+            queued_time: message.queued_time,
+            queue_length,
+        }
+    }
+}
+
+impl Simulation {
+    /// Returns every event of the run trace, ordered by time. Empty unless
+    /// `enable_trace` was set.
+    pub fn trace(&self) -> Vec<TraceEvent> {
+        if !self.enable_trace {
+            return vec![];
+        }
+
+        let mut events: Vec<TraceEvent> = self.trace_arrivals.clone();
+        for (agent, metadata) in self.agents.iter().zip(self.agent_metadata.iter()) {
+            events.extend(metadata.trace.iter().cloned());
+            events.extend(agent.state().consumed.iter().filter_map(|m| {
+                Some(TraceEvent::new(
+                    m.completed_time?,
+                    TraceEventKind::ServiceEnd,
+                    &agent.state().id,
+                    m,
+                    None,
+                ))
+            }));
+        }
+
+        events.sort_by_key(|e| (e.time, e.kind));
+        events
+    }
+
+    /// Renders the run trace in the JSON format documented in this module.
+    pub fn trace_json(&self) -> String {
+        let mut json = format!(
+            "{{\"format\":\"simul-trace\",\"version\":{},\"events\":[",
+            TRACE_FORMAT_VERSION
+        );
+
+        for (i, event) in self.trace().iter().enumerate() {
+            let kind = match event.kind {
+                TraceEventKind::Arrival => "arrival",
+                TraceEventKind::ServiceStart => "service_start",
+                TraceEventKind::ServiceEnd => "service_end",
+            };
+
+            let _ = write!(
+                json,
+                "{}{{\"time\":{},\"event\":\"{}\",\"agent\":{},\"source\":{},\"queued_time\":{}",
+                if i > 0 { "," } else { "" },
+                event.time,
+                kind,
+                json::string(&event.agent),
+                json::string(&event.source),
+                event.queued_time
+            );
+            if let Some(length) = event.queue_length {
+                let _ = write!(json, ",\"queue_length\":{}", length);
+            }
+            json.push('}');
+        }
+
+        json.push_str("]}");
+        json
+    }
+
+    /// Writes the JSON run trace to `path`.
+    pub fn write_trace<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        std::fs::write(path, self.trace_json())
+    }
+}
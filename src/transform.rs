@@ -0,0 +1,148 @@
+use crate::{DiscreteTime, Message, Simulation};
+use dyn_clone::DynClone;
+use std::collections::BTreeMap;
+
+/// A MessageTransform models the overhead of a middleware layer, e.g.
+/// encryption or compression, on every hop of every message. The engine
+/// applies it to each message it delivers, and delays the delivery by the
+/// transform's cost in ticks.
+pub trait MessageTransform: std::fmt::Debug + DynClone + Send {
+    /// The name the transform's cost is attributed to, e.g. "encryption".
+    fn name(&self) -> &'static str;
+
+    /// Transforms a message on one hop, returning the ticks it cost.
+    fn apply(&mut self, message: &mut Message) -> DiscreteTime;
+}
+
+dyn_clone::clone_trait_object!(MessageTransform);
+
+/// Costs a number of ticks per started kilobyte of `custom_payload`, e.g. +2
+/// ticks per KB for encryption. Leaves the message as is.
+#[derive(Clone, Debug)]
+pub struct PayloadSizeCost {
+    pub name: &'static str,
+    pub ticks_per_kilobyte: DiscreteTime,
+}
+
+impl MessageTransform for PayloadSizeCost {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn apply(&mut self, message: &mut Message) -> DiscreteTime {
+        let bytes = message.custom_payload.as_ref().map_or(0, Vec::len) as DiscreteTime;
+        (bytes + 1023) / 1024 * self.ticks_per_kilobyte
+    }
+}
+
+/// Delays every message by a fixed number of ticks on every hop, e.g. to
+/// model network latency.
+#[derive(Clone, Debug)]
+pub struct FixedLatency {
+    pub name: &'static str,
+    pub ticks: DiscreteTime,
+}
+
+impl MessageTransform for FixedLatency {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn apply(&mut self, _message: &mut Message) -> DiscreteTime {
+        self.ticks
+    }
+}
+
+/// Where the latency of the messages an Agent consumed went: the ticks spent
+/// in message transforms, and the rest, spent waiting in queues.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LatencyDecomposition {
+    /// The number of consumed messages decomposed.
+    pub messages: usize,
+    /// The mean of `completed_time - queued_time`.
+    pub mean_latency: f64,
+    /// The mean latency not spent in transforms.
+    pub mean_queueing: f64,
+    /// Maps from a transform's name => the mean ticks spent in it.
+    pub mean_transforms: BTreeMap<&'static str, f64>,
+}
+
+/// A message delayed by the cost of its transforms.
+#[derive(Clone, Debug)]
+pub(crate) struct InFlightMessage {
+    pub due: DiscreteTime,
+    pub handle: usize,
+    pub message: Message,
+    pub reorder: bool,
+}
+
+impl Simulation {
+    /// Decomposes the latency of the messages the Agent consumed.
+    pub fn latency_decomposition(&self, id: &str) -> Option<LatencyDecomposition> {
+        let agent = self.agents.iter().find(|a| a.state().id == id)?;
+        let consumed: Vec<&Message> = agent
+            .state()
+            .consumed
+            .iter()
+            .filter(|m| m.completed_time.is_some())
+            .collect();
+        if consumed.is_empty() {
+            return Some(LatencyDecomposition::default());
+        }
+
+        let n = consumed.len() as f64;
+        let mut transform_totals: BTreeMap<&'static str, DiscreteTime> = BTreeMap::new();
+        let mut total_latency = 0;
+        let mut total_transforms = 0;
+
+        for msg in consumed.iter() {
+            total_latency += msg.completed_time? - msg.queued_time;
+            for (name, cost) in msg.transform_costs.iter() {
+                *transform_totals.entry(*name).or_default() += cost;
+                total_transforms += cost;
+            }
+        }
+
+        Some(LatencyDecomposition {
+            messages: consumed.len(),
+            mean_latency: total_latency as f64 / n,
+            mean_queueing: total_latency.saturating_sub(total_transforms) as f64 / n,
+            mean_transforms: transform_totals
+                .into_iter()
+                .map(|(name, total)| (name, total as f64 / n))
+                .collect(),
+        })
+    }
+
+    /// Applies every transform to a message for one hop, recording their costs
+    /// on it. Returns the total cost in ticks.
+    pub(crate) fn apply_message_transforms(&mut self, message: &mut Message) -> DiscreteTime {
+        let mut total = 0;
+        for transform in self.message_transforms.iter_mut() {
+            let cost = transform.apply(message);
+            if cost > 0 {
+                message.transform_costs.push((transform.name(), cost));
+                total += cost;
+            }
+        }
+        total
+    }
+
+    /// Delivers the in-flight messages that are due by now. Returns how many.
+    pub(crate) fn deliver_due_in_flight_messages(&mut self) -> usize {
+        if self.in_flight.is_empty() {
+            return 0;
+        }
+
+        let (due, in_flight): (Vec<_>, Vec<_>) = std::mem::take(&mut self.in_flight)
+            .into_iter()
+            .partition(|m| m.due <= self.time);
+        self.in_flight = in_flight;
+
+        let delivered = due.len();
+        for m in due {
+            self.deliver(m.handle, m.message, m.reorder);
+        }
+        delivered
+    }
+}
@@ -0,0 +1,278 @@
+//! Closed-form results of classic queueing models, to validate the engine and
+//! models built with it against.
+//!
+//! `validate_mm1` and `validate_mmc` run the Simulation of a model with
+//! Poisson arrivals and exponential service, built from the Agents of
+//! `network`, and check its queue statistics against the formulas. Rates are
+//! per tick; the engine is discrete, so statistics carry an error of about a
+//! tick, which is small when rates are small, i.e. when a tick is a small
+//! unit of time.
+
+use crate::network::{exponential_station, poisson_arrivals};
+use crate::{
+    Agent, AgentMode, AgentState, DiscreteTime, Interrupt, Message, Simulation,
+    SimulationParameters, SimulationState,
+};
+use simul_macro::agent;
+
+/// An M/M/1 queue: Poisson arrivals and one server with exponential service.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MM1 {
+    /// λ, in messages per tick.
+    pub arrival_rate: f64,
+    /// μ, in messages per tick.
+    pub service_rate: f64,
+}
+
+impl MM1 {
+    /// ρ = λ/μ, the fraction of time the server is busy.
+    pub fn utilization(&self) -> f64 {
+        self.arrival_rate / self.service_rate
+    }
+
+    /// Lq = ρ²/(1 - ρ), the mean number of messages waiting.
+    pub fn mean_queue_length(&self) -> f64 {
+        let rho = self.utilization();
+        rho * rho / (1.0 - rho)
+    }
+
+    /// Wq = ρ/(μ - λ), the mean time a message waits before service.
+    pub fn mean_wait(&self) -> f64 {
+        self.utilization() / (self.service_rate - self.arrival_rate)
+    }
+
+    /// W = 1/(μ - λ), the mean time a message spends waiting and in service.
+    pub fn mean_response_time(&self) -> f64 {
+        1.0 / (self.service_rate - self.arrival_rate)
+    }
+
+    /// L = ρ/(1 - ρ), the mean number of messages waiting or in service.
+    pub fn mean_number_in_system(&self) -> f64 {
+        let rho = self.utilization();
+        rho / (1.0 - rho)
+    }
+}
+
+/// An M/M/c queue: Poisson arrivals and c identical servers with exponential
+/// service, sharing one queue.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MMc {
+    /// λ, in messages per tick.
+    pub arrival_rate: f64,
+    /// μ of every server, in messages per tick.
+    pub service_rate: f64,
+    pub servers: usize,
+}
+
+impl From<MM1> for MMc {
+    fn from(model: MM1) -> MMc {
+        MMc {
+            arrival_rate: model.arrival_rate,
+            service_rate: model.service_rate,
+            servers: 1,
+        }
+    }
+}
+
+impl MMc {
+    /// ρ = λ/(cμ), the fraction of time each server is busy.
+    pub fn utilization(&self) -> f64 {
+        self.arrival_rate / (self.servers as f64 * self.service_rate)
+    }
+
+    /// The probability that an arriving message waits, by Erlang's C formula.
+    pub fn probability_of_waiting(&self) -> f64 {
+        let offered = self.arrival_rate / self.service_rate;
+        let mut term = 1.0;
+        let mut sum = 0.0;
+        for k in 0..self.servers {
+            sum += term;
+            term *= offered / (k + 1) as f64;
+        }
+        let waiting = term / (1.0 - self.utilization());
+        waiting / (sum + waiting)
+    }
+
+    /// Lq = C ρ/(1 - ρ), the mean number of messages waiting.
+    pub fn mean_queue_length(&self) -> f64 {
+        let rho = self.utilization();
+        self.probability_of_waiting() * rho / (1.0 - rho)
+    }
+
+    /// Wq = Lq/λ, the mean time a message waits before service.
+    pub fn mean_wait(&self) -> f64 {
+        self.mean_queue_length() / self.arrival_rate
+    }
+
+    /// W = Wq + 1/μ, the mean time a message spends waiting and in service.
+    pub fn mean_response_time(&self) -> f64 {
+        self.mean_wait() + 1.0 / self.service_rate
+    }
+
+    /// L = λW, the mean number of messages waiting or in service.
+    pub fn mean_number_in_system(&self) -> f64 {
+        self.arrival_rate * self.mean_response_time()
+    }
+}
+
+/// The mean queue length and wait of a queue, predicted or observed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QueueStatistics {
+    /// Lq, in messages.
+    pub mean_queue_length: f64,
+    /// Wq, in ticks.
+    pub mean_wait: f64,
+}
+
+/// The statistics a model predicts and those a Simulation of it observed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Validation {
+    pub expected: QueueStatistics,
+    pub observed: QueueStatistics,
+}
+
+impl Validation {
+    /// The larger relative error of the observed mean queue length and wait.
+    pub fn relative_error(&self) -> f64 {
+        let error = |expected: f64, observed: f64| (observed - expected).abs() / expected;
+        error(
+            self.expected.mean_queue_length,
+            self.observed.mean_queue_length,
+        )
+        .max(error(self.expected.mean_wait, self.observed.mean_wait))
+    }
+
+    /// Whether the observed statistics are within `tolerance`, relative to
+    /// the expected ones, e.g. 0.1 for 10%.
+    pub fn is_within(&self, tolerance: f64) -> bool {
+        self.relative_error() <= tolerance
+    }
+}
+
+/// Returns an Agent that halts the Simulation at the given time.
+fn horizon(time: DiscreteTime) -> Box<dyn Agent> {
+    #[agent]
+    struct Horizon {}
+
+    impl Agent for Horizon {
+        fn process(&mut self, _: SimulationState, _: &Message) -> Option<Vec<Message>> {
+            Some(vec![Message {
+                source: self.state.id.clone(),
+                interrupt: Some(Interrupt::HaltSimulation("horizon".to_string())),
+                ..Default::default()
+            }])
+        }
+    }
+
+    Box::new(Horizon {
+        state: AgentState {
+            mode: AgentMode::AsleepUntil(time),
+            wake_mode: AgentMode::Proactive,
+            id: "horizon".to_string(),
+            ..Default::default()
+        },
+    })
+}
+
+/// Runs an M/M/1 model for `ticks` ticks and checks it; see `validate_mmc`.
+pub fn validate_mm1(model: &MM1, ticks: DiscreteTime, seed: u64, tolerance: f64) -> Validation {
+    validate_mmc(&MMc::from(*model), ticks, seed, tolerance)
+}
+
+/// Runs an M/M/c model for `ticks` ticks, excluding the first tenth as
+/// warm-up, and asserts its mean queue length and wait are within `tolerance`
+/// of the closed-form ones, relative to them. Returns the comparison.
+///
+/// # Panics
+///
+/// If the model is unstable (ρ ≥ 1), or the statistics are out of tolerance.
+pub fn validate_mmc(model: &MMc, ticks: DiscreteTime, seed: u64, tolerance: f64) -> Validation {
+    assert!(
+        model.utilization() < 1.0,
+        "an M/M/c queue with ρ ≥ 1 has no steady state: {:?}",
+        model
+    );
+
+    let mut simulation = Simulation::new(SimulationParameters {
+        agents: vec![
+            poisson_arrivals("arrivals", model.arrival_rate, "station"),
+            exponential_station("station", model.servers, model.service_rate, vec![]),
+            horizon(ticks),
+        ],
+        enable_queue_depth_metrics: true,
+        halt_check: |_| false,
+        seed: Some(seed),
+        ..Default::default()
+    });
+    simulation.warm_up = Some(ticks / 10);
+    simulation.run();
+
+    let observed = simulation
+        .littles_law("station")
+        .expect("the station records its queue depths");
+    let validation = Validation {
+        expected: QueueStatistics {
+            mean_queue_length: model.mean_queue_length(),
+            mean_wait: model.mean_wait(),
+        },
+        observed: QueueStatistics {
+            mean_queue_length: observed.mean_queue_length,
+            mean_wait: observed.mean_wait,
+        },
+    };
+
+    assert!(
+        validation.is_within(tolerance),
+        "{:?} is out of tolerance {}: {:?}",
+        model,
+        tolerance,
+        validation
+    );
+    validation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formulas_test() {
+        let mm1 = MM1 {
+            arrival_rate: 0.5,
+            service_rate: 1.0,
+        };
+        assert_eq!(mm1.mean_queue_length(), 0.5);
+        assert_eq!(mm1.mean_wait(), 1.0);
+        assert_eq!(mm1.mean_number_in_system(), 1.0);
+
+        let mmc = MMc::from(mm1);
+        assert!((mmc.probability_of_waiting() - mm1.utilization()).abs() < 1e-12);
+        assert!((mmc.mean_wait() - mm1.mean_wait()).abs() < 1e-12);
+        assert!((mmc.mean_response_time() - mm1.mean_response_time()).abs() < 1e-12);
+
+        // Two servers at ρ = 0.5: C = 1/3, Lq = 1/3.
+        let mm2 = MMc {
+            arrival_rate: 1.0,
+            service_rate: 1.0,
+            servers: 2,
+        };
+        assert!((mm2.probability_of_waiting() - 1.0 / 3.0).abs() < 1e-12);
+        assert!((mm2.mean_queue_length() - 1.0 / 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn validate_test() {
+        let mm1 = MM1 {
+            arrival_rate: 0.005,
+            service_rate: 0.01,
+        };
+        validate_mm1(&mm1, 2_000_000, 7, 0.1);
+
+        let mm2 = MMc {
+            arrival_rate: 0.015,
+            service_rate: 0.01,
+            servers: 2,
+        };
+        validate_mmc(&mm2, 2_000_000, 7, 0.1);
+    }
+}
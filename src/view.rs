@@ -0,0 +1,122 @@
+//! Read-only views of the other Agents, so Agents can make informed
+//! decisions, e.g. join the shortest queue, without asking by message.
+//!
+//! With `enable_agent_views`, the engine snapshots every Agent at the start
+//! of each tick, before any of them processes, i.e. as the previous tick
+//! left them. Agents peek at the snapshot in their SimulationState.
+
+use crate::{AgentMode, Simulation, SimulationState};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// What other Agents can see of an Agent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct AgentView {
+    pub mode: AgentMode,
+    pub queue_len: usize,
+    pub consumed: usize,
+    pub produced: usize,
+}
+
+/// The views of every Agent, by id.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AgentViews {
+    views: HashMap<String, AgentView>,
+}
+
+impl AgentViews {
+    pub fn get(&self, id: &str) -> Option<&AgentView> {
+        self.views.get(id)
+    }
+}
+
+impl SimulationState {
+    /// The view of an Agent as of the start of this tick. None if the Agent
+    /// doesn't exist, or agent views aren't enabled.
+    pub fn peek_agent(&self, id: &str) -> Option<&AgentView> {
+        self.agent_views.as_ref()?.get(id)
+    }
+}
+
+impl Simulation {
+    /// Snapshots every Agent for this tick, reusing the previous snapshot.
+    pub(crate) fn refresh_agent_views(&mut self) {
+        let views = Arc::make_mut(self.agent_views.get_or_insert_with(Default::default));
+        for agent in &self.agents {
+            let state = agent.state();
+            let view = AgentView {
+                mode: state.mode,
+                queue_len: state.queue.len(),
+                consumed: state.consumed.len(),
+                produced: state.produced.len(),
+            };
+            match views.views.get_mut(&state.id) {
+                Some(v) => *v = view,
+                None => {
+                    views.views.insert(state.id.clone(), view);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use simul_macro::agent;
+
+    /// Forwards every message to whichever target had the shortest queue.
+    fn informed_router(targets: &[&str]) -> Box<dyn Agent> {
+        #[agent]
+        struct InformedRouter {
+            targets: Vec<String>,
+        }
+
+        impl Agent for InformedRouter {
+            fn process(&mut self, state: SimulationState, msg: &Message) -> Option<Vec<Message>> {
+                assert!(state.peek_agent("nobody").is_none());
+                let target = self
+                    .targets
+                    .iter()
+                    .min_by_key(|t| state.peek_agent(t).map_or(usize::MAX, |v| v.queue_len))?;
+                Some(vec![Message {
+                    custom_payload: msg.custom_payload.clone(),
+                    ..Message::new(state.time, "router", target.as_str())
+                }])
+            }
+        }
+
+        Box::new(InformedRouter {
+            targets: targets.iter().map(|t| t.to_string()).collect(),
+            state: AgentState {
+                mode: AgentMode::Reactive,
+                wake_mode: AgentMode::Reactive,
+                id: "router".to_string(),
+                ..Default::default()
+            },
+        })
+    }
+
+    #[test]
+    fn peek_agent_test() {
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer", 1, "router"),
+                informed_router(&["slow", "fast"]),
+                periodic_consuming_agent("slow", 4),
+                periodic_consuming_agent("fast", 1),
+            ],
+            enable_agent_views: true,
+            halt_check: |s: &Simulation| s.time == 100,
+            ..Default::default()
+        });
+        simulation.run();
+
+        // The router keeps both queues short, sending each its share.
+        let slow = simulation.consumed_count("slow").unwrap();
+        let fast = simulation.consumed_count("fast").unwrap();
+        assert!(simulation.queue_len("slow").unwrap() <= 2);
+        assert!(simulation.queue_len("fast").unwrap() <= 2);
+        assert!(fast > 2 * slow, "{} <= 2 * {}", fast, slow);
+    }
+}
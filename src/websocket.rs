@@ -0,0 +1,520 @@
+//! Streams the events of a running Simulation over WebSocket, as JSON text
+//! messages, so dashboards and browser visualizations can watch it live.
+//! Requires the `websocket` feature.
+//!
+//! The events are:
+//! - `{"event":"tick","time":3}` at the start of every tick,
+//! - `{"event":"delivered","time":3,"source":"a","destination":"b"}` on
+//!   every message delivered to a queue, and
+//! - `{"event":"mode","time":3,"agent":"b","mode":"Reactive"}` at the end of
+//!   every tick an Agent's mode changed in.
+
+use crate::{json, AgentMode, DiscreteTime, Message, Simulation, SimulationObserver};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write as _};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+
+/// The GUID every WebSocket handshake hashes the client's key with.
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// How long a client may take to send its handshake, or to take a frame
+/// before it's dropped as too slow.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The longest frame read from a client; longer ones drop it.
+const MAX_FRAME: u64 = 64 * 1024;
+
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+/// A connected client. Dropping it closes the connection, which also ends
+/// the thread reading from it.
+#[derive(Debug)]
+struct Client {
+    id: u64,
+    stream: TcpStream,
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+/// A SimulationObserver that sends every event to the WebSocket clients
+/// connected to its address; see the module docs. Clients that connect late
+/// miss the events before, and clients that disconnect, close, or don't take
+/// a frame within 5 seconds are dropped. Pings are answered.
+#[derive(Clone, Debug)]
+pub struct WebSocketStreamer {
+    address: SocketAddr,
+    clients: Arc<Mutex<Vec<Client>>>,
+    /// The mode of every Agent at the end of the last tick.
+    modes: HashMap<String, AgentMode>,
+}
+
+impl WebSocketStreamer {
+    /// Binds the address and accepts clients from a background thread. Bind
+    /// port 0 to pick any free port.
+    pub fn bind<A: ToSocketAddrs>(address: A) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let streamer = WebSocketStreamer {
+            address: listener.local_addr()?,
+            clients: Arc::new(Mutex::new(vec![])),
+            modes: HashMap::new(),
+        };
+
+        // Only the streamer and its copies hold the clients, so they're
+        // disconnected once the Simulation is dropped.
+        let clients = Arc::downgrade(&streamer.clients);
+        std::thread::spawn(move || {
+            for (id, stream) in (0..).zip(listener.incoming().flatten()) {
+                if clients.strong_count() == 0 {
+                    break;
+                }
+                // Every client has its own thread, so a slow handshake
+                // holds up no other.
+                let clients = clients.clone();
+                std::thread::spawn(move || {
+                    if let Err(e) = accept(id, stream, &clients) {
+                        log::warn!("Failed a WebSocket handshake: {}", e);
+                    }
+                });
+            }
+        });
+
+        Ok(streamer)
+    }
+
+    /// The address clients connect to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// The number of clients connected.
+    pub fn clients(&self) -> usize {
+        self.clients.lock().map_or(0, |c| c.len())
+    }
+
+    /// Sends an event to every client, dropping those that fail or time out.
+    fn broadcast(&self, event: &str) {
+        let Ok(mut clients) = self.clients.lock() else {
+            return;
+        };
+        if clients.is_empty() {
+            return;
+        }
+        let frame = frame(OPCODE_TEXT, event.as_bytes());
+        clients.retain_mut(|client| client.stream.write_all(&frame).is_ok());
+    }
+}
+
+impl SimulationObserver for WebSocketStreamer {
+    fn before_tick(&mut self, simulation: &Simulation) {
+        self.broadcast(&format!(
+            "{{\"event\":\"tick\",\"time\":{}}}",
+            simulation.time
+        ));
+    }
+
+    fn after_tick(&mut self, simulation: &Simulation) {
+        for agent in simulation.agents.iter() {
+            let state = agent.state();
+            if self.modes.get(&state.id) == Some(&state.mode) {
+                continue;
+            }
+            self.modes.insert(state.id.clone(), state.mode);
+            self.broadcast(&format!(
+                "{{\"event\":\"mode\",\"time\":{},\"agent\":{},\"mode\":\"{:?}\"}}",
+                simulation.time,
+                json::string(&state.id),
+                state.mode
+            ));
+        }
+    }
+
+    fn on_message_delivered(&mut self, time: DiscreteTime, message: &Message) {
+        self.broadcast(&format!(
+            "{{\"event\":\"delivered\",\"time\":{},\"source\":{},\"destination\":{}}}",
+            time,
+            json::string(&message.source),
+            json::string(&message.destination)
+        ));
+    }
+}
+
+/// Answers a client's opening handshake, adds it to the clients, and then
+/// reads its frames until it leaves.
+fn accept(id: u64, stream: TcpStream, clients: &Weak<Mutex<Vec<Client>>>) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let mut key = None;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value.trim().to_string());
+            }
+        }
+    }
+    let key = key.ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, "no Sec-WebSocket-Key")
+    })?;
+
+    // Answered and added under the lock, so the client misses nothing sent
+    // after, and gets nothing before the answer.
+    stream.set_read_timeout(None)?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    stream.set_nodelay(true)?;
+    let mut client = Client { id, stream };
+    {
+        let Some(clients) = clients.upgrade() else {
+            return Ok(());
+        };
+        let mut clients = clients
+            .lock()
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "poisoned clients"))?;
+        write!(
+            client.stream,
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+            accept_key(&key)
+        )?;
+        clients.push(client);
+    }
+
+    serve(id, reader, clients);
+    Ok(())
+}
+
+/// Reads the frames of a client: answers its pings, and drops it once it
+/// closes, disconnects, or sends a frame it shouldn't.
+fn serve(id: u64, mut reader: impl Read, clients: &Weak<Mutex<Vec<Client>>>) {
+    loop {
+        let frame = read_frame(&mut reader);
+        let Some(clients) = clients.upgrade() else {
+            return;
+        };
+        let Ok(mut clients) = clients.lock() else {
+            return;
+        };
+        let Some(index) = clients.iter().position(|c| c.id == id) else {
+            return;
+        };
+        let answered = match frame {
+            Ok((OPCODE_PING, payload)) => clients[index]
+                .stream
+                .write_all(&self::frame(OPCODE_PONG, &payload))
+                .is_ok(),
+            Ok((OPCODE_CLOSE, payload)) => {
+                // Echoes the status code, as the closing handshake asks.
+                let status = payload.get(..2).unwrap_or_default();
+                let _ = clients[index]
+                    .stream
+                    .write_all(&self::frame(OPCODE_CLOSE, status));
+                false
+            }
+            Ok(_) => true,
+            Err(_) => false,
+        };
+        if !answered {
+            clients.remove(index);
+            return;
+        }
+    }
+}
+
+/// Reads a client's frame, unmasked, as its opcode and payload.
+fn read_frame(reader: &mut impl Read) -> std::io::Result<(u8, Vec<u8>)> {
+    let mut header = [0; 2];
+    reader.read_exact(&mut header)?;
+    let opcode = header[0] & 0x0F;
+    let masked = header[1] & 0x80 != 0;
+    let len = match header[1] & 0x7F {
+        126 => {
+            let mut len = [0; 2];
+            reader.read_exact(&mut len)?;
+            u16::from_be_bytes(len) as u64
+        }
+        127 => {
+            let mut len = [0; 8];
+            reader.read_exact(&mut len)?;
+            u64::from_be_bytes(len)
+        }
+        len => len as u64,
+    };
+    if len > MAX_FRAME {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "frame too long",
+        ));
+    }
+
+    let mut mask = [0; 4];
+    if masked {
+        reader.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0; len as usize];
+    reader.read_exact(&mut payload)?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    Ok((opcode, payload))
+}
+
+/// The Sec-WebSocket-Accept of a client's Sec-WebSocket-Key.
+fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key, HANDSHAKE_GUID).as_bytes()))
+}
+
+/// An unmasked, unfragmented frame, as servers send them.
+fn frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (h, x) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(x);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (bytes, h) in digest.chunks_mut(4).zip(h) {
+        bytes.copy_from_slice(&h.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        use std::fmt::Write as _;
+        bytes.iter().fold(String::new(), |mut hex, b| {
+            let _ = write!(hex, "{:02x}", b);
+            hex
+        })
+    }
+
+    #[test]
+    fn sha1_test() {
+        // The examples of FIPS 180-2.
+        assert_eq!(hex(&sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(
+            hex(&sha1(b"abc")),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            hex(&sha1(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+        );
+        assert_eq!(
+            hex(&sha1(&[b'a'; 1_000_000])),
+            "34aa973cd4c4daa4f61eeb2bdbad27316534016f"
+        );
+    }
+
+    #[test]
+    fn base64_test() {
+        // The examples of RFC 4648.
+        for (data, encoded) in [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(base64(data.as_bytes()), encoded);
+        }
+    }
+
+    #[test]
+    fn accept_key_test() {
+        // The example of RFC 6455.
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    /// Connects to the streamer and completes the opening handshake.
+    fn connect(streamer: &WebSocketStreamer) -> BufReader<TcpStream> {
+        let mut client = TcpStream::connect(streamer.local_addr()).unwrap();
+        write!(
+            client,
+            "GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n"
+        )
+        .unwrap();
+        let mut reader = BufReader::new(client);
+        let mut response = String::new();
+        while !response.ends_with("\r\n\r\n") {
+            reader.read_line(&mut response).unwrap();
+        }
+        assert!(response.starts_with("HTTP/1.1 101 Switching Protocols"));
+        assert!(response.contains("s3pPLMBiTxaQ9kYGzzhZRbK+xOo="));
+        reader
+    }
+
+    /// A masked frame, as clients send them.
+    fn client_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [1, 2, 3, 4];
+        let mut frame = vec![0x80 | opcode, 0x80 | payload.len() as u8];
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        frame
+    }
+
+    /// Reads the text messages of the frames a server sent, until it closes.
+    fn read_messages(reader: &mut impl Read) -> Vec<String> {
+        let mut messages = vec![];
+        while let Ok((opcode, payload)) = read_frame(reader) {
+            assert_eq!(opcode, OPCODE_TEXT);
+            messages.push(String::from_utf8(payload).unwrap());
+        }
+        messages
+    }
+
+    #[test]
+    fn websocket_streamer_test() {
+        let streamer = WebSocketStreamer::bind("127.0.0.1:0").unwrap();
+        let mut client = connect(&streamer);
+        assert_eq!(streamer.clients(), 1);
+
+        let mut simulation = Simulation::new(SimulationParameters {
+            agents: vec![
+                periodic_producing_agent("producer", 2, "consumer"),
+                periodic_consuming_agent("consumer", 1),
+            ],
+            observers: vec![Box::new(streamer)],
+            halt_check: |s: &Simulation| s.time == 2,
+            ..Default::default()
+        });
+        simulation.run();
+        drop(simulation);
+
+        assert_eq!(
+            read_messages(&mut client),
+            [
+                r#"{"event":"tick","time":0}"#,
+                r#"{"event":"delivered","time":0,"source":"producer","destination":"consumer"}"#,
+                r#"{"event":"mode","time":0,"agent":"producer","mode":"AsleepUntil(2)"}"#,
+                r#"{"event":"mode","time":0,"agent":"consumer","mode":"AsleepUntil(1)"}"#,
+                r#"{"event":"tick","time":1}"#,
+                r#"{"event":"mode","time":1,"agent":"consumer","mode":"AsleepUntil(2)"}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn ping_and_close_test() {
+        let streamer = WebSocketStreamer::bind("127.0.0.1:0").unwrap();
+        let mut client = connect(&streamer);
+        assert_eq!(streamer.clients(), 1);
+
+        client
+            .get_mut()
+            .write_all(&client_frame(OPCODE_PING, b"hi"))
+            .unwrap();
+        assert_eq!(
+            read_frame(&mut client).unwrap(),
+            (OPCODE_PONG, b"hi".to_vec())
+        );
+
+        client
+            .get_mut()
+            .write_all(&client_frame(OPCODE_CLOSE, &[0x03, 0xE8, b'b', b'y', b'e']))
+            .unwrap();
+        assert_eq!(
+            read_frame(&mut client).unwrap(),
+            (OPCODE_CLOSE, vec![0x03, 0xE8])
+        );
+        let mut rest = vec![];
+        client.read_to_end(&mut rest).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(streamer.clients(), 0);
+    }
+}
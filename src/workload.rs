@@ -0,0 +1,220 @@
+//! Workload specifications: how requests arrive at a system.
+//!
+//! In an open workload, requests arrive independently of the system's state,
+//! so a slow system builds up queues. In a closed workload, a fixed population
+//! of users each waits for the response to its request and thinks before
+//! sending the next one, so a slow system slows down the arrivals.
+
+use crate::{
+    poisson_distributed_producing_agent, Agent, AgentMode, AgentState, DiscreteTime, Message,
+    RequestHandle, Simulation, SimulationState,
+};
+use rand::prelude::*;
+use rand_distr::Poisson;
+use simul_macro::agent;
+use std::collections::BTreeMap;
+
+/// How requests arrive at a system.
+#[derive(Clone, Debug)]
+pub enum Workload {
+    /// Requests arrive independently of the system, with Poisson-distributed
+    /// ticks between arrivals.
+    Open { interarrival: Poisson<f64> },
+    /// A fixed population of users cycles through sending a request, waiting
+    /// for its reply, and thinking for a Poisson-distributed number of ticks.
+    Closed {
+        population: usize,
+        think_time: Poisson<f64>,
+    },
+}
+
+/// Returns an Agent that generates the workload's requests to target. In a
+/// closed workload the target must reply to every request, e.g. a
+/// `serving_agent`; the replies it consumed record each response time.
+pub fn workload_agent<T>(id: T, workload: Workload, target: T) -> Box<dyn Agent>
+where
+    T: Into<String>,
+{
+    match workload {
+        Workload::Open { interarrival } => {
+            poisson_distributed_producing_agent(id, interarrival, target)
+        }
+        Workload::Closed {
+            population,
+            think_time,
+        } => user_population(id, population, think_time, target),
+    }
+}
+
+/// A population of users, the building block of closed-loop performance
+/// models. Each user cycles through sending a request to target, waiting for
+/// the reply (matched by correlation id), and thinking for a
+/// Poisson-distributed number of ticks.
+///
+/// Every reply is recorded as consumed, with `queued_time` set to when its
+/// request was sent and the user's index as its `custom_payload`. See
+/// `user_sessions` for per-user statistics.
+pub fn user_population<T>(
+    id: T,
+    population: usize,
+    think_time: Poisson<f64>,
+    target: T,
+) -> Box<dyn Agent>
+where
+    T: Into<String>,
+{
+    #[derive(Clone, Debug)]
+    struct User {
+        /// The outstanding request and when it was sent.
+        pending: Option<(RequestHandle, DiscreteTime)>,
+        thinking_until: DiscreteTime,
+    }
+
+    #[agent]
+    struct UserPopulation {
+        users: Vec<User>,
+        think_time: Poisson<f64>,
+        target: String,
+    }
+
+    impl Agent for UserPopulation {
+        fn process(
+            &mut self,
+            simulation_state: SimulationState,
+            msg: &Message,
+        ) -> Option<Vec<Message>> {
+            let time = simulation_state.time;
+
+            // Replies arrive in bursts, so handle everything that is queued.
+            let incoming = std::iter::once(msg.clone()).chain(self.state.queue.drain(..));
+            for reply in incoming.collect::<Vec<_>>() {
+                let Some((index, user)) = self
+                    .users
+                    .iter_mut()
+                    .enumerate()
+                    .find(|(_, u)| u.pending.is_some_and(|(p, _)| p.matches(&reply)))
+                else {
+                    continue;
+                };
+
+                let (_, sent) = user.pending.take()?;
+                user.thinking_until = time + self.think_time.sample(&mut crate::rng()) as u64;
+                self.state.consumed.push(Message {
+                    queued_time: sent,
+                    completed_time: Some(time),
+                    custom_payload: Some((index as u64).to_le_bytes().to_vec()),
+                    ..reply
+                });
+            }
+
+            let mut requests = vec![];
+            for user in self.users.iter_mut() {
+                if user.pending.is_none() && user.thinking_until <= time {
+                    let (request, handle) =
+                        Message::request(time, self.state.id.as_str(), self.target.as_str(), None);
+                    user.pending = Some((handle, time));
+                    requests.push(request);
+                }
+            }
+
+            Some(requests)
+        }
+    }
+
+    Box::new(UserPopulation {
+        users: vec![
+            User {
+                pending: None,
+                thinking_until: 0,
+            };
+            population
+        ],
+        think_time,
+        target: target.into(),
+        state: AgentState {
+            mode: AgentMode::Proactive,
+            wake_mode: AgentMode::Proactive,
+            id: id.into(),
+            ..Default::default()
+        },
+    })
+}
+
+/// The session statistics of one user of a `user_population`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SessionStats {
+    /// The number of requests that were replied to.
+    pub requests: usize,
+    /// The mean ticks from sending a request to receiving its reply.
+    pub mean_response_time: f64,
+    pub max_response_time: DiscreteTime,
+}
+
+/// Returns the session statistics of every user of a `user_population` that
+/// got at least one reply, keyed by the user's index.
+pub fn user_sessions(
+    simulation: &Simulation,
+    population_id: &str,
+) -> Option<BTreeMap<usize, SessionStats>> {
+    let mut sessions: BTreeMap<usize, SessionStats> = BTreeMap::new();
+
+    for reply in simulation.consumed_for_agent(population_id)? {
+        let Some(user) = reply
+            .custom_payload
+            .as_deref()
+            .and_then(|p| p.try_into().ok())
+            .map(u64::from_le_bytes)
+        else {
+            continue;
+        };
+        let response_time = reply.completed_time.unwrap_or(reply.queued_time) - reply.queued_time;
+
+        let session = sessions.entry(user as usize).or_default();
+        session.mean_response_time = (session.mean_response_time * session.requests as f64
+            + response_time as f64)
+            / (session.requests + 1) as f64;
+        session.requests += 1;
+        session.max_response_time = session.max_response_time.max(response_time);
+    }
+
+    Some(sessions)
+}
+
+/// A server that takes `service_period` ticks per request and replies to
+/// each, in the order they arrived.
+pub fn serving_agent<T>(id: T, service_period: DiscreteTime) -> Box<dyn Agent>
+where
+    T: Into<String>,
+{
+    #[agent]
+    struct Server {
+        service_period: DiscreteTime,
+    }
+
+    impl Agent for Server {
+        fn process(
+            &mut self,
+            simulation_state: SimulationState,
+            msg: &Message,
+        ) -> Option<Vec<Message>> {
+            let time = simulation_state.time;
+            self.state.mode = AgentMode::AsleepUntil(time + self.service_period);
+            self.state.consumed.push(Message {
+                completed_time: Some(time),
+                ..msg.clone()
+            });
+
+            Some(vec![msg.reply(time, msg.custom_payload.clone())])
+        }
+    }
+
+    Box::new(Server {
+        service_period,
+        state: AgentState {
+            mode: AgentMode::Reactive,
+            wake_mode: AgentMode::Reactive,
+            id: id.into(),
+            ..Default::default()
+        },
+    })
+}
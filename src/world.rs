@@ -0,0 +1,80 @@
+use crate::DiscreteTime;
+use dyn_clone::DynClone;
+use std::collections::HashMap;
+
+/// The shared environment variables of a Simulation, e.g. a temperature or a
+/// price, keyed by name. Agents can read these via the `SimulationState`.
+pub type Environment = HashMap<String, f64>;
+
+/// WorldDynamics model continuous background processes of the Simulation.
+/// They are called once per tick, before any Agent processes, to update the
+/// environment according to some equation, e.g. temperature decay or price drift.
+pub trait WorldDynamics: std::fmt::Debug + DynClone + Send {
+    /// Advance the environment by one tick at the given time.
+    fn update(&mut self, time: DiscreteTime, environment: &mut Environment);
+}
+
+dyn_clone::clone_trait_object!(WorldDynamics);
+
+/// WorldDynamics given by a plain function, for equations that need no state.
+#[derive(Clone, Debug)]
+pub struct FnDynamics(pub fn(DiscreteTime, &mut Environment));
+
+impl WorldDynamics for FnDynamics {
+    fn update(&mut self, time: DiscreteTime, environment: &mut Environment) {
+        (self.0)(time, environment)
+    }
+}
+
+/// Decays a variable exponentially towards a target, by a fraction `rate` of
+/// the remaining distance every tick. Newton's law of cooling is the classic
+/// example. Missing variables start at the target.
+pub fn exponential_decay<T>(variable: T, rate: f64, target: f64) -> Box<dyn WorldDynamics>
+where
+    T: Into<String>,
+{
+    #[derive(Clone, Debug)]
+    struct ExponentialDecay {
+        variable: String,
+        rate: f64,
+        target: f64,
+    }
+
+    impl WorldDynamics for ExponentialDecay {
+        fn update(&mut self, _time: DiscreteTime, environment: &mut Environment) {
+            let value = environment
+                .entry(self.variable.clone())
+                .or_insert(self.target);
+            *value += (self.target - *value) * self.rate;
+        }
+    }
+
+    Box::new(ExponentialDecay {
+        variable: variable.into(),
+        rate,
+        target,
+    })
+}
+
+/// Changes a variable by a constant amount every tick. Missing variables start at 0.
+pub fn linear_drift<T>(variable: T, rate: f64) -> Box<dyn WorldDynamics>
+where
+    T: Into<String>,
+{
+    #[derive(Clone, Debug)]
+    struct LinearDrift {
+        variable: String,
+        rate: f64,
+    }
+
+    impl WorldDynamics for LinearDrift {
+        fn update(&mut self, _time: DiscreteTime, environment: &mut Environment) {
+            *environment.entry(self.variable.clone()).or_insert(0.0) += self.rate;
+        }
+    }
+
+    Box::new(LinearDrift {
+        variable: variable.into(),
+        rate,
+    })
+}
@@ -0,0 +1,82 @@
+//! Locks in the allocation behaviour of the engine's message delivery path: a
+//! bounded number of allocations per message, however many Agents the
+//! Simulation has, i.e. delivery looks its destination up by handle rather
+//! than by scanning the Agents.
+//!
+//! A counting global allocator counts the allocations made on this thread, so
+//! we can assert on how the allocations of a run grow with its size.
+
+use simul::agent::*;
+use simul::*;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|a| a.set(a.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Counts the allocations of running a producer -> consumer Simulation for
+/// the given number of ticks, alongside some idle bystander Agents.
+fn allocations_for_run(ticks: DiscreteTime, bystanders: usize) -> usize {
+    let mut agents = vec![
+        periodic_producing_agent("producer".to_string(), 1, "consumer".to_string()),
+        periodic_consuming_agent("consumer".to_string(), 1),
+    ];
+    agents
+        .extend((0..bystanders).map(|i| periodic_consuming_agent(format!("idle{}", i), u64::MAX)));
+
+    let mut simulation = Simulation::new(SimulationParameters {
+        agents,
+        halt_check: |s: &Simulation| {
+            s.environment
+                .get("ticks")
+                .map_or(true, |ticks| s.time == *ticks as DiscreteTime)
+        },
+        environment: [("ticks".to_string(), ticks as f64)].into(),
+        seed: Some(0),
+        ..Default::default()
+    });
+
+    let before = ALLOCATIONS.with(|a| a.get());
+    simulation.run();
+    ALLOCATIONS.with(|a| a.get()) - before
+}
+
+/// The allocations per tick in steady state, i.e. excluding start-up costs
+/// and the amortized growth of the message logs.
+fn steady_state_allocations_per_tick(bystanders: usize) -> f64 {
+    let short = allocations_for_run(1000, bystanders);
+    let long = allocations_for_run(2000, bystanders);
+    (long - short) as f64 / 1000.0
+}
+
+#[test]
+fn delivery_does_not_scale_with_agent_count() {
+    let alone = steady_state_allocations_per_tick(0);
+    let crowded = steady_state_allocations_per_tick(100);
+
+    // Every tick the producer allocates a message (two Strings and the Vec
+    // returning it) and the consumer keeps a copy in its consumed log (two
+    // Strings). The engine allocates the tick's message bus and a copy for the
+    // producer's produced log (two Strings), and moves the original onto the
+    // consumer's queue. Anything above 8 is the amortized growth of the logs.
+    assert!(alone < 8.5, "{} allocations per tick", alone);
+    // The idle Agents add nothing to the cost of a tick.
+    assert_eq!(crowded, alone);
+}